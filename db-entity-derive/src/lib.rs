@@ -3,10 +3,144 @@ use proc_macro::TokenStream;
 use quote::quote;
 use syn::DeriveInput;
 
-#[proc_macro_derive(DbEntity)]
+#[proc_macro_derive(DbEntity, attributes(db_entity))]
 pub fn db_entity(input: TokenStream) -> TokenStream {
     let ast: DeriveInput = syn::parse(input).unwrap();
+    // 只读的表(如任务、出错记录)不生成新增与更新的函数
+    let mut readonly = false;
+    for attr in ast.attrs.iter().filter(|attr| attr.path().is_ident("db_entity")) {
+        let result = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("readonly") {
+                readonly = true;
+                return Ok(());
+            }
+            Err(meta.error("unsupported db_entity attribute"))
+        });
+        if let Err(err) = result {
+            return err.to_compile_error().into();
+        }
+    }
     let id = ast.ident;
+    let write = if readonly {
+        quote! {}
+    } else {
+        quote! {
+            impl #id {
+                // 检查唯一键是否已存在，避免直接依赖数据库的出错信息
+                async fn check_unique<C: sea_orm::ConnectionTrait>(conn: &C, data: &ActiveModel, id: Option<i64>) -> Result<()> {
+                    'keys: for keys in Self::get_unique_keys().iter() {
+                        let mut cond = Condition::all();
+                        let mut changed = false;
+                        for column in keys.iter() {
+                            match data.get(*column) {
+                                sea_orm::ActiveValue::Set(value) => {
+                                    changed = true;
+                                    cond = cond.add(column.eq(value));
+                                }
+                                sea_orm::ActiveValue::Unchanged(value) => {
+                                    cond = cond.add(column.eq(value));
+                                }
+                                // 未设置的无法判断，由数据库保证
+                                sea_orm::ActiveValue::NotSet => continue 'keys,
+                            }
+                        }
+                        // 未修改的无需检查
+                        if !changed {
+                            continue;
+                        }
+                        if let Some(id) = id {
+                            cond = cond.add(Column::Id.ne(id));
+                        }
+                        let count = Self::scope(Entity::find())?.filter(cond).count(conn).await?;
+                        if count > 0 {
+                            return Err(Error::Conflict {
+                                fields: keys.iter().map(|item| item.to_string()).collect(),
+                            }
+                            .into());
+                        }
+                    }
+                    Ok(())
+                }
+                // 生成记录已被修改的出错，包含当前记录与提交数据的差异
+                fn outdated(current: &Model, value: &Value) -> Result<crate::error::HttpError> {
+                    let current = serde_json::to_value(current)?;
+                    Ok(Error::Outdated {
+                        items: super::diff_json(&current, value),
+                    }
+                    .into())
+                }
+                // 提交的数据需包含updated_at，仅在记录未被修改时才更新
+                pub async fn update_by_id(user: &str, id: i64, value: &Value) -> Result<()> {
+                    Self::update_by_id_with(get_database().await, user, id, value).await
+                }
+                // 使用指定的连接更新，用于在事务中批量更新
+                pub async fn update_by_id_with<C: sea_orm::ConnectionTrait>(conn: &C, user: &str, id: i64, value: &Value) -> Result<()> {
+                    Self::validate_for_update(user).await?;
+                    let result = Self::scope(Entity::find_by_id(id))?.one(conn).await?;
+                    if result.is_none() {
+                        return Err(Error::NotFound.into());
+                    }
+                    let model = result.unwrap();
+                    let expected = crate::util::json_get_date_time(value, Column::UpdatedAt.as_str())?
+                        .ok_or(Error::PreconditionRequired)?;
+                    if model.updated_at != expected {
+                        return Err(Self::outdated(&model, value)?);
+                    }
+                    let mut data: ActiveModel = model.into();
+                    Self::update_from_value(&mut data, value)?;
+                    Self::check_unique(conn, &data, Some(id)).await?;
+                    data.updater = Set(Some(user.to_string()));
+                    // 更新时以updated_at为条件，避免查询后被其它请求修改
+                    let data = sea_orm::ActiveModelBehavior::before_save(data, conn, false).await?;
+                    let result = Entity::update(data)
+                        .filter(Column::UpdatedAt.eq(expected))
+                        .exec(conn)
+                        .await;
+                    if let Err(sea_orm::DbErr::RecordNotUpdated) = result {
+                        let current = Self::scope(Entity::find_by_id(id))?
+                            .one(conn)
+                            .await?
+                            .ok_or(Error::NotFound)?;
+                        return Err(Self::outdated(&current, value)?);
+                    }
+                    result?;
+                    Ok(())
+                }
+                // 预览更新后的数据差异，仅校验数据不保存
+                pub async fn preview_by_id(user: &str, id: i64, value: &Value) -> Result<Vec<super::FieldDiff>> {
+                    Self::validate_for_update(user).await?;
+                    let conn = get_database().await;
+                    let model = Self::scope(Entity::find_by_id(id))?
+                        .one(conn)
+                        .await?
+                        .ok_or(Error::NotFound)?;
+                    let mut data: ActiveModel = model.clone().into();
+                    Self::update_from_value(&mut data, value)?;
+                    Self::check_unique(conn, &data, Some(id)).await?;
+                    let updated = sea_orm::TryIntoModel::try_into_model(data)?;
+                    Ok(super::diff_json(
+                        &serde_json::to_value(&model)?,
+                        &serde_json::to_value(&updated)?,
+                    ))
+                }
+                pub async fn insert(user: &str, value: &Value) -> Result<Model> {
+                    Self::validate_for_insert(user).await?;
+                    let mut data = ActiveModel {
+                        ..Default::default()
+                    };
+                    Self::update_from_value(&mut data, value)?;
+                    if let Some(column) = Self::get_tenant_column() {
+                        data.set(column, sea_orm::Value::from(super::current_tenant_id()?));
+                    }
+                    let conn = get_database().await;
+                    Self::check_unique(conn, &data, None).await?;
+                    data.creator = Set(user.to_string());
+                    let result = data.insert(conn).await?;
+                    Ok(result)
+                }
+            }
+        }
+    };
     let gen = quote! {
         impl #id {
             fn order_by<E>(sql: Select<E>, orders: &str) -> Result<Select<E>>
@@ -44,54 +178,59 @@ pub fn db_entity(input: TokenStream) -> TokenStream {
                 }
                 Ok(s)
             }
-            pub async fn update_by_id(user: &str, id: i64, value: &Value) -> Result<()> {
-                Self::validate_for_update(user).await?;
-                let conn = get_database().await;
-                let result = Entity::find_by_id(id).one(conn).await?;
-                if result.is_none() {
-                    return Err(Error::NotFound.into());
+            // 租户隔离的表添加当前租户的条件，未设置租户时不允许查询
+            fn scope<E>(sql: Select<E>) -> Result<Select<E>>
+            where E: EntityTrait {
+                match Self::get_tenant_column() {
+                    Some(column) => Ok(sql.filter(super::tenant_condition(column)?)),
+                    None => Ok(sql),
                 }
-                let mut data: ActiveModel = result.unwrap().into();
-                Self::update_from_value(&mut data, value)?;
-                data.updater = Set(Some(user.to_string()));
-                data.update(conn).await?;
-                Ok(())
             }
-            pub async fn insert(user: &str, value: &Value) -> Result<Model> {
-                Self::validate_for_insert(user).await?;
-                let mut data = ActiveModel {
-                    ..Default::default()
+            // 仅查询指定的字段，未指定时查询默认的字段
+            fn select_columns(sql: Select<Entity>, fields: &[String]) -> Select<Entity> {
+                let columns = if fields.is_empty() {
+                    Self::get_columns()
+                } else {
+                    Some(fields.to_vec())
                 };
-                Self::update_from_value(&mut data, value)?;
-                data.creator = Set(user.to_string());
-                let result = data.insert(get_database().await).await?;
-                Ok(result)
+                let Some(columns) = columns else {
+                    return sql;
+                };
+                let mut sql = sql.select_only();
+                for col in columns.iter() {
+                    if let Ok(column) = Column::from_str(col) {
+                        sql = sql.column(column);
+                    }
+                }
+                sql
             }
-            pub async fn find_by_id(user: &str, id: i64) -> Result<Option<Value>> {
+            pub async fn find_by_id(user: &str, id: i64, fields: &[String]) -> Result<Option<Value>> {
                 Self::validate_for_query(user).await?;
                 let conn = get_database().await;
-                let mut sql = Entity::find_by_id(id);
-                if let Some(columns) = Self::get_columns() {
-                    sql = sql.select_only();
-                    for col in columns.iter() {
-                        if let Ok(column) = Column::from_str(col) {
-                            sql = sql.column(column);
-                        }
-                    }
-                }
+                let sql = Self::select_columns(Self::scope(Entity::find_by_id(id))?, fields);
                 let item = sql.into_json().one(conn).await?;
                 Ok(item)
             }
+            // 按id顺序查询大于after的记录，用于导出等需要遍历所有记录的场景
+            pub async fn list_after(user: &str, params: &ListCountParams, after: i64, limit: u64) -> Result<Vec<Value>> {
+                Self::validate_for_query(user).await?;
+                let mut sql = Self::scope(Entity::find())?;
+                if let Some(cond) = Self::get_condition(params) {
+                    sql = sql.filter(cond);
+                }
+                sql = sql.filter(Column::Id.gt(after)).order_by_asc(Column::Id);
+                let sql = Self::select_columns(sql, &params.get_fields());
+                guarded_fetch_page(sql, limit, 0).await
+            }
             pub async fn list_count(user: &str, params: &ListCountParams) -> Result<(i64, Vec<Value>)> {
                 Self::validate_for_query(user).await?;
-                let conn = get_database().await;
-                let mut sql = Entity::find();
+                let mut sql = Self::scope(Entity::find())?;
                 if let Some(cond) = Self::get_condition(params) {
                     sql = sql.filter(cond);
                 }
 
                 let page_count = if params.counted {
-                    let count = sql.clone().count(conn).await?;
+                    let count = guarded_count(sql.clone()).await?;
                     let mut page_count = count / params.page_size;
                     if count % params.page_size != 0 {
                         page_count += 1;
@@ -101,27 +240,29 @@ pub fn db_entity(input: TokenStream) -> TokenStream {
                     -1
                 };
 
-                sql = Self::order_by(
-                    sql,
-                    &params.orders.clone().unwrap_or("-updated_at".to_string()),
-                )?;
-                if let Some(columns) = Self::get_columns() {
-                    sql = sql.select_only();
-                    for col in columns.iter() {
-                        if let Ok(column) = Column::from_str(col) {
-                            sql = sql.column(column);
-                        }
-                    }
-                }
-                let items = sql
-                    .into_json()
-                    .paginate(conn, params.page_size)
-                    .fetch_page(params.page)
-                    .await?;
+                // 指定cursor时按id查询，避免页数较大时offset扫描过多记录，
+                // 上一页按id正序查询，由调用方调整为倒序
+                let page = if let Some(cursor) = params.get_cursor() {
+                    sql = if cursor.prev {
+                        sql.filter(Column::Id.gt(cursor.id)).order_by_asc(Column::Id)
+                    } else {
+                        sql.filter(Column::Id.lt(cursor.id)).order_by_desc(Column::Id)
+                    };
+                    0
+                } else {
+                    sql = Self::order_by(
+                        sql,
+                        &params.orders.clone().unwrap_or("-updated_at".to_string()),
+                    )?;
+                    params.page
+                };
+                let sql = Self::select_columns(sql, &params.get_fields());
+                let items = guarded_fetch_page(sql, params.page_size, page).await?;
 
                 Ok((page_count, items))
             }
         }
+        #write
     };
     gen.into()
 }
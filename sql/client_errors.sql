@@ -0,0 +1,18 @@
+CREATE TABLE `client_errors` (
+  `id` bigint(20) NOT NULL AUTO_INCREMENT,
+  `created_at` timestamp NOT NULL comment '创建时间',
+  `updated_at` timestamp NOT NULL comment '更新时间',
+  `fingerprint` varchar(64) COLLATE utf8mb4_bin NOT NULL comment '出错指纹',
+  `severity` varchar(16) COLLATE utf8mb4_bin NOT NULL comment '出错级别',
+  `message` varchar(1024) COLLATE utf8mb4_bin NOT NULL comment '出错信息',
+  `stack` text COLLATE utf8mb4_bin comment '出错堆栈',
+  `url` varchar(2048) COLLATE utf8mb4_bin NOT NULL comment '页面地址',
+  `user_agent` varchar(512) COLLATE utf8mb4_bin NOT NULL comment '浏览器UA',
+  `release` varchar(64) COLLATE utf8mb4_bin NOT NULL comment '前端版本',
+  `device_id` varchar(64) COLLATE utf8mb4_bin NOT NULL comment '设备ID',
+  `updater` varchar(255) COLLATE utf8mb4_bin DEFAULT '' comment '更新者',
+  `creator` varchar(255) COLLATE utf8mb4_bin NOT NULL comment '创建者(登录账号)',
+  PRIMARY KEY (`id`) comment '主键',
+  KEY `client_error_created_at` (`created_at`),
+  KEY `client_error_fingerprint` (`fingerprint`)
+) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
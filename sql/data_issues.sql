@@ -0,0 +1,15 @@
+CREATE TABLE `data_issues` (
+  `id` bigint(20) NOT NULL AUTO_INCREMENT,
+  `created_at` timestamp NOT NULL comment '创建时间',
+  `updated_at` timestamp NOT NULL comment '更新时间',
+  `entity` varchar(64) COLLATE utf8mb4_bin NOT NULL comment '数据表',
+  `record_id` bigint(20) NOT NULL comment '记录id',
+  `code` varchar(64) COLLATE utf8mb4_bin NOT NULL comment '问题类型',
+  `detail` varchar(1024) COLLATE utf8mb4_bin NOT NULL comment '问题描述',
+  `first_seen_at` timestamp NOT NULL comment '首次发现时间',
+  `last_seen_at` timestamp NOT NULL comment '最近发现时间',
+  PRIMARY KEY (`id`) comment '主键',
+  UNIQUE KEY `data_issue_entity_record_code` (`entity`,`record_id`,`code`),
+  KEY `data_issue_code` (`code`),
+  KEY `data_issue_last_seen_at` (`last_seen_at`)
+) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
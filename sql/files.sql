@@ -6,11 +6,13 @@ CREATE TABLE `files` (
   `size` bigint(20) NOT NULL comment '文件大小',
   `content_type` varchar(255) COLLATE utf8mb4_bin NOT NULL comment '文件类型',
   `data` mediumblob NOT NULL comment '文件数据',
+  `hash` varchar(64) COLLATE utf8mb4_bin NOT NULL DEFAULT '' comment '文件内容的sha256',
   `updater` varchar(255) COLLATE utf8mb4_bin DEFAULT '' comment '更新者',
   `creator` varchar(255) COLLATE utf8mb4_bin NOT NULL comment '创建者',
+  `tenant_id` bigint(20) NOT NULL DEFAULT 1 comment '所属租户',
   PRIMARY KEY (`id`),
-  UNIQUE KEY `name` (`name`),
-  UNIQUE KEY `file_name` (`name`),
+  UNIQUE KEY `file_tenant_name` (`tenant_id`, `name`),
+  KEY `file_tenant_hash` (`tenant_id`, `hash`),
   KEY `file_created_at` (`created_at`),
   KEY `file_updated_at` (`updated_at`)
 ) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
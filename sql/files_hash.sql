@@ -0,0 +1,3 @@
+-- 文件内容的hash，用于上传相同文件时复用已有记录，已有的记录为空不参与匹配
+ALTER TABLE `files` ADD COLUMN `hash` varchar(64) COLLATE utf8mb4_bin NOT NULL DEFAULT '' comment '文件内容的sha256' AFTER `data`;
+ALTER TABLE `files` ADD KEY `file_tenant_hash` (`tenant_id`, `hash`);
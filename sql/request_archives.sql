@@ -0,0 +1,19 @@
+CREATE TABLE `request_archives` (
+  `id` bigint(20) NOT NULL AUTO_INCREMENT,
+  `created_at` timestamp NOT NULL comment '创建时间',
+  `updated_at` timestamp NOT NULL comment '更新时间',
+  `method` varchar(16) COLLATE utf8mb4_bin NOT NULL comment '请求方法',
+  `uri` varchar(2048) COLLATE utf8mb4_bin NOT NULL comment '请求地址',
+  `path` varchar(512) COLLATE utf8mb4_bin NOT NULL comment '请求路径',
+  `headers` json NOT NULL comment '请求头(已脱敏)',
+  `body` mediumtext COLLATE utf8mb4_bin comment '请求数据',
+  `body_truncated` tinyint(4) NOT NULL DEFAULT '0' comment '请求数据是否已截断',
+  `status` int(11) NOT NULL comment '响应状态码',
+  `latency` int(11) NOT NULL comment '处理时长(ms)',
+  `trace_id` varchar(64) COLLATE utf8mb4_bin NOT NULL comment 'trace id',
+  `account` varchar(255) COLLATE utf8mb4_bin NOT NULL DEFAULT '' comment '登录账号',
+  PRIMARY KEY (`id`) comment '主键',
+  KEY `request_archive_created_at` (`created_at`),
+  KEY `request_archive_path` (`path`),
+  KEY `request_archive_status` (`status`)
+) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
@@ -0,0 +1,18 @@
+CREATE TABLE `tasks` (
+  `id` bigint(20) NOT NULL AUTO_INCREMENT,
+  `status` tinyint(4) NOT NULL DEFAULT '0' comment '状态，0：待执行，1：执行中，2：成功，3：失败，4：已放弃',
+  `created_at` timestamp NOT NULL comment '创建时间',
+  `updated_at` timestamp NOT NULL comment '更新时间',
+  `category` varchar(64) COLLATE utf8mb4_bin NOT NULL comment '任务类型',
+  `payload` json DEFAULT NULL comment '任务参数',
+  `attempts` int(11) NOT NULL DEFAULT '0' comment '已执行次数',
+  `run_after` timestamp NOT NULL comment '可执行时间',
+  `locked_by` varchar(64) COLLATE utf8mb4_bin DEFAULT NULL comment '执行实例',
+  `locked_at` timestamp NULL DEFAULT NULL comment '锁定时间',
+  `message` varchar(1024) COLLATE utf8mb4_bin DEFAULT NULL comment '出错信息',
+  `updater` varchar(255) COLLATE utf8mb4_bin DEFAULT '' comment '更新者',
+  `creator` varchar(255) COLLATE utf8mb4_bin NOT NULL comment '创建者',
+  PRIMARY KEY (`id`) comment '主键',
+  KEY `task_category_status_run_after` (`category`,`status`,`run_after`),
+  KEY `task_updated_at` (`updated_at`)
+) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
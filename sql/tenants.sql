@@ -0,0 +1,10 @@
+CREATE TABLE `tenants` (
+  `id` bigint(20) NOT NULL AUTO_INCREMENT,
+  `status` tinyint(4) NOT NULL DEFAULT '0' comment '状态，0：禁用，1：启用',
+  `created_at` timestamp NOT NULL comment '创建时间',
+  `updated_at` timestamp NOT NULL comment '更新时间',
+  `name` varchar(255) COLLATE utf8mb4_bin NOT NULL comment '租户名称',
+  `remark` varchar(255) COLLATE utf8mb4_bin DEFAULT NULL comment '备注',
+  PRIMARY KEY (`id`) comment '主键',
+  UNIQUE KEY `tenant_name` (`name`)
+) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
@@ -0,0 +1,11 @@
+-- 多租户的数据迁移，已有数据均归属于默认租户
+
+-- 1. 创建默认租户，id固定为1
+INSERT INTO `tenants` (`id`, `status`, `created_at`, `updated_at`, `name`) VALUES (1, 1, NOW(), NOW(), 'default');
+
+-- 2. 用户与文件增加租户字段
+ALTER TABLE `users` ADD COLUMN `tenant_id` bigint(20) NOT NULL DEFAULT 1 comment '所属租户' AFTER `merged_into`, ADD KEY `user_tenant_id` (`tenant_id`);
+ALTER TABLE `files` ADD COLUMN `tenant_id` bigint(20) NOT NULL DEFAULT 1 comment '所属租户' AFTER `creator`;
+
+-- 3. 文件名仅在租户内唯一
+ALTER TABLE `files` DROP INDEX `name`, DROP INDEX `file_name`, ADD UNIQUE KEY `file_tenant_name` (`tenant_id`, `name`);
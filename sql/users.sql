@@ -9,6 +9,11 @@ CREATE TABLE `users` (
   `groups` json DEFAULT NULL comment '用户群组',
   `remark` varchar(255) COLLATE utf8mb4_bin DEFAULT NULL comment '备注',
   `email` varchar(255) COLLATE utf8mb4_bin DEFAULT NULL comment '用户邮箱',
+  `display_account` varchar(255) COLLATE utf8mb4_bin DEFAULT NULL comment '账号(原始大小写)',
+  `merged_into` bigint(20) DEFAULT NULL comment '已合并至的用户',
+  `tenant_id` bigint(20) NOT NULL DEFAULT 1 comment '所属租户',
+  `totp_secret` varchar(64) COLLATE utf8mb4_bin DEFAULT NULL comment '两步验证的密钥',
   PRIMARY KEY (`id`) comment '主键',
-  UNIQUE KEY `user_account` (`account`)
+  UNIQUE KEY `user_account` (`account`),
+  KEY `user_tenant_id` (`tenant_id`)
 ) ENGINE=InnoDB AUTO_INCREMENT=1 DEFAULT CHARSET=utf8mb4 COLLATE=utf8mb4_bin;
\ No newline at end of file
@@ -0,0 +1,2 @@
+-- 用户合并，记录源账号合并至的用户
+ALTER TABLE `users` ADD COLUMN `merged_into` bigint(20) DEFAULT NULL comment '已合并至的用户' AFTER `display_account`;
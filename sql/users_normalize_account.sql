@@ -0,0 +1,14 @@
+-- 账号规范化(去除首尾空格并转换为小写)的数据迁移
+
+-- 1. 增加显示账号字段，保留原始大小写
+ALTER TABLE `users` ADD COLUMN `display_account` varchar(255) COLLATE utf8mb4_bin DEFAULT NULL comment '账号(原始大小写)' AFTER `email`;
+UPDATE `users` SET `display_account` = `account` WHERE `display_account` IS NULL;
+
+-- 2. 检查规范化后冲突的账号，若有记录需先人工处理再继续
+SELECT LOWER(TRIM(`account`)) AS normalized_account, COUNT(*) AS count, GROUP_CONCAT(`id`) AS ids
+FROM `users`
+GROUP BY normalized_account
+HAVING count > 1;
+
+-- 3. 无冲突后更新账号，唯一索引user_account保证规范化后的账号唯一
+UPDATE `users` SET `account` = LOWER(TRIM(`account`)) WHERE `account` != LOWER(TRIM(`account`));
@@ -0,0 +1,2 @@
+-- 两步验证，设置密钥后登录需要输入验证码
+ALTER TABLE `users` ADD COLUMN `totp_secret` varchar(64) COLLATE utf8mb4_bin DEFAULT NULL comment '两步验证的密钥' AFTER `tenant_id`;
@@ -0,0 +1,187 @@
+use crate::config::get_env;
+use crate::db::{anonymize_entity, Anonymize, AnonymizeStats, ANONYMIZE_ENTITIES};
+use crate::util;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+const REDACTED: &str = "[redacted]";
+
+// 文本中需要替换的敏感内容
+static REDACT_PATTERNS: Lazy<Vec<(Regex, &'static str)>> = Lazy::new(|| {
+    [
+        // json中密钥相关的字段
+        (
+            r#"(?i)("[\w-]*(?:password|secret|token|key)[\w-]*"\s*:\s*)"[^"]*""#,
+            r#"$1"[redacted]""#,
+        ),
+        // 地址参数中的凭证
+        (
+            r"(?i)([?&][\w-]*(?:token|key|secret|signature)[\w-]*=)[^&\s#]+",
+            "${1}[redacted]",
+        ),
+        (r"[\w.+-]+@[\w-]+(?:\.[\w-]+)+", REDACTED),
+        (r"\b\d{1,3}\.\d{1,3}\.\d{1,3}\.\d{1,3}\b", REDACTED),
+        (r"\b1[3-9]\d{9}\b", REDACTED),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+/// 数据脱敏，相同的原始值替换后的值也相同，
+/// 因此各表中的账号仍可关联
+pub struct Anonymizer {
+    salt: String,
+}
+
+impl Anonymizer {
+    pub fn new(salt: &str) -> Self {
+        Anonymizer {
+            salt: salt.to_string(),
+        }
+    }
+    fn hash(&self, value: &str) -> String {
+        util::sha256(format!("{}:{value}", self.salt).as_bytes())
+    }
+    fn redact(&self, value: &str) -> String {
+        let mut result = value.to_string();
+        for (re, replacement) in REDACT_PATTERNS.iter() {
+            result = re.replace_all(&result, *replacement).to_string();
+        }
+        result
+    }
+    /// 按规则转换字段值，空值保持不变
+    pub fn transform(&self, rule: Anonymize, value: &Value) -> Option<Value> {
+        if value.is_null() {
+            return None;
+        }
+        if let Value::String(value) = value {
+            if value.is_empty() {
+                return None;
+            }
+        }
+        let result = match (rule, value) {
+            (Anonymize::Null, _) => Value::Null,
+            (Anonymize::Empty, _) => Value::String("".to_string()),
+            (Anonymize::Account, Value::String(value)) => {
+                Value::String(format!("user_{}", &self.hash(value)[..12]))
+            }
+            (Anonymize::Email, Value::String(value)) => {
+                Value::String(format!("{}@example.com", &self.hash(value)[..12]))
+            }
+            (Anonymize::Hash, Value::String(value)) => Value::String(self.hash(value)),
+            (Anonymize::Redact, Value::String(value)) => Value::String(self.redact(value)),
+            // json字段替换后需仍为合法的json
+            (Anonymize::Redact, _) => {
+                serde_json::from_str(&self.redact(&value.to_string())).unwrap_or(Value::Null)
+            }
+            _ => return None,
+        };
+        Some(result)
+    }
+}
+
+/// 对所有表执行脱敏，直接修改当前配置的数据库
+pub async fn anonymize(salt: &str) -> Vec<Result<AnonymizeStats, String>> {
+    let anonymizer = Anonymizer::new(salt);
+    let mut result = vec![];
+    for name in ANONYMIZE_ENTITIES.iter() {
+        let stats = anonymize_entity(name, |rule, value| anonymizer.transform(rule, value))
+            .await
+            .map_err(|err| format!("{name}: {}", err.message));
+        result.push(stats);
+    }
+    result
+}
+
+/// 命令行执行脱敏：`tibba anonymize --confirm [--salt xxx]`，
+/// 仅用于从备份恢复的数据库副本，不支持生产环境
+#[tokio::main]
+pub async fn run_command(args: &[String]) -> i32 {
+    if get_env() == "production" {
+        eprintln!("anonymize is not allowed in production");
+        return 1;
+    }
+    if !args.iter().any(|item| item == "--confirm") {
+        eprintln!("anonymize modifies the configured database in place, rerun with --confirm");
+        return 1;
+    }
+    // 未指定时随机生成，避免通过常见账号反推
+    let salt = args
+        .iter()
+        .position(|item| item == "--salt")
+        .and_then(|index| args.get(index + 1))
+        .cloned()
+        .unwrap_or_else(util::uuid);
+    let mut code = 0;
+    for item in anonymize(&salt).await {
+        match item {
+            Ok(stats) => println!("{}", serde_json::to_string(&stats).unwrap_or_default()),
+            Err(message) => {
+                code = 1;
+                eprintln!("{message}");
+            }
+        }
+    }
+    code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Anonymizer;
+    use crate::db::Anonymize;
+    use pretty_assertions::assert_eq;
+    use serde_json::{json, Value};
+
+    #[test]
+    fn transform() {
+        let anonymizer = Anonymizer::new("salt");
+        let account = anonymizer
+            .transform(Anonymize::Account, &json!("tree"))
+            .unwrap();
+        assert_eq!(
+            Some(account.clone()),
+            anonymizer.transform(Anonymize::Account, &json!("tree"))
+        );
+        assert_eq!(true, account.as_str().unwrap().starts_with("user_"));
+        assert_eq!(
+            true,
+            anonymizer
+                .transform(Anonymize::Email, &json!("tree@gmail.com"))
+                .unwrap()
+                .as_str()
+                .unwrap()
+                .ends_with("@example.com")
+        );
+        assert_eq!(None, anonymizer.transform(Anonymize::Account, &Value::Null));
+        assert_eq!(None, anonymizer.transform(Anonymize::Null, &json!("")));
+        assert_eq!(
+            Some(Value::Null),
+            anonymizer.transform(Anonymize::Null, &json!("remark"))
+        );
+
+        assert_eq!(
+            Some(json!(
+                r#"{"name":"abc","api_key":"[redacted]","mail":"[redacted]"}"#
+            )),
+            anonymizer.transform(
+                Anonymize::Redact,
+                &json!(r#"{"name":"abc","api_key":"123456","mail":"a@b.com"}"#)
+            )
+        );
+        assert_eq!(
+            Some(json!(
+                "https://a.com/?id=1&token=[redacted] from [redacted]"
+            )),
+            anonymizer.transform(
+                Anonymize::Redact,
+                &json!("https://a.com/?id=1&token=abc from 10.1.1.1")
+            )
+        );
+        assert_eq!(
+            Some(json!({"x-forwarded-for": "[redacted]"})),
+            anonymizer.transform(Anonymize::Redact, &json!({"x-forwarded-for": "10.1.1.1"}))
+        );
+    }
+}
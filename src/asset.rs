@@ -7,7 +7,15 @@ use rust_embed::{EmbeddedFile, RustEmbed};
 #[derive(RustEmbed)]
 #[folder = "assets/"]
 struct Assets;
-pub struct StaticFile(Option<EmbeddedFile>);
+pub struct StaticFile(Option<EmbeddedFile>, Option<String>);
+
+impl StaticFile {
+    /// 设置csp nonce，html中的内联脚本添加此nonce
+    pub fn with_nonce(mut self, nonce: &str) -> Self {
+        self.1 = Some(nonce.to_string());
+        self
+    }
+}
 
 impl IntoResponse for StaticFile {
     fn into_response(self) -> Response {
@@ -45,11 +53,26 @@ impl IntoResponse for StaticFile {
         } else {
             format!("public, max-age={max_age}")
         };
+        let content_type = mime_type.to_string();
+        // 内联脚本添加nonce，由于每次请求的nonce不同，
+        // 因此不设置etag，避免304时使用旧nonce的html
+        if let Some(nonce) = self.1.filter(|_| content_type.contains("text/html")) {
+            let html = std::string::String::from_utf8_lossy(&file.data)
+                .replace("<script", &format!(r#"<script nonce="{nonce}""#));
+            return (
+                [
+                    (header::CONTENT_TYPE, content_type),
+                    (header::CACHE_CONTROL, cache_control),
+                ],
+                html,
+            )
+                .into_response();
+        }
         // 静态文件压缩由前置缓存服务器处理
         (
             [
                 // content type
-                (header::CONTENT_TYPE, mime_type.to_string()),
+                (header::CONTENT_TYPE, content_type),
                 // 为啥不设置Last-Modified
                 // https://developer.mozilla.org/en-US/docs/Web/HTTP/Caching#heuristic_caching
                 // e tag
@@ -69,7 +92,7 @@ fn get_asset(file_path: &str) -> Option<EmbeddedFile> {
 // 获取静态资源文件
 pub fn get_static_file(file_path: &str) -> StaticFile {
     let file = get_asset(file_path);
-    StaticFile(file)
+    StaticFile(file, None)
 }
 
 // 获取资源文件并返回字符串(trim后)
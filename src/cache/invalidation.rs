@@ -0,0 +1,222 @@
+use super::redis_pool::must_get_redis_connection;
+use super::{Error, Result, TtlLruStore};
+use crate::config::must_new_redis_config;
+use async_trait::async_trait;
+use deadpool_redis::redis::cmd;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::{error, info};
+
+/// 缓存失效通知的频道，消息内容为包含前缀的完整key
+pub const INVALIDATION_CHANNEL: &str = "tibba:cache:invalidation";
+
+// 订阅断开后重连的初始与最长等待时间
+const INITIAL_RECONNECT_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_BACKOFF: Duration = Duration::from_secs(30);
+
+// 进程内的缓存，收到失效通知时删除对应的key
+#[async_trait]
+pub(super) trait LocalEvict: Send + Sync {
+    async fn evict(&self, key: &str);
+    async fn clear(&self);
+}
+
+#[async_trait]
+impl<T: Clone + Send + Sync> LocalEvict for TtlLruStore<T> {
+    async fn evict(&self, key: &str) {
+        self.del(key).await;
+    }
+    async fn clear(&self) {
+        TtlLruStore::clear(self).await;
+    }
+}
+
+// 订阅线程发送的事件
+enum InvalidationEvent {
+    // 订阅成功，断开期间的通知已丢失
+    Subscribed,
+    // 失效的key
+    Key(String),
+}
+
+type LocalStores = Vec<(String, Arc<dyn LocalEvict>)>;
+
+static LOCAL_STORES: Lazy<RwLock<LocalStores>> = Lazy::new(|| RwLock::new(vec![]));
+
+/// 注册进程内的缓存，prefix需与redis缓存的前缀一致
+pub(super) fn register_local_store(prefix: &str, store: Arc<dyn LocalEvict>) {
+    if let Ok(mut stores) = LOCAL_STORES.write() {
+        stores.push((prefix.to_string(), store));
+    }
+}
+
+/// 发布key失效的通知，其它实例收到后删除进程内的缓存
+pub(super) async fn publish_invalidation(key: &str) -> Result<()> {
+    let mut conn = must_get_redis_connection().await?;
+    cmd("PUBLISH")
+        .arg(INVALIDATION_CHANNEL)
+        .arg(key)
+        .query_async::<_, ()>(&mut conn)
+        .await
+        .map_err(|e| Error::Redis {
+            category: "publish".to_string(),
+            source: e,
+        })?;
+    Ok(())
+}
+
+/// 根据通知删除匹配前缀的进程内缓存，返回删除的数量，
+/// 无匹配前缀的消息直接忽略
+pub(super) async fn evict_local(message: &str) -> usize {
+    let matched: Vec<(String, Arc<dyn LocalEvict>)> = match LOCAL_STORES.read() {
+        Ok(stores) => stores
+            .iter()
+            .filter_map(|(prefix, store)| {
+                let key = message.strip_prefix(prefix.as_str())?;
+                (!key.is_empty()).then(|| (key.to_string(), store.clone()))
+            })
+            .collect(),
+        Err(_) => return 0,
+    };
+    for (key, store) in matched.iter() {
+        store.evict(key).await;
+    }
+    matched.len()
+}
+
+/// 清除所有进程内缓存，用于(重新)订阅成功时，
+/// 避免断开期间丢失的通知导致数据直到过期前都不一致
+pub(super) async fn clear_local() -> usize {
+    let stores: Vec<Arc<dyn LocalEvict>> = match LOCAL_STORES.read() {
+        Ok(stores) => stores.iter().map(|(_, store)| store.clone()).collect(),
+        Err(_) => return 0,
+    };
+    for store in stores.iter() {
+        store.clear().await;
+    }
+    stores.len()
+}
+
+fn next_backoff(current: Duration) -> Duration {
+    (current * 2).min(MAX_RECONNECT_BACKOFF)
+}
+
+// 订阅失效通知，订阅成功时调用on_subscribed，连接断开时返回出错
+fn subscribe(
+    url: &str,
+    tx: &mpsc::UnboundedSender<InvalidationEvent>,
+    on_subscribed: impl FnOnce(),
+) -> redis::RedisResult<()> {
+    let client = redis::Client::open(url)?;
+    let mut conn = client.get_connection()?;
+    let mut pubsub = conn.as_pubsub();
+    pubsub.subscribe(INVALIDATION_CHANNEL)?;
+    info!(category = "cache_invalidation", "subscribed");
+    on_subscribed();
+    if tx.send(InvalidationEvent::Subscribed).is_err() {
+        return Ok(());
+    }
+    loop {
+        let msg = pubsub.get_message()?;
+        // 非字符串的消息忽略
+        let Ok(key) = msg.get_payload::<String>() else {
+            continue;
+        };
+        if tx.send(InvalidationEvent::Key(key)).is_err() {
+            return Ok(());
+        }
+    }
+}
+
+/// 启动失效通知的订阅，redis的pubsub为阻塞式读取，因此使用单独的线程，
+/// 连接断开后按指数退避重连，订阅成功后重置退避时间并清除进程内缓存
+pub fn spawn_invalidation_subscriber() {
+    let (tx, mut rx) = mpsc::unbounded_channel::<InvalidationEvent>();
+    let url = must_new_redis_config().nodes[0].clone();
+    std::thread::spawn(move || {
+        let mut backoff = INITIAL_RECONNECT_BACKOFF;
+        loop {
+            match subscribe(&url, &tx, || backoff = INITIAL_RECONNECT_BACKOFF) {
+                // 接收方已关闭
+                Ok(_) => return,
+                Err(err) => {
+                    error!(
+                        category = "cache_invalidation",
+                        error = err.to_string(),
+                        "subscription is lost, reconnect after {backoff:?}"
+                    );
+                }
+            }
+            std::thread::sleep(backoff);
+            backoff = next_backoff(backoff);
+        }
+    });
+    tokio::spawn(async move {
+        while let Some(event) = rx.recv().await {
+            match event {
+                InvalidationEvent::Subscribed => {
+                    clear_local().await;
+                }
+                InvalidationEvent::Key(key) => {
+                    evict_local(&key).await;
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{clear_local, evict_local, next_backoff, register_local_store};
+    use crate::cache::{Lookup, TtlLruStore};
+    use pretty_assertions::assert_eq;
+    use std::num::NonZeroUsize;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn invalidation() {
+        let new_store = || Arc::new(TtlLruStore::<String>::new(NonZeroUsize::new(10).unwrap()));
+        // 两个实例各自的进程内缓存
+        let first = new_store();
+        let second = new_store();
+        let other = new_store();
+        register_local_store("invalidation-test:", first.clone());
+        register_local_store("invalidation-test:", second.clone());
+        register_local_store("invalidation-other:", other.clone());
+        for store in [&first, &second, &other] {
+            store
+                .set_with_ttl("tree", "tree".to_string(), Duration::from_secs(60))
+                .await;
+        }
+
+        assert_eq!(2, evict_local("invalidation-test:tree").await);
+        assert_eq!(Lookup::Miss, first.lookup("tree").await);
+        assert_eq!(Lookup::Miss, second.lookup("tree").await);
+        assert_eq!(
+            Lookup::Hit(Some("tree".to_string())),
+            other.lookup("tree").await
+        );
+
+        // 格式不正确的消息忽略
+        for message in ["", "tree", "invalidation-test:", "unknown:tree"] {
+            assert_eq!(0, evict_local(message).await);
+        }
+        assert_eq!(
+            Lookup::Hit(Some("tree".to_string())),
+            other.lookup("tree").await
+        );
+
+        // 重新订阅时清除所有进程内缓存
+        assert_eq!(true, clear_local().await >= 3);
+        assert_eq!(Lookup::Miss, other.lookup("tree").await);
+
+        assert_eq!(Duration::from_secs(2), next_backoff(Duration::from_secs(1)));
+        assert_eq!(
+            Duration::from_secs(30),
+            next_backoff(Duration::from_secs(20))
+        );
+    }
+}
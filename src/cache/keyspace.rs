@@ -0,0 +1,176 @@
+use super::redis_pool::{is_redis_cluster, must_get_redis_connection, RedisConnection};
+use super::{Error, Result};
+use crate::util::HumanBytes;
+use deadpool_redis::redis::{cmd, pipe};
+use serde::Serialize;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::time::Duration;
+
+// 每次scan的数量
+const SCAN_COUNT: usize = 100;
+// 每批scan之后的等待时长，避免影响线上服务
+const SCAN_INTERVAL: Duration = Duration::from_millis(10);
+// 每多少个key采样一次内存占用
+const MEMORY_SAMPLE_STEP: usize = 10;
+
+/// 相同模式的key的统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KeyBucket {
+    pub pattern: String,
+    pub count: u64,
+    // 根据采样的平均值估算的内存占用
+    pub memory: u64,
+    pub memory_human: HumanBytes,
+    // 未设置有效期的key的数量
+    pub no_ttl_count: u64,
+    #[serde(skip)]
+    sampled: u64,
+    #[serde(skip)]
+    sampled_memory: u64,
+}
+
+/// keyspace的统计报告，cursor为0表示已扫描完成，
+/// 否则可使用此cursor继续扫描
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct KeyspaceReport {
+    pub cursor: u64,
+    pub scanned: u64,
+    pub top_count: Vec<KeyBucket>,
+    pub top_memory: Vec<KeyBucket>,
+}
+
+fn is_id_segment(value: &str) -> bool {
+    if value.is_empty() {
+        return false;
+    }
+    // 纯数字
+    if value.chars().all(|c| c.is_ascii_digit()) {
+        return true;
+    }
+    // uuid或较长的hex
+    let hex = value.replace('-', "");
+    hex.len() >= 16 && hex.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// 将key中的id替换为*，用于按模式分组
+pub fn get_key_pattern(key: &str) -> String {
+    key.split(':')
+        .map(|item| if is_id_segment(item) { "*" } else { item })
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+/// 从cursor开始扫描匹配prefix的key，最多扫描max_keys个，
+/// 统计各模式的数量、内存占用以及未设置有效期的key
+pub async fn scan_keyspace(
+    prefix: &str,
+    cursor: u64,
+    max_keys: u64,
+    top: usize,
+) -> Result<KeyspaceReport> {
+    // cluster模式下scan仅针对单个节点，无法保证结果完整
+    if is_redis_cluster() {
+        return Err(Error::Common {
+            category: "scan".to_string(),
+            message: "Keyspace scan is not supported in cluster mode".to_string(),
+        });
+    }
+    let mut conn = must_get_redis_connection().await?;
+    let pattern = format!("{prefix}*");
+    let mut buckets: HashMap<String, KeyBucket> = HashMap::new();
+    let mut cursor = cursor;
+    let mut scanned = 0;
+    loop {
+        let (next, keys): (u64, Vec<String>) = cmd("SCAN")
+            .arg(cursor)
+            .arg("MATCH")
+            .arg(&pattern)
+            .arg("COUNT")
+            .arg(SCAN_COUNT)
+            .query_async::<RedisConnection, _>(&mut conn)
+            .await
+            .map_err(|e| Error::Redis {
+                category: "scan".to_string(),
+                source: e,
+            })?;
+        cursor = next;
+        if !keys.is_empty() {
+            let mut p = pipe();
+            for (index, key) in keys.iter().enumerate() {
+                p.cmd("TTL").arg(key);
+                if index % MEMORY_SAMPLE_STEP == 0 {
+                    p.cmd("MEMORY").arg("USAGE").arg(key);
+                }
+            }
+            let values: Vec<Option<i64>> = p
+                .query_async::<RedisConnection, _>(&mut conn)
+                .await
+                .map_err(|e| Error::Redis {
+                    category: "scan".to_string(),
+                    source: e,
+                })?;
+            let mut values = values.into_iter();
+            for (index, key) in keys.iter().enumerate() {
+                let ttl = values.next().flatten().unwrap_or_default();
+                let bucket = buckets.entry(get_key_pattern(key)).or_default();
+                bucket.count += 1;
+                // -1 表示未设置有效期
+                if ttl == -1 {
+                    bucket.no_ttl_count += 1;
+                }
+                if index % MEMORY_SAMPLE_STEP == 0 {
+                    if let Some(memory) = values.next().flatten() {
+                        bucket.sampled += 1;
+                        bucket.sampled_memory += memory.max(0) as u64;
+                    }
+                }
+            }
+            scanned += keys.len() as u64;
+        }
+        if cursor == 0 || scanned >= max_keys {
+            break;
+        }
+        tokio::time::sleep(SCAN_INTERVAL).await;
+    }
+
+    let mut items: Vec<KeyBucket> = buckets
+        .into_iter()
+        .map(|(pattern, mut bucket)| {
+            bucket.pattern = pattern;
+            if let Some(avg) = bucket.sampled_memory.checked_div(bucket.sampled) {
+                bucket.memory = avg * bucket.count;
+            }
+            bucket.memory_human = HumanBytes(bucket.memory);
+            bucket
+        })
+        .collect();
+    items.sort_by_key(|item| Reverse(item.count));
+    let top_count = items.iter().take(top).cloned().collect();
+    items.sort_by_key(|item| Reverse(item.memory));
+    let top_memory = items.into_iter().take(top).collect();
+    Ok(KeyspaceReport {
+        cursor,
+        scanned,
+        top_count,
+        top_memory,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::get_key_pattern;
+    use pretty_assertions::assert_eq;
+    #[test]
+    fn key_pattern() {
+        assert_eq!(
+            "ss:*",
+            get_key_pattern("ss:0190a8b2-6c4f-7d3e-9a1b-2c3d4e5f6a7b")
+        );
+        assert_eq!(
+            "{limit}:login_fail:*",
+            get_key_pattern("{limit}:login_fail:123")
+        );
+        assert_eq!("captcha:abc", get_key_pattern("captcha:abc"));
+    }
+}
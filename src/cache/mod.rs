@@ -46,6 +46,9 @@ impl From<Error> for HttpError {
     }
 }
 
+mod invalidation;
+mod keyspace;
+mod read_through;
 mod redis_client;
 mod redis_pool;
 /// 缓存相关功能，支持种缓存（lru+ttl)，以及
@@ -53,8 +56,8 @@ mod redis_pool;
 mod ttl_lru_store;
 mod two_level_store;
 
+pub use invalidation::spawn_invalidation_subscriber;
+pub use keyspace::{scan_keyspace, KeyspaceReport};
 pub use redis_client::{get_default_redis_cache, redis_ping, RedisCache};
-pub use ttl_lru_store::TtlLruStore;
+pub use ttl_lru_store::{Lookup, TtlLruStore};
 pub use two_level_store::TwoLevelStore;
-
-pub(self) use ttl_lru_store::Expired;
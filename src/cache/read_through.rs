@@ -0,0 +1,224 @@
+use super::{Error, RedisCache, Result};
+use crate::util::{zstd_decode, zstd_encode};
+use async_trait::async_trait;
+use rand::Rng;
+use serde::{de::DeserializeOwned, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+// 加载数据时锁的有效期，加载方异常退出后其它调用方可重新加载
+const LOADER_LOCK_TTL: Duration = Duration::from_secs(5);
+// 未获取锁时等待其它调用方写入缓存的间隔及次数
+const LOADER_POLL_INTERVAL: Duration = Duration::from_millis(50);
+const LOADER_POLL_TIMES: u32 = 20;
+
+/// 有效期增加最多10%的随机时长，避免同时写入的缓存同时过期
+fn jitter_ttl(ttl: Duration) -> Duration {
+    let max = ttl.as_millis() as u64 / 10;
+    if max == 0 {
+        return ttl;
+    }
+    ttl + Duration::from_millis(rand::thread_rng().gen_range(0..max))
+}
+
+// 读取缓存所需的操作，便于测试时替换
+#[async_trait]
+pub(super) trait ReadThroughStore: Sync {
+    async fn get_bytes(&self, key: &str) -> Result<Vec<u8>>;
+    async fn set_bytes(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<()>;
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<bool>;
+    async fn unlock(&self, key: &str) -> Result<()>;
+}
+
+#[async_trait]
+impl ReadThroughStore for RedisCache {
+    async fn get_bytes(&self, key: &str) -> Result<Vec<u8>> {
+        self.get(key).await
+    }
+    async fn set_bytes(&self, key: &str, value: Vec<u8>, ttl: Duration) -> Result<()> {
+        self.set(key, value, Some(ttl)).await
+    }
+    async fn try_lock(&self, key: &str, ttl: Duration) -> Result<bool> {
+        self.lock(key, Some(ttl)).await
+    }
+    async fn unlock(&self, key: &str) -> Result<()> {
+        self.del(key).await
+    }
+}
+
+fn encode_value<T: Serialize>(value: &T, compressed: bool) -> Result<Vec<u8>> {
+    let buf = serde_json::to_vec(value).map_err(|e| Error::Common {
+        category: "get_or_set_struct".to_string(),
+        message: e.to_string(),
+    })?;
+    if !compressed {
+        return Ok(buf);
+    }
+    zstd_encode(&buf).map_err(|e| Error::Compress { source: e })
+}
+
+fn decode_value<T: DeserializeOwned>(buf: &[u8], compressed: bool) -> Result<Option<T>> {
+    if buf.is_empty() {
+        return Ok(None);
+    }
+    let buf = if compressed {
+        zstd_decode(buf).map_err(|e| Error::Compress { source: e })?
+    } else {
+        buf.to_vec()
+    };
+    let result = serde_json::from_slice(&buf).map_err(|e| Error::Common {
+        category: "get_or_set_struct".to_string(),
+        message: e.to_string(),
+    })?;
+    Ok(Some(result))
+}
+
+/// 优先从缓存读取，无数据时仅获取锁的调用方执行loader并写入缓存，
+/// 其它调用方等待其写入，等待超时则自行加载
+pub(super) async fn read_through<S, T, F, Fut, E>(
+    store: &S,
+    key: &str,
+    ttl: Duration,
+    compressed: bool,
+    loader: F,
+) -> std::result::Result<T, E>
+where
+    S: ReadThroughStore + ?Sized,
+    T: Serialize + DeserializeOwned,
+    F: FnOnce() -> Fut,
+    Fut: Future<Output = std::result::Result<T, E>>,
+    E: From<Error>,
+{
+    if let Some(value) = decode_value(&store.get_bytes(key).await?, compressed)? {
+        return Ok(value);
+    }
+    let lock_key = format!("{key}:loading");
+    let locked = store.try_lock(&lock_key, LOADER_LOCK_TTL).await?;
+    if !locked {
+        for _ in 0..LOADER_POLL_TIMES {
+            tokio::time::sleep(LOADER_POLL_INTERVAL).await;
+            if let Some(value) = decode_value(&store.get_bytes(key).await?, compressed)? {
+                return Ok(value);
+            }
+        }
+    }
+    // 写入缓存后再释放锁，避免其它调用方重复加载
+    let result = async {
+        let value = loader().await?;
+        store
+            .set_bytes(key, encode_value(&value, compressed)?, jitter_ttl(ttl))
+            .await?;
+        Ok::<T, E>(value)
+    }
+    .await;
+    // 加载失败也需要释放锁，其它调用方可重新加载
+    if locked {
+        store.unlock(&lock_key).await?;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{jitter_ttl, read_through, ReadThroughStore};
+    use crate::cache::Result;
+    use crate::error::HttpError;
+    use async_trait::async_trait;
+    use pretty_assertions::assert_eq;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct MemoryStore {
+        data: Mutex<HashMap<String, Vec<u8>>>,
+        locks: Mutex<HashSet<String>>,
+    }
+
+    #[async_trait]
+    impl ReadThroughStore for MemoryStore {
+        async fn get_bytes(&self, key: &str) -> Result<Vec<u8>> {
+            Ok(self
+                .data
+                .lock()
+                .unwrap()
+                .get(key)
+                .cloned()
+                .unwrap_or_default())
+        }
+        async fn set_bytes(&self, key: &str, value: Vec<u8>, _ttl: Duration) -> Result<()> {
+            self.data.lock().unwrap().insert(key.to_string(), value);
+            Ok(())
+        }
+        async fn try_lock(&self, key: &str, _ttl: Duration) -> Result<bool> {
+            Ok(self.locks.lock().unwrap().insert(key.to_string()))
+        }
+        async fn unlock(&self, key: &str) -> Result<()> {
+            self.locks.lock().unwrap().remove(key);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn concurrent_misses() {
+        for compressed in [false, true] {
+            let store = Arc::new(MemoryStore::default());
+            let loads = Arc::new(AtomicUsize::new(0));
+            let mut handles = vec![];
+            for _ in 0..10 {
+                let store = store.clone();
+                let loads = loads.clone();
+                handles.push(tokio::spawn(async move {
+                    read_through(
+                        store.as_ref(),
+                        "policy",
+                        Duration::from_secs(60),
+                        compressed,
+                        || async move {
+                            loads.fetch_add(1, Ordering::Relaxed);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            Ok::<_, HttpError>(vec!["image/png".to_string()])
+                        },
+                    )
+                    .await
+                }));
+            }
+            for handle in handles {
+                assert_eq!(
+                    vec!["image/png".to_string()],
+                    handle.await.unwrap().unwrap()
+                );
+            }
+            assert_eq!(1, loads.load(Ordering::Relaxed));
+            assert_eq!(true, store.locks.lock().unwrap().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn loader_error() {
+        let store = MemoryStore::default();
+        let result: std::result::Result<String, HttpError> =
+            read_through(&store, "policy", Duration::from_secs(60), false, || async {
+                Err(HttpError::new("Database is unavailable"))
+            })
+            .await;
+        assert_eq!("Database is unavailable", result.unwrap_err().message);
+        // 失败时释放锁且不写入缓存
+        assert_eq!(true, store.locks.lock().unwrap().is_empty());
+        assert_eq!(true, store.data.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn jitter() {
+        let ttl = jitter_ttl(Duration::from_secs(60));
+        assert_eq!(
+            true,
+            ttl >= Duration::from_secs(60) && ttl < Duration::from_secs(66)
+        );
+        assert_eq!(
+            Duration::from_millis(5),
+            jitter_ttl(Duration::from_millis(5))
+        );
+    }
+}
@@ -1,10 +1,15 @@
+use super::invalidation::publish_invalidation;
+use super::read_through::read_through;
 use super::redis_pool::{must_get_redis_connection, RedisConnection};
 use super::{Error, Result};
-use crate::util::{lz4_decode, lz4_encode, zstd_decode, zstd_encode};
-use deadpool_redis::redis::{cmd, pipe};
-use once_cell::sync::OnceCell;
+use crate::util::{is_development, is_test};
+use deadpool_redis::redis::{cmd, pipe, Script};
+use once_cell::sync::{Lazy, OnceCell};
 use serde::{de::DeserializeOwned, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
 use std::time::Duration;
+use tracing::warn;
 
 pub async fn redis_ping() -> Result<String> {
     let mut conn = must_get_redis_connection().await?;
@@ -17,6 +22,39 @@ pub async fn redis_ping() -> Result<String> {
         })
 }
 
+// 多个key的限制检查与计数，
+// 所有key均未超出限制时才增加计数
+static LIMIT_SCRIPT: Lazy<Script> = Lazy::new(|| {
+    Script::new(
+        r#"
+local allowed = 1
+for i, key in ipairs(KEYS) do
+  local current = tonumber(redis.call('GET', key) or '0')
+  if current + 1 > tonumber(ARGV[i * 2 - 1]) then
+    allowed = 0
+  end
+end
+local result = {allowed}
+for i, key in ipairs(KEYS) do
+  local count
+  if allowed == 1 then
+    count = redis.call('INCR', key)
+    if count == 1 then
+      redis.call('PEXPIRE', key, ARGV[i * 2])
+    end
+  else
+    count = tonumber(redis.call('GET', key) or '0')
+  end
+  table.insert(result, count)
+  table.insert(result, redis.call('PTTL', key))
+end
+return result
+"#,
+    )
+});
+// 限制相关的key使用相同的hash tag，保证cluster模式下在同一slot
+static LIMIT_HASH_TAG: &str = "{limit}";
+
 #[derive(Default, Clone, Debug)]
 pub struct RedisCache {
     ttl: Duration,
@@ -60,11 +98,15 @@ impl RedisCache {
         let mut conn = must_get_redis_connection().await?;
 
         let seconds = ttl.unwrap_or(self.ttl).as_secs();
+        // 开发与测试环境检查未设置有效期的key
+        if seconds == 0 && (is_development() || is_test()) {
+            warn!(category = "cache", key, "set value without ttl");
+        }
         cmd("SETEX")
             .arg(key)
             .arg(seconds)
             .arg(value)
-            .query_async(&mut conn)
+            .query_async::<_, ()>(&mut conn)
             .await
             .map_err(|e| Error::Redis {
                 category: "set".to_string(),
@@ -115,7 +157,7 @@ impl RedisCache {
 
         cmd("DEL")
             .arg(&k)
-            .query_async(&mut conn)
+            .query_async::<_, ()>(&mut conn)
             .await
             .map_err(|e| Error::Redis {
                 category: "del".to_string(),
@@ -123,6 +165,27 @@ impl RedisCache {
             })?;
         Ok(())
     }
+    /// 从redis中删除key，返回key是否存在，
+    /// 并发删除时仅一个调用方返回true，可用于一次性凭证的认领
+    pub async fn remove(&self, key: &str) -> Result<bool> {
+        let mut conn = must_get_redis_connection().await?;
+        let k = self.get_key(key);
+
+        let count: i64 = cmd("DEL")
+            .arg(&k)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| Error::Redis {
+                category: "remove".to_string(),
+                source: e,
+            })?;
+        Ok(count > 0)
+    }
+    /// 从redis中删除key，并通知各实例删除进程内的缓存
+    pub async fn del_and_broadcast(&self, key: &str) -> Result<()> {
+        self.del(key).await?;
+        publish_invalidation(&self.get_key(key)).await
+    }
     /// 增加redis中key所对应的值，如果ttl未指定则使用默认值，
     /// 需要注意此ttl仅在首次时设置。
     pub async fn incr(&self, key: &str, delta: i64, ttl: Option<Duration>) -> Result<i64> {
@@ -146,6 +209,87 @@ impl RedisCache {
             })?;
         Ok(count)
     }
+    /// 增加redis中hash字段的值，每次均重新设置有效期，
+    /// 若未指定ttl则使用默认值
+    pub async fn hincr(
+        &self,
+        key: &str,
+        field: &str,
+        delta: i64,
+        ttl: Option<Duration>,
+    ) -> Result<i64> {
+        let mut conn = must_get_redis_connection().await?;
+        let k = self.get_key(key);
+        let (count, _) = pipe()
+            .cmd("HINCRBY")
+            .arg(&k)
+            .arg(field)
+            .arg(delta)
+            .cmd("EXPIRE")
+            .arg(&k)
+            .arg(ttl.unwrap_or(self.ttl).as_secs())
+            .query_async::<RedisConnection, (i64, bool)>(&mut conn)
+            .await
+            .map_err(|e| Error::Redis {
+                category: "hincr".to_string(),
+                source: e,
+            })?;
+        Ok(count)
+    }
+    /// 获取redis中hash的所有字段，不存在时返回空
+    pub async fn hget_all(&self, key: &str) -> Result<HashMap<String, i64>> {
+        let mut conn = must_get_redis_connection().await?;
+        let k = self.get_key(key);
+        let result = cmd("HGETALL")
+            .arg(&k)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| Error::Redis {
+                category: "hget_all".to_string(),
+                source: e,
+            })?;
+        Ok(result)
+    }
+
+    /// 原子性的检查多个key(key, 最大值, 有效期)是否超出限制，有效期精确至毫秒，
+    /// 若均未超出则计数+1，返回是否允许以及各key的当前计数与重置前的剩余时长。
+    /// 脚本优先使用EVALSHA，不存在时自动使用EVAL。
+    pub async fn limit(
+        &self,
+        items: &[(&str, i64, Duration)],
+    ) -> Result<(bool, Vec<(i64, Duration)>)> {
+        let mut conn = must_get_redis_connection().await?;
+        let mut invocation = LIMIT_SCRIPT.prepare_invoke();
+        for (key, max, ttl) in items.iter() {
+            let k = format!("{LIMIT_HASH_TAG}:{}", self.get_key(key));
+            let ttl = if ttl.is_zero() { self.ttl } else { *ttl };
+            // 使用毫秒，避免少于1秒的有效期为0时key直接被删除
+            invocation
+                .key(k)
+                .arg(*max)
+                .arg(ttl.as_millis().max(1) as u64);
+        }
+        let result: Vec<i64> =
+            invocation
+                .invoke_async(&mut conn)
+                .await
+                .map_err(|e| Error::Redis {
+                    category: "limit".to_string(),
+                    source: e,
+                })?;
+        let allowed = result.first().copied().unwrap_or_default() == 1;
+        // 无过期时间时pttl为负数，视为0
+        let counts = result
+            .get(1..)
+            .unwrap_or_default()
+            .chunks(2)
+            .map(|item| {
+                let ttl = item.get(1).copied().unwrap_or_default().max(0);
+                (item[0], Duration::from_millis(ttl as u64))
+            })
+            .collect();
+        Ok((allowed, counts))
+    }
 
     /// 将数据设置至redis中，如果未设置ttl则使用默认值
     pub async fn set<T: redis::ToRedisArgs>(
@@ -177,7 +321,7 @@ impl RedisCache {
         Ok(())
     }
     /// 从redis中获取数据并转换为struct，如果缓存中无数据则返回None
-    pub async fn get_struct<'a, T>(&self, key: &str) -> Result<Option<T>>
+    pub async fn get_struct<T>(&self, key: &str) -> Result<Option<T>>
     where
         T: DeserializeOwned,
     {
@@ -210,6 +354,76 @@ impl RedisCache {
             })?;
         Ok(result)
     }
+    /// 添加数据至列表头部并仅保留max个，同时重新设置有效期，
+    /// 返回超出数量被移除的数据
+    pub async fn lpush_trim(
+        &self,
+        key: &str,
+        value: &str,
+        max: usize,
+        ttl: Option<Duration>,
+    ) -> Result<Vec<String>> {
+        let mut conn = must_get_redis_connection().await?;
+        let k = self.get_key(key);
+        let (removed,): (Vec<String>,) = pipe()
+            .atomic()
+            .cmd("LPUSH")
+            .arg(&k)
+            .arg(value)
+            .ignore()
+            .cmd("LRANGE")
+            .arg(&k)
+            .arg(max)
+            .arg(-1)
+            .cmd("LTRIM")
+            .arg(&k)
+            .arg(0)
+            .arg(max as i64 - 1)
+            .ignore()
+            .cmd("EXPIRE")
+            .arg(&k)
+            .arg(ttl.unwrap_or(self.ttl).as_secs())
+            .ignore()
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| Error::Redis {
+                category: "lpush_trim".to_string(),
+                source: e,
+            })?;
+        Ok(removed)
+    }
+    /// 获取列表的所有数据，不存在时返回空
+    pub async fn lrange(&self, key: &str) -> Result<Vec<String>> {
+        let mut conn = must_get_redis_connection().await?;
+        let k = self.get_key(key);
+        let result = cmd("LRANGE")
+            .arg(&k)
+            .arg(0)
+            .arg(-1)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| Error::Redis {
+                category: "lrange".to_string(),
+                source: e,
+            })?;
+        Ok(result)
+    }
+    /// 从列表中删除该数据，返回是否存在
+    pub async fn lrem(&self, key: &str, value: &str) -> Result<bool> {
+        let mut conn = must_get_redis_connection().await?;
+        let k = self.get_key(key);
+        let count: i64 = cmd("LREM")
+            .arg(&k)
+            .arg(0)
+            .arg(value)
+            .query_async(&mut conn)
+            .await
+            .map_err(|e| Error::Redis {
+                category: "lrem".to_string(),
+                source: e,
+            })?;
+        Ok(count > 0)
+    }
     /// 获取后并删除该key在redis中的值，用于仅获取一次的场景
     pub async fn get_del<T: redis::FromRedisValue>(&self, key: &str) -> Result<T> {
         let k = self.get_key(key);
@@ -227,83 +441,69 @@ impl RedisCache {
             })?;
         Ok(value)
     }
-    /// 将struct转换为json后使用lz4压缩，
-    /// 再将压缩后的数据设置至redis中，若未指定ttl，
-    /// 则使用默认的有效期
-    pub async fn set_struct_lz4<T>(&self, key: &str, value: &T, ttl: Option<Duration>) -> Result<()>
-    where
-        T: ?Sized + Serialize,
-    {
-        let value = serde_json::to_vec(&value).map_err(|e| Error::Common {
-            category: "set_struct_lz4".to_string(),
-            message: e.to_string(),
-        })?;
-        let buf = lz4_encode(&value);
-        let k = self.get_key(key);
-        self.set_value(&k, &buf, ttl).await?;
-        Ok(())
-    }
-    /// 从redis获取数据后使用lz4解压，并转换为对应的struct
-    pub async fn get_struct_lz4<'a, T>(&self, key: &str) -> Result<Option<T>>
-    where
-        T: DeserializeOwned,
-    {
-        let k = self.get_key(key);
-        let value: Vec<u8> = self.get_value(&k).await?;
-
-        if value.is_empty() {
-            return Ok(None);
-        }
-
-        let buf = lz4_decode(value.as_slice()).map_err(|e| Error::Compress { source: e })?;
-
-        let deserializer = &mut serde_json::Deserializer::from_slice(&buf);
-        let result = T::deserialize(deserializer).map_err(|e| Error::Common {
-            category: "get_struct_lz4".to_string(),
-            message: e.to_string(),
-        })?;
-        Ok(Some(result))
-    }
-    /// 将struct转换为json后使用zstd压缩，
-    /// 再将压缩后的数据设置至redis中，若未指定ttl，
-    /// 则使用默认的有效期
-    pub async fn set_struct_zstd<T>(
+    /// 从redis中获取数据(zstd压缩)，无数据时执行loader并将结果写入redis，
+    /// 并发时仅一个调用方执行loader，有效期会增加少量随机时长
+    pub async fn get_or_set_struct_zstd<T, F, Fut, E>(
         &self,
         key: &str,
-        value: &T,
         ttl: Option<Duration>,
-    ) -> Result<()>
+        loader: F,
+    ) -> std::result::Result<T, E>
     where
-        T: ?Sized + Serialize,
+        T: Serialize + DeserializeOwned,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = std::result::Result<T, E>>,
+        E: From<Error>,
     {
-        let value = serde_json::to_vec(&value).map_err(|e| Error::Common {
-            category: "set_struct_zstd".to_string(),
-            message: e.to_string(),
-        })?;
-        let buf = zstd_encode(&value).map_err(|e| Error::Compress { source: e })?;
-        let k = self.get_key(key);
-        self.set_value(&k, &buf, ttl).await?;
-        Ok(())
+        read_through(self, key, ttl.unwrap_or(self.ttl), true, loader).await
     }
-    /// 从redis获取数据后使用zstd解压，并转换为对应的struct
-    pub async fn get_struct_zstd<T>(&self, key: &str) -> Result<Option<T>>
-    where
-        T: DeserializeOwned,
-    {
-        let k = self.get_key(key);
-        let value: Vec<u8> = self.get_value(&k).await?;
+}
 
-        if value.is_empty() {
-            return Ok(None);
+#[cfg(test)]
+mod tests {
+    use super::RedisCache;
+    use crate::util;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    // 并发调用时允许的次数不超出限制
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn limit_concurrency() {
+        let key = format!("limit-test:{}", util::uuid());
+        let mut handles = vec![];
+        for _ in 0..100 {
+            let key = key.clone();
+            handles.push(tokio::spawn(async move {
+                RedisCache::new()
+                    .limit(&[(&key, 10, Duration::from_secs(60))])
+                    .await
+                    .unwrap()
+                    .0
+            }));
+        }
+        let mut allowed = 0;
+        for handle in handles {
+            if handle.await.unwrap() {
+                allowed += 1;
+            }
         }
+        assert_eq!(10, allowed);
+    }
 
-        let buf = zstd_decode(value.as_slice()).map_err(|e| Error::Compress { source: e })?;
+    // 少于1秒的有效期也需要生效
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn limit_sub_second() {
+        let key = format!("limit-test:{}", util::uuid());
+        let cache = RedisCache::new();
+        let items = [(key.as_str(), 1, Duration::from_millis(300))];
+        let (allowed, counts) = cache.limit(&items).await.unwrap();
+        assert_eq!(true, allowed);
+        assert_eq!(true, counts[0].1 > Duration::ZERO);
+        assert_eq!(false, cache.limit(&items).await.unwrap().0);
 
-        let deserializer = &mut serde_json::Deserializer::from_slice(&buf);
-        let result = T::deserialize(deserializer).map_err(|e| Error::Common {
-            category: "get_struct_zstd".to_string(),
-            message: e.to_string(),
-        })?;
-        Ok(Some(result))
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(true, cache.limit(&items).await.unwrap().0);
     }
 }
@@ -83,6 +83,11 @@ fn must_get_redis_pool() -> &'static RedisPool {
         .unwrap()
 }
 
+/// 是否使用redis cluster
+pub fn is_redis_cluster() -> bool {
+    matches!(must_get_redis_pool(), RedisPool::Cluster(_))
+}
+
 pub async fn must_get_redis_connection() -> Result<RedisConnection> {
     let conn = match must_get_redis_pool() {
         RedisPool::Single(p) => {
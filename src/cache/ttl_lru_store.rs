@@ -1,10 +1,20 @@
 use lru::LruCache;
 use std::num::NonZeroUsize;
+use std::time::{Duration, Instant};
 use tokio::sync::RwLock;
 
-pub trait Expired {
-    // 数据是否过期
-    fn is_expired(&self) -> bool;
+/// 缓存的查询结果，Hit(None)表示已缓存数据不存在
+#[derive(Debug, Clone, PartialEq)]
+pub enum Lookup<T> {
+    Hit(Option<T>),
+    Miss,
+}
+
+#[derive(Clone)]
+struct Entry<T> {
+    // 为None时表示数据不存在
+    value: Option<T>,
+    expired_at: Instant,
 }
 
 /// 基于LRU带有效期的存储组件
@@ -13,32 +23,109 @@ pub struct TtlLruStore<T> {
     // 而全局的mut会需要unsafe代码
     // 后续有了解到其它方案再调整
     // 带锁的lru实例
-    cache: RwLock<LruCache<String, T>>,
+    cache: RwLock<LruCache<String, Entry<T>>>,
 }
-impl<T: Expired + Clone> TtlLruStore<T> {
+impl<T: Clone> TtlLruStore<T> {
     pub fn new(size: NonZeroUsize) -> Self {
-        let cache: LruCache<String, T> = LruCache::new(size);
+        let cache: LruCache<String, Entry<T>> = LruCache::new(size);
         TtlLruStore {
             cache: RwLock::new(cache),
         }
     }
-    pub async fn set(&self, key: &str, value: T) {
+    async fn put(&self, key: &str, value: Option<T>, ttl: Duration) {
         let cache = &mut self.cache.write().await;
-        cache.put(key.to_string(), value);
+        cache.put(
+            key.to_string(),
+            Entry {
+                value,
+                expired_at: Instant::now() + ttl,
+            },
+        );
+    }
+    /// 使用指定有效期缓存数据
+    pub async fn set_with_ttl(&self, key: &str, value: T, ttl: Duration) {
+        self.put(key, Some(value), ttl).await;
     }
-    pub async fn get(&self, key: &str) -> Option<T> {
+    /// 缓存数据不存在的标记，有效期一般短于正常数据，
+    /// 之后写入的数据直接覆盖此标记
+    pub async fn set_none(&self, key: &str, ttl: Duration) {
+        self.put(key, None, ttl).await;
+    }
+    /// 查询缓存，区分数据不存在与未缓存
+    pub async fn lookup(&self, key: &str) -> Lookup<T> {
         let cache = self.cache.read().await;
         // 性能考虑使用peek，但不会调整其顺序，因此热点数据也可能被清除
         // 由于其为ttl+lru，因此可设置更大的容量减少热点数据被清除
-        if let Some(v) = cache.peek(key) {
-            if !v.is_expired() {
-                return Some(v.clone());
-            }
+        match cache.peek(key) {
+            Some(entry) if entry.expired_at > Instant::now() => Lookup::Hit(entry.value.clone()),
+            _ => Lookup::Miss,
         }
-        None
     }
-    pub async fn del(&mut self, key: &str) {
+    pub async fn del(&self, key: &str) {
         let cache = &mut self.cache.write().await;
         cache.pop(key);
     }
+    /// 清除所有缓存
+    pub async fn clear(&self) {
+        self.cache.write().await.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lookup, TtlLruStore};
+    use pretty_assertions::assert_eq;
+    use std::num::NonZeroUsize;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    async fn find(store: &TtlLruStore<String>, queries: &AtomicUsize) -> Option<String> {
+        if let Lookup::Hit(value) = store.lookup("avatar/tree.png").await {
+            return value;
+        }
+        // 模拟查询数据库，记录不存在
+        queries.fetch_add(1, Ordering::Relaxed);
+        store
+            .set_none("avatar/tree.png", Duration::from_millis(50))
+            .await;
+        None
+    }
+
+    #[tokio::test]
+    async fn negative_cache() {
+        let store: TtlLruStore<String> = TtlLruStore::new(NonZeroUsize::new(10).unwrap());
+        let queries = AtomicUsize::new(0);
+        assert_eq!(Lookup::Miss, store.lookup("avatar/tree.png").await);
+        for _ in 0..3 {
+            assert_eq!(None, find(&store, &queries).await);
+        }
+        assert_eq!(1, queries.load(Ordering::Relaxed));
+        assert_eq!(Lookup::Hit(None), store.lookup("avatar/tree.png").await);
+
+        // 不存在的标记过期后重新查询
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(None, find(&store, &queries).await);
+        assert_eq!(2, queries.load(Ordering::Relaxed));
+
+        // 写入数据后直接覆盖不存在的标记
+        store
+            .set_with_ttl(
+                "avatar/tree.png",
+                "tree".to_string(),
+                Duration::from_secs(60),
+            )
+            .await;
+        assert_eq!(Some("tree".to_string()), find(&store, &queries).await);
+        assert_eq!(2, queries.load(Ordering::Relaxed));
+
+        store
+            .set_with_ttl("abc", "abc".to_string(), Duration::from_millis(10))
+            .await;
+        assert_eq!(
+            Lookup::Hit(Some("abc".to_string())),
+            store.lookup("abc").await
+        );
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(Lookup::Miss, store.lookup("abc").await);
+    }
 }
@@ -1,7 +1,9 @@
-use super::{Expired, RedisCache, Result, TtlLruStore};
+use super::invalidation::register_local_store;
+use super::{Lookup, RedisCache, Result, TtlLruStore};
 use chrono::Local;
 use serde::{de::DeserializeOwned, Serialize};
 use std::num::NonZeroUsize;
+use std::sync::Arc;
 use std::time::Duration;
 
 // 根据当前时间以及unit计算有效期，让有效期尽可能落在间隔点
@@ -16,30 +18,18 @@ fn get_ttl_by_unit(unit: Duration) -> Duration {
     Duration::from_secs(seconds)
 }
 
-fn now() -> i64 {
-    Local::now().timestamp()
-}
-
-#[derive(Clone)]
-struct ExpiredCache<T> {
-    data: T,
-    expired_at: i64,
-}
-impl<T> Expired for ExpiredCache<T> {
-    fn is_expired(&self) -> bool {
-        now() < self.expired_at
-    }
-}
-
 pub struct TwoLevelStore<T> {
-    lru: TtlLruStore<ExpiredCache<T>>,
+    lru: Arc<TtlLruStore<T>>,
     ttl: Duration,
     redis: RedisCache,
 }
-impl<T: Clone + ?Sized + Serialize + DeserializeOwned> TwoLevelStore<T> {
+impl<T: Clone + Send + Sync + Serialize + DeserializeOwned + 'static> TwoLevelStore<T> {
     pub fn new(size: NonZeroUsize, ttl: Duration, prefix: String) -> Self {
+        let lru = Arc::new(TtlLruStore::new(size));
+        // 其它实例删除缓存时，同时删除当前实例的lru缓存
+        register_local_store(&prefix, lru.clone());
         TwoLevelStore {
-            lru: TtlLruStore::new(size),
+            lru,
             ttl,
             redis: RedisCache::new_with_ttl_prefix(ttl, prefix),
         }
@@ -51,42 +41,99 @@ impl<T: Clone + ?Sized + Serialize + DeserializeOwned> TwoLevelStore<T> {
         // 先设置redis缓存
         self.redis.set_struct(key, &value, Some(ttl)).await?;
 
-        self.lru
-            .set(
-                key,
-                ExpiredCache {
-                    data: value,
-                    expired_at: now() + ttl.as_secs() as i64,
-                },
-            )
-            .await;
+        self.lru.set_with_ttl(key, value, ttl).await;
 
         Ok(())
     }
-    pub async fn get(&self, key: &str) -> Result<Option<T>> {
-        // 从先lru读取(get保证了肯定不过期)
-        if let Some(value) = self.lru.get(key).await {
-            return Ok(Some(value.data));
+    /// 缓存数据不存在的标记，redis中保存为null，
+    /// 之后调用set写入的数据直接覆盖
+    pub async fn set_none(&self, key: &str, ttl: Duration) -> Result<()> {
+        self.redis
+            .set_struct(key, &Option::<T>::None, Some(ttl))
+            .await?;
+        self.lru.set_none(key, ttl).await;
+        Ok(())
+    }
+    /// 查询缓存，Hit(None)表示已缓存数据不存在
+    pub async fn lookup(&self, key: &str) -> Result<Lookup<T>> {
+        // 从先lru读取(lookup保证了肯定不过期)
+        if let Lookup::Hit(value) = self.lru.lookup(key).await {
+            return Ok(Lookup::Hit(value));
         }
-        let result: Option<T> = self.redis.get_struct(key).await?;
-        // 如果有值，重新设置至lru
-        if let Some(ref value) = result {
-            let ttl = get_ttl_by_unit(self.ttl);
-            // 如果ttl > self.ttl
-            // 则表示数据可能要过期，不缓存
-            // 因此缓存ttl少于默认值的场景
-            if ttl <= self.ttl {
-                self.lru
-                    .set(
-                        key,
-                        ExpiredCache {
-                            data: value.clone(),
-                            expired_at: now() + ttl.as_secs() as i64,
-                        },
-                    )
-                    .await;
+        let result: Option<Option<T>> = self.redis.get_struct(key).await?;
+        let Some(value) = result else {
+            return Ok(Lookup::Miss);
+        };
+        match value {
+            Some(ref value) => {
+                let ttl = get_ttl_by_unit(self.ttl);
+                // 如果ttl > self.ttl
+                // 则表示数据可能要过期，不缓存
+                // 因此缓存ttl少于默认值的场景
+                if ttl <= self.ttl {
+                    self.lru.set_with_ttl(key, value.clone(), ttl).await;
+                }
+            }
+            // 不存在的标记同样缓存至lru，有效期与redis中剩余的一致
+            None => {
+                let ttl = self.redis.ttl(key).await?;
+                if ttl > 0 {
+                    self.lru
+                        .set_none(key, Duration::from_secs(ttl as u64))
+                        .await;
+                }
             }
         }
+        Ok(Lookup::Hit(value))
+    }
+    pub async fn get(&self, key: &str) -> Result<Option<T>> {
+        let result = match self.lookup(key).await? {
+            Lookup::Hit(value) => value,
+            Lookup::Miss => None,
+        };
         Ok(result)
     }
+    /// 删除缓存，并通知其它实例删除其lru缓存
+    pub async fn del(&self, key: &str) -> Result<()> {
+        self.lru.del(key).await;
+        self.redis.del_and_broadcast(key).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TwoLevelStore;
+    use crate::cache::{spawn_invalidation_subscriber, Lookup};
+    use pretty_assertions::assert_eq;
+    use std::num::NonZeroUsize;
+    use std::time::Duration;
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn invalidation_between_instances() {
+        spawn_invalidation_subscriber();
+        // 等待订阅完成
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        // 相同前缀的两个实例
+        let new_store = || {
+            TwoLevelStore::<String>::new(
+                NonZeroUsize::new(10).unwrap(),
+                Duration::from_secs(60),
+                "two-level-test:".to_string(),
+            )
+        };
+        let first = new_store();
+        let second = new_store();
+        first.set("tree", "tree".to_string()).await.unwrap();
+        second
+            .lru
+            .set_with_ttl("tree", "tree".to_string(), Duration::from_secs(60))
+            .await;
+        assert_eq!(Some("tree".to_string()), second.get("tree").await.unwrap());
+
+        first.del("tree").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(Lookup::Miss, second.lru.lookup("tree").await);
+        assert_eq!(None, second.get("tree").await.unwrap());
+    }
 }
@@ -1,7 +1,9 @@
+use crate::util::glob_match;
 use config::{Config, File, FileFormat, FileSourceString};
+use ipnet::IpNet;
 use once_cell::sync::OnceCell;
 use rust_embed::RustEmbed;
-use std::{collections::HashMap, env, time::Duration};
+use std::{collections::HashMap, env, net::IpAddr, time::Duration};
 use substring::Substring;
 use url::Url;
 use validator::Validate;
@@ -66,34 +68,6 @@ impl APPConfig {
         }
         default_value.unwrap_or(s)
     }
-    /// 从配置中获取对应的值(i32)
-    fn get_int(&self, key: &str, default_value: Option<i32>) -> i32 {
-        let value = self.get(key, None);
-        if !value.is_empty() {
-            return convert_string_to_i32(value);
-        }
-        default_value.unwrap_or_default()
-    }
-    /// 从配置中获取duration
-    fn get_duration(
-        &self,
-        key: &str,
-        default_value: Option<Duration>,
-    ) -> Result<Duration, humantime::DurationError> {
-        let value = self.get(key, None);
-        if !value.is_empty() {
-            return humantime::parse_duration(&value);
-        }
-        Ok(default_value.unwrap_or_default())
-    }
-    /// 从配置中获取对应的值(bool)
-    fn get_bool(&self, key: &str, default_value: Option<bool>) -> bool {
-        let value = self.get(key, None);
-        if !value.is_empty() {
-            return convert_string_to_bool(value);
-        }
-        default_value.unwrap_or_default()
-    }
     /// 优先从env中获取配置的值，如果env中未配置则调用get_value获取
     fn get_from_env_first(&self, key: &str, default_value: Option<String>) -> String {
         let k = self.get_key(key);
@@ -179,6 +153,46 @@ pub struct BasicConfig {
     pub timeout: Duration,
     #[validate(length(min = 6))]
     pub secret: String,
+    // 客户端出错上报的采样比例(0-100)
+    #[validate(range(min = 0, max = 100))]
+    pub client_error_sampling: i32,
+    // 客户端出错记录保留的最大条数，超出则删除最早的记录
+    #[validate(range(min = 1))]
+    pub client_error_max_rows: i32,
+    // 描述类接口(如实体描述)的缓存有效期
+    pub schema_cache_ttl: Duration,
+    // 就绪文件路径，为空则不写入
+    pub readiness_file: String,
+    // 授权文件路径，为空则为社区版
+    pub license_file: String,
+    // 文件内容与类型不一致时的处理，reject：拒绝，trust：使用识别的类型
+    #[validate(custom(function = "validate_file_type_mismatch"))]
+    pub file_type_mismatch: String,
+    // 文件的最大字节数，未配置上传策略的分组使用此限制
+    #[validate(range(min = 1))]
+    pub file_max_size: u64,
+    // 启动时是否执行自检，失败则不接收请求
+    pub selftest: bool,
+    // 单次导出的最大记录数
+    #[validate(range(min = 1))]
+    pub export_max_rows: u64,
+    // 新建trace的采样比例(0-100)，采样标记随traceparent传递至其它服务
+    #[validate(range(max = 100))]
+    pub trace_sampling: u8,
+    // 启动时同时执行的初始化任务数
+    #[validate(range(min = 1, max = 32))]
+    pub init_parallelism: usize,
+    // 启动初始化的最长时间，超时则启动失败
+    pub init_timeout: Duration,
+    // 列表cursor的有效期
+    pub cursor_ttl: Duration,
+}
+
+fn validate_file_type_mismatch(value: &str) -> Result<(), validator::ValidationError> {
+    if !["reject", "trust"].contains(&value) {
+        return Err(validator::ValidationError::new("file_type_mismatch"));
+    }
+    Ok(())
 }
 
 pub fn must_new_basic_config() -> BasicConfig {
@@ -189,6 +203,27 @@ pub fn must_new_basic_config() -> BasicConfig {
         processing_limit: config.get_int_from_env_first("processing_limit", Some(5000)),
         timeout,
         secret: config.get_from_env_first("secret", None),
+        client_error_sampling: config.get_int_from_env_first("client_error_sampling", Some(100)),
+        client_error_max_rows: config
+            .get_int_from_env_first("client_error_max_rows", Some(100_000)),
+        schema_cache_ttl: config
+            .get_duration_from_env_first("schema_cache_ttl", Some(Duration::from_secs(300))),
+        readiness_file: config.get_from_env_first("readiness_file", None),
+        license_file: config.get_from_env_first("license_file", None),
+        file_type_mismatch: config
+            .get_from_env_first("file_type_mismatch", Some("reject".to_string())),
+        file_max_size: config.get_int_from_env_first("file_max_size", Some(10 * 1024 * 1024))
+            as u64,
+        selftest: config.get_bool_from_env_first("selftest", Some(false)),
+        export_max_rows: config.get_int_from_env_first("export_max_rows", Some(100_000)) as u64,
+        trace_sampling: config
+            .get_int_from_env_first("trace_sampling", Some(0))
+            .clamp(0, 100) as u8,
+        init_parallelism: config.get_int_from_env_first("init_parallelism", Some(4)) as usize,
+        init_timeout: config
+            .get_duration_from_env_first("init_timeout", Some(Duration::from_secs(30))),
+        cursor_ttl: config
+            .get_duration_from_env_first("cursor_ttl", Some(Duration::from_secs(3600))),
     };
     basic_config.validate().unwrap();
     basic_config
@@ -275,7 +310,25 @@ pub struct SessionConfig {
     pub secret: String,
     #[validate(length(min = 1, max = 64))]
     pub cookie: String,
+    // 允许匿名访问的路由(支持*与**)
+    pub anonymous_patterns: Vec<String>,
+    // 需要登录的路由(支持*与**)
+    pub login_patterns: Vec<String>,
+    // 需要二次验证的敏感操作路由(支持*与**)，仅校验非GET/HEAD请求
+    pub elevation_patterns: Vec<String>,
+    // 二次验证的有效期(秒)
+    #[validate(range(min = 30, max = 3600))]
+    pub elevation_ttl: i64,
 }
+
+fn split_patterns(value: String) -> Vec<String> {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
 pub fn must_new_session_config() -> SessionConfig {
     let config = must_new_config().set_prefix("session");
     let ttl = config.get_duration_from_env_first("ttl", Some(Duration::from_secs(2 * 24 * 3600)));
@@ -284,11 +337,76 @@ pub fn must_new_session_config() -> SessionConfig {
         ttl: ttl.as_secs() as i64,
         secret: config.get_from_env_first("secret", None),
         cookie,
+        anonymous_patterns: split_patterns(config.get_from_env_first("anonymous_patterns", None)),
+        login_patterns: split_patterns(config.get_from_env_first("login_patterns", None)),
+        elevation_patterns: split_patterns(config.get_from_env_first("elevation_patterns", None)),
+        elevation_ttl: config
+            .get_duration_from_env_first("elevation_ttl", Some(Duration::from_secs(5 * 60)))
+            .as_secs() as i64,
     };
     session_config.validate().unwrap();
+    // 匿名与登录的规则有重叠则无法确定，直接panic
+    for anonymous in session_config.anonymous_patterns.iter() {
+        for login in session_config.login_patterns.iter() {
+            if glob_match(anonymous, login) || glob_match(login, anonymous) {
+                panic!("session pattern conflict, anonymous: {anonymous}, login: {login}");
+            }
+        }
+    }
     session_config
 }
 
+// 请求归档配置
+#[derive(Debug, Clone, Default, Validate)]
+pub struct ArchiveConfig {
+    // 需要归档的路由(支持*与**)，为空则不归档
+    pub patterns: Vec<String>,
+    // 请求数据最多保存的长度
+    #[validate(range(min = 0, max = 1048576))]
+    pub body_limit: usize,
+    // 保存的时长
+    pub retention: Duration,
+    // 同时写入的最大数量，超出时则不归档，避免影响请求
+    #[validate(range(min = 1, max = 1000))]
+    pub concurrency: usize,
+}
+
+pub fn must_new_archive_config() -> ArchiveConfig {
+    let config = must_new_config().set_prefix("archive");
+    let archive_config = ArchiveConfig {
+        patterns: split_patterns(config.get_from_env_first("patterns", None)),
+        body_limit: config.get_int_from_env_first("body_limit", Some(16 * 1024)) as usize,
+        retention: config
+            .get_duration_from_env_first("retention", Some(Duration::from_secs(7 * 24 * 3600))),
+        concurrency: config.get_int_from_env_first("concurrency", Some(10)) as usize,
+    };
+    archive_config.validate().unwrap();
+    archive_config
+}
+
+// 出错响应配置
+#[derive(Debug, Clone, Default, Validate)]
+pub struct ErrorConfig {
+    // 内部出错的类别，生产环境中不返回其出错信息
+    pub internal_categories: Vec<String>,
+    // 面向用户的出错类别，即使标记为异常也返回原出错信息
+    pub public_categories: Vec<String>,
+}
+
+pub fn must_new_error_config() -> ErrorConfig {
+    let config = must_new_config().set_prefix("error");
+    let error_config = ErrorConfig {
+        internal_categories: split_patterns(
+            config.get_from_env_first("internal_categories", Some("db".to_string())),
+        ),
+        public_categories: split_patterns(
+            config.get_from_env_first("public_categories", Some("validate".to_string())),
+        ),
+    };
+    error_config.validate().unwrap();
+    error_config
+}
+
 // 数据库配置
 #[derive(Debug, Clone, Default, Validate)]
 pub struct DatabaseConfig {
@@ -302,6 +420,13 @@ pub struct DatabaseConfig {
     pub connect_timeout: Duration,
     pub acquire_timeout: Duration,
     pub idle_timeout: Duration,
+    // 查询语句的最长执行时间
+    pub query_timeout: Duration,
+    // count语句的最长执行时间
+    pub count_timeout: Duration,
+    // 获取连接时设置当前请求的账号与trace id至会话变量，
+    // 每次获取连接会多一次查询，默认不启用
+    pub attribution: bool,
 }
 pub fn must_new_database_config() -> DatabaseConfig {
     let config = must_new_config().set_prefix("database");
@@ -313,6 +438,9 @@ pub fn must_new_database_config() -> DatabaseConfig {
     let mut connect_timeout = Duration::from_secs(3);
     let mut acquire_timeout = Duration::from_secs(5);
     let mut idle_timeout = Duration::from_secs(60);
+    let mut query_timeout = Duration::from_secs(10);
+    let mut count_timeout = Duration::from_secs(5);
+    let mut attribution = false;
 
     if let Some(query) = info.query() {
         url = url.replace(query, "");
@@ -345,6 +473,21 @@ pub fn must_new_database_config() -> DatabaseConfig {
                         idle_timeout = value;
                     }
                 }
+                "query_timeout" => {
+                    if let Ok(value) = humantime::parse_duration(&value) {
+                        query_timeout = value;
+                    }
+                }
+                "count_timeout" => {
+                    if let Ok(value) = humantime::parse_duration(&value) {
+                        count_timeout = value;
+                    }
+                }
+                "attribution" => {
+                    if let Ok(value) = value.parse::<bool>() {
+                        attribution = value;
+                    }
+                }
                 _ => {}
             }
         }
@@ -357,7 +500,171 @@ pub fn must_new_database_config() -> DatabaseConfig {
         connect_timeout,
         acquire_timeout,
         idle_timeout,
+        query_timeout,
+        count_timeout,
+        attribution,
     };
     database_config.validate().unwrap();
     database_config
 }
+
+// 服务间调用的签名配置
+#[derive(Debug, Clone, Default, Validate)]
+pub struct SignatureConfig {
+    // 签名的key列表(key id, secret)，第一个用于签名，其它的仅用于校验
+    pub keys: Vec<(String, String)>,
+    // 允许的时间偏差
+    pub skew: Duration,
+    // 需要校验签名的路由前缀
+    pub prefixes: Vec<String>,
+    // 调用的内部服务地址(如tibba-web)，为空则不调用
+    pub service_url: String,
+    // 校验签名时读取body的最大长度
+    pub body_limit: usize,
+}
+pub fn must_new_signature_config() -> SignatureConfig {
+    let config = must_new_config().set_prefix("signature");
+    // 格式为 id1:secret1,id2:secret2
+    let keys = config
+        .get_from_env_first("keys", None)
+        .split(',')
+        .filter_map(|item| {
+            let (id, secret) = item.trim().split_once(':')?;
+            if id.is_empty() || secret.is_empty() {
+                return None;
+            }
+            Some((id.to_string(), secret.to_string()))
+        })
+        .collect();
+    let prefixes = config
+        .get_from_env_first("prefixes", None)
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect();
+    let signature_config = SignatureConfig {
+        keys,
+        skew: config.get_duration_from_env_first("skew", Some(Duration::from_secs(5 * 60))),
+        prefixes,
+        service_url: config.get_from_env_first("service_url", None),
+        body_limit: config.get_int_from_env_first("body_limit", Some(1024 * 1024)) as usize,
+    };
+    signature_config.validate().unwrap();
+    signature_config
+}
+
+// 安全相关响应头配置
+#[derive(Debug, Clone, Default, Validate)]
+pub struct SecurityConfig {
+    // csp的各类资源来源，self、none等关键字无需添加引号
+    pub csp_default_src: Vec<String>,
+    pub csp_script_src: Vec<String>,
+    pub csp_style_src: Vec<String>,
+    pub csp_img_src: Vec<String>,
+    pub csp_connect_src: Vec<String>,
+    // 允许嵌入的页面来源
+    pub frame_ancestors: Vec<String>,
+    // 仅上报不拦截
+    pub csp_report_only: bool,
+    // hsts有效期，为0则不设置
+    pub hsts_max_age: Duration,
+    #[validate(custom(function = "validate_referrer_policy"))]
+    pub referrer_policy: String,
+    // 禁用的浏览器功能，如camera、microphone
+    pub permissions_disabled: Vec<String>,
+    // 可信的代理地址(CIDR)，仅来自这些地址的x-forwarded-proto有效
+    pub trusted_proxies: Vec<IpNet>,
+}
+
+fn validate_referrer_policy(value: &str) -> Result<(), validator::ValidationError> {
+    let policies = [
+        "no-referrer",
+        "no-referrer-when-downgrade",
+        "origin",
+        "origin-when-cross-origin",
+        "same-origin",
+        "strict-origin",
+        "strict-origin-when-cross-origin",
+        "unsafe-url",
+    ];
+    if !policies.contains(&value) {
+        return Err(validator::ValidationError::new("referrer_policy"));
+    }
+    Ok(())
+}
+
+// csp来源的关键字，生成时添加引号
+pub static CSP_SOURCE_KEYWORDS: &[&str] = &[
+    "self",
+    "none",
+    "unsafe-inline",
+    "unsafe-eval",
+    "strict-dynamic",
+];
+
+// 校验csp的来源是否合法，非关键字的只允许scheme或host
+fn validate_csp_source(value: &str) -> bool {
+    if CSP_SOURCE_KEYWORDS.contains(&value) {
+        return true;
+    }
+    !value.is_empty()
+        && !value
+            .chars()
+            .any(|c| c.is_whitespace() || [';', ',', '\'', '"'].contains(&c))
+}
+
+// 解析CIDR，单个ip则视为仅包含该ip的网段
+fn parse_ip_net(value: &str) -> Option<IpNet> {
+    if let Ok(net) = value.parse::<IpNet>() {
+        return Some(net);
+    }
+    value.parse::<IpAddr>().ok().map(IpNet::from)
+}
+
+pub fn must_new_security_config() -> SecurityConfig {
+    let config = must_new_config().set_prefix("security");
+    let get_values = |key: &str, default_value: &str| {
+        split_patterns(config.get_from_env_first(key, Some(default_value.to_string())))
+    };
+    let security_config = SecurityConfig {
+        csp_default_src: get_values("csp_default_src", "self"),
+        csp_script_src: get_values("csp_script_src", "self"),
+        csp_style_src: get_values("csp_style_src", "self,unsafe-inline"),
+        csp_img_src: get_values("csp_img_src", "self,data:"),
+        csp_connect_src: get_values("csp_connect_src", "self"),
+        frame_ancestors: get_values("frame_ancestors", "none"),
+        csp_report_only: config.get_bool_from_env_first("csp_report_only", Some(false)),
+        hsts_max_age: config.get_duration_from_env_first(
+            "hsts_max_age",
+            Some(Duration::from_secs(180 * 24 * 3600)),
+        ),
+        referrer_policy: config.get_from_env_first(
+            "referrer_policy",
+            Some("strict-origin-when-cross-origin".to_string()),
+        ),
+        permissions_disabled: get_values("permissions_disabled", "camera,microphone,geolocation"),
+        trusted_proxies: get_values("trusted_proxies", "")
+            .iter()
+            .map(|value| {
+                parse_ip_net(value).unwrap_or_else(|| panic!("trusted proxy is invalid: {value}"))
+            })
+            .collect(),
+    };
+    security_config.validate().unwrap();
+    for value in [
+        &security_config.csp_default_src,
+        &security_config.csp_script_src,
+        &security_config.csp_style_src,
+        &security_config.csp_img_src,
+        &security_config.csp_connect_src,
+        &security_config.frame_ancestors,
+    ]
+    .iter()
+    .flat_map(|items| items.iter())
+    {
+        if !validate_csp_source(value) {
+            panic!("csp source is invalid: {value}");
+        }
+    }
+    security_config
+}
@@ -1,6 +1,8 @@
 mod app_config;
 
 pub use app_config::{
-    get_env, must_new_basic_config, must_new_database_config, must_new_redis_config,
-    must_new_session_config, SessionConfig,
+    get_env, must_new_archive_config, must_new_basic_config, must_new_database_config,
+    must_new_error_config, must_new_redis_config, must_new_security_config,
+    must_new_session_config, must_new_signature_config, ArchiveConfig, DatabaseConfig, ErrorConfig,
+    SecurityConfig, SessionConfig, SignatureConfig, CSP_SOURCE_KEYWORDS,
 };
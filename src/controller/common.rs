@@ -1,13 +1,30 @@
-use super::{CacheJsonResult, JsonResult, Query};
-use crate::config::get_env;
+use super::{CacheJsonResult, JsonParams, JsonResult, Query};
+use crate::config::{get_env, must_new_basic_config};
+use crate::db::{
+    add_client_errors, get_hook_failures, get_user_cache_stats, ClientErrorData, UserCacheStats,
+};
+use crate::entitlement::{entitlements, Entitlements};
 use crate::error::{HttpError, HttpResult};
+use crate::logger::{get_log_filter, LogFilter};
+use crate::middleware::{
+    get_session_migrations, limiter, load_session, Claim, CspNonce, LimitParams,
+};
+use crate::startup::{check_components, ComponentHealth, InitTask};
 use crate::state::get_app_state;
-use crate::{asset, cache, util};
-use axum::{routing::get, Router};
-use captcha::filters::{Noise, Wave};
-use captcha::Captcha;
+use crate::{asset, cache, db, selftest, util};
+use axum::body::Bytes;
+use axum::extract::{DefaultBodyLimit, Path};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::middleware::{from_fn, from_fn_with_state};
+use axum::routing::{get, post};
+use axum::{Extension, Json, Router};
+use axum_extra::extract::cookie::CookieJar;
+use once_cell::sync::Lazy;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::time::Duration;
+use validator::Validate;
 
 const VERSION: &str = env!("CARGO_PKG_VERSION");
 
@@ -16,20 +33,70 @@ struct ApplicationInfo {
     builded_at: String,
     commit: String,
     uptime: String,
+    uptime_duration: util::HumanDuration,
     env: String,
     os: String,
     arch: String,
     version: String,
+    license: Entitlements,
+    // 当前的日志过滤，调整后可及时确认是否已恢复
+    log_filter: LogFilter,
+    // 数据变更处理失败的次数
+    hook_failures: u64,
+    // 各版本session的升级次数
+    session_migrations: HashMap<String, u64>,
+    // 用户信息缓存的命中统计
+    user_cache: UserCacheStats,
 }
 
 pub fn new_router() -> Router {
     let r = Router::new()
         .route("/application", get(get_application_info))
-        .route("/captcha", get(captcha));
+        .route("/captcha", get(captcha))
+        .route("/selftest", get(get_selftest))
+        .route("/health", get(get_health))
+        .route(
+            "/client-errors",
+            post(report_client_errors)
+                .route_layer(from_fn(load_session))
+                .route_layer(from_fn_with_state(
+                    LimitParams::new(60, 60, "client_error"),
+                    limiter,
+                ))
+                // 限制上报数据的大小
+                .route_layer(DefaultBodyLimit::max(64 * 1024)),
+        )
+        .route(
+            "/csp-report",
+            post(report_csp)
+                .route_layer(from_fn(load_session))
+                .route_layer(from_fn_with_state(
+                    LimitParams::new(60, 60, "csp_report"),
+                    limiter,
+                ))
+                .route_layer(DefaultBodyLimit::max(16 * 1024)),
+        );
 
-    Router::new().route("/ping", get(ping)).nest("/commons", r)
+    Router::new()
+        .route("/ping", get(ping))
+        .route("/assets/*file", get(get_asset))
+        .nest("/commons", r)
 }
 
+// 嵌入的静态资源，html中的内联脚本添加当前请求的csp nonce
+async fn get_asset(
+    Extension(nonce): Extension<CspNonce>,
+    Path(file): Path<String>,
+) -> asset::StaticFile {
+    asset::get_static_file(&file).with_nonce(&nonce.0)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/ping",
+    tag = "common",
+    responses((status = 200, description = "服务可用", body = String))
+)]
 async fn ping() -> HttpResult<&'static str> {
     let state = get_app_state();
     if !state.is_running() {
@@ -38,9 +105,53 @@ async fn ping() -> HttpResult<&'static str> {
     Ok("pong")
 }
 
+// 健康检测的最长等待时间
+const HEALTH_DEADLINE: Duration = Duration::from_secs(3);
+// 不可用时服务无法正常处理请求的组件
+const CRITICAL_COMPONENTS: [&str; 2] = ["mysql", "redis"];
+
+// 各依赖组件的状态，关键组件不可用时返回503
+#[utoipa::path(
+    get,
+    path = "/api/commons/health",
+    tag = "common",
+    responses(
+        (status = 200, description = "各组件的健康状态", body = HashMap<String, ComponentHealth>),
+        (status = 503, description = "关键组件不可用", body = HashMap<String, ComponentHealth>),
+    )
+)]
+async fn get_health() -> (StatusCode, Json<HashMap<&'static str, ComponentHealth>>) {
+    let tasks = vec![
+        InitTask::new("mysql", async {
+            db::get_database()
+                .await
+                .ping()
+                .await
+                .map_err(|err| err.to_string())
+        }),
+        InitTask::new("redis", async {
+            cache::redis_ping()
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        }),
+    ];
+    let result = check_components(tasks, HEALTH_DEADLINE).await;
+    let healthy = CRITICAL_COMPONENTS
+        .iter()
+        .all(|name| result.get(name).map(|item| item.ok).unwrap_or_default());
+    let status = if healthy {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, Json(result))
+}
+
 async fn get_application_info() -> CacheJsonResult<ApplicationInfo> {
     let app_state = get_app_state();
-    let uptime = util::get_duration_string(&app_state.get_started_at());
+    let started_at = app_state.get_started_at();
+    let uptime = util::get_duration_string(&started_at);
     let os = os_info::get().os_type().to_string();
     let mut arch = "x86";
     // 运行环境较为单一，此字段也只用于展示
@@ -53,10 +164,16 @@ async fn get_application_info() -> CacheJsonResult<ApplicationInfo> {
         builded_at: asset::get_build_date(),
         commit: asset::get_commit(),
         uptime,
+        uptime_duration: util::HumanDuration(util::get_duration(&started_at)),
         env: get_env(),
         arch: arch.to_string(),
         os,
         version: VERSION.to_string(),
+        license: entitlements().clone(),
+        log_filter: get_log_filter(),
+        hook_failures: get_hook_failures(),
+        session_migrations: get_session_migrations(),
+        user_cache: get_user_cache_stats(),
     };
     Ok((Duration::from_secs(60), info).into())
 }
@@ -73,20 +190,17 @@ struct CaptchaInfo {
     data: String,
 }
 
-async fn captcha(Query(params): Query<CaptchaParams>) -> JsonResult<CaptchaInfo> {
+// 验证码仅能使用一次，禁止任何缓存
+// 最近一次自检的结果，未执行时items为空
+async fn get_selftest() -> JsonResult<selftest::SelfTestReport> {
+    Ok(selftest::get_last_report().into())
+}
+
+async fn captcha(
+    Query(params): Query<CaptchaParams>,
+) -> HttpResult<([(header::HeaderName, &'static str); 1], Json<CaptchaInfo>)> {
     let level = params.level.unwrap_or_default();
-    // 未实现send，因此需要将其生命周期减短
-    let (text, data) = {
-        let mut c = Captcha::new();
-        // 设置允许0会导致0的时候不展示，后续确认
-        c.set_chars(&"123456789".chars().collect::<Vec<_>>())
-            .add_chars(4)
-            .apply_filter(Noise::new(0.4))
-            .apply_filter(Wave::new(2.0, 8.0).horizontal())
-            .apply_filter(Wave::new(2.0, 8.0).vertical())
-            .view(120, 38);
-        (c.chars_as_string(), c.as_base64().unwrap_or_default())
-    };
+    let (text, data) = util::new_captcha();
     let mut info = CaptchaInfo {
         data,
         ..Default::default()
@@ -103,5 +217,152 @@ async fn captcha(Query(params): Query<CaptchaParams>) -> JsonResult<CaptchaInfo>
         info.hash = hash;
     }
 
-    Ok(info.into())
+    Ok(([(header::CACHE_CONTROL, "no-store")], info.into()))
+}
+
+// 堆栈信息最多保存的字符数
+const CLIENT_ERROR_STACK_LIMIT: usize = 4096;
+static CLIENT_ERROR_SAMPLING: Lazy<i32> =
+    Lazy::new(|| must_new_basic_config().client_error_sampling);
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+struct ClientErrorItem {
+    #[validate(length(min = 1, max = 1024))]
+    message: String,
+    stack: Option<String>,
+    #[validate(length(max = 2048))]
+    url: String,
+    #[validate(length(max = 512))]
+    user_agent: String,
+    #[validate(length(max = 64))]
+    release: String,
+    #[validate(length(max = 16))]
+    severity: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ClientErrorParams {
+    #[validate(length(min = 1, max = 20))]
+    #[validate(nested)]
+    items: Vec<ClientErrorItem>,
+}
+
+// 是否包含非文本的字符
+fn contains_control_char(value: &str) -> bool {
+    value
+        .chars()
+        .any(|c| c.is_control() && !['\n', '\r', '\t'].contains(&c))
+}
+
+// 根据出错信息以及堆栈的首行生成指纹，
+// 出错信息中的数字统一替换，避免id等导致无法归类
+fn get_client_error_fingerprint(message: &str, stack: &str) -> String {
+    let message: String = message
+        .trim()
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_digit() { '0' } else { c })
+        .collect();
+    let frame = stack
+        .lines()
+        .map(|line| line.trim())
+        .find(|line| line.starts_with("at ") || line.contains('@'))
+        .unwrap_or_default();
+    util::sha256(format!("{message}\n{frame}").as_bytes())[0..16].to_string()
+}
+
+// 按比例采样，避免版本有问题时大量写入
+fn is_client_error_sampled() -> bool {
+    rand::thread_rng().gen_range(0..100) < *CLIENT_ERROR_SAMPLING
+}
+
+async fn report_client_errors(
+    jar: CookieJar,
+    claim: Claim,
+    JsonParams(params): JsonParams<ClientErrorParams>,
+) -> HttpResult<StatusCode> {
+    let mut items = vec![];
+    for item in params.items {
+        let stack = item.stack.unwrap_or_default();
+        if contains_control_char(&item.message) || contains_control_char(&stack) {
+            return Err(HttpError::new_with_category(
+                "Client error is invalid",
+                "client_error",
+            ));
+        }
+        let stack: String = stack.chars().take(CLIENT_ERROR_STACK_LIMIT).collect();
+        items.push(ClientErrorData {
+            fingerprint: get_client_error_fingerprint(&item.message, &stack),
+            severity: item.severity.unwrap_or("error".to_string()),
+            message: item.message,
+            stack: if stack.is_empty() { None } else { Some(stack) },
+            url: item.url,
+            user_agent: item.user_agent,
+            release: item.release,
+        });
+    }
+    if is_client_error_sampled() {
+        add_client_errors(
+            &claim.get_account(),
+            &util::get_device_id_from_cookie(&jar),
+            items,
+        )
+        .await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct CspReportItem {
+    document_uri: String,
+    violated_directive: String,
+    blocked_uri: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CspReport {
+    #[serde(rename = "csp-report")]
+    report: CspReportItem,
+}
+
+// 浏览器上报的csp违规信息，content-type为application/csp-report，
+// 因此直接读取body解析，记录至客户端出错信息中
+async fn report_csp(
+    jar: CookieJar,
+    claim: Claim,
+    headers: HeaderMap,
+    body: Bytes,
+) -> HttpResult<StatusCode> {
+    let CspReport { report } = serde_json::from_slice(&body)?;
+    let message: String = format!("{} {}", report.violated_directive, report.blocked_uri)
+        .chars()
+        .take(1024)
+        .collect();
+    if contains_control_char(&message) {
+        return Err(HttpError::new_with_category(
+            "Csp report is invalid",
+            "client_error",
+        ));
+    }
+    if is_client_error_sampled() {
+        let item = ClientErrorData {
+            fingerprint: get_client_error_fingerprint(&message, ""),
+            severity: "csp".to_string(),
+            message,
+            url: report.document_uri.chars().take(2048).collect(),
+            user_agent: util::get_header_value(&headers, header::USER_AGENT.as_str())
+                .chars()
+                .take(512)
+                .collect(),
+            ..Default::default()
+        };
+        add_client_errors(
+            &claim.get_account(),
+            &util::get_device_id_from_cookie(&jar),
+            vec![item],
+        )
+        .await?;
+    }
+    Ok(StatusCode::NO_CONTENT)
 }
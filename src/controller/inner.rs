@@ -1,35 +1,212 @@
-use super::{CacheJsonResult, JsonResult, Query};
+use super::{JsonParams, JsonResult, Query};
+use crate::cache;
+use crate::config::must_new_basic_config;
 use crate::db;
+use crate::draft;
+use crate::entitlement;
 use crate::error::{HttpError, HttpResult};
-use crate::middleware::{should_logged_in, validate_roles, Claim};
+use crate::feature::FeatureFlags;
+use crate::logger;
+use crate::middleware::{
+    get_trace_sampling, is_readonly_roles, limiter, list_deprecated_usages, require_entitlement,
+    set_trace_sampling, should_logged_in, validate_roles, Claim, DeprecatedUsage, LimitParams,
+    REDACTED_VALUE, REPLAY_ID_HEADER,
+};
+use crate::request;
+use crate::selftest;
+use crate::sensitive;
+use crate::task;
+use crate::util::{self, ChannelBody, NDJSON_CONTENT_TYPE};
+use crate::{task_local::*, tl_info};
+use axum::body::{Body, Bytes};
 use axum::extract::Path;
-use axum::http::StatusCode;
+use axum::http::{header, HeaderMap, HeaderName, StatusCode};
 use axum::middleware::{from_fn, from_fn_with_state};
+use axum::response::{IntoResponse, Response};
 use axum::routing::{get, patch, post};
 use axum::{Json, Router};
-use serde::Serialize;
-use serde_json::Value;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::future::Future;
 use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{interval_at, Instant};
+use tracing::{error, warn};
+use utoipa::{IntoParams, ToSchema};
+use validator::Validate;
+
+// 内部管理路由允许的角色
+static INNER_ROLES: Lazy<Vec<String>> = Lazy::new(|| {
+    vec![
+        db::ROLE_ADMIN.to_string(),
+        db::ROLE_SU.to_string(),
+        db::ROLE_READONLY.to_string(),
+    ]
+});
 
 pub fn new_router() -> Router {
     let r = Router::new()
         .route("/entity-descriptions/:entity", get(get_description))
         .route("/entities/:entity/:id", get(find_by_id))
         .route("/entities/:entity/:id", patch(update_by_id))
+        .route("/entities/:entity/:id/preview", post(preview_by_id))
+        .route("/entities/:entity/batch", post(batch))
+        .route("/entities/:entity/:id/delete-impact", get(delete_impact))
+        .route(
+            "/entities/:entity/:id/draft",
+            get(get_draft).put(save_draft).delete(delete_draft),
+        )
         .route("/entities/:entity", post(add))
         .route("/entities/:entity", get(list))
-        .layer(from_fn_with_state(
-            vec![db::ROLE_ADMIN.to_string(), db::ROLE_SU.to_string()],
-            validate_roles,
-        ))
+        .route(
+            "/entities/:entity/export",
+            get(export).layer(from_fn_with_state(
+                entitlement::FEATURE_ENTITY_EXPORT,
+                require_entitlement,
+            )),
+        )
+        .route(
+            "/tasks/:id/retry",
+            post(retry_task).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/tasks/:id/discard",
+            post(discard_task).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/users/merge",
+            post(merge_users).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/tenant",
+            post(switch_tenant).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route("/files/:id/content", get(get_file_content))
+        .route("/data-issues", get(list_data_issues))
+        .route("/client-errors/groups", get(list_client_error_groups))
+        .route("/deprecations", get(list_deprecations))
+        .route(
+            "/data-issues/scan",
+            post(scan_data_issues).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/request-archives",
+            get(list_request_archives).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/request-archives/:id/replay",
+            post(replay_request_archive).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/selftest",
+            post(rerun_selftest).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/sensitive-actions/test",
+            post(test_sensitive_action).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .route(
+            "/logging",
+            get(get_logging)
+                .put(update_logging)
+                .layer(from_fn_with_state(
+                    vec![db::ROLE_SU.to_string()],
+                    validate_roles,
+                )),
+        )
+        .route(
+            "/tracing",
+            get(get_tracing)
+                .put(update_tracing)
+                .layer(from_fn_with_state(
+                    vec![db::ROLE_SU.to_string()],
+                    validate_roles,
+                )),
+        )
+        .route(
+            "/cache/report",
+            get(cache_report)
+                .layer(from_fn_with_state(
+                    LimitParams::new(10, 60, "cache_report"),
+                    limiter,
+                ))
+                .layer(from_fn_with_state(
+                    vec![db::ROLE_SU.to_string()],
+                    validate_roles,
+                )),
+        )
+        .route(
+            "/reassign",
+            post(reassign).layer(from_fn_with_state(
+                vec![db::ROLE_SU.to_string()],
+                validate_roles,
+            )),
+        )
+        .layer(from_fn_with_state(INNER_ROLES.clone(), validate_roles))
         .layer(from_fn(should_logged_in));
 
     // TODO 增加鉴权处理
     Router::new().nest("/inners", r)
 }
 
-async fn find_by_id(claims: Claim, Path((entity, id)): Path<(String, i64)>) -> JsonResult<Value> {
-    let result = db::find_by_id(&entity, &claims.get_account(), id).await?;
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub(super) struct FindParams {
+    // 仅返回的字段，逗号分隔
+    fields: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/inners/entities/{entity}/{id}",
+    tag = "model",
+    params(
+        ("entity" = String, Path, description = "数据表"),
+        ("id" = i64, Path, description = "记录id"),
+        FindParams,
+    ),
+    responses(
+        (status = 200, description = "记录数据", body = Object),
+        (status = 400, description = "记录不存在", body = HttpError),
+        (status = 403, description = "权限不足", body = HttpError),
+    ),
+    security(("session_cookie" = []))
+)]
+async fn find_by_id(
+    claims: Claim,
+    Path((entity, id)): Path<(String, i64)>,
+    Query(params): Query<FindParams>,
+) -> JsonResult<Value> {
+    let fields = db::parse_fields(&params.fields);
+    let result = db::find_by_id(&entity, &claims.get_account(), id, &fields).await?;
     if result.is_none() {
         return Err(HttpError::new("Not found"));
     }
@@ -41,40 +218,1149 @@ struct AddRecordResp {
     id: i64,
 }
 
+#[derive(Debug, Deserialize)]
+struct AddParams {
+    // 文件默认复用已有相同内容的记录，设置为false则强制新增
+    dedupe: Option<bool>,
+    // 新建记录的草稿id，添加成功后删除该草稿
+    draft: Option<String>,
+}
+
+// 启用此功能开关的用户上传文件时默认不复用已有记录，用于逐步关闭去重
+const FEATURE_UPLOAD_WITHOUT_DEDUPE: &str = "upload_without_dedupe";
+
+#[derive(Debug, Serialize)]
+struct AddEntityResp {
+    id: i64,
+    // 复用已有记录时返回已有记录的名称
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deduplicated: Option<String>,
+}
+
 async fn add(
     claims: Claim,
+    flags: FeatureFlags,
     Path(entity): Path<String>,
+    Query(params): Query<AddParams>,
     Json(value): Json<Value>,
-) -> JsonResult<AddRecordResp> {
-    let id = db::add(&entity, &claims.get_account(), &value).await?;
-    Ok(AddRecordResp { id }.into())
+) -> JsonResult<AddEntityResp> {
+    let account = claims.get_account();
+    let dedupe = params
+        .dedupe
+        .unwrap_or(!flags.is_enabled(FEATURE_UPLOAD_WITHOUT_DEDUPE));
+    if dedupe {
+        if let Some((id, name)) = db::find_duplicate(&entity, &account, &value).await? {
+            return Ok(AddEntityResp {
+                id,
+                deduplicated: Some(name),
+            }
+            .into());
+        }
+    }
+    let id = db::add(&entity, &account, &value).await?;
+    if let Some(key) = &params.draft {
+        clear_draft(&account, &entity, key).await;
+    }
+    Ok(AddEntityResp {
+        id,
+        deduplicated: None,
+    }
+    .into())
 }
 
-#[derive(Debug, Serialize)]
-struct ListRecordResp {
+#[derive(Debug, Serialize, ToSchema)]
+pub(super) struct ListRecordResp {
     page_count: i64,
+    #[schema(value_type = Vec<Object>)]
     items: Vec<serde_json::Value>,
+    // 上一页及下一页的cursor，仅在cursor模式且有更多记录时返回
+    #[serde(flatten)]
+    cursors: db::PageCursors,
+    // 查询语句的最长执行时间(ms)
+    max_execution_time: u64,
 }
+#[utoipa::path(
+    get,
+    path = "/api/inners/entities/{entity}",
+    tag = "model",
+    params(("entity" = String, Path, description = "数据表"), db::ListCountParams),
+    responses(
+        (status = 200, description = "记录列表", body = ListRecordResp),
+        (status = 400, description = "参数不合法", body = HttpError),
+        (status = 403, description = "权限不足", body = HttpError),
+        (status = 422, description = "查询超时", body = HttpError),
+    ),
+    security(("session_cookie" = []))
+)]
 async fn list(
     claims: Claim,
     Path(entity): Path<String>,
     Query(params): Query<db::ListCountParams>,
 ) -> JsonResult<ListRecordResp> {
     params.validate()?;
-    let (page_count, items) = db::list_count(&entity, &claims.get_account(), &params).await?;
-    Ok(ListRecordResp { page_count, items }.into())
+    let (page_count, items, cursors) =
+        db::list_count(&entity, &claims.get_account(), &params).await?;
+    Ok(ListRecordResp {
+        page_count,
+        items,
+        cursors,
+        max_execution_time: db::get_query_timeout().as_millis() as u64,
+    }
+    .into())
 }
 
-async fn get_description(Path(entity): Path<String>) -> CacheJsonResult<db::EntityDescription> {
-    let description = db::description(&entity)?;
-    Ok((Duration::from_secs(300), description).into())
+static SCHEMA_CACHE_TTL: Lazy<Duration> = Lazy::new(|| must_new_basic_config().schema_cache_ttl);
+
+// 由于返回的权限与当前账号相关，因此仅允许客户端缓存，
+// 并根据内容生成ETag，描述调整后缓存则失效
+#[utoipa::path(
+    get,
+    path = "/api/inners/entity-descriptions/{entity}",
+    tag = "model",
+    params(("entity" = String, Path, description = "数据表")),
+    responses(
+        (status = 200, description = "数据表描述及当前账号的权限", body = Object),
+        (status = 304, description = "客户端缓存仍有效"),
+    ),
+    security(("session_cookie" = []))
+)]
+async fn get_description(
+    claims: Claim,
+    headers: HeaderMap,
+    Path(entity): Path<String>,
+) -> HttpResult<Response> {
+    let mut description = db::description(&entity)?;
+    let roles = db::get_user_roles(&claims.get_account()).await?;
+    description.readonly = is_readonly_roles(&roles, &INNER_ROLES);
+    let data = serde_json::to_vec(&description)?;
+    let entity_tag = format!(r#""{:x}-{}""#, data.len(), &util::sha256(&data)[0..8]);
+    let cache_control = format!("private, max-age={}", SCHEMA_CACHE_TTL.as_secs());
+    if util::get_header_value(&headers, header::IF_NONE_MATCH.as_str()) == entity_tag {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, entity_tag),
+                (header::CACHE_CONTROL, cache_control),
+            ],
+        )
+            .into_response());
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (header::ETAG, entity_tag),
+            (header::CACHE_CONTROL, cache_control),
+        ],
+        data,
+    )
+        .into_response())
 }
 
+#[utoipa::path(
+    patch,
+    path = "/api/inners/entities/{entity}/{id}",
+    tag = "model",
+    params(
+        ("entity" = String, Path, description = "数据表"),
+        ("id" = i64, Path, description = "记录id"),
+    ),
+    request_body = Object,
+    responses(
+        (status = 204, description = "更新成功"),
+        (status = 400, description = "数据不合法", body = HttpError),
+        (status = 403, description = "权限不足", body = HttpError),
+        (status = 409, description = "记录已被修改", body = HttpError),
+        (status = 428, description = "未提交updated_at", body = HttpError),
+    ),
+    security(("session_cookie" = []))
+)]
 async fn update_by_id(
     claims: Claim,
     Path((entity, id)): Path<(String, i64)>,
     Json(value): Json<Value>,
 ) -> HttpResult<StatusCode> {
-    db::update_by_id(&entity, &claims.get_account(), id, &value).await?;
+    let account = claims.get_account();
+    db::update_by_id(&entity, &account, id, &value).await?;
+    clear_draft(&account, &entity, &id.to_string()).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// 提交成功后删除对应的草稿，失败仅记录日志
+async fn clear_draft(account: &str, entity: &str, key: &str) {
+    if let Err(err) = draft::delete_draft(account, entity, key).await {
+        error!(
+            category = "draft",
+            account,
+            entity,
+            key,
+            error = err.message
+        );
+    }
+}
+
+// 草稿仅保存当前账号的编辑数据，id为记录的id，
+// 新建记录时为客户端生成的`new-`开头的草稿id
+async fn get_draft(claims: Claim, Path((entity, id)): Path<(String, String)>) -> JsonResult<Value> {
+    db::entity_profiles(&entity)?;
+    let value = draft::get_draft(&claims.get_account(), &entity, &id)
+        .await?
+        .ok_or(HttpError::new_with_category_status(
+            "Draft is not found",
+            "draft",
+            404,
+        ))?;
+    Ok(value.into())
+}
+
+async fn save_draft(
+    claims: Claim,
+    Path((entity, id)): Path<(String, String)>,
+    body: Bytes,
+) -> HttpResult<StatusCode> {
+    db::entity_profiles(&entity)?;
+    draft::save_draft(&claims.get_account(), &entity, &id, &body).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_draft(
+    claims: Claim,
+    Path((entity, id)): Path<(String, String)>,
+) -> HttpResult<StatusCode> {
+    draft::delete_draft(&claims.get_account(), &entity, &id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct BatchParams {
+    // 仅支持update，数据均禁止删除
+    action: String,
+    // 单次最多200条，每条记录需提交其updated_at
+    #[validate(length(min = 1, max = 200))]
+    items: Vec<db::BatchItem>,
+    #[serde(default)]
+    params: Value,
+    // 是否在同一事务中执行，有任一失败则全部回滚
+    #[serde(default)]
+    transactional: bool,
+}
+
+async fn batch(
+    claims: Claim,
+    Path(entity): Path<String>,
+    JsonParams(params): JsonParams<BatchParams>,
+) -> JsonResult<Vec<db::BatchResult>> {
+    if params.action != "update" {
+        return Err(HttpError::new(&format!(
+            "Action {} is not supported",
+            params.action
+        )));
+    }
+    let account = claims.get_account();
+    let results = db::batch_update_by_ids(
+        &entity,
+        &account,
+        &params.items,
+        &params.params,
+        params.transactional,
+    )
+    .await?;
+    tl_info!(
+        category = "batch",
+        entity,
+        action = params.action,
+        count = params.items.len(),
+        failed = results.iter().filter(|item| !item.ok).count(),
+        transactional = params.transactional,
+    );
+    Ok(results.into())
+}
+
+async fn preview_by_id(
+    claims: Claim,
+    Path((entity, id)): Path<(String, i64)>,
+    Json(value): Json<Value>,
+) -> JsonResult<Vec<db::FieldDiff>> {
+    let items = db::preview_by_id(&entity, &claims.get_account(), id, &value).await?;
+    Ok(items.into())
+}
+
+// 数据均禁止删除，用于停用或转移前确认影响的记录
+async fn delete_impact(
+    Path((entity, id)): Path<(String, i64)>,
+) -> JsonResult<Vec<db::EntityDependent>> {
+    let items = db::delete_impact(&entity, id).await?;
+    Ok(items.into())
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportParams {
+    keyword: Option<String>,
+    // 导出格式，ndjson(默认)或csv
+    format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ExportFormat {
+    Ndjson,
+    Csv,
+}
+
+impl ExportFormat {
+    fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => NDJSON_CONTENT_TYPE,
+            ExportFormat::Csv => util::CSV_CONTENT_TYPE,
+        }
+    }
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Ndjson => "ndjson",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+// 导出时每批查询的记录数
+const EXPORT_BATCH_SIZE: u64 = 500;
+// 导出时缓存的批次数，限制导出占用的内存
+const EXPORT_CHANNEL_SIZE: usize = 4;
+// 导出无数据时发送空行的间隔
+const EXPORT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+static EXPORT_MAX_ROWS_HEADER: &str = "x-export-max-rows";
+static EXPORT_MAX_ROWS: Lazy<u64> = Lazy::new(|| must_new_basic_config().export_max_rows);
+
+// 按id分批查询记录并发送至channel，fetch查询id大于after的limit条记录，
+// 客户端断开(channel关闭)时中止查询
+async fn stream_export<F, Fut>(
+    entity: &str,
+    format: ExportFormat,
+    fields: &[String],
+    max_rows: u64,
+    keep_alive: Duration,
+    tx: mpsc::Sender<Bytes>,
+    mut fetch: F,
+) where
+    F: FnMut(i64, u64) -> Fut,
+    Fut: Future<Output = HttpResult<Vec<Value>>>,
+{
+    if format == ExportFormat::Csv && tx.send(util::to_csv_line(fields).into()).await.is_err() {
+        return;
+    }
+    let mut after = 0;
+    let mut rows = 0;
+    // 首次在间隔后才触发，避免立即发送空行
+    let mut ticker = interval_at(Instant::now() + keep_alive, keep_alive);
+    loop {
+        let limit = EXPORT_BATCH_SIZE.min(max_rows - rows);
+        let query = fetch(after, limit);
+        tokio::pin!(query);
+        // 查询过慢时发送空行，避免代理服务因空闲而断开连接，
+        // csv的空行会被当作空记录，因此不发送
+        let result = loop {
+            tokio::select! {
+                result = &mut query => break result,
+                // 客户端已断开则直接中止查询
+                _ = tx.closed() => {
+                    warn!(category = "export", entity, "client disconnected, export cancelled");
+                    return;
+                }
+                _ = ticker.tick(), if format == ExportFormat::Ndjson => {
+                    if tx.send(Bytes::from_static(b"\n")).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+        let items = match result {
+            Ok(items) => items,
+            Err(err) => {
+                error!(category = "export", entity, error = err.message);
+                let line = match format {
+                    ExportFormat::Ndjson => json!({ "error": err.message }).to_string() + "\n",
+                    ExportFormat::Csv => util::to_csv_line(&[format!("error: {}", err.message)]),
+                };
+                let _ = tx.send(Bytes::from(line)).await;
+                return;
+            }
+        };
+        if items.is_empty() {
+            return;
+        }
+        let mut buf = vec![];
+        for item in items.iter() {
+            if let Some(id) = item.get("id").and_then(|v| v.as_i64()) {
+                after = id;
+            }
+            match format {
+                ExportFormat::Ndjson => {
+                    if let Ok(data) = serde_json::to_vec(item) {
+                        buf.extend(data);
+                        buf.push(b'\n');
+                    }
+                }
+                ExportFormat::Csv => {
+                    buf.extend(util::json_to_csv_line(fields, item).into_bytes());
+                }
+            }
+        }
+        rows += items.len() as u64;
+        // 客户端已断开则结束查询
+        if tx.send(Bytes::from(buf)).await.is_err() {
+            return;
+        }
+        ticker.reset();
+        if (items.len() as u64) < limit {
+            return;
+        }
+        if rows >= max_rows {
+            // 还有未导出的记录则在最后添加截断的标记
+            let more = fetch(after, 1).await;
+            if more.is_ok_and(|items| !items.is_empty()) {
+                warn!(
+                    category = "export",
+                    entity, rows, "export reaches the max rows"
+                );
+                let line = match format {
+                    ExportFormat::Ndjson => {
+                        json!({ "truncated": true, "rows": rows }).to_string() + "\n"
+                    }
+                    ExportFormat::Csv => util::to_csv_line(&[format!("truncated: {rows} rows")]),
+                };
+                let _ = tx.send(Bytes::from(line)).await;
+            }
+            return;
+        }
+    }
+}
+
+async fn export(
+    claims: Claim,
+    Path(entity): Path<String>,
+    Query(params): Query<ExportParams>,
+) -> HttpResult<Response> {
+    // 先校验表是否支持
+    db::description(&entity)?;
+    let format = match params.format.as_deref().unwrap_or("ndjson") {
+        "ndjson" => ExportFormat::Ndjson,
+        "csv" => ExportFormat::Csv,
+        value => {
+            return Err(HttpError::new(&format!(
+                "Export format {value} is not supported"
+            )))
+        }
+    };
+    let fields = db::export_fields(&entity)?;
+    let filename = format!(
+        "{entity}-{}.{}",
+        chrono::Utc::now().format("%Y%m%d%H%M%S"),
+        format.extension()
+    );
+    let account = claims.get_account();
+    let params = db::ListCountParams {
+        orders: None,
+        keyword: params.keyword,
+        page: 0,
+        page_size: EXPORT_BATCH_SIZE,
+        counted: false,
+        fields: None,
+        cursor: None,
+        resolved_cursor: None,
+    };
+    // channel的容量限制了内存的占用
+    let (tx, rx) = mpsc::channel::<Bytes>(EXPORT_CHANNEL_SIZE);
+    tokio::spawn(async move {
+        stream_export(
+            &entity,
+            format,
+            &fields,
+            *EXPORT_MAX_ROWS,
+            EXPORT_KEEP_ALIVE,
+            tx,
+            |after, limit| db::list_after(&entity, &account, &params, after, limit),
+        )
+        .await;
+    });
+    Ok((
+        [
+            (header::CONTENT_TYPE, format.content_type().to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+            // 超出则截断，并在最后添加截断的标记
+            (
+                HeaderName::from_static(EXPORT_MAX_ROWS_HEADER),
+                EXPORT_MAX_ROWS.to_string(),
+            ),
+        ],
+        Body::new(ChannelBody::new(rx)),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ReassignParams {
+    #[validate(length(min = 2))]
+    from: String,
+    #[validate(length(min = 2))]
+    to: String,
+}
+
+async fn reassign(
+    JsonParams(params): JsonParams<ReassignParams>,
+) -> JsonResult<Vec<db::ReassignResult>> {
+    let params = ReassignParams {
+        from: util::normalize_account(&params.from),
+        to: util::normalize_account(&params.to),
+    };
+    if params.from == params.to {
+        return Err(HttpError::new(
+            "Source and target account should be different",
+        ));
+    }
+    let result = db::reassign_creator(&params.from, &params.to).await?;
+    // 每条记录均记录日志，方便审计
+    for item in result.iter() {
+        for id in item.ids.iter() {
+            tl_info!(
+                category = "reassign",
+                entity = item.entity,
+                id,
+                from = params.from,
+                to = params.to,
+            );
+        }
+    }
+    Ok(result.into())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct MergeUsersParams {
+    #[validate(range(min = 1))]
+    source: i64,
+    #[validate(range(min = 1))]
+    target: i64,
+    // 源账号为超级管理员时需要确认
+    #[serde(default)]
+    confirmed: bool,
+    // 仅预览会转移的记录
+    #[serde(default)]
+    preview: bool,
+}
+
+async fn merge_users(
+    claims: Claim,
+    JsonParams(params): JsonParams<MergeUsersParams>,
+) -> JsonResult<db::MergeUserResult> {
+    let result = db::merge_users(
+        &claims.get_account(),
+        params.source,
+        params.target,
+        params.confirmed,
+        params.preview,
+    )
+    .await?;
+    if !params.preview {
+        let counts: Vec<String> = result
+            .items
+            .iter()
+            .map(|item| format!("{}:{}", item.entity, item.ids.len()))
+            .collect();
+        tl_info!(
+            category = "merge_users",
+            operator = claims.get_account(),
+            source = result.source,
+            target = result.target,
+            roles = result.roles.join(","),
+            groups = result.groups.join(","),
+            items = counts.join(","),
+        );
+    }
+    Ok(result.into())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct SwitchTenantParams {
+    #[validate(range(min = 1))]
+    tenant_id: i64,
+}
+
+// 超级管理员切换当前session的租户，用于管理其它租户的数据
+async fn switch_tenant(
+    mut claims: Claim,
+    JsonParams(params): JsonParams<SwitchTenantParams>,
+) -> HttpResult<StatusCode> {
+    let tenant = db::find_enabled_tenant(params.tenant_id)
+        .await?
+        .ok_or(HttpError::new("Tenant is not exists or disabled"))?;
+    let from = claims.get_tenant_id();
+    claims.set_tenant_id(tenant.id);
+    claims.save().await?;
+    tl_info!(
+        category = "switch_tenant",
+        from,
+        to = tenant.id,
+        tenant = tenant.name,
+    );
     Ok(StatusCode::NO_CONTENT)
 }
+
+// html、svg等类型始终作为附件下载，避免内联渲染时执行脚本，
+// 客户端缓存仍有效时返回304，不查询文件数据
+#[utoipa::path(
+    get,
+    path = "/api/inners/files/{id}/content",
+    tag = "file",
+    params(("id" = i64, Path, description = "文件id")),
+    responses(
+        (status = 200, description = "文件内容", content_type = "application/octet-stream"),
+        (status = 304, description = "客户端缓存仍有效"),
+        (status = 400, description = "文件不存在", body = HttpError),
+    ),
+    security(("session_cookie" = []))
+)]
+async fn get_file_content(headers: HeaderMap, Path(id): Path<i64>) -> HttpResult<Response> {
+    let meta = db::FileEntity::find_file_meta(id)
+        .await?
+        .ok_or(HttpError::new("Not found"))?;
+    let entity_tag = meta.entity_tag();
+    let last_modified = util::to_http_date(&meta.updated_at);
+    // 租户的数据，仅允许客户端缓存且每次需校验
+    let cache_control = "private, no-cache".to_string();
+    if util::is_not_modified(&headers, &entity_tag, &meta.updated_at) {
+        return Ok((
+            StatusCode::NOT_MODIFIED,
+            [
+                (header::ETAG, entity_tag),
+                (header::LAST_MODIFIED, last_modified),
+                (header::CACHE_CONTROL, cache_control),
+            ],
+        )
+            .into_response());
+    }
+    let file = db::FileEntity::find_file(id)
+        .await?
+        .ok_or(HttpError::new("Not found"))?;
+    let name = file.name.replace(['"', '\\', '\r', '\n'], "_");
+    let disposition = if util::is_active_content_type(&file.content_type) {
+        format!(r#"attachment; filename="{name}""#)
+    } else {
+        format!(r#"inline; filename="{name}""#)
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, file.content_type.clone()),
+            (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+            (header::ETAG, entity_tag),
+            (header::LAST_MODIFIED, last_modified),
+            (header::CACHE_CONTROL, cache_control),
+        ],
+        db::decode_file_data(&file.data),
+    )
+        .into_response())
+}
+
+#[derive(Debug, Serialize)]
+struct ListDataIssuesResp {
+    count: u64,
+    items: Vec<Value>,
+}
+
+async fn list_data_issues(
+    Query(params): Query<db::DataIssueListParams>,
+) -> JsonResult<ListDataIssuesResp> {
+    let (count, items) = db::list_data_issues(&params).await?;
+    let items = items
+        .into_iter()
+        .map(|item| serde_json::to_value(item).unwrap_or_default())
+        .collect();
+    Ok(ListDataIssuesResp { count, items }.into())
+}
+
+async fn list_client_error_groups(
+    Query(params): Query<db::ClientErrorGroupParams>,
+) -> JsonResult<Vec<db::ClientErrorGroup>> {
+    let groups = db::group_client_errors(&params).await?;
+    Ok(groups.into())
+}
+
+async fn list_deprecations() -> JsonResult<Vec<DeprecatedUsage>> {
+    let usages = list_deprecated_usages().await?;
+    Ok(usages.into())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+struct ScanDataIssuesParams {
+    entity: Option<String>,
+}
+
+// 添加数据校验任务，由任务worker执行
+async fn scan_data_issues(
+    claims: Claim,
+    JsonParams(params): JsonParams<ScanDataIssuesParams>,
+) -> JsonResult<AddRecordResp> {
+    if let Some(entity) = &params.entity {
+        if !db::DATA_VALIDATION_ENTITIES.contains(&entity.as_str()) {
+            return Err(HttpError::new(&format!("{entity} is not supported")));
+        }
+    }
+    let id = task::enqueue(
+        task::TASK_DATA_VALIDATION,
+        json!({ "entity": params.entity }),
+        &claims.get_account(),
+    )
+    .await?;
+    Ok(AddRecordResp { id }.into())
+}
+
+#[derive(Debug, Serialize)]
+struct ListRequestArchivesResp {
+    count: u64,
+    items: Vec<Value>,
+}
+
+async fn list_request_archives(
+    Query(params): Query<db::RequestArchiveListParams>,
+) -> JsonResult<ListRequestArchivesResp> {
+    let (count, items) = db::list_request_archives(&params).await?;
+    let items = items
+        .into_iter()
+        .map(|item| serde_json::to_value(item).unwrap_or_default())
+        .collect();
+    Ok(ListRequestArchivesResp { count, items }.into())
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplayParams {
+    // 非GET/HEAD请求需确认后才发送，否则仅返回将发送的请求
+    confirm: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReplayResp {
+    dry_run: bool,
+    method: String,
+    url: String,
+    headers: Vec<(String, String)>,
+    status: u16,
+    body: String,
+}
+
+// 重放请求时不发送的请求头
+const REPLAY_SKIP_HEADERS: [&str; 4] =
+    ["host", "content-length", "connection", "transfer-encoding"];
+
+// 重放归档的请求至当前实例，已脱敏的请求头不发送，
+// 因此依赖凭证或签名的请求需自行处理
+async fn replay_request_archive(
+    claims: Claim,
+    Path(id): Path<i64>,
+    Query(params): Query<ReplayParams>,
+) -> JsonResult<ReplayResp> {
+    let Some(archive) = db::find_request_archive(id).await? else {
+        return Err(HttpError::new_with_status(
+            "Request archive is not found",
+            404,
+        ));
+    };
+    if archive.body_truncated != 0 {
+        return Err(HttpError::new_with_category(
+            "Request body is truncated, it can not be replayed",
+            "replay",
+        ));
+    }
+    let mut headers: Vec<(String, String)> = archive
+        .headers
+        .as_object()
+        .map(|map| {
+            map.iter()
+                .filter(|(name, _)| !REPLAY_SKIP_HEADERS.contains(&name.as_str()))
+                .filter_map(|(name, value)| {
+                    let value = value.as_str()?;
+                    (value != REDACTED_VALUE).then(|| (name.clone(), value.to_string()))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let replay_id = util::uuid();
+    headers.push((REPLAY_ID_HEADER.to_string(), replay_id.clone()));
+    let listen = must_new_basic_config()
+        .listen
+        .replace("0.0.0.0", "127.0.0.1");
+    let url = format!("http://{listen}{}", archive.uri);
+
+    let idempotent = ["GET", "HEAD"].contains(&archive.method.as_str());
+    if !idempotent && !params.confirm.unwrap_or_default() {
+        return Ok(ReplayResp {
+            dry_run: true,
+            method: archive.method,
+            url,
+            headers,
+            status: 0,
+            body: archive.body.unwrap_or_default(),
+        }
+        .into());
+    }
+    tl_info!(
+        category = "request_replay",
+        operator = claims.get_account(),
+        id,
+        replay_id,
+        method = archive.method,
+        uri = archive.uri,
+    );
+    let resp = request::replay(&archive.method, &url, headers.clone(), archive.body).await?;
+    Ok(ReplayResp {
+        dry_run: false,
+        method: archive.method,
+        url,
+        headers,
+        status: resp.status,
+        body: String::from_utf8_lossy(&resp.body).to_string(),
+    }
+    .into())
+}
+
+// 修复依赖问题后重新自检，成功则开始接收请求
+async fn rerun_selftest(claims: Claim) -> JsonResult<selftest::SelfTestReport> {
+    let report = selftest::rerun().await;
+    tl_info!(
+        category = "selftest",
+        operator = claims.get_account(),
+        ok = report.ok,
+    );
+    Ok(report.into())
+}
+
+// 触发测试告警，用于确认告警可触达值班人员
+async fn test_sensitive_action(claims: Claim) -> HttpResult<StatusCode> {
+    let account = claims.get_account();
+    sensitive::notify(
+        sensitive::SensitiveRule::Test,
+        &account,
+        &format!("test notification triggered by {account}"),
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn get_logging() -> JsonResult<logger::LogFilter> {
+    Ok(logger::get_log_filter().into())
+}
+
+#[derive(Debug, Deserialize, Validate)]
+pub(super) struct UpdateLoggingParams {
+    #[validate(length(min = 1, max = 1024))]
+    pub filter: String,
+    // 多少秒后恢复为默认值，避免调试日志一直开启
+    #[validate(range(min = 1, max = 86400))]
+    pub ttl: Option<u64>,
+}
+
+async fn update_logging(
+    claims: Claim,
+    JsonParams(params): JsonParams<UpdateLoggingParams>,
+) -> JsonResult<logger::LogFilter> {
+    let previous = logger::get_log_filter();
+    logger::set_log_filter(&params.filter, params.ttl.map(Duration::from_secs))?;
+    tl_info!(
+        category = "logging",
+        operator = claims.get_account(),
+        from = previous.filter,
+        to = params.filter,
+        ttl = params.ttl.unwrap_or_default(),
+    );
+    Ok(logger::get_log_filter().into())
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+struct TracingParams {
+    // 新建trace的采样比例(0-100)
+    #[validate(range(max = 100))]
+    sampling: u8,
+}
+
+async fn get_tracing() -> JsonResult<TracingParams> {
+    Ok(TracingParams {
+        sampling: get_trace_sampling(),
+    }
+    .into())
+}
+
+// 排查问题时临时调高采样比例，重启后恢复为配置的值
+async fn update_tracing(
+    claims: Claim,
+    JsonParams(params): JsonParams<TracingParams>,
+) -> JsonResult<TracingParams> {
+    let previous = get_trace_sampling();
+    set_trace_sampling(params.sampling);
+    tl_info!(
+        category = "tracing",
+        operator = claims.get_account(),
+        from = previous,
+        to = params.sampling,
+    );
+    get_tracing().await
+}
+
+#[derive(Debug, Deserialize)]
+struct CacheReportParams {
+    prefix: Option<String>,
+    // 上次返回的cursor，用于继续扫描
+    cursor: Option<u64>,
+    // 本次最多扫描的key数量
+    limit: Option<u64>,
+    top: Option<usize>,
+}
+
+// 扫描在请求中执行，客户端断开时随请求一起中止，
+// 可通过返回的cursor继续扫描
+async fn cache_report(
+    Query(params): Query<CacheReportParams>,
+) -> JsonResult<cache::KeyspaceReport> {
+    let report = cache::scan_keyspace(
+        &params.prefix.unwrap_or_default(),
+        params.cursor.unwrap_or_default(),
+        params.limit.unwrap_or(10_000).min(100_000),
+        params.top.unwrap_or(20),
+    )
+    .await?;
+    Ok(report.into())
+}
+
+async fn retry_task(claims: Claim, Path(id): Path<i64>) -> HttpResult<StatusCode> {
+    db::retry_task(id, &claims.get_account()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn discard_task(claims: Claim, Path(id): Path<i64>) -> HttpResult<StatusCode> {
+    db::discard_task(id, &claims.get_account()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stream_export, ExportFormat, EXPORT_BATCH_SIZE, EXPORT_CHANNEL_SIZE};
+    use crate::db;
+    use crate::entities::settings;
+    use crate::error::HttpResult;
+    use crate::util;
+    use axum::body::Bytes;
+    use pretty_assertions::assert_eq;
+    use sea_orm::{ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+    use serde_json::{json, Value};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    // 模拟按id分批查询，共total条记录
+    fn fake_rows(total: i64, after: i64, limit: u64) -> Vec<Value> {
+        (after + 1..=total)
+            .take(limit as usize)
+            .map(|id| json!({ "id": id, "name": format!("name-{id}") }))
+            .collect()
+    }
+
+    // 读取导出的所有数据，返回各数据块
+    async fn collect(mut rx: mpsc::Receiver<Bytes>) -> Vec<String> {
+        let mut chunks = vec![];
+        while let Some(data) = rx.recv().await {
+            chunks.push(String::from_utf8_lossy(&data).to_string());
+        }
+        chunks
+    }
+
+    fn count_rows(chunks: &[String]) -> usize {
+        chunks
+            .iter()
+            .flat_map(|item| item.lines())
+            .filter(|item| !item.is_empty())
+            .count()
+    }
+
+    #[tokio::test]
+    async fn export_batches() {
+        let total = 100_000;
+        let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        tokio::spawn(async move {
+            stream_export(
+                "test",
+                ExportFormat::Ndjson,
+                &[],
+                200_000,
+                Duration::from_secs(15),
+                tx,
+                |after, limit| async move { HttpResult::Ok(fake_rows(total, after, limit)) },
+            )
+            .await;
+        });
+        let chunks = collect(rx).await;
+        assert_eq!(total as usize, count_rows(&chunks));
+        // 每次仅发送一批的数据
+        assert_eq!(
+            EXPORT_BATCH_SIZE as usize,
+            chunks
+                .iter()
+                .map(|item| item.lines().count())
+                .max()
+                .unwrap()
+        );
+        assert_eq!(
+            r#"{"id":100000,"name":"name-100000"}"#,
+            chunks.last().unwrap().lines().last().unwrap()
+        );
+
+        // 超出最大行数则截断并添加标记
+        let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let fields = vec!["id".to_string(), "name".to_string()];
+        tokio::spawn(async move {
+            stream_export(
+                "test",
+                ExportFormat::Csv,
+                &fields,
+                1000,
+                Duration::from_secs(15),
+                tx,
+                |after, limit| async move { HttpResult::Ok(fake_rows(1200, after, limit)) },
+            )
+            .await;
+        });
+        let chunks = collect(rx).await;
+        assert_eq!("id,name", chunks[0].trim_end());
+        // 表头、1000条记录以及截断的标记
+        assert_eq!(1002, count_rows(&chunks));
+        assert_eq!("truncated: 1000 rows", chunks.last().unwrap().trim_end());
+    }
+
+    #[tokio::test]
+    async fn export_backpressure() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let fetch_calls = calls.clone();
+        let task = tokio::spawn(async move {
+            stream_export(
+                "test",
+                ExportFormat::Ndjson,
+                &[],
+                200_000,
+                Duration::from_secs(15),
+                tx,
+                |after, limit| {
+                    fetch_calls.fetch_add(1, Ordering::Relaxed);
+                    async move { HttpResult::Ok(fake_rows(100_000, after, limit)) }
+                },
+            )
+            .await;
+        });
+        // 未读取数据时，查询的批次受channel容量限制
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert_eq!(EXPORT_CHANNEL_SIZE + 1, calls.load(Ordering::Relaxed));
+
+        // 客户端断开后中止导出
+        drop(rx);
+        task.await.unwrap();
+        assert_eq!(EXPORT_CHANNEL_SIZE + 1, calls.load(Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    async fn export_keep_alive() {
+        for format in [ExportFormat::Ndjson, ExportFormat::Csv] {
+            let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+            let fields = vec!["id".to_string()];
+            tokio::spawn(async move {
+                stream_export(
+                    "test",
+                    format,
+                    &fields,
+                    200_000,
+                    Duration::from_millis(20),
+                    tx,
+                    |after, limit| async move {
+                        // 查询较慢
+                        tokio::time::sleep(Duration::from_millis(70)).await;
+                        HttpResult::Ok(fake_rows(10, after, limit))
+                    },
+                )
+                .await;
+            });
+            let chunks = collect(rx).await;
+            let keep_alives = chunks.iter().filter(|item| item.trim().is_empty()).count();
+            assert_eq!(
+                10,
+                count_rows(&chunks) - usize::from(format == ExportFormat::Csv)
+            );
+            // csv的空行会被当作记录，因此不发送
+            if format == ExportFormat::Ndjson {
+                assert_eq!(true, keep_alives >= 2);
+            } else {
+                assert_eq!(0, keep_alives);
+            }
+        }
+    }
+
+    // 导出10万条记录，记录数需一致
+    #[tokio::test]
+    #[ignore = "requires mysql"]
+    async fn export_from_database() {
+        let conn = db::get_database().await;
+        let category = format!("export-{}", util::uuid());
+        let total = 100_000;
+        for start in (0..total).step_by(1000) {
+            let items = (start..start + 1000).map(|index| settings::ActiveModel {
+                name: Set(format!("{category}-{index}")),
+                category: Set(category.clone()),
+                data: Set("".to_string()),
+                remark: Set("".to_string()),
+                creator: Set("test".to_string()),
+                ..Default::default()
+            });
+            settings::Entity::insert_many(items)
+                .exec(conn)
+                .await
+                .unwrap();
+        }
+        let params = db::ListCountParams {
+            orders: None,
+            keyword: Some(category.clone()),
+            page: 0,
+            page_size: EXPORT_BATCH_SIZE,
+            counted: false,
+            fields: None,
+            cursor: None,
+            resolved_cursor: None,
+        };
+        let (tx, rx) = mpsc::channel(EXPORT_CHANNEL_SIZE);
+        let task = tokio::spawn(async move {
+            stream_export(
+                db::TABLE_NAME_SETTINGS,
+                ExportFormat::Ndjson,
+                &[],
+                200_000,
+                Duration::from_secs(15),
+                tx,
+                |after, limit| {
+                    db::list_after(db::TABLE_NAME_SETTINGS, "test", &params, after, limit)
+                },
+            )
+            .await;
+        });
+        let chunks = collect(rx).await;
+        task.await.unwrap();
+        settings::Entity::delete_many()
+            .filter(settings::Column::Category.eq(&category))
+            .exec(conn)
+            .await
+            .unwrap();
+
+        assert_eq!(total, count_rows(&chunks));
+        assert_eq!(
+            EXPORT_BATCH_SIZE as usize,
+            chunks
+                .iter()
+                .map(|item| item.lines().count())
+                .max()
+                .unwrap()
+        );
+    }
+}
@@ -14,6 +14,8 @@ use validator::Validate;
 
 mod common;
 mod inner;
+mod openapi;
+mod service;
 mod user;
 
 // json响应的result
@@ -123,11 +125,12 @@ fn json_content_type(headers: &HeaderMap) -> bool {
 }
 
 pub fn new_router() -> Router {
-    Router::new().nest(
+    Router::new().merge(openapi::new_router()).nest(
         "/api",
         Router::new()
             .merge(common::new_router())
             .merge(user::new_router())
-            .merge(inner::new_router()),
+            .merge(inner::new_router())
+            .merge(service::new_router()),
     )
 }
@@ -0,0 +1,102 @@
+use super::{common, inner, user};
+use crate::config::must_new_session_config;
+use crate::db::PageCursors;
+use crate::error::HttpError;
+use crate::middleware::ClaimResp;
+use crate::startup::ComponentHealth;
+use crate::util::SIGNATURE_KEY_HEADER;
+use axum::routing::get;
+use axum::{Json, Router};
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+// 认证方式：登录后的session cookie，以及内部服务的签名
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "session_cookie",
+            SecurityScheme::ApiKey(ApiKey::Cookie(ApiKeyValue::new(
+                must_new_session_config().cookie,
+            ))),
+        );
+        components.add_security_scheme(
+            "signature",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                SIGNATURE_KEY_HEADER,
+                "内部服务的key，需同时设置x-signature-timestamp与x-signature",
+            ))),
+        );
+    }
+}
+
+/// 根据路由函数的类型生成的接口文档
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "tibba",
+        description = "tibba的http接口",
+        license(name = "Apache-2.0")
+    ),
+    paths(
+        common::ping,
+        common::get_health,
+        user::login_token,
+        user::login,
+        user::me,
+        user::logout,
+        user::refresh,
+        inner::list,
+        inner::find_by_id,
+        inner::update_by_id,
+        inner::get_description,
+        inner::get_file_content,
+    ),
+    components(schemas(
+        HttpError,
+        ClaimResp,
+        ComponentHealth,
+        PageCursors,
+        user::LoginParams,
+        user::LoginTokenResp,
+        user::UserMeResp,
+        inner::ListRecordResp,
+    )),
+    modifiers(&SecurityAddon),
+    tags(
+        (name = "common", description = "通用接口"),
+        (name = "user", description = "用户相关接口"),
+        (name = "model", description = "数据表的增删改查"),
+        (name = "file", description = "文件相关接口"),
+    )
+)]
+struct ApiDoc;
+
+async fn get_openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+pub fn new_router() -> Router {
+    Router::new().route("/openapi.json", get(get_openapi))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ApiDoc;
+    use pretty_assertions::assert_eq;
+    use utoipa::OpenApi;
+
+    // 接口调整后需更新快照：UPDATE_OPENAPI=1 cargo test openapi
+    #[test]
+    fn openapi() {
+        let doc = ApiDoc::openapi().to_pretty_json().unwrap();
+        let file = concat!(env!("CARGO_MANIFEST_DIR"), "/src/controller/openapi.json");
+        if std::env::var("UPDATE_OPENAPI").is_ok() {
+            std::fs::write(file, &doc).unwrap();
+        }
+        let snapshot = std::fs::read_to_string(file).unwrap_or_default();
+        assert_eq!(snapshot, doc);
+    }
+}
@@ -0,0 +1,74 @@
+use super::inner::UpdateLoggingParams;
+use super::{JsonParams, JsonResult};
+use crate::middleware::{validate_roles, ServiceIdentity};
+use crate::{db, logger, task_local::*, tl_info};
+use axum::middleware::from_fn_with_state;
+use axum::routing::get;
+use axum::{Extension, Router};
+use std::time::Duration;
+
+/// 内部服务调用的接口，需通过签名校验，不使用登录session
+pub fn new_router() -> Router {
+    let r = Router::new()
+        .route("/logging", get(get_logging).put(update_logging))
+        .layer(from_fn_with_state(
+            vec![db::ROLE_SERVICE.to_string()],
+            validate_roles,
+        ));
+    Router::new().nest("/services", r)
+}
+
+async fn get_logging() -> JsonResult<logger::LogFilter> {
+    Ok(logger::get_log_filter().into())
+}
+
+// 运维服务统一调整各实例的日志级别
+async fn update_logging(
+    Extension(identity): Extension<ServiceIdentity>,
+    JsonParams(params): JsonParams<UpdateLoggingParams>,
+) -> JsonResult<logger::LogFilter> {
+    let previous = logger::get_log_filter();
+    logger::set_log_filter(&params.filter, params.ttl.map(Duration::from_secs))?;
+    tl_info!(
+        category = "logging",
+        service = identity.key_id,
+        from = previous.filter,
+        to = params.filter,
+        ttl = params.ttl.unwrap_or_default(),
+    );
+    Ok(logger::get_log_filter().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::new_router;
+    use crate::middleware::ServiceIdentity;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::Extension;
+    use pretty_assertions::assert_eq;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn require_service() {
+        let req = || {
+            Request::get("/services/logging")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        // 未通过签名校验的请求
+        let resp = new_router().oneshot(req()).await.unwrap();
+        assert_eq!(StatusCode::UNAUTHORIZED, resp.status());
+
+        // 签名校验成功后设置的服务身份
+        let resp = new_router()
+            .layer(Extension(ServiceIdentity {
+                key_id: "web".to_string(),
+            }))
+            .oneshot(req())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::OK, resp.status());
+    }
+}
@@ -1,30 +1,49 @@
 use super::JsonParams;
+use crate::cache::get_default_redis_cache;
 use crate::controller::JsonResult;
-use crate::db::{add_user, find_user_by_account};
+use crate::db::{
+    add_user, get_cached_user, set_user_totp_secret, unscoped_find_user_by_account,
+    update_user_password,
+};
+use crate::draft::{list_drafts, DraftInfo};
 use crate::error::{HttpError, HttpResult};
+use crate::feature::FeatureFlags;
+use crate::middleware::{
+    consume_refresh_token, create_refresh_token, list_refresh_tokens, revoke_refresh_token,
+    rotate_refresh_token, should_logged_in, Claim, RefreshTokenInfo,
+};
 use crate::middleware::{
-    error_limiter, load_session, validate_captcha, wait, LimitParams, WaitParams,
+    error_limiter, load_session, validate_captcha, wait, DeprecatedFeature, LimitParams, WaitParams,
 };
-use crate::middleware::{should_logged_in, Claim};
-use crate::util;
-use crate::{task_local::*, tl_error};
+use crate::{sensitive, util};
+use crate::{task_local::*, tl_error, tl_info};
+use axum::extract::Path;
 use axum::http::StatusCode;
 use axum::middleware::{from_fn, from_fn_with_state};
-use axum::routing::{delete, get, post};
+use axum::routing::{delete, get, patch, post};
 use axum::{Json, Router};
+use axum_client_ip::InsecureClientIp;
 use axum_extra::extract::cookie::CookieJar;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::time::Duration;
+use utoipa::ToSchema;
 use validator::Validate;
 
-#[derive(Debug, Clone, Serialize, Default)]
-struct UserMeResp {
+#[derive(Debug, Clone, Serialize, Default, ToSchema)]
+pub(super) struct UserMeResp {
     name: String,
+    display_name: String,
+    tenant_id: i64,
     expired_at: String,
     issued_at: String,
     time: String,
+    #[schema(value_type = Option<Vec<String>>)]
     roles: Option<Value>,
+    #[schema(value_type = Option<Vec<String>>)]
     groups: Option<Value>,
+    // 是否已启用两步验证
+    totp_enabled: bool,
 }
 
 pub fn new_router() -> Router {
@@ -42,42 +61,130 @@ pub fn new_router() -> Router {
                     error_limiter,
                 ))
                 .layer(from_fn(validate_captcha)),
+        )
+        .route(
+            "/token/refresh",
+            post(refresh_by_token).layer(from_fn_with_state(
+                LimitParams::new(10, 3600, "refresh_token_fail"),
+                error_limiter,
+            )),
         );
-    let refresh_router =
-        Router::new().route("/refresh", get(refresh).layer(from_fn(should_logged_in)));
+    let refresh_router = Router::new().route(
+        "/refresh",
+        post(refresh)
+            .get(refresh_deprecated)
+            .layer(from_fn(should_logged_in)),
+    );
     let r = Router::new()
         .route("/me", get(me))
+        .route("/me/features", get(me_features))
+        .route(
+            "/me/drafts",
+            get(me_drafts).layer(from_fn(should_logged_in)),
+        )
         .route("/logout", delete(logout))
+        .route("/tokens", get(list_tokens).layer(from_fn(should_logged_in)))
+        .route(
+            "/token/:id",
+            delete(revoke_token).layer(from_fn(should_logged_in)),
+        )
+        .route(
+            "/password",
+            patch(change_password)
+                .layer(from_fn(should_logged_in))
+                .layer(from_fn_with_state(
+                    LimitParams::new(10, 3600, "password_fail"),
+                    error_limiter,
+                )),
+        )
+        .route(
+            "/elevate",
+            post(elevate)
+                .layer(from_fn(should_logged_in))
+                .layer(from_fn_with_state(
+                    LimitParams::new(5, 3600, "elevate_fail"),
+                    error_limiter,
+                )),
+        )
+        .route(
+            "/2fa/enable",
+            post(enable_totp).layer(from_fn(should_logged_in)),
+        )
+        .route(
+            "/2fa/verify",
+            post(verify_totp).layer(from_fn(should_logged_in)),
+        )
         .layer(from_fn(load_session));
 
     Router::new().nest("/users", r.merge(login_router).merge(refresh_router))
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/users/refresh",
+    tag = "user",
+    responses(
+        (status = 204, description = "刷新session有效期"),
+        (status = 401, description = "未登录", body = HttpError),
+    ),
+    security(("session_cookie" = []))
+)]
 async fn refresh(mut claim: Claim) -> HttpResult<StatusCode> {
     claim.refresh().await?;
     Ok(StatusCode::NO_CONTENT)
 }
 
+// 刷新会修改session，不应使用GET，保留至下线时间
+async fn refresh_deprecated(claim: Claim) -> HttpResult<(DeprecatedFeature, StatusCode)> {
+    let status = refresh(claim).await?;
+    Ok((
+        DeprecatedFeature {
+            name: "users_refresh_get",
+            // 2026-10-16
+            deprecated_at: 1792108800,
+            // 2027-04-01
+            sunset_at: 1806537600,
+        },
+        status,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/users/me",
+    tag = "user",
+    responses((status = 200, description = "当前账号信息，未登录时账号为空", body = UserMeResp)),
+    security((), ("session_cookie" = []))
+)]
 async fn me(mut jar: CookieJar, claim: Claim) -> HttpResult<(CookieJar, Json<UserMeResp>)> {
     let account = claim.get_account();
     let mut roles = None;
     let mut groups = None;
+    let mut display_name = account.clone();
+    let mut totp_enabled = false;
     if !account.is_empty() {
-        let result = find_user_by_account(&account).await?;
+        let result = get_cached_user(&account).await?;
         if result.is_none() {
             return Err(HttpError::new("Account is not exists"));
         }
         let user = result.unwrap();
+        if let Some(value) = user.display_account {
+            display_name = value;
+        }
         roles = user.roles;
         groups = user.groups;
+        totp_enabled = user.totp_enabled;
     }
 
     let me = UserMeResp {
         name: account,
+        display_name,
+        tenant_id: claim.get_tenant_id(),
         expired_at: claim.get_expired_at(),
         issued_at: claim.get_issued_at(),
         roles,
         groups,
+        totp_enabled,
         time: util::now(),
     };
     // 如果未设置device，则设置
@@ -88,65 +195,356 @@ async fn me(mut jar: CookieJar, claim: Claim) -> HttpResult<(CookieJar, Json<Use
     Ok((jar, me.into()))
 }
 
-#[derive(Deserialize, Validate)]
-struct LoginParams {
+async fn me_features(flags: FeatureFlags) -> JsonResult<FeatureFlags> {
+    Ok(flags.into())
+}
+
+// 未提交的草稿，用于提示继续编辑
+async fn me_drafts(claim: Claim) -> JsonResult<Vec<DraftInfo>> {
+    let drafts = list_drafts(&claim.get_account()).await?;
+    Ok(drafts.into())
+}
+
+// 账号的角色或群组，未设置则为空
+fn to_strings(value: &Option<Value>) -> HttpResult<Vec<String>> {
+    match value {
+        Some(value) => Ok(util::json_value_to_strings(value)?.unwrap_or_default()),
+        None => Ok(vec![]),
+    }
+}
+
+#[derive(Deserialize, Validate, ToSchema)]
+pub(super) struct LoginParams {
     ts: i64,
     #[validate(length(min = 32))]
+    #[schema(min_length = 32)]
     token: String,
     #[validate(length(min = 32))]
+    #[schema(min_length = 32)]
     hash: String,
     #[validate(length(min = 2))]
+    #[schema(min_length = 2)]
     account: String,
     #[validate(length(min = 32))]
+    #[schema(min_length = 32)]
     password: String,
+    // 启用两步验证的账号需要
+    totp_code: Option<String>,
+    // 是否生成刷新token，用于长期保持登录
+    remember: Option<bool>,
+}
+
+// 校验登录token，密码均以hash与token生成，避免重放
+fn validate_login_token(ts: i64, token: &str, hash: &str) -> HttpResult<()> {
+    // 测试环境需要，设置为0则跳过
+    if ts <= 0 && (util::is_development() || util::is_test()) {
+        return Ok(());
+    }
+    if (ts - util::timestamp()).abs() > 60 {
+        return Err(HttpError::new("Timestamp is invalid"));
+    }
+    util::validate_timestamp_hash(ts, token, hash)?;
+    Ok(())
 }
 
 impl LoginParams {
     fn validate_token(&self) -> HttpResult<()> {
-        // 测试环境需要，设置为0则跳过
-        if self.ts <= 0 && (util::is_development() || util::is_test()) {
-            return Ok(());
-        }
-        if (self.ts - util::timestamp()).abs() > 60 {
-            return Err(HttpError::new("Timestamp is invalid"));
-        }
-        util::validate_timestamp_hash(self.ts, &self.token, &self.hash)?;
-        Ok(())
+        validate_login_token(self.ts, &self.token, &self.hash)
     }
 }
 
-async fn login(JsonParams(params): JsonParams<LoginParams>) -> HttpResult<Claim> {
+#[utoipa::path(
+    post,
+    path = "/api/users/login",
+    tag = "user",
+    request_body = LoginParams,
+    responses(
+        (status = 200, description = "登录成功并设置session cookie", body = ClaimResp),
+        (status = 400, description = "账号或密码错误", body = HttpError),
+        (status = 429, description = "出错次数过多", body = HttpError),
+    )
+)]
+async fn login(
+    InsecureClientIp(ip): InsecureClientIp,
+    jar: CookieJar,
+    JsonParams(params): JsonParams<LoginParams>,
+) -> HttpResult<Claim> {
     params.validate_token()?;
 
-    let result = find_user_by_account(&params.account).await?;
+    let result = unscoped_find_user_by_account(&params.account).await?;
     let account_password_err = HttpError::new("Account or password is wrong");
     if result.is_none() {
         return Err(account_password_err);
     }
-    let password = result.unwrap().password;
-    let msg = format!("{}:{password}", params.hash);
+    let user = result.unwrap();
+    let msg = format!("{}:{}", params.hash, user.password);
     if util::sha256(msg.as_bytes()) != params.password {
         return Err(account_password_err);
     }
+    // 已合并的账号需使用合并后的账号登录，
+    // 在密码校验后才提示且不返回合并后的账号，避免泄露账号信息
+    if user.merged_into.is_some() {
+        return Err(HttpError::new_with_category(
+            "Account has been merged, please login with the merged account",
+            "account_merged",
+        ));
+    }
+
+    if let Some(secret) = &user.totp_secret {
+        let Some(code) = &params.totp_code else {
+            return Err(HttpError::new_with_category(
+                "Totp code is required",
+                "totp_required",
+            ));
+        };
+        validate_totp_code(&user.account, secret, code).await?;
+    }
 
-    let mut claim = Claim::new(&params.account);
+    let roles = to_strings(&user.roles)?;
+    // 检查失败不影响登录
+    if let Err(err) = sensitive::check_login(&user.account, &roles, &ip.to_string()).await {
+        tl_error!(category = "sensitive_action", error = err.message);
+    }
+
+    // 使用规范化后的账号
+    let mut claim = Claim::new(&user.account, user.tenant_id);
+    claim.set_roles_groups(roles, to_strings(&user.groups)?);
+    claim.bind_generation().await?;
     // 记录session
     claim.save().await?;
+    if params.remember.unwrap_or_default() {
+        let device_id = util::get_device_id_from_cookie(&jar);
+        let token = create_refresh_token(&user.account, user.tenant_id, &device_id).await?;
+        claim.set_refresh_token(token);
+    }
 
     Ok(claim)
 }
 
+#[derive(Deserialize, Validate)]
+struct RefreshByTokenParams {
+    #[validate(length(min = 32))]
+    token: String,
+}
+
+// 使用刷新token重新登录，原token失效并返回新的token
+async fn refresh_by_token(
+    jar: CookieJar,
+    JsonParams(params): JsonParams<RefreshByTokenParams>,
+) -> HttpResult<Claim> {
+    let device_id = util::get_device_id_from_cookie(&jar);
+    let token = consume_refresh_token(&params.token, &device_id).await?;
+    // 账号已合并的不允许再使用
+    let user = unscoped_find_user_by_account(&token.account)
+        .await?
+        .filter(|item| item.merged_into.is_none())
+        .ok_or(HttpError::new_with_category(
+            "Refresh token is invalid",
+            "refresh_token",
+        ))?;
+    let mut claim = Claim::new(&user.account, token.tenant_id);
+    claim.set_roles_groups(to_strings(&user.roles)?, to_strings(&user.groups)?);
+    claim.bind_generation().await?;
+    claim.save().await?;
+    claim.set_refresh_token(rotate_refresh_token(&token).await?);
+    Ok(claim)
+}
+
+async fn list_tokens(claim: Claim) -> JsonResult<Vec<RefreshTokenInfo>> {
+    let tokens = list_refresh_tokens(&claim.get_account()).await?;
+    Ok(tokens.into())
+}
+
+async fn revoke_token(claim: Claim, Path(id): Path<String>) -> HttpResult<StatusCode> {
+    revoke_refresh_token(&claim.get_account(), &id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// 校验验证码，每个时间窗口的验证码仅可使用一次
+async fn validate_totp_code(account: &str, secret: &str, code: &str) -> HttpResult<()> {
+    let invalid_err = HttpError::new_with_category("Totp code is invalid", "totp_invalid");
+    let Some(step) = util::verify_totp(secret, code, util::timestamp())? else {
+        return Err(invalid_err);
+    };
+    // 前后各一个窗口均可使用，因此需要保存3个窗口的时长
+    let ttl = Duration::from_secs(3 * util::TOTP_PERIOD as u64);
+    let key = format!("totp:used:{account}:{step}");
+    if !get_default_redis_cache().lock(&key, Some(ttl)).await? {
+        return Err(invalid_err);
+    }
+    Ok(())
+}
+
+fn get_totp_pending_key(account: &str) -> String {
+    format!("totp:pending:{account}")
+}
+
+#[derive(Serialize)]
+struct EnableTotpResp {
+    secret: String,
+    url: String,
+}
+
+// 生成密钥，需要验证通过后才启用
+async fn enable_totp(claim: Claim) -> JsonResult<EnableTotpResp> {
+    let account = claim.get_account();
+    let user = unscoped_find_user_by_account(&account)
+        .await?
+        .ok_or(HttpError::new("Account is not exists"))?;
+    if user.totp_secret.is_some() {
+        return Err(HttpError::new_with_category(
+            "Totp is already enabled",
+            "totp",
+        ));
+    }
+    let secret = util::generate_totp_secret();
+    get_default_redis_cache()
+        .set(
+            &get_totp_pending_key(&account),
+            &secret,
+            Some(Duration::from_secs(10 * 60)),
+        )
+        .await?;
+    Ok(EnableTotpResp {
+        url: util::get_totp_url(&account, &secret),
+        secret,
+    }
+    .into())
+}
+
+#[derive(Deserialize, Validate)]
+struct VerifyTotpParams {
+    #[validate(length(equal = 6))]
+    code: String,
+}
+
+async fn verify_totp(
+    claim: Claim,
+    JsonParams(params): JsonParams<VerifyTotpParams>,
+) -> HttpResult<StatusCode> {
+    let account = claim.get_account();
+    let key = get_totp_pending_key(&account);
+    let cache = get_default_redis_cache();
+    let secret: Option<String> = cache.get(&key).await?;
+    let Some(secret) = secret else {
+        return Err(HttpError::new_with_category(
+            "Totp secret is expired, please enable again",
+            "totp",
+        ));
+    };
+    validate_totp_code(&account, &secret, &params.code).await?;
+    set_user_totp_secret(&account, Some(secret)).await?;
+    cache.del(&key).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Validate)]
+struct ChangePasswordParams {
+    ts: i64,
+    #[validate(length(min = 32))]
+    token: String,
+    #[validate(length(min = 32))]
+    hash: String,
+    #[validate(length(min = 32))]
+    current_password: String,
+    #[validate(length(min = 32))]
+    new_password: String,
+}
+
+// 修改密码后其它设备的session均失效，当前session保持有效
+async fn change_password(
+    mut claim: Claim,
+    JsonParams(params): JsonParams<ChangePasswordParams>,
+) -> HttpResult<StatusCode> {
+    validate_login_token(params.ts, &params.token, &params.hash)?;
+    let account = claim.get_account();
+    // 账号不存在与密码错误使用相同的出错信息
+    let password_err = HttpError::new("Current password is wrong");
+    let user = unscoped_find_user_by_account(&account)
+        .await?
+        .ok_or_else(|| password_err.clone())?;
+    let msg = format!("{}:{}", params.hash, user.password);
+    if util::sha256(msg.as_bytes()) != params.current_password {
+        return Err(password_err);
+    }
+    update_user_password(&account, &params.new_password).await?;
+    claim.invalidate_others().await?;
+    tl_info!(category = "change_password", account);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[derive(Deserialize, Validate)]
+struct ElevateParams {
+    ts: i64,
+    #[validate(length(min = 32))]
+    token: String,
+    #[validate(length(min = 32))]
+    hash: String,
+    // 密码与验证码二选一，启用两步验证的账号可使用验证码
+    password: Option<String>,
+    totp_code: Option<String>,
+}
+
+#[derive(Serialize)]
+struct ElevateResp {
+    elevated_until: String,
+}
+
+// 敏感操作前重新验证身份，有效期内无需再次验证
+async fn elevate(
+    mut claim: Claim,
+    JsonParams(params): JsonParams<ElevateParams>,
+) -> JsonResult<ElevateResp> {
+    let account = claim.get_account();
+    let password_err = HttpError::new("Password is wrong");
+    let user = unscoped_find_user_by_account(&account)
+        .await?
+        .ok_or_else(|| password_err.clone())?;
+    let method = match (&user.totp_secret, &params.totp_code) {
+        (Some(secret), Some(code)) => {
+            validate_totp_code(&account, secret, code).await?;
+            "totp"
+        }
+        _ => {
+            validate_login_token(params.ts, &params.token, &params.hash)?;
+            let msg = format!("{}:{}", params.hash, user.password);
+            if params.password.as_deref() != Some(util::sha256(msg.as_bytes()).as_str()) {
+                return Err(password_err);
+            }
+            "password"
+        }
+    };
+    claim.elevate();
+    claim.save().await?;
+    let elevated_until = claim.get_elevated_until();
+    tl_info!(category = "elevate", account, method, elevated_until);
+    Ok(ElevateResp { elevated_until }.into())
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/users/logout",
+    tag = "user",
+    responses((status = 200, description = "退出登录", body = ClaimResp)),
+    security(("session_cookie" = []))
+)]
 async fn logout(mut claim: Claim) -> HttpResult<Claim> {
     claim.destroy();
     Ok(claim)
 }
 
-#[derive(Serialize)]
-struct LoginTokenResp {
+#[derive(Serialize, ToSchema)]
+pub(super) struct LoginTokenResp {
     ts: i64,
     hash: String,
     token: String,
 }
+#[utoipa::path(
+    get,
+    path = "/api/users/login-token",
+    tag = "user",
+    responses((status = 200, description = "登录使用的token", body = LoginTokenResp))
+)]
 async fn login_token() -> JsonResult<LoginTokenResp> {
     let token = util::uuid();
     let (ts, hash) = util::timestamp_hash(&token);
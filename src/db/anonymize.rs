@@ -0,0 +1,204 @@
+use super::{
+    data_issue_sensitivity, get_database, request_archive_sensitivity, tenant_sensitivity,
+    ClientErrorEntity, FileEntity, Result, SettingEntity, TaskEntity, UserEntity,
+    TABLE_INVALID_MSG, TABLE_NAME_CLIENT_ERRORS, TABLE_NAME_FILES, TABLE_NAME_SETTINGS,
+    TABLE_NAME_TASKS, TABLE_NAME_USERS,
+};
+use crate::error::HttpError;
+use sea_orm::{
+    ColumnTrait, ConnectionTrait, DatabaseBackend, FromQueryResult, Iterable, JsonValue, Statement,
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+// 每批处理的记录数
+const ANONYMIZE_BATCH_SIZE: u64 = 500;
+
+/// 敏感字段的脱敏方式
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Anonymize {
+    // 账号，同一账号在所有表中替换为相同的值
+    Account,
+    // 邮箱
+    Email,
+    // 替换为hash
+    Hash,
+    // 置为null(仅可为空的字段)
+    Null,
+    // 置为空字符串
+    Empty,
+    // 按规则替换文本中的敏感内容
+    Redact,
+}
+
+/// 表字段的敏感分类，所有字段均需声明为敏感或安全
+#[derive(Debug, Clone, Default)]
+pub struct EntitySensitivity {
+    pub sensitive: Vec<(String, Anonymize)>,
+    pub safe: Vec<String>,
+    // 表的所有字段
+    columns: Vec<String>,
+}
+
+impl EntitySensitivity {
+    pub fn new<C: ColumnTrait + Iterable>(sensitive: &[(C, Anonymize)], safe: &[C]) -> Self {
+        EntitySensitivity {
+            sensitive: sensitive
+                .iter()
+                .map(|(column, rule)| (column.as_str().to_string(), *rule))
+                .collect(),
+            safe: safe.iter().map(|item| item.as_str().to_string()).collect(),
+            columns: C::iter().map(|item| item.as_str().to_string()).collect(),
+        }
+    }
+    /// 未声明为敏感或安全的字段，新增字段时需明确分类
+    pub fn unclassified(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter(|item| {
+                !self.safe.contains(item) && !self.sensitive.iter().any(|(name, _)| name == *item)
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+/// 需要脱敏的表
+pub static ANONYMIZE_ENTITIES: &[&str] = &[
+    TABLE_NAME_SETTINGS,
+    TABLE_NAME_USERS,
+    TABLE_NAME_FILES,
+    TABLE_NAME_CLIENT_ERRORS,
+    TABLE_NAME_TASKS,
+    "tenants",
+    "request_archives",
+    "data_issues",
+];
+
+pub fn entity_sensitivity(name: &str) -> Result<EntitySensitivity> {
+    let result = match name {
+        TABLE_NAME_SETTINGS => SettingEntity::sensitivity(),
+        TABLE_NAME_USERS => UserEntity::sensitivity(),
+        TABLE_NAME_FILES => FileEntity::sensitivity(),
+        TABLE_NAME_CLIENT_ERRORS => ClientErrorEntity::sensitivity(),
+        TABLE_NAME_TASKS => TaskEntity::sensitivity(),
+        "tenants" => tenant_sensitivity(),
+        "request_archives" => request_archive_sensitivity(),
+        "data_issues" => data_issue_sensitivity(),
+        _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
+    };
+    Ok(result)
+}
+
+/// 表的脱敏统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct AnonymizeStats {
+    pub entity: String,
+    pub rows: u64,
+    // 各字段被替换的数量
+    pub values: HashMap<String, u64>,
+}
+
+fn to_db_value(value: Value) -> sea_orm::Value {
+    match value {
+        Value::String(value) => value.into(),
+        Value::Null => Option::<String>::None.into(),
+        _ => value.into(),
+    }
+}
+
+/// 按id顺序遍历表中的敏感字段，transform返回Some时更新为新的值，
+/// 直接修改数据库，仅可用于恢复的数据库副本
+pub async fn anonymize_entity<F>(name: &str, mut transform: F) -> Result<AnonymizeStats>
+where
+    F: FnMut(Anonymize, &Value) -> Option<Value>,
+{
+    let sensitivity = entity_sensitivity(name)?;
+    // 未分类的字段有可能包含敏感数据，需先声明再脱敏
+    let fields = sensitivity.unclassified();
+    if !fields.is_empty() {
+        return Err(HttpError::new_with_category(
+            &format!("Fields {} of {name} are not classified", fields.join(",")),
+            "anonymize",
+        ));
+    }
+    let mut stats = AnonymizeStats {
+        entity: name.to_string(),
+        ..Default::default()
+    };
+    if sensitivity.sensitive.is_empty() {
+        return Ok(stats);
+    }
+    let columns = sensitivity
+        .sensitive
+        .iter()
+        .map(|(column, _)| format!("`{column}`"))
+        .collect::<Vec<_>>()
+        .join(",");
+    let conn = get_database().await;
+    let mut after = 0;
+    loop {
+        let rows = JsonValue::find_by_statement(Statement::from_sql_and_values(
+            DatabaseBackend::MySql,
+            format!("SELECT `id`,{columns} FROM `{name}` WHERE `id` > ? ORDER BY `id` LIMIT ?"),
+            [after.into(), ANONYMIZE_BATCH_SIZE.into()],
+        ))
+        .all(conn)
+        .await?;
+        let Some(last) = rows.last() else {
+            break;
+        };
+        after = last.get("id").and_then(Value::as_i64).unwrap_or_default();
+        for row in rows.iter() {
+            let id = row.get("id").and_then(Value::as_i64).unwrap_or_default();
+            let mut sets = vec![];
+            let mut values = vec![];
+            for (column, rule) in sensitivity.sensitive.iter() {
+                let current = row.get(column).unwrap_or(&Value::Null);
+                let Some(value) = transform(*rule, current) else {
+                    continue;
+                };
+                if &value == current {
+                    continue;
+                }
+                sets.push(format!("`{column}` = ?"));
+                values.push(to_db_value(value));
+                *stats.values.entry(column.clone()).or_default() += 1;
+            }
+            stats.rows += 1;
+            if sets.is_empty() {
+                continue;
+            }
+            values.push(id.into());
+            conn.execute(Statement::from_sql_and_values(
+                DatabaseBackend::MySql,
+                format!("UPDATE `{name}` SET {} WHERE `id` = ?", sets.join(",")),
+                values,
+            ))
+            .await?;
+        }
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entity_sensitivity, ANONYMIZE_ENTITIES};
+    use pretty_assertions::assert_eq;
+    #[test]
+    fn sensitivity() {
+        for name in ANONYMIZE_ENTITIES.iter() {
+            let sensitivity = entity_sensitivity(name).unwrap();
+            assert_eq!(Vec::<String>::new(), sensitivity.unclassified(), "{name}");
+        }
+        let users = entity_sensitivity("users").unwrap();
+        for field in ["account", "email", "password", "totp_secret"] {
+            assert_eq!(
+                true,
+                users.sensitive.iter().any(|(name, _)| name == field),
+                "{field}"
+            );
+        }
+    }
+}
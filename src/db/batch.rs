@@ -0,0 +1,179 @@
+use super::{
+    find_by_id, get_database, has_entity_hooks, run_update_hooks, update_by_id, update_by_id_with,
+    Result,
+};
+use sea_orm::TransactionTrait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// 批量操作中每条记录的结果
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct BatchResult {
+    pub id: i64,
+    pub ok: bool,
+    pub error: Option<String>,
+}
+
+/// 批量更新的记录，updated_at为该记录更新的前置条件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchItem {
+    pub id: i64,
+    pub updated_at: String,
+}
+
+// 每条记录使用各自的updated_at作为前置条件
+fn with_precondition(value: &Value, item: &BatchItem) -> Value {
+    let mut value = value.clone();
+    if let Some(map) = value.as_object_mut() {
+        map.insert(
+            "updated_at".to_string(),
+            Value::String(item.updated_at.clone()),
+        );
+    }
+    value
+}
+
+// 事务中其它记录失败导致回滚时的出错信息
+const ROLLED_BACK_MSG: &str = "Rolled back because of other failures";
+
+/// 批量更新记录，权限校验与单条更新一致。
+/// transactional为true时在同一事务中更新，有任一失败则全部回滚，
+/// 否则逐条更新，失败的记录不影响其它记录
+pub async fn batch_update_by_ids(
+    name: &str,
+    user: &str,
+    items: &[BatchItem],
+    value: &Value,
+    transactional: bool,
+) -> Result<Vec<BatchResult>> {
+    if !transactional {
+        let mut results = vec![];
+        for item in items.iter() {
+            let result = update_by_id(name, user, item.id, &with_precondition(value, item)).await;
+            results.push(BatchResult {
+                id: item.id,
+                ok: result.is_ok(),
+                error: result.err().map(|err| err.message),
+            });
+        }
+        return Ok(results);
+    }
+
+    // 变更处理需要修改前的数据，在事务外查询
+    let hooks_enabled = has_entity_hooks(name);
+    let mut olds = vec![];
+    if hooks_enabled {
+        for item in items.iter() {
+            olds.push(find_by_id(name, user, item.id, &[]).await.ok().flatten());
+        }
+    }
+
+    let txn = get_database().await.begin().await?;
+    let mut results = vec![];
+    for item in items.iter() {
+        let result =
+            update_by_id_with(&txn, name, user, item.id, &with_precondition(value, item)).await;
+        results.push(BatchResult {
+            id: item.id,
+            ok: result.is_ok(),
+            error: result.err().map(|err| err.message),
+        });
+    }
+    if results.iter().any(|item| !item.ok) {
+        txn.rollback().await?;
+        for item in results.iter_mut().filter(|item| item.ok) {
+            item.ok = false;
+            item.error = Some(ROLLED_BACK_MSG.to_string());
+        }
+        return Ok(results);
+    }
+    txn.commit().await?;
+
+    // 提交成功后才触发变更处理
+    for (item, old) in items.iter().zip(olds) {
+        let Some(old) = old else {
+            continue;
+        };
+        if let Ok(Some(new)) = find_by_id(name, user, item.id, &[]).await {
+            run_update_hooks(name, user, item.id, old, new);
+        }
+    }
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{batch_update_by_ids, with_precondition, BatchItem};
+    use crate::db::{get_database, TABLE_NAME_SETTINGS};
+    use crate::entities::settings::{ActiveModel, Column, Entity};
+    use crate::util;
+    use pretty_assertions::assert_eq;
+    use sea_orm::{ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, QueryFilter};
+    use serde_json::json;
+
+    #[test]
+    fn precondition() {
+        let item = BatchItem {
+            id: 1,
+            updated_at: "2024-01-01T00:00:00Z".to_string(),
+        };
+        assert_eq!(
+            json!({"remark": "batch", "updated_at": "2024-01-01T00:00:00Z"}),
+            with_precondition(
+                &json!({"remark": "batch", "updated_at": "2023-01-01T00:00:00Z"}),
+                &item
+            )
+        );
+    }
+
+    // 多条记录各自使用其updated_at，事务中均可更新成功
+    #[tokio::test]
+    #[ignore = "requires mysql"]
+    async fn batch_update() {
+        let conn = get_database().await;
+        let category = format!("batch-{}", util::uuid());
+        let mut items = vec![];
+        for index in 0..3 {
+            let model = ActiveModel {
+                name: Set(format!("{category}-{index}")),
+                category: Set(category.clone()),
+                data: Set("".to_string()),
+                remark: Set("".to_string()),
+                creator: Set("test".to_string()),
+                ..Default::default()
+            }
+            .insert(conn)
+            .await
+            .unwrap();
+            items.push(BatchItem {
+                id: model.id,
+                updated_at: model.updated_at.to_rfc3339(),
+            });
+        }
+        let results = batch_update_by_ids(
+            TABLE_NAME_SETTINGS,
+            "test",
+            &items,
+            &json!({"remark": "batch"}),
+            true,
+        )
+        .await;
+        let remarks: Vec<String> = Entity::find()
+            .filter(Column::Category.eq(&category))
+            .all(conn)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|item| item.remark)
+            .collect();
+        Entity::delete_many()
+            .filter(Column::Category.eq(&category))
+            .exec(conn)
+            .await
+            .unwrap();
+
+        let results = results.unwrap();
+        assert_eq!(3, results.iter().filter(|item| item.ok).count());
+        assert_eq!(vec!["batch".to_string(); 3], remarks);
+    }
+}
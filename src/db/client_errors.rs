@@ -0,0 +1,280 @@
+use super::CommonEntity;
+use super::{
+    get_database, guarded_count, guarded_fetch_page, Anonymize, EntityDescription,
+    EntityItemCategory, EntityItemDescription, EntityProfiles, EntitySensitivity, Error,
+    ListCountParams, Result,
+};
+use crate::entities::client_errors::{ActiveModel, Column, Entity};
+use chrono::Utc;
+use db_entity_derive::DbEntity;
+use once_cell::sync::Lazy;
+use sea_orm::query::{Order, Select};
+use sea_orm::sea_query::{Alias, Expr};
+use sea_orm::ColumnTrait;
+use sea_orm::Condition;
+use sea_orm::FromQueryResult;
+use sea_orm::QuerySelect;
+use sea_orm::{entity::prelude::*, ActiveValue::Set, QueryOrder};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::str::FromStr;
+use substring::Substring;
+
+static SUPPORT_ORDERS: Lazy<Vec<Column>> =
+    Lazy::new(|| vec![Column::Id, Column::Fingerprint, Column::UpdatedAt]);
+
+#[derive(Debug, Clone, Default)]
+pub struct ClientErrorData {
+    pub fingerprint: String,
+    pub severity: String,
+    pub message: String,
+    pub stack: Option<String>,
+    pub url: String,
+    pub user_agent: String,
+    pub release: String,
+}
+
+/// 批量保存客户端上报的出错信息
+pub async fn add_client_errors(
+    account: &str,
+    device_id: &str,
+    items: Vec<ClientErrorData>,
+) -> Result<()> {
+    let conn = get_database().await;
+    for item in items {
+        ActiveModel {
+            fingerprint: Set(item.fingerprint),
+            severity: Set(item.severity),
+            message: Set(item.message),
+            stack: Set(item.stack),
+            url: Set(item.url),
+            user_agent: Set(item.user_agent),
+            release: Set(item.release),
+            device_id: Set(device_id.to_string()),
+            creator: Set(account.to_string()),
+            ..Default::default()
+        }
+        .insert(conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// 删除超出最大条数的出错记录，id自增因此保留id最大的记录
+pub async fn prune_client_errors(max_rows: u64) -> Result<u64> {
+    let conn = get_database().await;
+    let Some(model) = Entity::find()
+        .order_by_desc(Column::Id)
+        .offset(max_rows)
+        .one(conn)
+        .await?
+    else {
+        return Ok(0);
+    };
+    let result = Entity::delete_many()
+        .filter(Column::Id.lte(model.id))
+        .exec(conn)
+        .await?;
+    Ok(result.rows_affected)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ClientErrorGroupParams {
+    // 统计最近多少小时的记录，默认24小时，最多30天
+    pub hours: Option<u32>,
+    // 返回的分组数，默认50，最多200
+    pub limit: Option<u64>,
+}
+
+/// 相同指纹出错记录的汇总
+#[derive(Debug, Clone, Serialize, FromQueryResult)]
+pub struct ClientErrorGroup {
+    pub fingerprint: String,
+    pub count: i64,
+    // 同一指纹的出错信息仅数字不同，取其一展示
+    pub message: String,
+    pub last_seen_at: DateTimeUtc,
+}
+
+/// 按指纹分组统计出错记录，按出现次数倒序
+pub async fn group_client_errors(params: &ClientErrorGroupParams) -> Result<Vec<ClientErrorGroup>> {
+    let hours = params.hours.unwrap_or(24).clamp(1, 30 * 24);
+    let since = Utc::now() - chrono::Duration::hours(hours as i64);
+    let groups = Entity::find()
+        .select_only()
+        .column(Column::Fingerprint)
+        .column_as(Column::Id.count(), "count")
+        .column_as(Column::Message.max(), "message")
+        .column_as(Column::CreatedAt.max(), "last_seen_at")
+        .filter(Column::CreatedAt.gte(since))
+        .group_by(Column::Fingerprint)
+        .order_by_desc(Expr::col(Alias::new("count")))
+        .limit(params.limit.unwrap_or(50).clamp(1, 200))
+        .into_model::<ClientErrorGroup>()
+        .all(get_database().await)
+        .await?;
+    Ok(groups)
+}
+
+// 客户端出错记录不允许修改，不生成新增与更新的函数
+#[derive(DbEntity)]
+#[db_entity(readonly)]
+pub struct ClientErrorEntity {}
+impl CommonEntity for ClientErrorEntity {}
+
+impl ClientErrorEntity {
+    fn get_support_orders() -> Vec<Column> {
+        SUPPORT_ORDERS.to_vec()
+    }
+    fn get_tenant_column() -> Option<Column> {
+        None
+    }
+    fn get_condition(params: &ListCountParams) -> Option<Condition> {
+        if let Some(keyword) = &params.keyword {
+            let cond = Condition::any()
+                .add(Column::Fingerprint.eq(keyword))
+                .add(Column::Creator.eq(keyword))
+                .add(Column::Message.contains(keyword));
+            Some(cond)
+        } else {
+            None
+        }
+    }
+    pub fn sensitivity() -> EntitySensitivity {
+        EntitySensitivity::new(
+            &[
+                (Column::Message, Anonymize::Redact),
+                (Column::Stack, Anonymize::Null),
+                (Column::Url, Anonymize::Redact),
+                (Column::DeviceId, Anonymize::Hash),
+                (Column::Updater, Anonymize::Account),
+                (Column::Creator, Anonymize::Account),
+            ],
+            &[
+                Column::Id,
+                Column::CreatedAt,
+                Column::UpdatedAt,
+                Column::Fingerprint,
+                Column::Severity,
+                Column::UserAgent,
+                Column::Release,
+            ],
+        )
+    }
+    pub fn profiles() -> EntityProfiles {
+        let list = [
+            Column::Id,
+            Column::Fingerprint,
+            Column::Severity,
+            Column::Message,
+            Column::Url,
+            Column::UserAgent,
+            Column::Release,
+            Column::DeviceId,
+            Column::Creator,
+            Column::CreatedAt,
+        ];
+        let mut detail = list.to_vec();
+        detail.extend([Column::Stack, Column::Updater, Column::UpdatedAt]);
+        EntityProfiles::new(&list, &detail, &detail, &[])
+    }
+    pub fn description() -> EntityDescription {
+        let items = vec![
+            EntityItemDescription {
+                name: Column::Id.to_string(),
+                label: "ID".to_string(),
+                width: Some(60),
+                category: EntityItemCategory::Number,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Fingerprint.to_string(),
+                label: "指纹".to_string(),
+                width: Some(120),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Severity.to_string(),
+                label: "级别".to_string(),
+                width: Some(60),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Message.to_string(),
+                label: "出错信息".to_string(),
+                width: Some(200),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Stack.to_string(),
+                label: "堆栈".to_string(),
+                width: Some(200),
+                span: Some(3),
+                category: EntityItemCategory::Editor,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Url.to_string(),
+                label: "页面地址".to_string(),
+                width: Some(150),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::UserAgent.to_string(),
+                label: "浏览器".to_string(),
+                width: Some(150),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Release.to_string(),
+                label: "版本".to_string(),
+                width: Some(80),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Creator.to_string(),
+                label: "账号".to_string(),
+                width: Some(80),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::DeviceId.to_string(),
+                label: "设备ID".to_string(),
+                width: Some(80),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::CreatedAt.to_string(),
+                label: "创建时间".to_string(),
+                width: Some(150),
+                category: EntityItemCategory::DateTime,
+                readonly: true,
+                ..Default::default()
+            },
+        ];
+        // 出错记录不允许修改，因此不设置modify_roles
+        EntityDescription {
+            items,
+            support_orders: SUPPORT_ORDERS.iter().map(|item| item.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+}
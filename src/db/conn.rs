@@ -1,12 +1,81 @@
-use crate::config::must_new_database_config;
+use crate::config::{must_new_database_config, DatabaseConfig};
+use crate::task_local::{ACCOUNT, TRACE_ID};
 use crate::util::is_development;
 use regex::Regex;
-use sea_orm::{ConnectOptions, Database, DatabaseConnection};
+use sea_orm::sqlx::mysql::{MySqlConnectOptions, MySqlConnection, MySqlPoolOptions};
+use sea_orm::sqlx::{ConnectOptions as _, Executor};
+use sea_orm::{ConnectOptions, Database, DatabaseConnection, SqlxMySqlConnector};
+use std::str::FromStr;
 use tokio::sync::OnceCell;
-use tracing::info;
+use tracing::{error, info};
+
+// 设置当前请求的账号与trace id，非请求中(如定时任务)则不设置
+async fn set_attribution(conn: &mut MySqlConnection) -> Result<(), sea_orm::sqlx::Error> {
+    let account = ACCOUNT.try_with(|value| value.clone()).unwrap_or_default();
+    let trace_id = TRACE_ID.try_with(|value| value.clone()).unwrap_or_default();
+    if account.is_empty() && trace_id.is_empty() {
+        return Ok(());
+    }
+    conn.execute(
+        sea_orm::sqlx::query("SET @app_account = ?, @app_request_id = ?")
+            .bind(account)
+            .bind(trace_id),
+    )
+    .await?;
+    Ok(())
+}
+
+// 连接池由sqlx创建，在获取连接时设置会话变量，归还时清除，
+// 避免连接复用时的变量被其它请求使用
+async fn get_attribution_conn(config: &DatabaseConfig) -> DatabaseConnection {
+    let opts = MySqlConnectOptions::from_str(&config.url).unwrap();
+    let opts = if is_development() {
+        opts
+    } else {
+        opts.disable_statement_logging()
+    };
+    let pool = MySqlPoolOptions::new()
+        .max_connections(config.max_connections)
+        .min_connections(config.min_connections)
+        .acquire_timeout(config.acquire_timeout)
+        .idle_timeout(config.idle_timeout)
+        .after_connect(|conn, _meta| Box::pin(async move { set_attribution(conn).await }))
+        .before_acquire(|conn, _meta| {
+            Box::pin(async move {
+                // 设置失败则关闭此连接
+                Ok(set_attribution(conn).await.is_ok())
+            })
+        })
+        .after_release(|conn, _meta| {
+            Box::pin(async move {
+                let result = conn
+                    .execute("SET @app_account = NULL, @app_request_id = NULL")
+                    .await;
+                if let Err(err) = &result {
+                    error!(category = "db_attribution", error = err.to_string());
+                }
+                Ok(result.is_ok())
+            })
+        })
+        .connect_with(opts)
+        .await
+        .unwrap();
+    SqlxMySqlConnector::from_sqlx_mysql_pool(pool)
+}
 
 async fn get_conn() -> DatabaseConnection {
     let config = must_new_database_config();
+    let re = Regex::new(r"\:\S+?@").unwrap();
+    let url = re.replace(&config.origin_url, ":***@");
+    if config.attribution {
+        let db = get_attribution_conn(&config).await;
+        info!(
+            url = url.to_string(),
+            attribution = true,
+            "connect to database success"
+        );
+        return db;
+    }
     let mut opt = ConnectOptions::new(&config.url);
     opt.max_connections(config.max_connections)
         .min_connections(config.min_connections)
@@ -17,8 +86,6 @@ async fn get_conn() -> DatabaseConnection {
 
     // opt.sqlx_logging(false) // Disabling SQLx log
     // .sqlx_logging_level(log::LevelFilter::Info);
-    let re = Regex::new(r"\:\S+?@").unwrap();
-    let url = re.replace(&config.origin_url, ":***@");
     let db = Database::connect(opt).await.unwrap();
     info!(url = url.to_string(), "connect to database success");
 
@@ -29,3 +96,63 @@ pub async fn get_database() -> &'static DatabaseConnection {
     static DB: OnceCell<DatabaseConnection> = OnceCell::const_new();
     DB.get_or_init(get_conn).await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::get_attribution_conn;
+    use crate::config::must_new_database_config;
+    use crate::task_local::{ACCOUNT, TRACE_ID};
+    use pretty_assertions::assert_eq;
+    use sea_orm::{ConnectionTrait, DatabaseConnection, DbBackend, Statement};
+
+    async fn get_attribution(db: &DatabaseConnection) -> (Option<String>, Option<String>) {
+        let row = db
+            .query_one(Statement::from_string(
+                DbBackend::MySql,
+                "SELECT @app_account AS account, @app_request_id AS request_id",
+            ))
+            .await
+            .unwrap()
+            .unwrap();
+        (
+            row.try_get("", "account").unwrap(),
+            row.try_get("", "request_id").unwrap(),
+        )
+    }
+
+    // 请求中的查询可获取会话变量，连接归还后再次获取时已清除
+    #[tokio::test]
+    #[ignore = "requires mysql"]
+    async fn attribution() {
+        let mut config = must_new_database_config();
+        // 仅一个连接，保证复用同一连接
+        config.max_connections = 1;
+        config.min_connections = 0;
+        let db = get_attribution_conn(&config).await;
+
+        let result = ACCOUNT
+            .scope(
+                "tree".to_string(),
+                TRACE_ID.scope("trace-1".to_string(), get_attribution(&db)),
+            )
+            .await;
+        assert_eq!(
+            (Some("tree".to_string()), Some("trace-1".to_string())),
+            result
+        );
+
+        // 非请求中的查询
+        assert_eq!((None, None), get_attribution(&db).await);
+
+        let result = ACCOUNT
+            .scope(
+                "vicanso".to_string(),
+                TRACE_ID.scope("trace-2".to_string(), get_attribution(&db)),
+            )
+            .await;
+        assert_eq!(
+            (Some("vicanso".to_string()), Some("trace-2".to_string())),
+            result
+        );
+    }
+}
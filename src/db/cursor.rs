@@ -0,0 +1,195 @@
+use super::Result;
+use crate::config::must_new_basic_config;
+use crate::error::HttpError;
+use crate::keygrip::KeyGrip;
+use crate::util;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+// cursor按id倒序分页
+const CURSOR_ORDER: &str = "-id";
+
+static CURSOR_KEYGRIP: Lazy<KeyGrip> =
+    Lazy::new(|| KeyGrip::new(vec![must_new_basic_config().secret.into_bytes()]));
+static CURSOR_TTL: Lazy<i64> = Lazy::new(|| must_new_basic_config().cursor_ttl.as_secs() as i64);
+
+/// 解析后的cursor
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    // 边界记录的id
+    pub id: i64,
+    // 是否查询上一页(id大于边界的记录)
+    pub prev: bool,
+}
+
+// cursor中保存的内容，字段名尽量短以减少长度
+#[derive(Debug, Serialize, Deserialize)]
+struct CursorToken {
+    // 排序字段及方向
+    o: String,
+    // 查询条件的hash
+    f: String,
+    // 边界记录的id
+    v: i64,
+    // 是否上一页
+    #[serde(default)]
+    p: bool,
+    // 过期时间(秒)
+    e: i64,
+}
+
+/// 查询条件的hash，条件变化后之前的cursor不可再使用
+pub fn cursor_filter_hash(name: &str, keyword: &Option<String>) -> String {
+    let value = format!("{name}\n{}", keyword.as_deref().unwrap_or_default());
+    util::sha256(value.as_bytes())[..16].to_string()
+}
+
+fn new_cursor_error(message: &str, code: &str) -> HttpError {
+    let mut he = HttpError::new_with_category(message, "cursor");
+    he.code = code.to_string();
+    he
+}
+
+fn encode_cursor_with(
+    keygrip: &KeyGrip,
+    cursor: Cursor,
+    filter: &str,
+    expired_at: i64,
+) -> Result<String> {
+    let token = CursorToken {
+        o: CURSOR_ORDER.to_string(),
+        f: filter.to_string(),
+        v: cursor.id,
+        p: cursor.prev,
+        e: expired_at,
+    };
+    let data = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&token)?);
+    let digest = keygrip.sign(data.as_bytes())?;
+    Ok(format!("{data}.{digest}"))
+}
+
+fn decode_cursor_with(keygrip: &KeyGrip, value: &str, filter: &str, now: i64) -> Result<Cursor> {
+    let invalid_err = || new_cursor_error("Cursor is invalid", "cursor_invalid");
+    let (data, digest) = value.split_once('.').ok_or_else(invalid_err)?;
+    // 签名校验失败说明被篡改
+    if !keygrip.verify(data.as_bytes(), digest)?.0 {
+        return Err(invalid_err());
+    }
+    let token: CursorToken = URL_SAFE_NO_PAD
+        .decode(data)
+        .ok()
+        .and_then(|data| serde_json::from_slice(&data).ok())
+        .ok_or_else(invalid_err)?;
+    if token.o != CURSOR_ORDER || token.v <= 0 {
+        return Err(invalid_err());
+    }
+    if token.e < now {
+        return Err(new_cursor_error("Cursor is expired", "cursor_expired"));
+    }
+    if token.f != filter {
+        return Err(new_cursor_error(
+            "Cursor does not match the current filter",
+            "cursor_mismatch",
+        ));
+    }
+    Ok(Cursor {
+        id: token.v,
+        prev: token.p,
+    })
+}
+
+/// 生成签名的cursor，对客户端不透明且不可篡改
+pub fn encode_cursor(cursor: Cursor, filter: &str) -> Result<String> {
+    encode_cursor_with(
+        &CURSOR_KEYGRIP,
+        cursor,
+        filter,
+        util::timestamp() + *CURSOR_TTL,
+    )
+}
+
+/// 校验并解析cursor，查询条件需与生成时一致
+pub fn decode_cursor(value: &str, filter: &str) -> Result<Cursor> {
+    decode_cursor_with(&CURSOR_KEYGRIP, value, filter, util::timestamp())
+}
+
+/// 列表的上一页及下一页cursor，无可查询的记录时为空
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct PageCursors {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_cursor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prev_cursor: Option<String>,
+}
+
+/// 根据当前页(已按id倒序)的首尾记录生成cursor，
+/// 向后翻页时若未满一页则无下一页，向前翻页时若未满一页则无上一页
+pub fn page_cursors(
+    cursor: Cursor,
+    first: Option<i64>,
+    last: Option<i64>,
+    full: bool,
+    filter: &str,
+) -> Result<PageCursors> {
+    let (next, prev) = if cursor.prev {
+        (Some(last.unwrap_or(cursor.id)), first.filter(|_| full))
+    } else {
+        (last.filter(|_| full), Some(first.unwrap_or(cursor.id)))
+    };
+    let encode = |id: Option<i64>, prev: bool| {
+        id.map(|id| encode_cursor(Cursor { id, prev }, filter))
+            .transpose()
+    };
+    Ok(PageCursors {
+        next_cursor: encode(next, false)?,
+        prev_cursor: encode(prev, true)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{cursor_filter_hash, decode_cursor_with, encode_cursor_with, Cursor};
+    use crate::keygrip::KeyGrip;
+    use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn cursor() {
+        let keygrip = KeyGrip::new(vec![b"secret".to_vec()]);
+        let filter = cursor_filter_hash("users", &Some("tree".to_string()));
+        let cursor = Cursor {
+            id: 123,
+            prev: true,
+        };
+        let value = encode_cursor_with(&keygrip, cursor, &filter, 1000).unwrap();
+        assert_eq!(
+            cursor,
+            decode_cursor_with(&keygrip, &value, &filter, 900).unwrap()
+        );
+
+        // 过期
+        let err = decode_cursor_with(&keygrip, &value, &filter, 1001).unwrap_err();
+        assert_eq!("cursor_expired", err.code);
+        // 查询条件不一致
+        let other = cursor_filter_hash("users", &None);
+        let err = decode_cursor_with(&keygrip, &value, &other, 900).unwrap_err();
+        assert_eq!("cursor_mismatch", err.code);
+        assert_eq!(400, err.status);
+        // 其它key签名的
+        let other_keygrip = KeyGrip::new(vec![b"other".to_vec()]);
+        let err = decode_cursor_with(&other_keygrip, &value, &filter, 900).unwrap_err();
+        assert_eq!("cursor_invalid", err.code);
+
+        // 篡改边界值
+        let digest = value.split_once('.').unwrap().1;
+        let data = format!(r#"{{"o":"-id","f":"{filter}","v":1,"p":true,"e":1000}}"#);
+        let forged = format!("{}.{digest}", URL_SAFE_NO_PAD.encode(data));
+        for item in ["", "abc", "aWQ6MTIz", &forged] {
+            let err = decode_cursor_with(&keygrip, item, &filter, 900).unwrap_err();
+            assert_eq!("cursor", err.category);
+            assert_eq!("cursor_invalid", err.code);
+        }
+    }
+}
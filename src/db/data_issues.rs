@@ -0,0 +1,328 @@
+use super::{
+    get_database, unscoped_find, Anonymize, EntitySensitivity, Result, ROLE_ADMIN, ROLE_READONLY,
+    ROLE_SU,
+};
+use crate::entities::data_issues::{ActiveModel, Column, Entity, Model};
+use crate::entities::{files, settings, tenants, users};
+use crate::error::HttpError;
+use crate::util::json_value_to_strings;
+use chrono::Utc;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::{entity::prelude::*, ActiveValue::Set, QueryOrder, QuerySelect};
+use serde::Deserialize;
+use std::collections::HashSet;
+
+// 数据校验为后台任务，不属于任何租户，
+// 按租户隔离的表均通过unscoped_find校验所有租户的数据
+
+// 每批校验的记录数
+const SCAN_BATCH_SIZE: u64 = 200;
+
+/// 支持数据校验的表
+pub static DATA_VALIDATION_ENTITIES: &[&str] = &["files", "settings", "users"];
+
+// 校验发现的问题
+struct Finding {
+    record_id: i64,
+    code: &'static str,
+    detail: String,
+}
+
+impl Finding {
+    fn new(record_id: i64, code: &'static str, detail: String) -> Self {
+        Finding {
+            record_id,
+            code,
+            detail,
+        }
+    }
+}
+
+// 返回已存在的账号
+async fn find_existing_accounts(accounts: Vec<String>) -> Result<HashSet<String>> {
+    if accounts.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let result: Vec<String> = unscoped_find::<users::Entity>()
+        .select_only()
+        .column(users::Column::Account)
+        .filter(users::Column::Account.is_in(accounts))
+        .into_tuple()
+        .all(get_database().await)
+        .await?;
+    Ok(result.into_iter().collect())
+}
+
+// 返回已存在的租户
+async fn find_existing_tenants(ids: Vec<i64>) -> Result<HashSet<i64>> {
+    if ids.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let result: Vec<i64> = tenants::Entity::find()
+        .select_only()
+        .column(tenants::Column::Id)
+        .filter(tenants::Column::Id.is_in(ids))
+        .into_tuple()
+        .all(get_database().await)
+        .await?;
+    Ok(result.into_iter().collect())
+}
+
+// 校验文件，文件数据较大，仅查询数据长度
+async fn check_files(after: i64) -> Result<(Option<i64>, Vec<Finding>)> {
+    let rows: Vec<(i64, i64, String, String, i64, i64)> = unscoped_find::<files::Entity>()
+        .select_only()
+        .column(files::Column::Id)
+        .column(files::Column::Size)
+        .column(files::Column::ContentType)
+        .column(files::Column::Creator)
+        .column(files::Column::TenantId)
+        .column_as(Expr::cust("LENGTH(`data`)"), "data_length")
+        .filter(files::Column::Id.gt(after))
+        .order_by_asc(files::Column::Id)
+        .limit(SCAN_BATCH_SIZE)
+        .into_tuple()
+        .all(get_database().await)
+        .await?;
+    let accounts = find_existing_accounts(rows.iter().map(|item| item.3.clone()).collect()).await?;
+    let tenants = find_existing_tenants(rows.iter().map(|item| item.4).collect()).await?;
+    let mut findings = vec![];
+    for (id, size, content_type, creator, tenant_id, data_length) in rows.iter() {
+        if *data_length == 0 {
+            findings.push(Finding::new(
+                *id,
+                "file_data_missing",
+                "data is empty".to_string(),
+            ));
+        } else if size != data_length {
+            findings.push(Finding::new(
+                *id,
+                "file_size_mismatch",
+                format!("size is {size}, but data length is {data_length}"),
+            ));
+        }
+        if content_type.is_empty() {
+            findings.push(Finding::new(
+                *id,
+                "file_content_type_missing",
+                "content type is empty".to_string(),
+            ));
+        }
+        if !accounts.contains(creator) {
+            findings.push(Finding::new(
+                *id,
+                "creator_missing",
+                format!("creator {creator} is not exists"),
+            ));
+        }
+        if !tenants.contains(tenant_id) {
+            findings.push(Finding::new(
+                *id,
+                "tenant_missing",
+                format!("tenant {tenant_id} is not exists"),
+            ));
+        }
+    }
+    Ok((rows.last().map(|item| item.0), findings))
+}
+
+async fn check_settings(after: i64) -> Result<(Option<i64>, Vec<Finding>)> {
+    let rows = settings::Entity::find()
+        .filter(settings::Column::Id.gt(after))
+        .order_by_asc(settings::Column::Id)
+        .limit(SCAN_BATCH_SIZE)
+        .all(get_database().await)
+        .await?;
+    let accounts =
+        find_existing_accounts(rows.iter().map(|item| item.creator.clone()).collect()).await?;
+    let mut findings = vec![];
+    for item in rows.iter() {
+        if item.started_at > item.ended_at {
+            findings.push(Finding::new(
+                item.id,
+                "setting_invalid_period",
+                format!(
+                    "started at {} is after ended at {}",
+                    item.started_at, item.ended_at
+                ),
+            ));
+        }
+        if !accounts.contains(&item.creator) {
+            findings.push(Finding::new(
+                item.id,
+                "creator_missing",
+                format!("creator {} is not exists", item.creator),
+            ));
+        }
+    }
+    Ok((rows.last().map(|item| item.id), findings))
+}
+
+async fn check_users(after: i64) -> Result<(Option<i64>, Vec<Finding>)> {
+    let rows = unscoped_find::<users::Entity>()
+        .filter(users::Column::Id.gt(after))
+        .order_by_asc(users::Column::Id)
+        .limit(SCAN_BATCH_SIZE)
+        .all(get_database().await)
+        .await?;
+    let tenants = find_existing_tenants(rows.iter().map(|item| item.tenant_id).collect()).await?;
+    let merged_ids: Vec<i64> = rows.iter().filter_map(|item| item.merged_into).collect();
+    let merged_targets: HashSet<i64> = if merged_ids.is_empty() {
+        HashSet::new()
+    } else {
+        unscoped_find::<users::Entity>()
+            .select_only()
+            .column(users::Column::Id)
+            .filter(users::Column::Id.is_in(merged_ids))
+            .into_tuple::<i64>()
+            .all(get_database().await)
+            .await?
+            .into_iter()
+            .collect()
+    };
+    let roles = [ROLE_SU, ROLE_ADMIN, ROLE_READONLY];
+    let mut findings = vec![];
+    for item in rows.iter() {
+        if let Some(value) = &item.roles {
+            match json_value_to_strings(value) {
+                Ok(values) => {
+                    for role in values.unwrap_or_default().iter() {
+                        if !roles.contains(&role.as_str()) {
+                            findings.push(Finding::new(
+                                item.id,
+                                "user_unknown_role",
+                                format!("role {role} is unknown"),
+                            ));
+                        }
+                    }
+                }
+                Err(err) => findings.push(Finding::new(item.id, "user_invalid_roles", err.message)),
+            }
+        }
+        if let Some(value) = &item.groups {
+            if let Err(err) = json_value_to_strings(value) {
+                findings.push(Finding::new(item.id, "user_invalid_groups", err.message));
+            }
+        }
+        if let Some(id) = item.merged_into {
+            if !merged_targets.contains(&id) {
+                findings.push(Finding::new(
+                    item.id,
+                    "user_merged_target_missing",
+                    format!("merged user {id} is not exists"),
+                ));
+            }
+        }
+        if !tenants.contains(&item.tenant_id) {
+            findings.push(Finding::new(
+                item.id,
+                "tenant_missing",
+                format!("tenant {} is not exists", item.tenant_id),
+            ));
+        }
+    }
+    Ok((rows.last().map(|item| item.id), findings))
+}
+
+// 保存发现的问题，已存在的更新最近发现时间
+async fn save_findings(entity: &str, findings: Vec<Finding>) -> Result<()> {
+    if findings.is_empty() {
+        return Ok(());
+    }
+    let now = Utc::now();
+    let models = findings.into_iter().map(|item| ActiveModel {
+        entity: Set(entity.to_string()),
+        record_id: Set(item.record_id),
+        code: Set(item.code.to_string()),
+        detail: Set(item.detail),
+        first_seen_at: Set(now),
+        last_seen_at: Set(now),
+        created_at: Set(now),
+        updated_at: Set(now),
+        ..Default::default()
+    });
+    Entity::insert_many(models)
+        .on_conflict(
+            OnConflict::columns([Column::Entity, Column::RecordId, Column::Code])
+                .update_columns([Column::Detail, Column::LastSeenAt, Column::UpdatedAt])
+                .to_owned(),
+        )
+        .exec(get_database().await)
+        .await?;
+    Ok(())
+}
+
+/// 分批校验该表的所有记录，将问题记录至data_issues，
+/// 本次未再发现的问题则认为已解决并清除。仅记录问题，不修改数据
+pub async fn scan_data_issues(entity: &str) -> Result<u64> {
+    if !DATA_VALIDATION_ENTITIES.contains(&entity) {
+        return Err(HttpError::new(&format!("{entity} is not supported")));
+    }
+    let started_at = Utc::now();
+    let mut after = 0;
+    let mut count = 0;
+    loop {
+        let (last, findings) = match entity {
+            "files" => check_files(after).await?,
+            "settings" => check_settings(after).await?,
+            _ => check_users(after).await?,
+        };
+        count += findings.len() as u64;
+        save_findings(entity, findings).await?;
+        let Some(last) = last else {
+            break;
+        };
+        after = last;
+    }
+    Entity::delete_many()
+        .filter(Column::Entity.eq(entity))
+        .filter(Column::LastSeenAt.lt(started_at))
+        .exec(get_database().await)
+        .await?;
+    Ok(count)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DataIssueListParams {
+    pub entity: Option<String>,
+    pub code: Option<String>,
+    pub page: u64,
+    pub page_size: u64,
+}
+
+/// 查询数据问题，按最近发现时间倒序
+pub async fn list_data_issues(params: &DataIssueListParams) -> Result<(u64, Vec<Model>)> {
+    if params.page_size == 0 {
+        return Err(HttpError::new("每页记录数不能为0"));
+    }
+    let mut sql = Entity::find();
+    if let Some(entity) = &params.entity {
+        sql = sql.filter(Column::Entity.eq(entity));
+    }
+    if let Some(code) = &params.code {
+        sql = sql.filter(Column::Code.eq(code));
+    }
+    let paginator = sql
+        .order_by_desc(Column::LastSeenAt)
+        .paginate(get_database().await, params.page_size);
+    let count = paginator.num_items().await?;
+    let items = paginator.fetch_page(params.page).await?;
+    Ok((count, items))
+}
+
+/// 数据问题的敏感字段，问题描述中可能包含账号等数据
+pub fn data_issue_sensitivity() -> EntitySensitivity {
+    EntitySensitivity::new(
+        &[(Column::Detail, Anonymize::Redact)],
+        &[
+            Column::Id,
+            Column::CreatedAt,
+            Column::UpdatedAt,
+            Column::Entity,
+            Column::RecordId,
+            Column::Code,
+            Column::FirstSeenAt,
+            Column::LastSeenAt,
+        ],
+    )
+}
@@ -1,24 +1,228 @@
 use super::CommonEntity;
 use super::{
-    get_database, EntityDescription, EntityItemCategory, EntityItemDescription, Error,
-    ListCountParams, Result, ROLE_SU,
+    find_valid_settings_by_category, get_database, guarded_count, guarded_fetch_page, Anonymize,
+    EntityDescription, EntityItemCategory, EntityItemDescription, EntityProfiles,
+    EntitySensitivity, Error, ListCountParams, Result, ROLE_SU,
 };
+use crate::cache::get_default_redis_cache;
+use crate::config::must_new_basic_config;
 use crate::entities::files::{ActiveModel, Column, Entity, Model};
-use crate::util::json_get_string;
+use crate::error::HttpError;
+use crate::util::{
+    format_bytes, image_dimensions, is_content_type_compatible, json_get_string, sha256,
+    sniff_content_type, HumanBytes,
+};
+use base64::{engine::general_purpose::STANDARD, Engine as _};
 use db_entity_derive::DbEntity;
 use once_cell::sync::Lazy;
 use sea_orm::query::{Order, Select};
 use sea_orm::ColumnTrait;
 use sea_orm::Condition;
+use sea_orm::FromQueryResult;
 use sea_orm::QuerySelect;
-use sea_orm::{entity::prelude::*, ActiveValue::Set, QueryOrder};
+use sea_orm::{entity::prelude::*, ActiveValue, ActiveValue::Set, QueryOrder};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::str::FromStr;
+use std::time::Duration;
 use substring::Substring;
 
 static SUPPORT_ORDERS: Lazy<Vec<Column>> =
     Lazy::new(|| vec![Column::Id, Column::Name, Column::UpdatedAt]);
 
+// 文件内容与类型不一致时是否使用识别的类型，否则拒绝
+static TRUST_SNIFFED_TYPE: Lazy<bool> =
+    Lazy::new(|| must_new_basic_config().file_type_mismatch == "trust");
+// 未配置上传策略时文件的最大字节数
+static FILE_MAX_SIZE: Lazy<u64> = Lazy::new(|| must_new_basic_config().file_max_size);
+
+/// 文件数据为base64时解码，否则为原始数据
+pub fn decode_file_data(data: &str) -> Vec<u8> {
+    STANDARD
+        .decode(data)
+        .unwrap_or_else(|_| data.as_bytes().to_vec())
+}
+
+/// 文件内容的sha256，base64的数据以解码后的内容计算
+pub fn get_file_hash(data: &str) -> String {
+    sha256(&decode_file_data(data))
+}
+
+// 根据文件内容确定类型，声明的类型(或根据文件名获取的类型)
+// 与内容不一致时拒绝或使用识别的类型，避免如html伪装为pdf
+fn resolve_content_type(name: &str, declared: Option<String>, data: &str) -> Result<String> {
+    let expected = declared
+        .filter(|item| !item.is_empty())
+        .or_else(|| {
+            mime_guess::from_path(name)
+                .first_raw()
+                .map(|item| item.to_string())
+        })
+        .unwrap_or("application/octet-stream".to_string());
+    let Some(sniffed) = sniff_content_type(&decode_file_data(data)) else {
+        return Ok(expected);
+    };
+    if is_content_type_compatible(&expected, sniffed) {
+        return Ok(expected);
+    }
+    if *TRUST_SNIFFED_TYPE {
+        return Ok(sniffed.to_string());
+    }
+    Err(HttpError::new_with_category(
+        &format!("File content is {sniffed}, but type is {expected}"),
+        "file_type_mismatch",
+    ))
+}
+
+/// 文件上传策略对应的配置分类，配置名称为分组
+pub static UPLOAD_POLICY_CATEGORY: &str = "upload_policy";
+
+/// 分组的上传策略，类型与扩展名为空时不限制
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UploadPolicy {
+    pub max_size: Option<u64>,
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+}
+
+/// 文件的分组，为文件名中第一个`/`之前的部分，
+/// 如`avatar/tree.png`的分组为avatar，无则为空
+pub fn get_file_group(name: &str) -> &str {
+    name.split_once('/')
+        .map(|(group, _)| group)
+        .unwrap_or_default()
+}
+
+// 上传策略的缓存有效期，调整配置后最长在此时间后生效
+const UPLOAD_POLICY_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// 获取分组的上传策略，未配置时使用默认的大小限制
+pub async fn get_upload_policy(group: &str) -> Result<UploadPolicy> {
+    get_default_redis_cache()
+        .get_or_set_struct_zstd(
+            &format!("upload_policy:{group}"),
+            Some(UPLOAD_POLICY_CACHE_TTL),
+            || load_upload_policy(group),
+        )
+        .await
+}
+
+async fn load_upload_policy(group: &str) -> Result<UploadPolicy> {
+    let settings = find_valid_settings_by_category(UPLOAD_POLICY_CATEGORY).await?;
+    let policy = settings
+        .into_iter()
+        .find(|item| item.name == group)
+        .map(|item| {
+            serde_json::from_str::<UploadPolicy>(&item.data).map_err(|err| {
+                HttpError::new_with_category(
+                    &format!("Upload policy of group {group} is invalid, {err}"),
+                    "upload_policy",
+                )
+            })
+        })
+        .transpose()?;
+    Ok(policy.unwrap_or_default())
+}
+
+// 校验文件是否符合上传策略，超出大小返回413，类型不允许返回415
+fn check_upload_policy(
+    group: &str,
+    policy: &UploadPolicy,
+    default_max_size: u64,
+    name: &str,
+    content_type: &str,
+    data: &[u8],
+) -> Result<()> {
+    let group = if group.is_empty() { "default" } else { group };
+    let max_size = policy.max_size.unwrap_or(default_max_size);
+    if data.len() as u64 > max_size {
+        return Err(HttpError::new_with_category_status(
+            &format!(
+                "File size {} exceeds the limit {} of group {group}",
+                format_bytes(data.len() as u64, true),
+                format_bytes(max_size, true)
+            ),
+            "upload_policy",
+            413,
+        ));
+    }
+    let content_type = content_type.split(';').next().unwrap_or_default().trim();
+    if !policy.content_types.is_empty()
+        && !policy
+            .content_types
+            .iter()
+            .any(|item| item.eq_ignore_ascii_case(content_type))
+    {
+        return Err(HttpError::new_with_category_status(
+            &format!(
+                "Content type {content_type} is not allowed in group {group}, allowed: {}",
+                policy.content_types.join(",")
+            ),
+            "upload_policy",
+            415,
+        ));
+    }
+    let ext = name
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_lowercase())
+        .unwrap_or_default();
+    if !policy.extensions.is_empty()
+        && !policy
+            .extensions
+            .iter()
+            .any(|item| item.trim_start_matches('.').eq_ignore_ascii_case(&ext))
+    {
+        return Err(HttpError::new_with_category_status(
+            &format!(
+                "Extension .{ext} is not allowed in group {group}, allowed: {}",
+                policy.extensions.join(",")
+            ),
+            "upload_policy",
+            415,
+        ));
+    }
+    if policy.max_width.is_some() || policy.max_height.is_some() {
+        if let Some((width, height)) = image_dimensions(data) {
+            let max_width = policy.max_width.unwrap_or(u32::MAX);
+            let max_height = policy.max_height.unwrap_or(u32::MAX);
+            if width > max_width || height > max_height {
+                return Err(HttpError::new_with_category_status(
+                    &format!(
+                        "Image {width}x{height} exceeds the limit {}x{} of group {group}",
+                        max_width, max_height
+                    ),
+                    "upload_policy",
+                    413,
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 文件的基本信息(不含文件数据)，用于判断客户端缓存是否有效
+#[derive(Debug, Clone, FromQueryResult)]
+pub struct FileMeta {
+    pub id: i64,
+    pub hash: String,
+    pub updated_at: DateTimeUtc,
+}
+
+impl FileMeta {
+    /// 文件的ETag，未记录hash的文件以更新时间生成
+    pub fn entity_tag(&self) -> String {
+        if self.hash.is_empty() {
+            format!(r#""{}-{:x}""#, self.id, self.updated_at.timestamp())
+        } else {
+            format!(r#""{}""#, &self.hash[..self.hash.len().min(16)])
+        }
+    }
+}
+
 #[derive(DbEntity)]
 pub struct FileEntity {}
 impl CommonEntity for FileEntity {}
@@ -27,15 +231,33 @@ impl FileEntity {
     fn get_support_orders() -> Vec<Column> {
         SUPPORT_ORDERS.to_vec()
     }
+    fn get_unique_keys() -> Vec<Vec<Column>> {
+        vec![vec![Column::Name]]
+    }
+    // 租户隔离的表返回租户字段
+    fn get_tenant_column() -> Option<Column> {
+        Some(Column::TenantId)
+    }
     fn update_from_value(model: &mut ActiveModel, value: &Value) -> Result<()> {
         if let Some(name) = json_get_string(value, Column::Name.as_str())? {
             model.name = Set(name);
         }
-        if let Some(content_type) = json_get_string(value, Column::ContentType.as_str())? {
-            model.content_type = Set(content_type);
-        }
+        let content_type = json_get_string(value, Column::ContentType.as_str())?;
         if let Some(data) = json_get_string(value, Column::Data.as_str())? {
+            let name = match &model.name {
+                ActiveValue::Set(name) | ActiveValue::Unchanged(name) => name.clone(),
+                ActiveValue::NotSet => "".to_string(),
+            };
+            model.content_type = Set(resolve_content_type(&name, content_type, &data)?);
+            model.hash = Set(get_file_hash(&data));
             model.data = Set(data)
+        } else if let Some(content_type) = content_type {
+            // 仅修改类型时也需要与已有内容一致
+            if let ActiveValue::Unchanged(data) = &model.data {
+                model.content_type = Set(resolve_content_type("", Some(content_type), data)?);
+            } else {
+                model.content_type = Set(content_type);
+            }
         }
 
         Ok(())
@@ -50,6 +272,116 @@ impl FileEntity {
             None
         }
     }
+    /// 按文件分组的上传策略校验提交的数据，未提交文件数据时忽略，
+    /// 修改时若未提交文件名则使用原有的文件名
+    pub async fn validate_upload(value: &Value, id: Option<i64>) -> Result<()> {
+        let Some(data) = json_get_string(value, Column::Data.as_str())? else {
+            return Ok(());
+        };
+        let name = match json_get_string(value, Column::Name.as_str())? {
+            Some(name) => name,
+            None => match id {
+                Some(id) => Self::find_file(id)
+                    .await?
+                    .map(|item| item.name)
+                    .unwrap_or_default(),
+                None => "".to_string(),
+            },
+        };
+        let content_type = resolve_content_type(
+            &name,
+            json_get_string(value, Column::ContentType.as_str())?,
+            &data,
+        )?;
+        let group = get_file_group(&name);
+        let policy = get_upload_policy(group).await?;
+        check_upload_policy(
+            group,
+            &policy,
+            *FILE_MAX_SIZE,
+            &name,
+            &content_type,
+            &decode_file_data(&data),
+        )
+    }
+    /// 获取文件的基本信息，不查询文件数据
+    pub async fn find_file_meta(id: i64) -> Result<Option<FileMeta>> {
+        let result = Self::scope(Entity::find_by_id(id))?
+            .select_only()
+            .columns([Column::Id, Column::Hash, Column::UpdatedAt])
+            .into_model::<FileMeta>()
+            .one(get_database().await)
+            .await?;
+        Ok(result)
+    }
+    /// 获取文件，用于下载文件内容
+    pub async fn find_file(id: i64) -> Result<Option<Model>> {
+        let result = Self::scope(Entity::find_by_id(id))?
+            .one(get_database().await)
+            .await?;
+        Ok(result)
+    }
+    /// 查询当前租户中相同内容的文件，未记录hash的文件不会匹配
+    pub async fn find_by_hash(user: &str, hash: &str) -> Result<Option<Model>> {
+        Self::validate_for_insert(user).await?;
+        if hash.is_empty() {
+            return Ok(None);
+        }
+        let result = Self::scope(Entity::find())?
+            .filter(Column::Hash.eq(hash))
+            .order_by_asc(Column::Id)
+            .one(get_database().await)
+            .await?;
+        Ok(result)
+    }
+    /// 添加格式化后的文件大小，原有的size保持不变
+    pub fn humanize(mut value: Value) -> Value {
+        let size = value.get(Column::Size.as_str()).and_then(Value::as_u64);
+        if let (Some(size), Some(map)) = (size, value.as_object_mut()) {
+            map.insert(
+                "size_human".to_string(),
+                serde_json::to_value(HumanBytes(size)).unwrap_or_default(),
+            );
+        }
+        value
+    }
+    pub fn sensitivity() -> EntitySensitivity {
+        // 文件内容清空，保留hash便于排查去重相关问题
+        EntitySensitivity::new(
+            &[
+                (Column::Name, Anonymize::Hash),
+                (Column::Data, Anonymize::Empty),
+                (Column::Updater, Anonymize::Account),
+                (Column::Creator, Anonymize::Account),
+            ],
+            &[
+                Column::Id,
+                Column::CreatedAt,
+                Column::UpdatedAt,
+                Column::Size,
+                Column::ContentType,
+                Column::Hash,
+                Column::TenantId,
+            ],
+        )
+    }
+    pub fn profiles() -> EntityProfiles {
+        let list = [
+            Column::Id,
+            Column::Name,
+            Column::Size,
+            Column::ContentType,
+            Column::Creator,
+            Column::CreatedAt,
+            Column::UpdatedAt,
+        ];
+        // 文件数据较大，仅详情返回
+        let mut export = list.to_vec();
+        export.extend([Column::Hash, Column::Updater, Column::TenantId]);
+        let mut detail = export.clone();
+        detail.push(Column::Data);
+        EntityProfiles::new(&list, &detail, &export, &[])
+    }
     pub fn description() -> EntityDescription {
         let items = vec![
             EntityItemDescription {
@@ -118,3 +450,60 @@ impl FileEntity {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check_upload_policy, get_file_group, UploadPolicy};
+    use pretty_assertions::assert_eq;
+    #[test]
+    fn upload_policy() {
+        assert_eq!("avatar", get_file_group("avatar/tree.png"));
+        assert_eq!("", get_file_group("tree.png"));
+
+        let policy = UploadPolicy {
+            max_size: Some(4),
+            extensions: vec![".png".to_string(), "jpg".to_string()],
+            ..Default::default()
+        };
+        let err = check_upload_policy(
+            "avatar",
+            &policy,
+            100,
+            "avatar/a.png",
+            "image/png",
+            b"12345",
+        )
+        .unwrap_err();
+        assert_eq!(413, err.status);
+        assert_eq!(
+            "File size 5 B exceeds the limit 4 B of group avatar",
+            err.message
+        );
+
+        let err =
+            check_upload_policy("avatar", &policy, 100, "avatar/a.exe", "", b"1").unwrap_err();
+        assert_eq!(415, err.status);
+        assert_eq!(
+            "Extension .exe is not allowed in group avatar, allowed: .png,jpg",
+            err.message
+        );
+        assert_eq!(
+            true,
+            check_upload_policy("avatar", &policy, 100, "avatar/a.JPG", "", b"1").is_ok()
+        );
+
+        // 未配置策略的分组使用默认限制
+        let policy = UploadPolicy::default();
+        assert_eq!(
+            true,
+            check_upload_policy("", &policy, 4, "a.exe", "application/x-msdownload", b"1234")
+                .is_ok()
+        );
+        let err = check_upload_policy("", &policy, 4, "a.txt", "text/plain", b"12345").unwrap_err();
+        assert_eq!(413, err.status);
+        assert_eq!(
+            "File size 5 B exceeds the limit 4 B of group default",
+            err.message
+        );
+    }
+}
@@ -0,0 +1,156 @@
+use super::{diff_json, Result};
+use crate::task_local::TRACE_ID;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use serde_json::Value;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use tracing::{error, info};
+
+/// 注册为此名称的处理对所有表生效
+pub const ALL_ENTITIES: &str = "*";
+
+/// 触发处理的上下文
+#[derive(Debug, Clone, Default)]
+pub struct HookContext {
+    pub entity: String,
+    pub user: String,
+    pub trace_id: String,
+}
+
+/// 数据变更成功后的处理，如清除缓存、记录变更等。
+/// 处理均异步执行，出错仅记录日志，不影响请求的结果。
+/// 数据均禁止删除，因此无删除后的处理
+#[async_trait]
+pub trait EntityHooks: Send + Sync {
+    async fn after_insert(&self, _ctx: &HookContext, _id: i64, _value: &Value) -> Result<()> {
+        Ok(())
+    }
+    async fn after_update(
+        &self,
+        _ctx: &HookContext,
+        _id: i64,
+        _old: &Value,
+        _new: &Value,
+    ) -> Result<()> {
+        Ok(())
+    }
+}
+
+enum HookEvent {
+    Insert { id: i64, value: Value },
+    Update { id: i64, old: Value, new: Value },
+}
+
+type HookRegistry = Vec<(String, Arc<dyn EntityHooks>)>;
+
+static ENTITY_HOOKS: Lazy<RwLock<HookRegistry>> = Lazy::new(|| {
+    let hooks: HookRegistry = vec![(ALL_ENTITIES.to_string(), Arc::new(AuditHooks {}))];
+    RwLock::new(hooks)
+});
+// 处理失败的次数
+static HOOK_FAILURES: AtomicU64 = AtomicU64::new(0);
+
+/// 注册表的变更处理，一般在启动时注册
+pub fn register_entity_hooks(entity: &str, hooks: Arc<dyn EntityHooks>) {
+    if let Ok(mut items) = ENTITY_HOOKS.write() {
+        items.push((entity.to_string(), hooks));
+    }
+}
+
+/// 变更处理失败的次数
+pub fn get_hook_failures() -> u64 {
+    HOOK_FAILURES.load(Ordering::Relaxed)
+}
+
+fn get_entity_hooks(entity: &str) -> Vec<Arc<dyn EntityHooks>> {
+    let Ok(items) = ENTITY_HOOKS.read() else {
+        return vec![];
+    };
+    items
+        .iter()
+        .filter(|(name, _)| name == ALL_ENTITIES || name == entity)
+        .map(|(_, hooks)| hooks.clone())
+        .collect()
+}
+
+pub(super) fn has_entity_hooks(entity: &str) -> bool {
+    !get_entity_hooks(entity).is_empty()
+}
+
+fn run_hooks(entity: &str, user: &str, event: HookEvent) {
+    let hooks = get_entity_hooks(entity);
+    if hooks.is_empty() {
+        return;
+    }
+    let ctx = HookContext {
+        entity: entity.to_string(),
+        user: user.to_string(),
+        trace_id: TRACE_ID.try_with(|id| id.clone()).unwrap_or_default(),
+    };
+    tokio::spawn(async move {
+        for hook in hooks.iter() {
+            let result = match &event {
+                HookEvent::Insert { id, value } => hook.after_insert(&ctx, *id, value).await,
+                HookEvent::Update { id, old, new } => hook.after_update(&ctx, *id, old, new).await,
+            };
+            if let Err(err) = result {
+                HOOK_FAILURES.fetch_add(1, Ordering::Relaxed);
+                error!(
+                    category = "entity_hook",
+                    entity = ctx.entity,
+                    traceId = ctx.trace_id,
+                    error = err.message,
+                );
+            }
+        }
+    });
+}
+
+pub(super) fn run_insert_hooks(entity: &str, user: &str, id: i64, value: Value) {
+    run_hooks(entity, user, HookEvent::Insert { id, value });
+}
+
+pub(super) fn run_update_hooks(entity: &str, user: &str, id: i64, old: Value, new: Value) {
+    run_hooks(entity, user, HookEvent::Update { id, old, new });
+}
+
+// 记录数据的变更
+struct AuditHooks {}
+
+#[async_trait]
+impl EntityHooks for AuditHooks {
+    async fn after_insert(&self, ctx: &HookContext, id: i64, _value: &Value) -> Result<()> {
+        info!(
+            category = "entity_audit",
+            traceId = ctx.trace_id,
+            operator = ctx.user,
+            entity = ctx.entity,
+            action = "insert",
+            id,
+        );
+        Ok(())
+    }
+    async fn after_update(
+        &self,
+        ctx: &HookContext,
+        id: i64,
+        old: &Value,
+        new: &Value,
+    ) -> Result<()> {
+        let fields: Vec<String> = diff_json(old, new)
+            .into_iter()
+            .map(|item| item.field)
+            .collect();
+        info!(
+            category = "entity_audit",
+            traceId = ctx.trace_id,
+            operator = ctx.user,
+            entity = ctx.entity,
+            action = "update",
+            id,
+            fields = fields.join(","),
+        );
+        Ok(())
+    }
+}
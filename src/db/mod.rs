@@ -4,20 +4,49 @@ use sea_orm::{ColumnTrait, Condition};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use snafu::Snafu;
+use utoipa::IntoParams;
 
+pub use anonymize::*;
+pub use batch::*;
+pub use client_errors::*;
 pub use conn::get_database;
+pub use cursor::*;
+pub use data_issues::*;
 pub use files::*;
+pub use hooks::*;
+pub use profile::*;
+pub use query::*;
+pub use reassign::*;
+pub use request_archives::*;
 pub use settings::*;
+pub use tasks::*;
+pub use tenants::*;
 pub use users::*;
 
 pub type Result<T, E = HttpError> = std::result::Result<T, E>;
 
 pub static ROLE_SU: &str = "su";
 pub static ROLE_ADMIN: &str = "admin";
+// 只读角色，仅允许查询数据
+pub static ROLE_READONLY: &str = "readonly";
+// 内部服务角色，通过签名校验的请求使用
+pub static ROLE_SERVICE: &str = "service";
 
+mod anonymize;
+mod batch;
+mod client_errors;
 mod conn;
+mod cursor;
+mod data_issues;
 mod files;
+mod hooks;
+mod profile;
+mod query;
+mod reassign;
+mod request_archives;
 mod settings;
+mod tasks;
+mod tenants;
 mod users;
 
 #[async_trait]
@@ -45,22 +74,94 @@ pub enum Error {
     NotFound,
     #[snafu(display("Order by {order} is unsupported"))]
     OrderNotSupport { order: String },
+    #[snafu(display("Record already exists"))]
+    Conflict { fields: Vec<String> },
+    #[snafu(display("Record has been modified"))]
+    Outdated { items: Vec<FieldDiff> },
+    #[snafu(display("Updated at is required"))]
+    PreconditionRequired,
+    #[snafu(display("Tenant is required"))]
+    TenantRequired,
 }
 
 impl From<Error> for HttpError {
     fn from(value: Error) -> Self {
-        HttpError::new_with_category(&value.to_string(), "db")
+        match value {
+            Error::Conflict { ref fields } => {
+                let mut he = HttpError::new_with_category_status(&value.to_string(), "db", 409);
+                he.code = "conflict".to_string();
+                for field in fields.iter() {
+                    he.add_extra(field);
+                }
+                he
+            }
+            Error::Outdated { ref items } => {
+                let mut he = HttpError::new_with_category_status(&value.to_string(), "db", 409);
+                he.code = "outdated".to_string();
+                for item in items.iter() {
+                    he.add_extra(&serde_json::to_string(item).unwrap_or_default());
+                }
+                he
+            }
+            Error::PreconditionRequired => {
+                let mut he = HttpError::new_with_category_status(&value.to_string(), "db", 428);
+                he.code = "precondition_required".to_string();
+                he
+            }
+            Error::TenantRequired => {
+                let mut he = HttpError::new_with_category_status(&value.to_string(), "db", 403);
+                he.code = "tenant_required".to_string();
+                he
+            }
+            _ => HttpError::new_with_category(&value.to_string(), "db"),
+        }
+    }
+}
+
+/// 字段的修改差异
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldDiff {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+/// 对比两个对象，返回after中与before不一致的字段
+pub fn diff_json(before: &Value, after: &Value) -> Vec<FieldDiff> {
+    let Some(values) = after.as_object() else {
+        return vec![];
+    };
+    let mut items = vec![];
+    for (field, value) in values.iter() {
+        let current = before.get(field).cloned().unwrap_or_default();
+        if &current != value {
+            items.push(FieldDiff {
+                field: field.to_string(),
+                before: current,
+                after: value.clone(),
+            });
+        }
     }
+    items
 }
 
-#[derive(Debug, Deserialize, Clone)]
+#[derive(Debug, Deserialize, Clone, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct ListCountParams {
     // pub table: String,
     pub orders: Option<String>,
     pub keyword: Option<String>,
     pub page: u64,
+    #[param(minimum = 1)]
     pub page_size: u64,
     pub counted: bool,
+    // 仅返回的字段，逗号分隔
+    pub fields: Option<String>,
+    // 上次返回的cursor，指定时按id倒序查询之前(或之后)的记录，忽略page与orders
+    pub cursor: Option<String>,
+    // 校验后的cursor，由cursor解析，不可由客户端指定
+    #[serde(skip)]
+    pub resolved_cursor: Option<Cursor>,
 }
 
 impl ListCountParams {
@@ -70,6 +171,72 @@ impl ListCountParams {
         }
         Ok(())
     }
+    pub fn get_fields(&self) -> Vec<String> {
+        parse_fields(&self.fields)
+    }
+    /// 已校验的cursor，需先调用resolve_cursor
+    pub fn get_cursor(&self) -> Option<Cursor> {
+        self.resolved_cursor
+    }
+    /// 校验cursor，其查询条件需与当前的一致
+    fn resolve_cursor(&self, name: &str) -> Result<Self> {
+        let filter = cursor_filter_hash(name, &self.keyword);
+        let resolved_cursor = self
+            .cursor
+            .as_deref()
+            .map(|value| decode_cursor(value, &filter))
+            .transpose()?;
+        Ok(ListCountParams {
+            resolved_cursor,
+            ..self.clone()
+        })
+    }
+    fn with_fields(&self, fields: &[String]) -> Self {
+        ListCountParams {
+            fields: Some(fields.join(",")),
+            ..self.clone()
+        }
+    }
+}
+
+/// 解析逗号分隔的字段列表
+pub fn parse_fields(value: &Option<String>) -> Vec<String> {
+    value
+        .as_ref()
+        .map(|value| {
+            value
+                .split(',')
+                .map(|item| item.trim().to_string())
+                .filter(|item| !item.is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// 未指定字段时使用该场景声明的字段
+fn resolve_fields(name: &str, fields: Vec<String>, profile: Profile) -> Result<Vec<String>> {
+    if fields.is_empty() {
+        return Ok(entity_profiles(name)?.get(profile).to_vec());
+    }
+    validate_fields(name, &fields)?;
+    Ok(fields)
+}
+
+// 指定的字段需在表的描述中，不在描述中的字段(如密码)不允许查询
+fn validate_fields(name: &str, fields: &[String]) -> Result<()> {
+    if fields.is_empty() {
+        return Ok(());
+    }
+    let description = description(name)?;
+    for field in fields.iter() {
+        if !description.items.iter().any(|item| &item.name == field) {
+            return Err(HttpError::new_with_category(
+                &format!("Field {field} is not supported"),
+                "fields",
+            ));
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Serialize, Default)]
@@ -77,6 +244,8 @@ pub struct EntityDescription {
     pub items: Vec<EntityItemDescription>,
     pub support_orders: Vec<String>,
     pub modify_roles: Vec<String>,
+    // 当前账号是否仅有只读权限
+    pub readonly: bool,
 }
 
 #[derive(Debug, Eq, PartialEq, Serialize, Default)]
@@ -112,29 +281,99 @@ pub struct EntityItemDescription {
     pub span: Option<u8>,
 }
 
-const TABLE_NAME_SETTINGS: &str = "settings";
-const TABLE_NAME_USERS: &str = "users";
+pub const TABLE_NAME_SETTINGS: &str = "settings";
+pub const TABLE_NAME_USERS: &str = "users";
 const TABLE_NAME_FILES: &str = "files";
+const TABLE_NAME_CLIENT_ERRORS: &str = "client_errors";
+const TABLE_NAME_TASKS: &str = "tasks";
 const TABLE_INVALID_MSG: &str = "Table is invalid";
 
+/// 查询列表，cursor模式下返回上一页及下一页的cursor，无更多记录时为空
 pub async fn list_count(
     name: &str,
     user: &str,
     params: &ListCountParams,
-) -> Result<(i64, Vec<Value>)> {
-    let result = match name {
+) -> Result<(i64, Vec<Value>, PageCursors)> {
+    let fields = resolve_fields(name, params.get_fields(), Profile::List)?;
+    let params = params.resolve_cursor(name)?;
+    let cursor = params.get_cursor();
+    // cursor模式需要id生成上一页及下一页的cursor
+    let mut query_fields = fields.clone();
+    if cursor.is_some() && !query_fields.iter().any(|item| item == "id") {
+        query_fields.push("id".to_string());
+    }
+    let params = &params.with_fields(&query_fields);
+    let (page_count, mut items) = match name {
         TABLE_NAME_SETTINGS => SettingEntity::list_count(user, params).await?,
         TABLE_NAME_FILES => FileEntity::list_count(user, params).await?,
         TABLE_NAME_USERS => UserEntity::list_count(user, params).await?,
+        TABLE_NAME_CLIENT_ERRORS => ClientErrorEntity::list_count(user, params).await?,
+        TABLE_NAME_TASKS => TaskEntity::list_count(user, params).await?,
         _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
     };
-    Ok(result)
+    let mut cursors = PageCursors::default();
+    if let Some(cursor) = cursor {
+        // 上一页按id正序查询，调整为与其它页一致的倒序
+        if cursor.prev {
+            items.reverse();
+        }
+        let get_id = |item: Option<&Value>| item.and_then(|item| item.get("id")?.as_i64());
+        cursors = page_cursors(
+            cursor,
+            get_id(items.first()),
+            get_id(items.last()),
+            items.len() as u64 == params.page_size,
+            &cursor_filter_hash(name, &params.keyword),
+        )?;
+    }
+    let items = items
+        .into_iter()
+        .map(|item| humanize(name, project_value(item, &fields)))
+        .collect();
+    Ok((page_count, items, cursors))
+}
+/// 导出时的字段，用于生成csv的表头
+pub fn export_fields(name: &str) -> Result<Vec<String>> {
+    resolve_fields(name, vec![], Profile::Export)
+}
+pub async fn list_after(
+    name: &str,
+    user: &str,
+    params: &ListCountParams,
+    after: i64,
+    limit: u64,
+) -> Result<Vec<Value>> {
+    let fields = resolve_fields(name, params.get_fields(), Profile::Export)?;
+    let params = &params.with_fields(&fields);
+    let result = match name {
+        TABLE_NAME_SETTINGS => SettingEntity::list_after(user, params, after, limit).await?,
+        TABLE_NAME_FILES => FileEntity::list_after(user, params, after, limit).await?,
+        TABLE_NAME_USERS => UserEntity::list_after(user, params, after, limit).await?,
+        TABLE_NAME_CLIENT_ERRORS => {
+            ClientErrorEntity::list_after(user, params, after, limit).await?
+        }
+        TABLE_NAME_TASKS => TaskEntity::list_after(user, params, after, limit).await?,
+        _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
+    };
+    Ok(result
+        .into_iter()
+        .map(|item| project_value(item, &fields))
+        .collect())
+}
+// 列表与详情中添加便于展示的字段，导出的数据则保持原样
+fn humanize(name: &str, value: Value) -> Value {
+    match name {
+        TABLE_NAME_FILES => FileEntity::humanize(value),
+        _ => value,
+    }
 }
 pub fn description(name: &str) -> Result<EntityDescription> {
     let result = match name {
         TABLE_NAME_SETTINGS => SettingEntity::description(),
         TABLE_NAME_USERS => UserEntity::description(),
         TABLE_NAME_FILES => FileEntity::description(),
+        TABLE_NAME_CLIENT_ERRORS => ClientErrorEntity::description(),
+        TABLE_NAME_TASKS => TaskEntity::description(),
         _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
     };
     Ok(result)
@@ -146,29 +385,108 @@ pub async fn add(name: &str, user: &str, value: &Value) -> Result<i64> {
             result.id
         }
         TABLE_NAME_FILES => {
+            FileEntity::validate_upload(value, None).await?;
             let result = FileEntity::insert(user, value).await?;
             result.id
         }
         _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
     };
+    if has_entity_hooks(name) {
+        // 查询失败则使用提交的数据
+        let current = find_by_id(name, user, id, &[]).await.ok().flatten();
+        run_insert_hooks(name, user, id, current.unwrap_or_else(|| value.clone()));
+    }
     Ok(id)
 }
-pub async fn find_by_id(name: &str, user: &str, id: i64) -> Result<Option<Value>> {
+/// 添加文件前查询是否已有相同内容的文件，返回已有文件的id与名称，
+/// 其它表均返回None
+pub async fn find_duplicate(
+    name: &str,
+    user: &str,
+    value: &Value,
+) -> Result<Option<(i64, String)>> {
+    if name != TABLE_NAME_FILES {
+        return Ok(None);
+    }
+    let Some(data) = crate::util::json_get_string(value, "data")? else {
+        return Ok(None);
+    };
+    let result = FileEntity::find_by_hash(user, &get_file_hash(&data)).await?;
+    Ok(result.map(|item| (item.id, item.name)))
+}
+pub async fn find_by_id(
+    name: &str,
+    user: &str,
+    id: i64,
+    fields: &[String],
+) -> Result<Option<Value>> {
+    let fields = &resolve_fields(name, fields.to_vec(), Profile::Detail)?;
     let result = match name {
-        TABLE_NAME_SETTINGS => SettingEntity::find_by_id(user, id).await?,
-        TABLE_NAME_USERS => UserEntity::find_by_id(user, id).await?,
-        TABLE_NAME_FILES => FileEntity::find_by_id(user, id).await?,
+        TABLE_NAME_SETTINGS => SettingEntity::find_by_id(user, id, fields).await?,
+        TABLE_NAME_USERS => UserEntity::find_by_id(user, id, fields).await?,
+        TABLE_NAME_FILES => FileEntity::find_by_id(user, id, fields).await?,
+        TABLE_NAME_CLIENT_ERRORS => ClientErrorEntity::find_by_id(user, id, fields).await?,
+        TABLE_NAME_TASKS => TaskEntity::find_by_id(user, id, fields).await?,
         _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
     };
-    Ok(result)
+    Ok(result.map(|item| humanize(name, project_value(item, fields))))
 }
 pub async fn update_by_id(name: &str, user: &str, id: i64, value: &Value) -> Result<()> {
+    // 变更处理需要修改前后的数据，以详情的字段查询
+    let old = if has_entity_hooks(name) {
+        find_by_id(name, user, id, &[]).await.ok().flatten()
+    } else {
+        None
+    };
+    update_by_id_with(get_database().await, name, user, id, value).await?;
+    if let Some(old) = old {
+        if let Ok(Some(new)) = find_by_id(name, user, id, &[]).await {
+            run_update_hooks(name, user, id, old, new);
+        }
+    }
+
+    Ok(())
+}
+// 使用指定的连接更新，不触发变更处理
+async fn update_by_id_with<C: sea_orm::ConnectionTrait>(
+    conn: &C,
+    name: &str,
+    user: &str,
+    id: i64,
+    value: &Value,
+) -> Result<()> {
     match name {
-        TABLE_NAME_SETTINGS => SettingEntity::update_by_id(user, id, value).await?,
-        TABLE_NAME_USERS => UserEntity::update_by_id(user, id, value).await?,
-        TABLE_NAME_FILES => FileEntity::update_by_id(user, id, value).await?,
+        TABLE_NAME_SETTINGS => SettingEntity::update_by_id_with(conn, user, id, value).await?,
+        TABLE_NAME_USERS => UserEntity::update_by_id_with(conn, user, id, value).await?,
+        TABLE_NAME_FILES => {
+            FileEntity::validate_upload(value, Some(id)).await?;
+            FileEntity::update_by_id_with(conn, user, id, value).await?
+        }
         _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
     };
-
     Ok(())
 }
+pub async fn preview_by_id(
+    name: &str,
+    user: &str,
+    id: i64,
+    value: &Value,
+) -> Result<Vec<FieldDiff>> {
+    let result = match name {
+        TABLE_NAME_SETTINGS => SettingEntity::preview_by_id(user, id, value).await?,
+        TABLE_NAME_FILES => FileEntity::preview_by_id(user, id, value).await?,
+        _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
+    };
+    Ok(result)
+}
+/// 删除该记录会影响的数据，其它记录均无依赖
+pub async fn delete_impact(name: &str, id: i64) -> Result<Vec<EntityDependent>> {
+    let result = match name {
+        TABLE_NAME_USERS => UserEntity::dependents(id).await?,
+        TABLE_NAME_SETTINGS | TABLE_NAME_FILES | TABLE_NAME_CLIENT_ERRORS | TABLE_NAME_TASKS => {
+            vec![]
+        }
+        _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
+    };
+    Ok(result)
+}
@@ -0,0 +1,158 @@
+use super::{
+    ClientErrorEntity, FileEntity, Result, SettingEntity, TaskEntity, UserEntity,
+    TABLE_INVALID_MSG, TABLE_NAME_CLIENT_ERRORS, TABLE_NAME_FILES, TABLE_NAME_SETTINGS,
+    TABLE_NAME_TASKS, TABLE_NAME_USERS,
+};
+use crate::error::HttpError;
+use sea_orm::{ColumnTrait, Iterable};
+use serde_json::Value;
+use tracing::warn;
+
+/// 数据的输出场景
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    // 列表
+    List,
+    // 详情
+    Detail,
+    // 导出
+    Export,
+}
+
+/// 各输出场景包含的字段，
+/// hidden为不在任何场景输出的字段(如密码)
+#[derive(Debug, Clone, Default)]
+pub struct EntityProfiles {
+    pub list: Vec<String>,
+    pub detail: Vec<String>,
+    pub export: Vec<String>,
+    pub hidden: Vec<String>,
+    // 表的所有字段
+    columns: Vec<String>,
+}
+
+fn column_names<C: ColumnTrait>(columns: &[C]) -> Vec<String> {
+    columns
+        .iter()
+        .map(|item| item.as_str().to_string())
+        .collect()
+}
+
+impl EntityProfiles {
+    pub fn new<C: ColumnTrait + Iterable>(
+        list: &[C],
+        detail: &[C],
+        export: &[C],
+        hidden: &[C],
+    ) -> Self {
+        EntityProfiles {
+            list: column_names(list),
+            detail: column_names(detail),
+            export: column_names(export),
+            hidden: column_names(hidden),
+            columns: C::iter().map(|item| item.as_str().to_string()).collect(),
+        }
+    }
+    pub fn get(&self, profile: Profile) -> &[String] {
+        match profile {
+            Profile::List => &self.list,
+            Profile::Detail => &self.detail,
+            Profile::Export => &self.export,
+        }
+    }
+    /// 未在任何场景中声明的字段，新增字段时需明确是否输出
+    pub fn undeclared(&self) -> Vec<String> {
+        self.columns
+            .iter()
+            .filter(|item| {
+                ![&self.list, &self.detail, &self.export, &self.hidden]
+                    .iter()
+                    .any(|fields| fields.contains(item))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+static PROFILE_ENTITIES: &[&str] = &[
+    TABLE_NAME_SETTINGS,
+    TABLE_NAME_USERS,
+    TABLE_NAME_FILES,
+    TABLE_NAME_CLIENT_ERRORS,
+    TABLE_NAME_TASKS,
+];
+
+pub fn entity_profiles(name: &str) -> Result<EntityProfiles> {
+    let result = match name {
+        TABLE_NAME_SETTINGS => SettingEntity::profiles(),
+        TABLE_NAME_USERS => UserEntity::profiles(),
+        TABLE_NAME_FILES => FileEntity::profiles(),
+        TABLE_NAME_CLIENT_ERRORS => ClientErrorEntity::profiles(),
+        TABLE_NAME_TASKS => TaskEntity::profiles(),
+        _ => return Err(HttpError::new(TABLE_INVALID_MSG)),
+    };
+    Ok(result)
+}
+
+/// 启动时检查各表是否有未声明输出场景的字段
+pub fn check_entity_profiles() {
+    for name in PROFILE_ENTITIES.iter() {
+        let Ok(profiles) = entity_profiles(name) else {
+            continue;
+        };
+        let fields = profiles.undeclared();
+        if !fields.is_empty() {
+            warn!(
+                category = "entity_profile",
+                entity = name,
+                fields = fields.join(","),
+                "fields are not declared in any profile"
+            );
+        }
+    }
+}
+
+/// 仅保留指定的字段
+pub fn project_value(value: Value, fields: &[String]) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .filter(|(key, _)| fields.contains(key))
+                .collect(),
+        ),
+        _ => value,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{entity_profiles, project_value, Profile, PROFILE_ENTITIES};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    #[test]
+    fn profiles() {
+        for name in PROFILE_ENTITIES.iter() {
+            let profiles = entity_profiles(name).unwrap();
+            assert_eq!(Vec::<String>::new(), profiles.undeclared(), "{name}");
+        }
+        let users = entity_profiles("users").unwrap();
+        for profile in [Profile::List, Profile::Detail, Profile::Export] {
+            assert_eq!(false, users.get(profile).contains(&"password".to_string()));
+        }
+        let files = entity_profiles("files").unwrap();
+        assert_eq!(
+            false,
+            files.get(Profile::List).contains(&"data".to_string())
+        );
+        assert_eq!(
+            false,
+            files.get(Profile::Export).contains(&"data".to_string())
+        );
+
+        let value = project_value(
+            json!({"id": 1, "password": "abc"}),
+            users.get(Profile::Detail),
+        );
+        assert_eq!(json!({"id": 1}), value);
+    }
+}
@@ -0,0 +1,100 @@
+use super::{get_database, Result};
+use crate::config::must_new_database_config;
+use once_cell::sync::Lazy;
+use sea_orm::{
+    ConnectionTrait, DbBackend, EntityTrait, FromQueryResult, QuerySelect, QueryTrait, Select,
+    Statement,
+};
+use serde_json::Value;
+use std::time::Duration;
+
+// 查询与count语句的最长执行时间
+static QUERY_TIMEOUT: Lazy<(Duration, Duration)> = Lazy::new(|| {
+    let config = must_new_database_config();
+    (config.query_timeout, config.count_timeout)
+});
+
+/// 获取查询语句的最长执行时间
+pub fn get_query_timeout() -> Duration {
+    QUERY_TIMEOUT.0
+}
+
+// 添加mysql的optimizer hint，仅对select语句生效
+fn add_max_execution_time(sql: &str, timeout: Duration) -> String {
+    if let Some(value) = sql.strip_prefix("SELECT ") {
+        return format!(
+            "SELECT /*+ MAX_EXECUTION_TIME({}) */ {value}",
+            timeout.as_millis()
+        );
+    }
+    sql.to_string()
+}
+
+/// 查询记录总数，语句执行超时则返回出错
+pub async fn guarded_count<E: EntityTrait>(sql: Select<E>) -> Result<u64> {
+    let stmt = sql.build(DbBackend::MySql);
+    let count_sql = format!(
+        "SELECT COUNT(*) AS num_items FROM ({}) AS sub_query",
+        stmt.sql
+    );
+    let values = stmt.values.map(|item| item.0).unwrap_or_default();
+    let stmt = Statement::from_sql_and_values(
+        DbBackend::MySql,
+        add_max_execution_time(&count_sql, QUERY_TIMEOUT.1),
+        values,
+    );
+    let result = get_database().await.query_one(stmt).await?;
+    let count = if let Some(row) = result {
+        row.try_get::<i64>("", "num_items")?
+    } else {
+        0
+    };
+    Ok(count as u64)
+}
+
+/// 分页查询记录，语句执行超时则返回出错
+pub async fn guarded_fetch_page<E: EntityTrait>(
+    sql: Select<E>,
+    page_size: u64,
+    page: u64,
+) -> Result<Vec<Value>> {
+    let stmt = sql
+        .limit(page_size)
+        .offset(page * page_size)
+        .build(DbBackend::MySql);
+    let stmt = Statement {
+        sql: add_max_execution_time(&stmt.sql, QUERY_TIMEOUT.0),
+        ..stmt
+    };
+    let items = Value::find_by_statement(stmt)
+        .all(get_database().await)
+        .await?;
+    Ok(items)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{add_max_execution_time, get_database};
+    use crate::error::HttpError;
+    use pretty_assertions::assert_eq;
+    use sea_orm::{ConnectionTrait, DbBackend, Statement};
+    use std::time::Duration;
+
+    // 语句中的SLEEP被中断时返回3024出错(仅SLEEP的语句被中断时不出错)
+    #[tokio::test]
+    #[ignore = "requires mysql"]
+    async fn query_too_expensive() {
+        let sql = add_max_execution_time(
+            "SELECT v FROM (SELECT 1 AS v UNION SELECT 2) AS t WHERE SLEEP(1) = 0",
+            Duration::from_millis(100),
+        );
+        let err: HttpError = get_database()
+            .await
+            .query_all(Statement::from_string(DbBackend::MySql, sql))
+            .await
+            .unwrap_err()
+            .into();
+        assert_eq!(422, err.status);
+        assert_eq!("query_too_expensive", err.code);
+    }
+}
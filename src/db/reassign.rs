@@ -0,0 +1,279 @@
+use super::{
+    get_database, invalidate_cached_user, tenant_condition, tenant_scope, Result, ROLE_SU,
+};
+use crate::entities::constants::Status;
+use crate::entities::{client_errors, files, settings, tasks, users};
+use crate::error::HttpError;
+use crate::sensitive;
+use crate::util::{json_value_to_strings, normalize_account};
+use sea_orm::sea_query::Expr;
+use sea_orm::{entity::prelude::*, ActiveValue::Set, QuerySelect, TransactionTrait};
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct ReassignResult {
+    pub entity: String,
+    pub ids: Vec<i64>,
+}
+
+// 将该表中from创建的记录转移至to，preview时仅查询不更新，
+// 按租户隔离的表仅转移当前租户的记录
+async fn reassign_entity<E, C>(
+    conn: &C,
+    id_column: E::Column,
+    creator_column: E::Column,
+    tenant_column: Option<E::Column>,
+    from: &str,
+    to: &str,
+    preview: bool,
+) -> Result<ReassignResult>
+where
+    E: EntityTrait,
+    C: ConnectionTrait,
+{
+    let ids: Vec<i64> = tenant_scope(E::find(), tenant_column)?
+        .select_only()
+        .column(id_column)
+        .filter(creator_column.eq(from))
+        .into_tuple()
+        .all(conn)
+        .await?;
+    if !preview && !ids.is_empty() {
+        tenant_scope(E::update_many(), tenant_column)?
+            .col_expr(creator_column, Expr::value(to))
+            .filter(id_column.is_in(ids.clone()))
+            .exec(conn)
+            .await?;
+    }
+    Ok(ReassignResult {
+        entity: E::default().table_name().to_string(),
+        ids,
+    })
+}
+
+/// 将该账号创建的记录转移至其它账号，
+/// 目标账号必须为当前租户下存在且启用状态的账号
+pub async fn reassign_creator(from: &str, to: &str) -> Result<Vec<ReassignResult>> {
+    let user = users::Entity::find()
+        .filter(users::Column::Account.eq(normalize_account(to)))
+        .filter(tenant_condition(users::Column::TenantId)?)
+        .one(get_database().await)
+        .await?
+        .ok_or(HttpError::new("Target account is not exists"))?;
+    if user.status != Status::Enabled.to_value() {
+        return Err(HttpError::new("Target account is disabled"));
+    }
+
+    let txn = get_database().await.begin().await?;
+    let result = vec![
+        reassign_entity::<files::Entity, _>(
+            &txn,
+            files::Column::Id,
+            files::Column::Creator,
+            Some(files::Column::TenantId),
+            from,
+            to,
+            false,
+        )
+        .await?,
+        reassign_entity::<settings::Entity, _>(
+            &txn,
+            settings::Column::Id,
+            settings::Column::Creator,
+            None,
+            from,
+            to,
+            false,
+        )
+        .await?,
+    ];
+    txn.commit().await?;
+
+    Ok(result)
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct MergeUserResult {
+    pub source: String,
+    pub target: String,
+    // 合并后目标账号的角色与群组
+    pub roles: Vec<String>,
+    pub groups: Vec<String>,
+    pub items: Vec<ReassignResult>,
+}
+
+// 合并两个字符串数组，保持原有顺序
+fn union_strings(target: Option<&Json>, source: Option<&Json>) -> Result<Vec<String>> {
+    let mut values = vec![];
+    for value in [target, source].into_iter().flatten() {
+        for item in json_value_to_strings(value)?.unwrap_or_default() {
+            if !values.contains(&item) {
+                values.push(item);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// 合并用户，源账号的记录转移至目标账号，角色与群组取并集，
+/// 源账号设置为禁用并记录合并至的账号。
+/// 若源账号为超级管理员，需要确认后才可合并。
+/// 角色直接通过事务更新，不会触发数据变更处理，因此在提交后检查角色提升
+pub async fn merge_users(
+    operator: &str,
+    source_id: i64,
+    target_id: i64,
+    confirmed: bool,
+    preview: bool,
+) -> Result<MergeUserResult> {
+    if source_id == target_id {
+        return Err(HttpError::new("Source and target user should be different"));
+    }
+    let txn = get_database().await.begin().await?;
+    let source = users::Entity::find_by_id(source_id)
+        .filter(tenant_condition(users::Column::TenantId)?)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or(HttpError::new("Source user is not exists"))?;
+    let target = users::Entity::find_by_id(target_id)
+        .filter(tenant_condition(users::Column::TenantId)?)
+        .lock_exclusive()
+        .one(&txn)
+        .await?
+        .ok_or(HttpError::new("Target user is not exists"))?;
+    if source.merged_into.is_some() {
+        return Err(HttpError::new("Source user has been merged"));
+    }
+    if target.merged_into.is_some() || target.status != Status::Enabled.to_value() {
+        return Err(HttpError::new("Target user is disabled"));
+    }
+    let roles = union_strings(target.roles.as_ref(), source.roles.as_ref())?;
+    let groups = union_strings(target.groups.as_ref(), source.groups.as_ref())?;
+    let target_roles = union_strings(target.roles.as_ref(), None)?;
+    let su = ROLE_SU.to_string();
+    if !preview && !confirmed && roles.contains(&su) && !target_roles.contains(&su) {
+        return Err(HttpError::new(
+            "Merge will grant super admin role, please confirm",
+        ));
+    }
+
+    let (from, to) = (source.account.as_str(), target.account.as_str());
+    let items = vec![
+        reassign_entity::<files::Entity, _>(
+            &txn,
+            files::Column::Id,
+            files::Column::Creator,
+            Some(files::Column::TenantId),
+            from,
+            to,
+            preview,
+        )
+        .await?,
+        reassign_entity::<settings::Entity, _>(
+            &txn,
+            settings::Column::Id,
+            settings::Column::Creator,
+            None,
+            from,
+            to,
+            preview,
+        )
+        .await?,
+        reassign_entity::<tasks::Entity, _>(
+            &txn,
+            tasks::Column::Id,
+            tasks::Column::Creator,
+            None,
+            from,
+            to,
+            preview,
+        )
+        .await?,
+        reassign_entity::<client_errors::Entity, _>(
+            &txn,
+            client_errors::Column::Id,
+            client_errors::Column::Creator,
+            None,
+            from,
+            to,
+            preview,
+        )
+        .await?,
+    ];
+    let result = MergeUserResult {
+        source: source.account.clone(),
+        target: target.account.clone(),
+        roles: roles.clone(),
+        groups: groups.clone(),
+        items,
+    };
+    if preview {
+        txn.rollback().await?;
+        return Ok(result);
+    }
+
+    let mut target: users::ActiveModel = target.into();
+    target.roles = Set(Some(json!(roles)));
+    target.groups = Set(Some(json!(groups)));
+    target.update(&txn).await?;
+
+    let mut source: users::ActiveModel = source.into();
+    source.status = Set(Status::Disabled.to_value());
+    source.merged_into = Set(Some(target_id));
+    source.update(&txn).await?;
+
+    txn.commit().await?;
+    invalidate_cached_user(&result.source).await;
+    invalidate_cached_user(&result.target).await;
+    sensitive::check_role_escalation(operator, &result.target, target_id, &target_roles, &roles)
+        .await;
+    Ok(result)
+}
+
+/// 依赖于某记录的数据
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct EntityDependent {
+    pub entity: String,
+    pub count: u64,
+}
+
+/// 统计该账号创建的记录，用于删除或停用账号前确认影响
+pub async fn get_creator_dependents(account: &str) -> Result<Vec<EntityDependent>> {
+    let conn = get_database().await;
+    let files_count = tenant_scope(files::Entity::find(), Some(files::Column::TenantId))?
+        .filter(files::Column::Creator.eq(account))
+        .count(conn)
+        .await?;
+    let settings_count = settings::Entity::find()
+        .filter(settings::Column::Creator.eq(account))
+        .count(conn)
+        .await?;
+    let tasks_count = tasks::Entity::find()
+        .filter(tasks::Column::Creator.eq(account))
+        .count(conn)
+        .await?;
+    let client_errors_count = client_errors::Entity::find()
+        .filter(client_errors::Column::Creator.eq(account))
+        .count(conn)
+        .await?;
+    Ok(vec![
+        EntityDependent {
+            entity: files::Entity.table_name().to_string(),
+            count: files_count,
+        },
+        EntityDependent {
+            entity: settings::Entity.table_name().to_string(),
+            count: settings_count,
+        },
+        EntityDependent {
+            entity: tasks::Entity.table_name().to_string(),
+            count: tasks_count,
+        },
+        EntityDependent {
+            entity: client_errors::Entity.table_name().to_string(),
+            count: client_errors_count,
+        },
+    ])
+}
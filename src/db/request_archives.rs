@@ -0,0 +1,132 @@
+use super::{get_database, Anonymize, EntitySensitivity, Result};
+use crate::entities::request_archives::{ActiveModel, Column, Entity, Model};
+use crate::error::HttpError;
+use chrono::{DateTime, Utc};
+use sea_orm::{entity::prelude::*, ActiveValue::Set, QueryOrder};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use tracing::{error, info};
+
+/// 归档的请求
+#[derive(Debug, Clone, Default)]
+pub struct RequestArchiveData {
+    pub method: String,
+    pub uri: String,
+    pub path: String,
+    pub headers: Value,
+    pub body: Option<String>,
+    pub body_truncated: bool,
+    pub status: u16,
+    pub latency: u64,
+    pub trace_id: String,
+    pub account: String,
+}
+
+/// 保存归档的请求
+pub async fn add_request_archive(data: RequestArchiveData) -> Result<()> {
+    ActiveModel {
+        method: Set(data.method),
+        uri: Set(data.uri),
+        path: Set(data.path),
+        headers: Set(data.headers),
+        body: Set(data.body),
+        body_truncated: Set(data.body_truncated as i8),
+        status: Set(data.status as i32),
+        latency: Set(data.latency as i32),
+        trace_id: Set(data.trace_id),
+        account: Set(data.account),
+        ..Default::default()
+    }
+    .insert(get_database().await)
+    .await?;
+    Ok(())
+}
+
+pub async fn find_request_archive(id: i64) -> Result<Option<Model>> {
+    let result = Entity::find_by_id(id).one(get_database().await).await?;
+    Ok(result)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RequestArchiveListParams {
+    // 路径前缀
+    pub path: Option<String>,
+    pub status: Option<u16>,
+    pub begin: Option<DateTime<Utc>>,
+    pub end: Option<DateTime<Utc>>,
+    pub page: u64,
+    pub page_size: u64,
+}
+
+/// 查询归档的请求，按时间倒序
+pub async fn list_request_archives(params: &RequestArchiveListParams) -> Result<(u64, Vec<Model>)> {
+    if params.page_size == 0 {
+        return Err(HttpError::new("每页记录数不能为0"));
+    }
+    let mut sql = Entity::find();
+    if let Some(path) = &params.path {
+        sql = sql.filter(Column::Path.starts_with(path));
+    }
+    if let Some(status) = params.status {
+        sql = sql.filter(Column::Status.eq(status as i32));
+    }
+    if let Some(begin) = params.begin {
+        sql = sql.filter(Column::CreatedAt.gte(begin));
+    }
+    if let Some(end) = params.end {
+        sql = sql.filter(Column::CreatedAt.lte(end));
+    }
+    let paginator = sql
+        .order_by_desc(Column::Id)
+        .paginate(get_database().await, params.page_size);
+    let count = paginator.num_items().await?;
+    let items = paginator.fetch_page(params.page).await?;
+    Ok((count, items))
+}
+
+/// 定时删除超过保留时长的归档
+pub fn start_request_archive_cleanup(retention: Duration) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(3600));
+        loop {
+            interval.tick().await;
+            let before = Utc::now() - chrono::Duration::from_std(retention).unwrap_or_default();
+            match Entity::delete_many()
+                .filter(Column::CreatedAt.lt(before))
+                .exec(get_database().await)
+                .await
+            {
+                Ok(result) => info!(
+                    category = "request_archive",
+                    count = result.rows_affected,
+                    "clean up request archives"
+                ),
+                Err(err) => error!(category = "request_archive", error = err.to_string()),
+            }
+        }
+    });
+}
+
+/// 归档请求的敏感字段，请求数据中可能包含任意的个人信息，直接清除
+pub fn request_archive_sensitivity() -> EntitySensitivity {
+    EntitySensitivity::new(
+        &[
+            (Column::Uri, Anonymize::Redact),
+            (Column::Headers, Anonymize::Redact),
+            (Column::Body, Anonymize::Null),
+            (Column::Account, Anonymize::Account),
+        ],
+        &[
+            Column::Id,
+            Column::CreatedAt,
+            Column::UpdatedAt,
+            Column::Method,
+            Column::Path,
+            Column::BodyTruncated,
+            Column::Status,
+            Column::Latency,
+            Column::TraceId,
+        ],
+    )
+}
@@ -1,10 +1,16 @@
 use super::CommonEntity;
 use super::{
-    get_database, EntityDescription, EntityItemCategory, EntityItemDescription, EntityItemOption,
-    Error, ListCountParams, Result, ROLE_SU,
+    get_database, guarded_count, guarded_fetch_page, register_entity_hooks, Anonymize,
+    EntityDescription, EntityHooks, EntityItemCategory, EntityItemDescription, EntityItemOption,
+    EntityProfiles, EntitySensitivity, Error, HookContext, ListCountParams, Result, ROLE_SU,
+    TABLE_NAME_SETTINGS,
 };
+use crate::cache::TwoLevelStore;
+use crate::entities::constants::Status;
 use crate::entities::settings::{ActiveModel, Column, Entity, Model};
 use crate::util::{json_get_date_time, json_get_i64, json_get_string};
+use async_trait::async_trait;
+use chrono::Utc;
 use db_entity_derive::DbEntity;
 use once_cell::sync::Lazy;
 use sea_orm::query::{Order, Select};
@@ -13,8 +19,12 @@ use sea_orm::Condition;
 use sea_orm::QuerySelect;
 use sea_orm::{entity::prelude::*, ActiveValue::Set, QueryOrder};
 use serde_json::Value;
+use std::num::NonZeroUsize;
 use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
 use substring::Substring;
+use tracing::error;
 
 static SUPPORT_ORDERS: Lazy<Vec<Column>> = Lazy::new(|| {
     vec![
@@ -25,6 +35,99 @@ static SUPPORT_ORDERS: Lazy<Vec<Column>> = Lazy::new(|| {
     ]
 });
 
+// 按分类缓存启用的配置，配置变更后通知所有实例删除
+static SETTINGS_CACHE: Lazy<TwoLevelStore<Vec<Model>>> = Lazy::new(|| {
+    TwoLevelStore::new(
+        NonZeroUsize::new(128).unwrap(),
+        Duration::from_secs(60),
+        "settings:".to_string(),
+    )
+});
+
+async fn find_enabled_settings_by_category(category: &str) -> Result<Vec<Model>> {
+    let result = Entity::find()
+        .filter(Column::Category.eq(category))
+        .filter(Column::Status.eq(Status::Enabled.to_value()))
+        .all(get_database().await)
+        .await?;
+    Ok(result)
+}
+
+/// 获取该分类下启用且在有效期内的配置，
+/// 优先从缓存中获取，缓存不可用(如redis异常)时查询数据库
+pub async fn find_valid_settings_by_category(category: &str) -> Result<Vec<Model>> {
+    let settings = match SETTINGS_CACHE.get(category).await {
+        Ok(Some(settings)) => settings,
+        result => {
+            if let Err(err) = result {
+                error!(
+                    category = "settings_cache",
+                    name = category,
+                    error = err.to_string()
+                );
+            }
+            let settings = find_enabled_settings_by_category(category).await?;
+            if let Err(err) = SETTINGS_CACHE.set(category, settings.clone()).await {
+                error!(
+                    category = "settings_cache",
+                    name = category,
+                    error = err.to_string()
+                );
+            }
+            settings
+        }
+    };
+    // 缓存的为所有启用的配置，有效期在读取时判断
+    let now = Utc::now();
+    Ok(settings
+        .into_iter()
+        .filter(|item| item.started_at <= now && item.ended_at >= now)
+        .collect())
+}
+
+fn get_setting_category(value: &Value) -> Option<&str> {
+    value.get(Column::Category.as_str()).and_then(Value::as_str)
+}
+
+// 配置变更后删除该分类的缓存，并通知其它实例删除其进程内缓存
+struct SettingCacheHooks {}
+
+impl SettingCacheHooks {
+    async fn invalidate(&self, values: &[&Value]) -> Result<()> {
+        let mut categories: Vec<&str> = values
+            .iter()
+            .filter_map(|value| get_setting_category(value))
+            .collect();
+        categories.dedup();
+        for category in categories {
+            SETTINGS_CACHE.del(category).await?;
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl EntityHooks for SettingCacheHooks {
+    async fn after_insert(&self, _ctx: &HookContext, _id: i64, value: &Value) -> Result<()> {
+        self.invalidate(&[value]).await
+    }
+    async fn after_update(
+        &self,
+        _ctx: &HookContext,
+        _id: i64,
+        old: &Value,
+        new: &Value,
+    ) -> Result<()> {
+        // 修改分类的两个分类均需要删除
+        self.invalidate(&[old, new]).await
+    }
+}
+
+/// 注册配置的变更处理，变更后删除配置的缓存
+pub fn register_setting_hooks() {
+    register_entity_hooks(TABLE_NAME_SETTINGS, Arc::new(SettingCacheHooks {}));
+}
+
 #[derive(DbEntity)]
 pub struct SettingEntity {}
 impl CommonEntity for SettingEntity {}
@@ -33,6 +136,12 @@ impl SettingEntity {
     fn get_support_orders() -> Vec<Column> {
         SUPPORT_ORDERS.to_vec()
     }
+    fn get_unique_keys() -> Vec<Vec<Column>> {
+        vec![vec![Column::Name]]
+    }
+    fn get_tenant_column() -> Option<Column> {
+        None
+    }
     fn update_from_value(model: &mut ActiveModel, value: &Value) -> Result<()> {
         if let Some(status) = json_get_i64(value, Column::Status.as_str())? {
             model.status = Set(status as i8);
@@ -68,6 +177,45 @@ impl SettingEntity {
             None
         }
     }
+    pub fn sensitivity() -> EntitySensitivity {
+        // 配置数据中可能包含密钥
+        EntitySensitivity::new(
+            &[
+                (Column::Data, Anonymize::Redact),
+                (Column::Remark, Anonymize::Redact),
+                (Column::Updater, Anonymize::Account),
+                (Column::Creator, Anonymize::Account),
+            ],
+            &[
+                Column::Id,
+                Column::Status,
+                Column::CreatedAt,
+                Column::UpdatedAt,
+                Column::Name,
+                Column::Category,
+                Column::StartedAt,
+                Column::EndedAt,
+            ],
+        )
+    }
+    pub fn profiles() -> EntityProfiles {
+        let list = [
+            Column::Id,
+            Column::Status,
+            Column::Name,
+            Column::Category,
+            Column::Data,
+            Column::Remark,
+            Column::StartedAt,
+            Column::EndedAt,
+            Column::Creator,
+            Column::CreatedAt,
+            Column::UpdatedAt,
+        ];
+        let mut detail = list.to_vec();
+        detail.push(Column::Updater);
+        EntityProfiles::new(&list, &detail, &detail, &[])
+    }
     pub fn description() -> EntityDescription {
         let items = vec![
             EntityItemDescription {
@@ -109,6 +257,11 @@ impl SettingEntity {
                         str_value: Some("biz".to_string()),
                         ..Default::default()
                     },
+                    EntityItemOption {
+                        label: "功能开关".to_string(),
+                        str_value: Some("feature".to_string()),
+                        ..Default::default()
+                    },
                 ]),
                 ..Default::default()
             },
@@ -176,3 +329,58 @@ impl SettingEntity {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{get_database, Error, SettingEntity};
+    use crate::entities::settings::{ActiveModel, Column, Entity};
+    use crate::error::HttpError;
+    use crate::util;
+    use pretty_assertions::assert_eq;
+    use sea_orm::{
+        ActiveModelTrait, ActiveValue::Set, ColumnTrait, EntityTrait, IdenStatic, QueryFilter,
+    };
+
+    // 两个请求同时通过唯一键检查后插入，由唯一索引拒绝，
+    // 出错需与检查时的一致
+    #[tokio::test]
+    #[ignore = "requires mysql"]
+    async fn unique_index_conflict() {
+        let conn = get_database().await;
+        let name = format!("unique-{}", util::uuid());
+        let new_setting = || ActiveModel {
+            name: Set(name.clone()),
+            category: Set("test".to_string()),
+            data: Set("".to_string()),
+            remark: Set("".to_string()),
+            creator: Set("test".to_string()),
+            ..Default::default()
+        };
+        let (first, second) = (new_setting(), new_setting());
+        SettingEntity::check_unique(conn, &first, None)
+            .await
+            .unwrap();
+        SettingEntity::check_unique(conn, &second, None)
+            .await
+            .unwrap();
+        let (first, second) = tokio::join!(first.insert(conn), second.insert(conn));
+        Entity::delete_many()
+            .filter(Column::Name.eq(&name))
+            .exec(conn)
+            .await
+            .unwrap();
+
+        let err = match (first, second) {
+            (Ok(_), Err(err)) | (Err(err), Ok(_)) => HttpError::from(err),
+            _ => panic!("only one insert should succeed"),
+        };
+        let checked: HttpError = Error::Conflict {
+            fields: vec![Column::Name.as_str().to_string()],
+        }
+        .into();
+        assert_eq!(checked.status, err.status);
+        assert_eq!(checked.category, err.category);
+        assert_eq!(checked.code, err.code);
+        assert_eq!(checked.message, err.message);
+    }
+}
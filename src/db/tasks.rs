@@ -0,0 +1,330 @@
+use super::CommonEntity;
+use super::{
+    get_database, guarded_count, guarded_fetch_page, Anonymize, EntityDescription,
+    EntityItemCategory, EntityItemDescription, EntityItemOption, EntityProfiles, EntitySensitivity,
+    Error, ListCountParams, Result, ROLE_SU,
+};
+use crate::entities::tasks::{ActiveModel, Column, Entity, Model};
+use crate::error::HttpError;
+use chrono::{Duration, Utc};
+use db_entity_derive::DbEntity;
+use once_cell::sync::Lazy;
+use sea_orm::query::{Order, Select};
+use sea_orm::sea_query::{LockBehavior, LockType};
+use sea_orm::ColumnTrait;
+use sea_orm::Condition;
+use sea_orm::QuerySelect;
+use sea_orm::TransactionTrait;
+use sea_orm::{entity::prelude::*, ActiveValue::Set, QueryOrder};
+use serde_json::Value;
+use std::str::FromStr;
+use substring::Substring;
+
+pub const TASK_STATUS_PENDING: i8 = 0;
+pub const TASK_STATUS_RUNNING: i8 = 1;
+pub const TASK_STATUS_DONE: i8 = 2;
+pub const TASK_STATUS_FAILED: i8 = 3;
+pub const TASK_STATUS_DISCARDED: i8 = 4;
+
+static SUPPORT_ORDERS: Lazy<Vec<Column>> =
+    Lazy::new(|| vec![Column::Id, Column::RunAfter, Column::UpdatedAt]);
+
+/// 添加任务，任务由对应类型的worker执行
+pub async fn add_task(category: &str, payload: Value, creator: &str) -> Result<Model> {
+    let result = ActiveModel {
+        status: Set(TASK_STATUS_PENDING),
+        category: Set(category.to_string()),
+        payload: Set(Some(payload)),
+        attempts: Set(0),
+        creator: Set(creator.to_string()),
+        ..Default::default()
+    }
+    .insert(get_database().await)
+    .await?;
+    Ok(result)
+}
+
+/// 获取一个可执行的任务并锁定，
+/// 执行中的任务若锁定时间超过timeout则认为执行实例已异常，可重新获取
+pub async fn lock_task(
+    category: &str,
+    locked_by: &str,
+    timeout: Duration,
+) -> Result<Option<Model>> {
+    let now = Utc::now();
+    let txn = get_database().await.begin().await?;
+    let cond = Condition::any()
+        .add(
+            Condition::all()
+                .add(Column::Status.eq(TASK_STATUS_PENDING))
+                .add(Column::RunAfter.lte(now)),
+        )
+        .add(
+            Condition::all()
+                .add(Column::Status.eq(TASK_STATUS_RUNNING))
+                .add(Column::LockedAt.lt(now - timeout)),
+        );
+    let result = Entity::find()
+        .filter(Column::Category.eq(category))
+        .filter(cond)
+        .order_by_asc(Column::Id)
+        .lock_with_behavior(LockType::Update, LockBehavior::SkipLocked)
+        .one(&txn)
+        .await?;
+    let Some(task) = result else {
+        txn.commit().await?;
+        return Ok(None);
+    };
+    let attempts = task.attempts + 1;
+    let mut data: ActiveModel = task.into();
+    data.status = Set(TASK_STATUS_RUNNING);
+    data.locked_by = Set(Some(locked_by.to_string()));
+    data.locked_at = Set(Some(now));
+    data.attempts = Set(attempts);
+    let task = data.update(&txn).await?;
+    txn.commit().await?;
+    Ok(Some(task))
+}
+
+// 仅更新由该实例锁定的任务，锁定超时后任务可能已被其它实例重新获取
+async fn update_locked_task(task: &Model, data: ActiveModel) -> Result<bool> {
+    let result = Entity::update_many()
+        .set(data)
+        .filter(Column::Id.eq(task.id))
+        .filter(Column::Status.eq(TASK_STATUS_RUNNING))
+        .filter(Column::LockedBy.eq(task.locked_by.clone()))
+        .exec(get_database().await)
+        .await?;
+    Ok(result.rows_affected > 0)
+}
+
+/// 任务执行成功，返回false表示任务已被其它实例重新获取
+pub async fn finish_task(task: &Model) -> Result<bool> {
+    let data = ActiveModel {
+        status: Set(TASK_STATUS_DONE),
+        message: Set(None),
+        updated_at: Set(Utc::now()),
+        ..Default::default()
+    };
+    update_locked_task(task, data).await
+}
+
+/// 任务执行失败，未超过最大次数则延时后重试，
+/// 否则设置为失败，返回false表示任务已被其它实例重新获取
+pub async fn fail_task(task: &Model, message: &str, max_attempts: i32) -> Result<bool> {
+    let status = if task.attempts >= max_attempts {
+        TASK_STATUS_FAILED
+    } else {
+        TASK_STATUS_PENDING
+    };
+    // 指数退避，最长1小时
+    let secs = 2_i64.pow(task.attempts.clamp(0, 12) as u32).min(3600);
+    let data = ActiveModel {
+        status: Set(status),
+        run_after: Set(Utc::now() + Duration::seconds(secs)),
+        message: Set(Some(message.chars().take(1024).collect())),
+        updated_at: Set(Utc::now()),
+        ..Default::default()
+    };
+    update_locked_task(task, data).await
+}
+
+// 更新任务状态，仅允许失败或待执行的任务
+async fn update_task_status(id: i64, status: i8, user: &str) -> Result<()> {
+    let conn = get_database().await;
+    let task = Entity::find_by_id(id)
+        .one(conn)
+        .await?
+        .ok_or(Error::NotFound)?;
+    if ![TASK_STATUS_PENDING, TASK_STATUS_FAILED].contains(&task.status) {
+        return Err(HttpError::new("Task status is invalid"));
+    }
+    let mut data: ActiveModel = task.into();
+    data.status = Set(status);
+    data.run_after = Set(Utc::now());
+    data.updater = Set(Some(user.to_string()));
+    if status == TASK_STATUS_PENDING {
+        data.attempts = Set(0);
+    }
+    data.update(conn).await?;
+    Ok(())
+}
+
+/// 重新执行任务
+pub async fn retry_task(id: i64, user: &str) -> Result<()> {
+    update_task_status(id, TASK_STATUS_PENDING, user).await
+}
+
+/// 放弃执行任务
+pub async fn discard_task(id: i64, user: &str) -> Result<()> {
+    update_task_status(id, TASK_STATUS_DISCARDED, user).await
+}
+
+// 任务仅能通过重试或放弃修改，不生成新增与更新的函数
+#[derive(DbEntity)]
+#[db_entity(readonly)]
+pub struct TaskEntity {}
+impl CommonEntity for TaskEntity {}
+
+impl TaskEntity {
+    fn get_support_orders() -> Vec<Column> {
+        SUPPORT_ORDERS.to_vec()
+    }
+    fn get_tenant_column() -> Option<Column> {
+        None
+    }
+    fn get_condition(params: &ListCountParams) -> Option<Condition> {
+        if let Some(keyword) = &params.keyword {
+            let cond = Condition::any()
+                .add(Column::Category.eq(keyword))
+                .add(Column::Creator.eq(keyword));
+            Some(cond)
+        } else {
+            None
+        }
+    }
+    pub fn sensitivity() -> EntitySensitivity {
+        EntitySensitivity::new(
+            &[
+                (Column::Payload, Anonymize::Null),
+                (Column::Message, Anonymize::Null),
+                (Column::Updater, Anonymize::Account),
+                (Column::Creator, Anonymize::Account),
+            ],
+            &[
+                Column::Id,
+                Column::Status,
+                Column::CreatedAt,
+                Column::UpdatedAt,
+                Column::Category,
+                Column::Attempts,
+                Column::RunAfter,
+                Column::LockedBy,
+                Column::LockedAt,
+            ],
+        )
+    }
+    pub fn profiles() -> EntityProfiles {
+        let list = [
+            Column::Id,
+            Column::Category,
+            Column::Status,
+            Column::Attempts,
+            Column::Message,
+            Column::RunAfter,
+            Column::LockedBy,
+            Column::Creator,
+            Column::CreatedAt,
+            Column::UpdatedAt,
+        ];
+        let mut detail = list.to_vec();
+        detail.extend([Column::Payload, Column::LockedAt, Column::Updater]);
+        EntityProfiles::new(&list, &detail, &detail, &[])
+    }
+    pub fn description() -> EntityDescription {
+        let status_options = [
+            ("待执行", TASK_STATUS_PENDING),
+            ("执行中", TASK_STATUS_RUNNING),
+            ("成功", TASK_STATUS_DONE),
+            ("失败", TASK_STATUS_FAILED),
+            ("已放弃", TASK_STATUS_DISCARDED),
+        ]
+        .iter()
+        .map(|(label, value)| EntityItemOption {
+            label: label.to_string(),
+            num_value: Some(*value as i32),
+            ..Default::default()
+        })
+        .collect();
+        let items = vec![
+            EntityItemDescription {
+                name: Column::Id.to_string(),
+                label: "ID".to_string(),
+                width: Some(60),
+                category: EntityItemCategory::Number,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Category.to_string(),
+                label: "类型".to_string(),
+                width: Some(100),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Status.to_string(),
+                label: "状态".to_string(),
+                width: Some(80),
+                category: EntityItemCategory::Number,
+                options: Some(status_options),
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Attempts.to_string(),
+                label: "执行次数".to_string(),
+                width: Some(60),
+                category: EntityItemCategory::Number,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Payload.to_string(),
+                label: "参数".to_string(),
+                width: Some(200),
+                span: Some(3),
+                category: EntityItemCategory::Json,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Message.to_string(),
+                label: "出错信息".to_string(),
+                width: Some(150),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::RunAfter.to_string(),
+                label: "可执行时间".to_string(),
+                width: Some(150),
+                category: EntityItemCategory::DateTime,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::LockedBy.to_string(),
+                label: "执行实例".to_string(),
+                width: Some(100),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::Creator.to_string(),
+                label: "创建人".to_string(),
+                width: Some(80),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
+            EntityItemDescription {
+                name: Column::UpdatedAt.to_string(),
+                label: "更新时间".to_string(),
+                width: Some(150),
+                category: EntityItemCategory::DateTime,
+                readonly: true,
+                ..Default::default()
+            },
+        ];
+        EntityDescription {
+            items,
+            modify_roles: vec![ROLE_SU.to_string()],
+            support_orders: SUPPORT_ORDERS.iter().map(|item| item.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use super::{get_database, Anonymize, EntitySensitivity, Error, Result};
+use crate::entities::constants::Status;
+use crate::entities::tenants::{Column, Entity, Model};
+use crate::task_local::TENANT_ID;
+use sea_orm::{entity::prelude::*, Condition, QueryFilter, Select};
+
+/// 默认租户，已有数据均归属于此租户
+pub static DEFAULT_TENANT_ID: i64 = 1;
+
+/// 获取当前请求的租户，未设置时返回出错，
+/// 避免遗漏条件时查询到其它租户的数据
+pub fn current_tenant_id() -> Result<i64> {
+    TENANT_ID
+        .try_with(|value| *value)
+        .map_err(|_| Error::TenantRequired.into())
+}
+
+/// 租户隔离的条件，所有租户隔离的表查询时均需添加
+pub fn tenant_condition<C: ColumnTrait>(column: C) -> Result<Condition> {
+    Ok(Condition::all().add(column.eq(current_tenant_id()?)))
+}
+
+/// 添加租户条件，tenant_column为None表示该表不按租户隔离
+pub fn tenant_scope<Q: QueryFilter, C: ColumnTrait>(sql: Q, tenant_column: Option<C>) -> Result<Q> {
+    match tenant_column {
+        Some(column) => Ok(sql.filter(tenant_condition(column)?)),
+        None => Ok(sql),
+    }
+}
+
+/// 不添加租户条件的查询，仅用于有意跨租户的场景，
+/// 如按全局唯一的账号登录或后台的数据校验任务。
+/// 调用处需说明原因，审查时可通过`unscoped_`检索
+pub fn unscoped_find<E: EntityTrait>() -> Select<E> {
+    E::find()
+}
+
+/// 查询启用状态的租户
+pub async fn find_enabled_tenant(id: i64) -> Result<Option<Model>> {
+    let result = Entity::find_by_id(id)
+        .filter(Column::Status.eq(Status::Enabled.to_value()))
+        .one(get_database().await)
+        .await?;
+    Ok(result)
+}
+
+/// 租户表的敏感字段
+pub fn tenant_sensitivity() -> EntitySensitivity {
+    EntitySensitivity::new(
+        &[
+            (Column::Name, Anonymize::Hash),
+            (Column::Remark, Anonymize::Null),
+        ],
+        &[
+            Column::Id,
+            Column::Status,
+            Column::CreatedAt,
+            Column::UpdatedAt,
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::tenant_scope;
+    use crate::entities::{files, settings};
+    use crate::task_local::TENANT_ID;
+    use pretty_assertions::assert_eq;
+    use sea_orm::{DbBackend, EntityTrait, QuerySelect, QueryTrait};
+
+    #[tokio::test]
+    async fn scope() {
+        // 未设置租户时按租户隔离的表查询出错
+        assert_eq!(
+            true,
+            tenant_scope(files::Entity::find(), Some(files::Column::TenantId)).is_err()
+        );
+        // 不按租户隔离的表无需租户
+        assert_eq!(
+            "SELECT `settings`.`id` FROM `settings`",
+            tenant_scope(settings::Entity::find(), None::<settings::Column>)
+                .unwrap()
+                .select_only()
+                .column(settings::Column::Id)
+                .build(DbBackend::MySql)
+                .to_string()
+        );
+
+        let sql = TENANT_ID
+            .scope(2, async {
+                tenant_scope(files::Entity::update_many(), Some(files::Column::TenantId))
+                    .unwrap()
+                    .col_expr(files::Column::Creator, sea_orm::sea_query::Expr::value("b"))
+                    .build(DbBackend::MySql)
+                    .to_string()
+            })
+            .await;
+        assert_eq!(
+            "UPDATE `files` SET `creator` = 'b' WHERE `files`.`tenant_id` = 2",
+            sql
+        );
+    }
+}
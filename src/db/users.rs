@@ -1,21 +1,55 @@
 use super::{
-    get_database, EntityDescription, EntityItemCategory, EntityItemDescription, EntityItemOption,
-    Error, ListCountParams, Result, ROLE_ADMIN, ROLE_SU,
+    current_tenant_id, diff_json, get_creator_dependents, get_database, guarded_count,
+    guarded_fetch_page, tenant_condition, unscoped_find, Anonymize, EntityDependent,
+    EntityDescription, EntityItemCategory, EntityItemDescription, EntityItemOption, EntityProfiles,
+    EntitySensitivity, Error, ListCountParams, Result, DEFAULT_TENANT_ID, ROLE_ADMIN,
+    ROLE_READONLY, ROLE_SU,
 };
+use crate::cache::{Lookup, TwoLevelStore};
 use crate::entities::users::{ActiveModel, Column, Entity, Model};
-use crate::util::{json_get_i64, json_get_strings};
-use sea_orm::{entity::prelude::*, ActiveValue::Set, Condition, Iterable, QuerySelect};
+use crate::util::{
+    account_skeleton, json_get_date_time, json_get_i64, json_get_strings, json_value_to_strings,
+    normalize_account,
+};
+use once_cell::sync::Lazy;
+use sea_orm::{
+    entity::prelude::*, ActiveValue::Set, Condition, Iterable, QueryOrder, QuerySelect, Select,
+};
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tracing::error;
 
+/// 添加用户，账号规范化后保存，原始账号用于展示。
+/// 未指定租户时(如注册)归属于默认租户
 pub async fn add_user(account: &str, password: &str) -> Result<Model> {
+    let conflict = Error::Conflict {
+        fields: vec![Column::Account.to_string()],
+    };
+    if unscoped_find_user_by_account(account).await?.is_some() {
+        return Err(conflict.into());
+    }
+    // 与已有账号仅有易混淆字符差异的不允许注册
+    let skeleton = account_skeleton(account);
+    if skeleton != normalize_account(account)
+        && unscoped_find_user_by_account(&skeleton).await?.is_some()
+    {
+        return Err(conflict.into());
+    }
     let conn = get_database().await;
     let result = ActiveModel {
-        account: Set(account.to_string()),
+        account: Set(normalize_account(account)),
+        display_account: Set(Some(account.trim().to_string())),
         password: Set(password.to_string()),
+        tenant_id: Set(current_tenant_id().unwrap_or(DEFAULT_TENANT_ID)),
         ..Default::default()
     }
     .insert(conn)
     .await?;
+    // 清除账号不存在的缓存
+    invalidate_cached_user(&result.account).await;
     if result.id == 1 {
         let mut user: ActiveModel = result.clone().into();
         user.roles = Set(Some(json!([ROLE_SU])));
@@ -25,24 +59,212 @@ pub async fn add_user(account: &str, password: &str) -> Result<Model> {
     Ok(result)
 }
 
-pub async fn find_user_by_account(account: &str) -> Result<Option<Model>> {
-    let result = Entity::find()
-        .filter(Column::Account.eq(account))
+/// 按账号查询用户，不限制租户。
+/// 账号全局唯一，用于登录、注册查重以及当前账号自身的操作，
+/// 此时租户尚未确定或由账号本身决定
+pub async fn unscoped_find_user_by_account(account: &str) -> Result<Option<Model>> {
+    let result = unscoped_find::<Entity>()
+        .filter(Column::Account.eq(normalize_account(account)))
         .one(get_database().await)
         .await?;
     Ok(result)
 }
 
+/// 修改用户密码
+pub async fn update_user_password(account: &str, password: &str) -> Result<()> {
+    let user = unscoped_find_user_by_account(account)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let mut data: ActiveModel = user.into();
+    data.password = Set(password.to_string());
+    data.update(get_database().await).await?;
+    invalidate_cached_user(account).await;
+    Ok(())
+}
+
+/// 设置两步验证的密钥，为None时则关闭
+pub async fn set_user_totp_secret(account: &str, secret: Option<String>) -> Result<()> {
+    let user = unscoped_find_user_by_account(account)
+        .await?
+        .ok_or(Error::NotFound)?;
+    let mut data: ActiveModel = user.into();
+    data.totp_secret = Set(secret);
+    data.update(get_database().await).await?;
+    invalidate_cached_user(account).await;
+    Ok(())
+}
+
+pub async fn get_user_roles(account: &str) -> Result<Vec<String>> {
+    let mut roles = vec![];
+    if let Some(user) = get_cached_user(account).await? {
+        roles = user.get_roles()?;
+    }
+    Ok(roles)
+}
+
+/// 缓存的用户信息，不包含密码与两步验证密钥，
+/// 登录及修改密码等需要校验的场景仍直接查询数据库
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CachedUser {
+    pub id: i64,
+    pub status: i8,
+    pub account: String,
+    pub display_account: Option<String>,
+    pub roles: Option<Value>,
+    pub groups: Option<Value>,
+    pub merged_into: Option<i64>,
+    pub tenant_id: i64,
+    pub totp_enabled: bool,
+}
+
+impl From<Model> for CachedUser {
+    fn from(value: Model) -> Self {
+        CachedUser {
+            id: value.id,
+            status: value.status,
+            account: value.account,
+            display_account: value.display_account,
+            roles: value.roles,
+            groups: value.groups,
+            merged_into: value.merged_into,
+            tenant_id: value.tenant_id,
+            totp_enabled: value.totp_secret.is_some(),
+        }
+    }
+}
+
+impl CachedUser {
+    pub fn get_roles(&self) -> Result<Vec<String>> {
+        let Some(value) = &self.roles else {
+            return Ok(vec![]);
+        };
+        Ok(json_value_to_strings(value)?.unwrap_or_default())
+    }
+}
+
+// 用户信息的缓存，有效期较短，变更时主动清除
+static USER_CACHE: Lazy<TwoLevelStore<CachedUser>> = Lazy::new(|| {
+    TwoLevelStore::new(
+        NonZeroUsize::new(1024).unwrap(),
+        Duration::from_secs(60),
+        "user:".to_string(),
+    )
+});
+// 账号不存在的缓存有效期，注册后会清除
+const USER_ABSENT_TTL: Duration = Duration::from_secs(10);
+static USER_CACHE_HITS: AtomicU64 = AtomicU64::new(0);
+static USER_CACHE_MISSES: AtomicU64 = AtomicU64::new(0);
+static USER_CACHE_INVALIDATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// 用户缓存的统计
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct UserCacheStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub invalidations: u64,
+    // 命中率(0-100)
+    pub hit_rate: f64,
+}
+
+pub fn get_user_cache_stats() -> UserCacheStats {
+    let hits = USER_CACHE_HITS.load(Ordering::Relaxed);
+    let misses = USER_CACHE_MISSES.load(Ordering::Relaxed);
+    let hit_rate = if hits + misses == 0 {
+        0.0
+    } else {
+        hits as f64 * 100.0 / (hits + misses) as f64
+    };
+    UserCacheStats {
+        hits,
+        misses,
+        invalidations: USER_CACHE_INVALIDATIONS.load(Ordering::Relaxed),
+        hit_rate,
+    }
+}
+
+/// 优先从缓存中获取用户信息，缓存不可用(如redis异常)时查询数据库
+pub async fn get_cached_user(account: &str) -> Result<Option<CachedUser>> {
+    let account = normalize_account(account);
+    match USER_CACHE.lookup(&account).await {
+        Ok(Lookup::Hit(user)) => {
+            USER_CACHE_HITS.fetch_add(1, Ordering::Relaxed);
+            return Ok(user);
+        }
+        Ok(Lookup::Miss) => {}
+        Err(err) => {
+            error!(category = "user_cache", account, error = err.to_string());
+        }
+    }
+    USER_CACHE_MISSES.fetch_add(1, Ordering::Relaxed);
+    // 账号不存在的缓存较短时间，避免不存在的账号每次均查询数据库
+    let Some(user) = unscoped_find_user_by_account(&account).await? else {
+        if let Err(err) = USER_CACHE.set_none(&account, USER_ABSENT_TTL).await {
+            error!(category = "user_cache", account, error = err.to_string());
+        }
+        return Ok(None);
+    };
+    let user: CachedUser = user.into();
+    if let Err(err) = USER_CACHE.set(&account, user.clone()).await {
+        error!(category = "user_cache", account, error = err.to_string());
+    }
+    Ok(Some(user))
+}
+
+/// 清除用户信息的缓存，用户信息变更后调用
+pub async fn invalidate_cached_user(account: &str) {
+    USER_CACHE_INVALIDATIONS.fetch_add(1, Ordering::Relaxed);
+    let account = normalize_account(account);
+    if let Err(err) = USER_CACHE.del(&account).await {
+        error!(category = "user_cache", account, error = err.to_string());
+    }
+}
+
 pub struct UserEntity {}
 
 impl UserEntity {
-    pub async fn update_by_id(user: &str, id: i64, value: &Value) -> Result<()> {
-        let conn = get_database().await;
-        let result = Entity::find_by_id(id).one(conn).await?;
+    // 用户按租户隔离，仅可查询当前租户的用户
+    fn scope(sql: Select<Entity>) -> Result<Select<Entity>> {
+        Ok(sql.filter(tenant_condition(Column::TenantId)?))
+    }
+    // 未指定字段时查询除密码与两步验证密钥外的所有字段
+    fn select_columns(sql: Select<Entity>, fields: &[String]) -> Select<Entity> {
+        let sql = sql.select_only();
+        let columns =
+            Column::iter().filter(|col| !matches!(col, Column::Password | Column::TotpSecret));
+        if fields.is_empty() {
+            return sql.columns(columns);
+        }
+        sql.columns(columns.filter(|col| fields.contains(&col.to_string())))
+    }
+    /// 依赖于该用户的记录
+    pub async fn dependents(id: i64) -> Result<Vec<EntityDependent>> {
+        let user = Self::scope(Entity::find_by_id(id))?
+            .one(get_database().await)
+            .await?
+            .ok_or(Error::NotFound)?;
+        get_creator_dependents(&user.account).await
+    }
+    pub async fn update_by_id_with<C: ConnectionTrait>(
+        conn: &C,
+        _user: &str,
+        id: i64,
+        value: &Value,
+    ) -> Result<()> {
+        let result = Self::scope(Entity::find_by_id(id))?.one(conn).await?;
         if result.is_none() {
             return Err(Error::NotFound.into());
         }
-        let mut data: ActiveModel = result.unwrap().into();
+        let model = result.unwrap();
+        // 与其它数据表一致，需提交updated_at，记录已被修改则不更新
+        let expected = json_get_date_time(value, Column::UpdatedAt.as_str())?
+            .ok_or(Error::PreconditionRequired)?;
+        if model.updated_at != expected {
+            return Err(Error::Outdated {
+                items: diff_json(&serde_json::to_value(&model)?, value),
+            }
+            .into());
+        }
+        let mut data: ActiveModel = model.into();
         if let Some(value) = json_get_i64(value, Column::Status.as_str())? {
             data.status = Set(value as i8);
         }
@@ -52,11 +274,76 @@ impl UserEntity {
         if let Some(value) = json_get_strings(value, Column::Groups.as_str())? {
             data.groups = Set(Some(json!(value)))
         }
-        data.update(conn).await?;
+        // 仅允许清除两步验证(如用户丢失设备)，设置需用户自行绑定
+        if value.get(Column::TotpSecret.as_str()) == Some(&Value::Null) {
+            data.totp_secret = Set(None);
+        }
+        // 更新时以updated_at为条件，避免查询后被其它请求修改
+        let data = ActiveModelBehavior::before_save(data, conn, false).await?;
+        let result = Entity::update(data)
+            .filter(Column::UpdatedAt.eq(expected))
+            .exec(conn)
+            .await;
+        if let Err(DbErr::RecordNotUpdated) = result {
+            let current = Self::scope(Entity::find_by_id(id))?
+                .one(conn)
+                .await?
+                .ok_or(Error::NotFound)?;
+            return Err(Error::Outdated {
+                items: diff_json(&serde_json::to_value(&current)?, value),
+            }
+            .into());
+        }
+        invalidate_cached_user(&result?.account).await;
         Ok(())
     }
+    pub fn sensitivity() -> EntitySensitivity {
+        // 密码及totp密钥替换后无法登录，本地使用需重新设置
+        EntitySensitivity::new(
+            &[
+                (Column::Account, Anonymize::Account),
+                (Column::Password, Anonymize::Hash),
+                (Column::Remark, Anonymize::Null),
+                (Column::Email, Anonymize::Email),
+                (Column::DisplayAccount, Anonymize::Null),
+                (Column::TotpSecret, Anonymize::Null),
+            ],
+            &[
+                Column::Id,
+                Column::Status,
+                Column::CreatedAt,
+                Column::UpdatedAt,
+                Column::Roles,
+                Column::Groups,
+                Column::MergedInto,
+                Column::TenantId,
+            ],
+        )
+    }
+    pub fn profiles() -> EntityProfiles {
+        let list = [
+            Column::Id,
+            Column::Account,
+            Column::DisplayAccount,
+            Column::Status,
+            Column::Email,
+            Column::Roles,
+            Column::Groups,
+            Column::CreatedAt,
+            Column::UpdatedAt,
+        ];
+        let mut detail = list.to_vec();
+        detail.extend([Column::Remark, Column::MergedInto, Column::TenantId]);
+        // 密码与两步验证密钥不允许输出
+        EntityProfiles::new(
+            &list,
+            &detail,
+            &detail,
+            &[Column::Password, Column::TotpSecret],
+        )
+    }
     pub fn description() -> EntityDescription {
-        let roles = [ROLE_SU, ROLE_ADMIN];
+        let roles = [ROLE_SU, ROLE_ADMIN, ROLE_READONLY];
         let role_options = roles
             .iter()
             .map(|item| EntityItemOption {
@@ -81,6 +368,13 @@ impl UserEntity {
                 readonly: true,
                 ..Default::default()
             },
+            EntityItemDescription {
+                name: Column::DisplayAccount.to_string(),
+                label: "显示账号".to_string(),
+                category: EntityItemCategory::Text,
+                readonly: true,
+                ..Default::default()
+            },
             EntityItemDescription {
                 name: Column::Status.to_string(),
                 label: "状态".to_string(),
@@ -139,28 +433,42 @@ impl UserEntity {
             ..Default::default()
         }
     }
-    pub async fn find_by_id(_user: &str, id: i64) -> Result<Option<Value>> {
+    pub async fn find_by_id(_user: &str, id: i64, fields: &[String]) -> Result<Option<Value>> {
         let conn = get_database().await;
-        let item = Entity::find_by_id(id)
-            .select_only()
-            .columns(Column::iter().filter(|col| !matches!(col, Column::Password)))
+        let item = Self::select_columns(Self::scope(Entity::find_by_id(id))?, fields)
             .into_json()
             .one(conn)
             .await?;
         Ok(item)
     }
+    pub async fn list_after(
+        _user: &str,
+        params: &ListCountParams,
+        after: i64,
+        limit: u64,
+    ) -> Result<Vec<Value>> {
+        let mut sql = Self::scope(Entity::find())?;
+        if let Some(keyword) = &params.keyword {
+            let cond = Condition::any()
+                .add(Column::Account.contains(normalize_account(keyword)))
+                .add(Column::Email.contains(keyword));
+            sql = sql.filter(cond);
+        }
+        let sql = sql.filter(Column::Id.gt(after)).order_by_asc(Column::Id);
+        let sql = Self::select_columns(sql, &params.get_fields());
+        guarded_fetch_page(sql, limit, 0).await
+    }
     pub async fn list_count(_user: &str, params: &ListCountParams) -> Result<(i64, Vec<Value>)> {
         // TODO 判断权限
-        let conn = get_database().await;
-        let mut sql = Entity::find();
+        let mut sql = Self::scope(Entity::find())?;
         if let Some(keyword) = &params.keyword {
             let cond = Condition::any()
-                .add(Column::Account.contains(keyword))
+                .add(Column::Account.contains(normalize_account(keyword)))
                 .add(Column::Email.contains(keyword));
             sql = sql.filter(cond);
         }
         let page_count = if params.counted {
-            let count = sql.clone().count(conn).await?;
+            let count = guarded_count(sql.clone()).await?;
             let mut page_count = count / params.page_size;
             if count % params.page_size != 0 {
                 page_count += 1;
@@ -170,14 +478,54 @@ impl UserEntity {
             -1
         };
 
-        let items = sql
-            .select_only()
-            .columns(Column::iter().filter(|col| !matches!(col, Column::Password)))
-            .into_json()
-            .paginate(conn, params.page_size)
-            .fetch_page(params.page)
-            .await?;
+        let mut page = params.page;
+        if let Some(cursor) = params.get_cursor() {
+            sql = if cursor.prev {
+                sql.filter(Column::Id.gt(cursor.id))
+                    .order_by_asc(Column::Id)
+            } else {
+                sql.filter(Column::Id.lt(cursor.id))
+                    .order_by_desc(Column::Id)
+            };
+            page = 0;
+        }
+        let sql = Self::select_columns(sql, &params.get_fields());
+        let items = guarded_fetch_page(sql, params.page_size, page).await?;
 
         Ok((page_count, items))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CachedUser;
+    use crate::entities::users::Model;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn cached_user() {
+        let user: CachedUser = Model {
+            id: 1,
+            status: 1,
+            created_at: Default::default(),
+            updated_at: Default::default(),
+            account: "tree".to_string(),
+            password: "password-hash".to_string(),
+            roles: Some(json!(["admin"])),
+            groups: None,
+            remark: None,
+            email: None,
+            display_account: None,
+            merged_into: None,
+            tenant_id: 1,
+            totp_secret: Some("totp-secret".to_string()),
+        }
+        .into();
+        assert_eq!(vec!["admin".to_string()], user.get_roles().unwrap());
+        assert_eq!(true, user.totp_enabled);
+        let data = serde_json::to_string(&user).unwrap();
+        assert_eq!(false, data.contains("password-hash"));
+        assert_eq!(false, data.contains("totp-secret"));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::cache::get_default_redis_cache;
+use crate::error::{HttpError, HttpResult};
+use crate::util;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+// 草稿的有效期
+const DRAFT_TTL: Duration = Duration::from_secs(24 * 3600);
+/// 单个草稿的最大字节数
+pub const DRAFT_MAX_SIZE: usize = 256 * 1024;
+// 每个账号最多的草稿数量
+const DRAFT_LIMIT: usize = 50;
+// 新建记录的草稿标识前缀，后接客户端生成的id
+const NEW_DRAFT_PREFIX: &str = "new-";
+
+/// 草稿的基本信息，用于客户端提示继续编辑
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DraftInfo {
+    pub entity: String,
+    // 记录的id，或新建记录时为`new-`开头的草稿id
+    pub key: String,
+    pub size: usize,
+    pub updated_at: i64,
+}
+
+fn get_draft_key(account: &str, entity: &str, key: &str) -> String {
+    format!("draft:{account}:{entity}:{key}")
+}
+
+fn get_account_key(account: &str) -> String {
+    format!("draft:list:{account}")
+}
+
+/// 校验草稿标识，为记录的id或新建记录的草稿id(`new-`开头)
+pub fn validate_draft_key(key: &str) -> HttpResult<()> {
+    let valid = if let Some(id) = key.strip_prefix(NEW_DRAFT_PREFIX) {
+        !id.is_empty()
+            && id.len() <= 64
+            && id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    } else {
+        key.parse::<i64>().map(|id| id > 0).unwrap_or_default()
+    };
+    if !valid {
+        return Err(HttpError::new_with_category(
+            "Draft key is invalid",
+            "draft",
+        ));
+    }
+    Ok(())
+}
+
+async fn get_draft_infos(account: &str) -> HttpResult<Vec<DraftInfo>> {
+    let infos = get_default_redis_cache()
+        .get_struct(&get_account_key(account))
+        .await?;
+    Ok(infos.unwrap_or_default())
+}
+
+async fn set_draft_infos(account: &str, infos: &[DraftInfo]) -> HttpResult<()> {
+    get_default_redis_cache()
+        .set_struct(&get_account_key(account), &infos, Some(DRAFT_TTL))
+        .await?;
+    Ok(())
+}
+
+/// 保存草稿，仅校验大小及是否为json，不校验字段
+pub async fn save_draft(account: &str, entity: &str, key: &str, data: &[u8]) -> HttpResult<()> {
+    validate_draft_key(key)?;
+    if data.len() > DRAFT_MAX_SIZE {
+        return Err(HttpError::new_with_category_status(
+            &format!(
+                "Draft size {} exceeds the limit {}",
+                util::format_bytes(data.len() as u64, true),
+                util::format_bytes(DRAFT_MAX_SIZE as u64, true)
+            ),
+            "draft",
+            413,
+        ));
+    }
+    let value: Value = serde_json::from_slice(data)
+        .map_err(|err| HttpError::new_with_category(&err.to_string(), "draft"))?;
+    get_default_redis_cache()
+        .set_struct(
+            &get_draft_key(account, entity, key),
+            &value,
+            Some(DRAFT_TTL),
+        )
+        .await?;
+    let mut infos = get_draft_infos(account).await?;
+    infos.retain(|item| item.entity != entity || item.key != key);
+    infos.insert(
+        0,
+        DraftInfo {
+            entity: entity.to_string(),
+            key: key.to_string(),
+            size: data.len(),
+            updated_at: util::timestamp(),
+        },
+    );
+    // 超出数量的最早的草稿删除
+    for item in infos.iter().skip(DRAFT_LIMIT) {
+        get_default_redis_cache()
+            .del(&get_draft_key(account, &item.entity, &item.key))
+            .await?;
+    }
+    infos.truncate(DRAFT_LIMIT);
+    set_draft_infos(account, &infos).await
+}
+
+/// 获取草稿，仅可获取当前账号的
+pub async fn get_draft(account: &str, entity: &str, key: &str) -> HttpResult<Option<Value>> {
+    validate_draft_key(key)?;
+    let value = get_default_redis_cache()
+        .get_struct(&get_draft_key(account, entity, key))
+        .await?;
+    Ok(value)
+}
+
+/// 删除草稿，不存在时忽略
+pub async fn delete_draft(account: &str, entity: &str, key: &str) -> HttpResult<()> {
+    let mut infos = get_draft_infos(account).await?;
+    let count = infos.len();
+    infos.retain(|item| item.entity != entity || item.key != key);
+    if infos.len() == count {
+        return Ok(());
+    }
+    get_default_redis_cache()
+        .del(&get_draft_key(account, entity, key))
+        .await?;
+    set_draft_infos(account, &infos).await
+}
+
+/// 账号未提交的草稿，已过期的不返回
+pub async fn list_drafts(account: &str) -> HttpResult<Vec<DraftInfo>> {
+    let infos = get_draft_infos(account).await?;
+    let expired_at = util::timestamp() - DRAFT_TTL.as_secs() as i64;
+    Ok(infos
+        .into_iter()
+        .filter(|item| item.updated_at > expired_at)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::validate_draft_key;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn draft_key() {
+        assert_eq!(true, validate_draft_key("12").is_ok());
+        assert_eq!(true, validate_draft_key("new-8f3a_b").is_ok());
+        assert_eq!(false, validate_draft_key("0").is_ok());
+        assert_eq!(false, validate_draft_key("new-").is_ok());
+        assert_eq!(false, validate_draft_key("new-a:b").is_ok());
+        assert_eq!(false, validate_draft_key("abc").is_ok());
+    }
+}
@@ -1,6 +1,9 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
+use async_trait::async_trait;
+use chrono::Utc;
 use sea_orm::entity::prelude::*;
+use sea_orm::ActiveValue;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
@@ -10,14 +13,15 @@ pub struct Model {
     pub id: i64,
     pub created_at: DateTimeUtc,
     pub updated_at: DateTimeUtc,
-    #[sea_orm(unique)]
     pub name: String,
     pub size: i64,
     pub content_type: String,
     #[sea_orm(column_type = "custom(\"mediumblob\")")]
     pub data: String,
+    pub hash: String,
     pub updater: Option<String>,
     pub creator: String,
+    pub tenant_id: i64,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
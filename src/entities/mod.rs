@@ -1,8 +1,11 @@
 //! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
 
-pub mod prelude;
-
+pub mod client_errors;
 pub mod constants;
+pub mod data_issues;
 pub mod files;
+pub mod request_archives;
 pub mod settings;
+pub mod tasks;
+pub mod tenants;
 pub mod users;
@@ -1,5 +0,0 @@
-//! `SeaORM` Entity, @generated by sea-orm-codegen 1.0.0
-
-pub use super::files::Entity as Files;
-pub use super::settings::Entity as Settings;
-pub use super::users::Entity as Users;
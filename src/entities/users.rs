@@ -22,6 +22,10 @@ pub struct Model {
     pub groups: Option<Json>,
     pub remark: Option<String>,
     pub email: Option<String>,
+    pub display_account: Option<String>,
+    pub merged_into: Option<i64>,
+    pub tenant_id: i64,
+    pub totp_secret: Option<String>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
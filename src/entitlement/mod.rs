@@ -0,0 +1,174 @@
+use crate::config::must_new_basic_config;
+use chrono::{DateTime, Duration, Utc};
+use once_cell::sync::Lazy;
+use ring::signature::{UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+/// 实体数据导出，仅授权版可用
+pub static FEATURE_ENTITY_EXPORT: &str = "entity_export";
+
+/// 无授权文件时使用社区版
+pub static LICENSE_MODE_COMMUNITY: &str = "community";
+/// 授权文件校验通过
+pub static LICENSE_MODE_LICENSED: &str = "licensed";
+/// 授权文件无效，使用社区版功能
+pub static LICENSE_MODE_INVALID: &str = "invalid";
+
+// 社区版默认启用的功能，需授权的功能不可添加至此
+static COMMUNITY_FEATURES: &[&str] = &[];
+
+// 校验授权文件的公钥(hex)，编译时设置
+const LICENSE_PUBLIC_KEY: Option<&str> = option_env!("TIBBA_LICENSE_PUBLIC_KEY");
+
+// 授权即将过期的提醒天数
+const EXPIRY_WARNING_DAYS: i64 = 30;
+
+#[derive(Debug, Deserialize)]
+struct LicenseFile {
+    // 授权信息(json字符串)
+    payload: String,
+    // 对payload的Ed25519签名(hex)
+    signature: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct LicensePayload {
+    customer: String,
+    expired_at: DateTime<Utc>,
+    features: Vec<String>,
+}
+
+/// 当前部署的授权信息
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Entitlements {
+    pub mode: String,
+    pub customer: String,
+    pub expired_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    features: Vec<String>,
+}
+
+impl Entitlements {
+    fn community(mode: &str) -> Self {
+        Entitlements {
+            mode: mode.to_string(),
+            features: COMMUNITY_FEATURES
+                .iter()
+                .map(|item| item.to_string())
+                .collect(),
+            ..Default::default()
+        }
+    }
+    fn is_expired(&self) -> bool {
+        self.expired_at
+            .map(|value| value < Utc::now())
+            .unwrap_or_default()
+    }
+    /// 判断是否有该功能的授权，授权过期后仅有社区版功能
+    pub fn has(&self, name: &str) -> bool {
+        if self.is_expired() {
+            return COMMUNITY_FEATURES.contains(&name);
+        }
+        self.features.iter().any(|item| item == name)
+    }
+}
+
+fn load_license(file: &str) -> Result<LicensePayload, String> {
+    let public_key = LICENSE_PUBLIC_KEY.ok_or("public key is not embedded")?;
+    let public_key = hex::decode(public_key).map_err(|err| err.to_string())?;
+    let data = std::fs::read(file).map_err(|err| err.to_string())?;
+    let license: LicenseFile = serde_json::from_slice(&data).map_err(|err| err.to_string())?;
+    let signature = hex::decode(&license.signature).map_err(|err| err.to_string())?;
+    UnparsedPublicKey::new(&ED25519, public_key)
+        .verify(license.payload.as_bytes(), &signature)
+        .map_err(|_| "signature is invalid".to_string())?;
+    serde_json::from_str(&license.payload).map_err(|err| err.to_string())
+}
+
+fn new_entitlements() -> Entitlements {
+    let file = must_new_basic_config().license_file;
+    if file.is_empty() {
+        return Entitlements::community(LICENSE_MODE_COMMUNITY);
+    }
+    match load_license(&file) {
+        Ok(payload) => {
+            let mut features: Vec<String> = COMMUNITY_FEATURES
+                .iter()
+                .map(|item| item.to_string())
+                .collect();
+            features.extend(payload.features);
+            info!(
+                category = "license",
+                customer = payload.customer,
+                expired_at = payload.expired_at.to_rfc3339(),
+                features = features.join(","),
+            );
+            Entitlements {
+                mode: LICENSE_MODE_LICENSED.to_string(),
+                customer: payload.customer,
+                expired_at: Some(payload.expired_at),
+                features,
+            }
+        }
+        Err(err) => {
+            // 授权文件无效不影响启动，仅使用社区版功能
+            error!(category = "license", file, error = err);
+            Entitlements::community(LICENSE_MODE_INVALID)
+        }
+    }
+}
+
+static ENTITLEMENTS: Lazy<Entitlements> = Lazy::new(new_entitlements);
+
+/// 获取当前部署的授权信息
+pub fn entitlements() -> &'static Entitlements {
+    &ENTITLEMENTS
+}
+
+/// 每日检查授权是否即将过期，即将过期时输出告警日志
+pub fn start_expiry_warning() {
+    let Some(expired_at) = entitlements().expired_at else {
+        return;
+    };
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(24 * 3600));
+        loop {
+            interval.tick().await;
+            let remaining = expired_at - Utc::now();
+            if remaining < Duration::days(EXPIRY_WARNING_DAYS) {
+                warn!(
+                    category = "license",
+                    expired_at = expired_at.to_rfc3339(),
+                    remaining_days = remaining.num_days(),
+                    "license is about to expire"
+                );
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entitlements, FEATURE_ENTITY_EXPORT, LICENSE_MODE_COMMUNITY};
+    use chrono::{Duration, Utc};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn has_feature() {
+        let community = Entitlements::community(LICENSE_MODE_COMMUNITY);
+        assert_eq!(false, community.has(FEATURE_ENTITY_EXPORT));
+
+        let mut licensed = Entitlements {
+            mode: "licensed".to_string(),
+            expired_at: Some(Utc::now() + Duration::days(1)),
+            features: vec![FEATURE_ENTITY_EXPORT.to_string()],
+            ..Default::default()
+        };
+        assert_eq!(true, licensed.has(FEATURE_ENTITY_EXPORT));
+
+        // 过期后仅有社区版功能
+        licensed.expired_at = Some(Utc::now() - Duration::days(1));
+        assert_eq!(false, licensed.has(FEATURE_ENTITY_EXPORT));
+    }
+}
@@ -1,12 +1,19 @@
+use crate::config::{must_new_error_config, ErrorConfig};
+use crate::task_local::TRACE_ID;
+use crate::util::is_production;
 use axum::http::HeaderValue;
 use axum::http::{header, Method, StatusCode, Uri};
 use axum::response::{IntoResponse, Response};
 use axum::{BoxError, Json};
-use sea_orm::DbErr;
+use once_cell::sync::Lazy;
+use sea_orm::sqlx::mysql::MySqlDatabaseError;
+use sea_orm::{DbErr, RuntimeErr, SqlErr};
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use tracing::error;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct HttpError {
     // 出错信息
     pub message: String,
@@ -18,8 +25,16 @@ pub struct HttpError {
     pub status: u16,
     // 其它额外信息
     pub extra: Option<Vec<String>>,
+    // 建议的重试等待时长(ms)，同时设置Retry-After响应头
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_ms: Option<u64>,
+    // 是否非预期的异常，生产环境中不返回其出错信息
+    #[serde(skip)]
+    pub exception: bool,
 }
 
+static ERROR_CONFIG: Lazy<ErrorConfig> = Lazy::new(must_new_error_config);
+
 pub type HttpResult<T> = Result<T, HttpError>;
 
 impl Default for HttpError {
@@ -32,13 +47,51 @@ impl Default for HttpError {
             status: StatusCode::BAD_REQUEST.as_u16(),
             code: "".to_string(),
             extra: None,
+            retry_after_ms: None,
+            exception: false,
         }
     }
 }
 
+// 语句执行超过max_execution_time的mysql出错码
+const MYSQL_QUERY_TIMEOUT: u16 = 3024;
+
+// 获取mysql的出错码，非数据库返回的出错则为None
+fn get_mysql_error_number(err: &DbErr) -> Option<u16> {
+    match err {
+        DbErr::Exec(RuntimeErr::SqlxError(sea_orm::sqlx::Error::Database(e)))
+        | DbErr::Query(RuntimeErr::SqlxError(sea_orm::sqlx::Error::Database(e))) => e
+            .try_downcast_ref::<MySqlDatabaseError>()
+            .map(|e| e.number()),
+        _ => None,
+    }
+}
+
 impl From<DbErr> for HttpError {
     fn from(err: DbErr) -> Self {
-        HttpError::new_with_category(&err.to_string(), "db")
+        // 唯一键冲突(mysql 1062)转换为409
+        if let Some(SqlErr::UniqueConstraintViolation(message)) = err.sql_err() {
+            let mut he = HttpError::new_with_category_status("Record already exists", "db", 409);
+            he.code = "conflict".to_string();
+            // Duplicate entry 'xxx' for key 'users.user_account'
+            if let Some(key) = message.split("for key ").nth(1) {
+                he.add_extra(key.trim_matches('\''));
+            }
+            return he;
+        }
+        // 语句执行超时(mysql 3024)
+        if get_mysql_error_number(&err) == Some(MYSQL_QUERY_TIMEOUT) {
+            let mut he = HttpError::new_with_category_status(
+                "Query is too expensive, please narrow the filter",
+                "db",
+                422,
+            );
+            he.code = "query_too_expensive".to_string();
+            return he;
+        }
+        let mut he = HttpError::new_with_category(&err.to_string(), "db");
+        he.exception = true;
+        he
     }
 }
 impl From<serde_json::Error> for HttpError {
@@ -81,6 +134,34 @@ impl HttpError {
             ..Default::default()
         }
     }
+    /// 设置建议的重试等待时长
+    pub fn with_retry_after(mut self, value: Duration) -> Self {
+        self.retry_after_ms = Some(value.as_millis() as u64);
+        self
+    }
+    /// 是否需要隐藏出错信息，面向用户的类别不隐藏
+    fn should_sanitize(&self, config: &ErrorConfig) -> bool {
+        if config.public_categories.contains(&self.category) {
+            return false;
+        }
+        self.exception || config.internal_categories.contains(&self.category)
+    }
+    /// 替换为通用的出错信息，仅保留出错码及请求id，便于根据日志排查
+    fn sanitize(&self, trace_id: &str) -> HttpError {
+        let code = if self.code.is_empty() {
+            "internal_error"
+        } else {
+            &self.code
+        };
+        HttpError {
+            message: format!("Internal error, code: {code}, request id: {trace_id}"),
+            category: self.category.clone(),
+            code: code.to_string(),
+            status: self.status,
+            retry_after_ms: self.retry_after_ms,
+            ..Default::default()
+        }
+    }
     pub fn add_extra(&mut self, value: &str) {
         if self.extra.is_none() {
             self.extra = Some(vec![value.to_string()]);
@@ -91,20 +172,44 @@ impl HttpError {
     }
 }
 
-impl IntoResponse for HttpError {
-    fn into_response(self) -> Response {
+impl HttpError {
+    fn into_response_with(self, sanitize: bool) -> Response {
         let status = match StatusCode::from_u16(self.status) {
             Ok(status) => status,
             Err(_) => StatusCode::BAD_REQUEST,
         };
+        let retry_after_ms = self.retry_after_ms;
+        // 隐藏出错信息时，原始出错保存在extensions中，用于日志及告警
+        let mut res = if sanitize {
+            let trace_id = TRACE_ID.try_with(|id| id.clone()).unwrap_or_default();
+            let mut res = Json(self.sanitize(&trace_id)).into_response();
+            res.extensions_mut().insert(self);
+            res
+        } else {
+            Json(self).into_response()
+        };
         // 对于出错设置为no-cache
-        let mut res = Json(self).into_response();
         res.headers_mut()
             .insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        // Retry-After仅支持秒，向上取整
+        if let Some(ms) = retry_after_ms {
+            res.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(ms.div_ceil(1000).max(1)),
+            );
+        }
         (status, res).into_response()
     }
 }
 
+impl IntoResponse for HttpError {
+    fn into_response(self) -> Response {
+        // 仅生产环境隐藏，开发与测试环境返回完整的出错信息
+        let sanitize = is_production() && self.should_sanitize(&ERROR_CONFIG);
+        self.into_response_with(sanitize)
+    }
+}
+
 pub async fn handle_error(
     // `Method` and `Uri` are extractors so they can be used here
     method: Method,
@@ -116,5 +221,56 @@ pub async fn handle_error(
     if err.is::<tower::timeout::error::Elapsed>() {
         return HttpError::new_with_category_status("Request took too long", "timeout", 408);
     }
-    HttpError::new(&err.to_string())
+    let mut he = HttpError::new(&err.to_string());
+    he.exception = true;
+    he
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpError;
+    use crate::config::ErrorConfig;
+    use axum::body::to_bytes;
+    use pretty_assertions::assert_eq;
+    use sea_orm::{DbErr, RuntimeErr};
+
+    #[tokio::test]
+    async fn sanitize_error() {
+        let config = ErrorConfig {
+            internal_categories: vec!["db".to_string()],
+            public_categories: vec!["validate".to_string()],
+        };
+        let sql_err = "error returned from database: 1064 (42000): You have an error in your SQL syntax near 'SELECT `password` FROM `users`'";
+        let err: HttpError = DbErr::Query(RuntimeErr::Internal(sql_err.to_string())).into();
+        assert_eq!(true, err.exception);
+        assert_eq!(true, err.should_sanitize(&config));
+
+        let res = err.into_response_with(true);
+        assert_eq!(400, res.status().as_u16());
+        // 原始出错保留在extensions中，用于记录日志
+        let original = res.extensions().get::<HttpError>().unwrap();
+        assert_eq!(true, original.message.contains("SELECT `password`"));
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        let body = std::str::from_utf8(&body).unwrap();
+        assert_eq!(
+            r#"{"message":"Internal error, code: internal_error, request id: ","category":"db","code":"internal_error","status":400,"extra":null}"#,
+            body
+        );
+        assert_eq!(false, body.contains("SELECT"));
+
+        // 开发及测试环境返回完整出错信息
+        let err: HttpError = DbErr::Query(RuntimeErr::Internal(sql_err.to_string())).into();
+        let res = err.into_response_with(false);
+        assert_eq!(true, res.extensions().get::<HttpError>().is_none());
+        let body = to_bytes(res.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(true, std::str::from_utf8(&body).unwrap().contains("SELECT"));
+
+        let mut err = HttpError::new_with_category("Account is invalid", "validate");
+        err.exception = true;
+        assert_eq!(false, err.should_sanitize(&config));
+        assert_eq!(
+            false,
+            HttpError::new_with_category("Record not found", "user").should_sanitize(&config)
+        );
+    }
 }
@@ -0,0 +1,326 @@
+use crate::db::{
+    diff_json, find_valid_settings_by_category, register_entity_hooks, EntityHooks, HookContext,
+    TABLE_NAME_SETTINGS,
+};
+use crate::error::{HttpError, HttpResult};
+use crate::middleware::Claim;
+use crate::util;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use axum_extra::extract::cookie::CookieJar;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// 功能开关对应的配置分类
+pub static FEATURE_CATEGORY: &str = "feature";
+// 功能开关配置的缓存时长(秒)
+const FEATURE_CACHE_TTL: i64 = 60;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+struct FeatureFlagData {
+    // 灰度比例(0-100)，未设置则为全量
+    percentage: Option<u8>,
+    // 指定启用的账号
+    accounts: Option<Vec<String>>,
+    // 指定启用的角色
+    roles: Option<Vec<String>>,
+    // 指定启用的群组
+    groups: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct FeatureFlag {
+    name: String,
+    data: FeatureFlagData,
+}
+
+#[derive(Default)]
+struct FeatureFlagCache {
+    loaded_at: i64,
+    flags: Vec<FeatureFlag>,
+}
+
+static FEATURE_FLAG_CACHE: Lazy<RwLock<FeatureFlagCache>> =
+    Lazy::new(|| RwLock::new(FeatureFlagCache::default()));
+
+async fn load_feature_flags() -> HttpResult<Vec<FeatureFlag>> {
+    let settings = find_valid_settings_by_category(FEATURE_CATEGORY).await?;
+    let mut flags = vec![];
+    for item in settings {
+        let data = if item.data.trim().is_empty() {
+            FeatureFlagData::default()
+        } else {
+            match serde_json::from_str::<FeatureFlagData>(&item.data) {
+                Ok(data) => data,
+                Err(err) => {
+                    // 配置出错的忽略，不影响其它开关
+                    error!(
+                        category = "feature_flag",
+                        name = item.name,
+                        error = err.to_string(),
+                    );
+                    continue;
+                }
+            }
+        };
+        flags.push(FeatureFlag {
+            name: item.name,
+            data,
+        });
+    }
+    Ok(flags)
+}
+
+// 获取功能开关配置，缓存过期后重新从数据库加载
+// 若加载失败则继续使用原有配置
+async fn get_feature_flags() -> Vec<FeatureFlag> {
+    let now = util::timestamp();
+    {
+        let cache = FEATURE_FLAG_CACHE.read().await;
+        if now - cache.loaded_at < FEATURE_CACHE_TTL {
+            return cache.flags.clone();
+        }
+    }
+    // 在锁外查询数据库，避免查询期间阻塞其它请求读取配置，
+    // 并发的请求有可能重复加载
+    let result = load_feature_flags().await;
+    let mut cache = FEATURE_FLAG_CACHE.write().await;
+    // 有可能已被其它请求更新
+    if now - cache.loaded_at < FEATURE_CACHE_TTL {
+        return cache.flags.clone();
+    }
+    match result {
+        Ok(flags) => {
+            // 配置有变化时记录日志
+            if flags != cache.flags {
+                let names: Vec<String> = flags.iter().map(|item| item.name.clone()).collect();
+                info!(
+                    category = "feature_flag",
+                    features = names.join(","),
+                    "feature flags changed"
+                );
+            }
+            cache.flags = flags;
+        }
+        Err(err) => {
+            error!(category = "feature_flag", error = err.message);
+        }
+    }
+    cache.loaded_at = now;
+    cache.flags.clone()
+}
+
+fn is_feature_setting(value: &Value) -> bool {
+    value.get("category").and_then(Value::as_str) == Some(FEATURE_CATEGORY)
+}
+
+// 功能开关的配置变更后记录审计日志并清除当前实例的缓存，
+// 其它实例仍在缓存过期后更新
+struct FeatureFlagHooks {}
+
+impl FeatureFlagHooks {
+    async fn expire(&self) {
+        FEATURE_FLAG_CACHE.write().await.loaded_at = 0;
+    }
+}
+
+fn get_setting_name(value: &Value) -> &str {
+    value
+        .get("name")
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+}
+
+#[async_trait]
+impl EntityHooks for FeatureFlagHooks {
+    async fn after_insert(&self, ctx: &HookContext, id: i64, value: &Value) -> HttpResult<()> {
+        if is_feature_setting(value) {
+            let data = value
+                .get("data")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            info!(
+                category = "feature_flag_audit",
+                traceId = ctx.trace_id,
+                operator = ctx.user,
+                action = "insert",
+                id,
+                name = get_setting_name(value),
+                data,
+            );
+            self.expire().await;
+        }
+        Ok(())
+    }
+    async fn after_update(
+        &self,
+        ctx: &HookContext,
+        id: i64,
+        old: &Value,
+        new: &Value,
+    ) -> HttpResult<()> {
+        // 修改分类的也需要清除
+        if is_feature_setting(old) || is_feature_setting(new) {
+            let diff = serde_json::to_string(&diff_json(old, new)).unwrap_or_default();
+            info!(
+                category = "feature_flag_audit",
+                traceId = ctx.trace_id,
+                operator = ctx.user,
+                action = "update",
+                id,
+                name = get_setting_name(new),
+                diff,
+            );
+            self.expire().await;
+        }
+        Ok(())
+    }
+}
+
+/// 注册功能开关配置的变更处理
+pub fn register_hooks() {
+    register_entity_hooks(TABLE_NAME_SETTINGS, Arc::new(FeatureFlagHooks {}));
+}
+
+/// 获取当前已配置的功能开关名称
+pub async fn get_feature_names() -> Vec<String> {
+    get_feature_flags()
+        .await
+        .into_iter()
+        .map(|item| item.name)
+        .collect()
+}
+
+// 根据名称与标识计算分桶，保证同一标识的结果稳定
+fn get_bucket(name: &str, id: &str) -> u8 {
+    let hash = util::sha256(format!("{name}:{id}").as_bytes());
+    let value = u32::from_str_radix(&hash[0..8], 16).unwrap_or_default();
+    (value % 100) as u8
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct FeatureFlags {
+    features: Vec<String>,
+}
+
+impl FeatureFlags {
+    /// 当前请求是否启用该功能
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.features.iter().any(|item| item == name)
+    }
+}
+
+// 角色与群组使用session中登录时记录的，不再查询用户信息
+fn evaluate(flags: &[FeatureFlag], claim: &Claim, device_id: &str) -> FeatureFlags {
+    let account = claim.get_account();
+    let account = account.as_str();
+    let roles = claim.get_roles();
+    let groups = claim.get_groups();
+    // 未登录则使用设备ID分桶
+    let id = if account.is_empty() {
+        device_id
+    } else {
+        account
+    };
+
+    let mut features = vec![];
+    for flag in flags.iter() {
+        let data = &flag.data;
+        let has_allowlist =
+            data.accounts.is_some() || data.roles.is_some() || data.groups.is_some();
+        let mut allowed = false;
+        if let Some(values) = &data.accounts {
+            allowed = allowed || (!account.is_empty() && values.iter().any(|v| v == account));
+        }
+        if let Some(values) = &data.roles {
+            allowed = allowed || roles.iter().any(|v| values.contains(v));
+        }
+        if let Some(values) = &data.groups {
+            allowed = allowed || groups.iter().any(|v| values.contains(v));
+        }
+        // 指定的账号、角色或群组直接启用，其它的按灰度比例
+        let enabled = if allowed {
+            true
+        } else if let Some(percentage) = data.percentage {
+            !id.is_empty() && get_bucket(&flag.name, id) < percentage
+        } else {
+            !has_allowlist
+        };
+        if enabled {
+            features.push(flag.name.clone());
+        }
+    }
+    FeatureFlags { features }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for FeatureFlags
+where
+    S: Send + Sync,
+{
+    type Rejection = HttpError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(flags) = parts.extensions.get::<FeatureFlags>() {
+            return Ok(flags.clone());
+        }
+        let claim = Claim::from_request_parts(parts, state).await?;
+        let jar = CookieJar::from_headers(&parts.headers);
+        let device_id = util::get_device_id_from_cookie(&jar);
+        let flags = evaluate(&get_feature_flags().await, &claim, &device_id);
+        parts.extensions.insert(flags.clone());
+        Ok(flags)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{evaluate, FeatureFlag, FeatureFlagData};
+    use crate::middleware::Claim;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn evaluate_from_claim() {
+        let flags = vec![
+            FeatureFlag {
+                name: "all".to_string(),
+                data: FeatureFlagData::default(),
+            },
+            FeatureFlag {
+                name: "admin_only".to_string(),
+                data: FeatureFlagData {
+                    roles: Some(vec!["admin".to_string()]),
+                    ..Default::default()
+                },
+            },
+            FeatureFlag {
+                name: "beta_group".to_string(),
+                data: FeatureFlagData {
+                    groups: Some(vec!["beta".to_string()]),
+                    ..Default::default()
+                },
+            },
+        ];
+        let mut claim = Claim::new("tree", 1);
+        let features = evaluate(&flags, &claim, "device");
+        assert_eq!(vec!["all".to_string()], features.features);
+        assert_eq!(true, features.is_enabled("all"));
+        assert_eq!(false, features.is_enabled("admin_only"));
+
+        // 角色与群组由session中获取
+        claim.set_roles_groups(vec!["admin".to_string()], vec!["beta".to_string()]);
+        assert_eq!(
+            vec![
+                "all".to_string(),
+                "admin_only".to_string(),
+                "beta_group".to_string()
+            ],
+            evaluate(&flags, &claim, "device").features
+        );
+    }
+}
@@ -0,0 +1,109 @@
+use crate::error::{HttpError, HttpResult};
+use crate::util::{self, is_development};
+use once_cell::sync::{Lazy, OnceCell};
+use serde::Serialize;
+use std::env;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::info;
+use tracing_subscriber::filter::Targets;
+use tracing_subscriber::{fmt, prelude::*, reload, Registry};
+
+static RELOAD_HANDLE: OnceCell<reload::Handle<Targets, Registry>> = OnceCell::new();
+// 启动时的日志过滤，修改后超时则恢复至此值
+static DEFAULT_FILTER: Lazy<String> =
+    Lazy::new(|| env::var("LOG_LEVEL").unwrap_or("info".to_string()));
+// 每次修改递增，用于判断恢复时是否已被再次修改
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+static CURRENT: Lazy<Mutex<LogFilter>> = Lazy::new(|| {
+    Mutex::new(LogFilter {
+        filter: DEFAULT_FILTER.clone(),
+        revert_at: None,
+    })
+});
+
+/// 当前生效的日志过滤
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LogFilter {
+    pub filter: String,
+    // 恢复至默认值的时间(秒)
+    pub revert_at: Option<i64>,
+}
+
+fn parse_filter(value: &str) -> HttpResult<Targets> {
+    Targets::from_str(value).map_err(|err| {
+        HttpError::new_with_category(&format!("Log filter is invalid: {err}"), "logger")
+    })
+}
+
+/// 初始化日志，日志过滤可在运行时调整，
+/// 如`info,tibba::db=debug`
+pub fn init_logger() {
+    let targets =
+        parse_filter(&DEFAULT_FILTER).unwrap_or_else(|_| Targets::from_str("info").unwrap());
+    let (filter, handle) = reload::Layer::new(targets);
+    let _ = RELOAD_HANDLE.set(handle);
+
+    let timer = fmt::time::OffsetTime::local_rfc_3339().unwrap_or_else(|_| {
+        fmt::time::OffsetTime::new(
+            time::UtcOffset::from_hms(0, 0, 0).unwrap(),
+            time::format_description::well_known::Rfc3339,
+        )
+    });
+
+    // TODO HTTPTraceLayer 需要通过trace的记录，有无可能仅针对此layer处理
+    // .with(httptrace::HTTPTraceLayer)
+    let subscriber = tracing_subscriber::registry()
+        .with(filter)
+        .with(fmt::layer().with_timer(timer).with_ansi(is_development()));
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+}
+
+/// 获取当前的日志过滤
+pub fn get_log_filter() -> LogFilter {
+    CURRENT.lock().map(|item| item.clone()).unwrap_or_default()
+}
+
+fn reload_filter(value: &str, revert_at: Option<i64>) -> HttpResult<()> {
+    let targets = parse_filter(value)?;
+    let handle = RELOAD_HANDLE.get().ok_or(HttpError::new_with_category(
+        "Logger is not initialized",
+        "logger",
+    ))?;
+    handle
+        .reload(targets)
+        .map_err(|err| HttpError::new_with_category(&err.to_string(), "logger"))?;
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = LogFilter {
+            filter: value.to_string(),
+            revert_at,
+        };
+    }
+    Ok(())
+}
+
+/// 修改日志过滤，若指定了ttl则到期后恢复为默认值
+pub fn set_log_filter(value: &str, ttl: Option<Duration>) -> HttpResult<()> {
+    let revert_at = ttl.map(|ttl| util::timestamp() + ttl.as_secs() as i64);
+    reload_filter(value, revert_at)?;
+    let generation = GENERATION.fetch_add(1, Ordering::Relaxed) + 1;
+    if let Some(ttl) = ttl {
+        tokio::spawn(async move {
+            tokio::time::sleep(ttl).await;
+            // 期间已被再次修改则不处理
+            if GENERATION.load(Ordering::Relaxed) != generation {
+                return;
+            }
+            if reload_filter(&DEFAULT_FILTER, None).is_ok() {
+                info!(
+                    category = "logger",
+                    filter = *DEFAULT_FILTER,
+                    "log filter reverted"
+                );
+            }
+        });
+    }
+    Ok(())
+}
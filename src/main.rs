@@ -1,63 +1,44 @@
-use axum::extract::FromRef;
-use axum::middleware::from_fn_with_state;
+use axum::middleware::{from_fn, from_fn_with_state};
 use axum::{error_handling::HandleErrorLayer, Router};
-use axum_extra::extract::cookie::Key;
 use once_cell::sync::Lazy;
 use sea_orm::{ActiveModelTrait, ActiveValue};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use std::{env, str::FromStr};
 use tokio::signal;
 use tower::ServiceBuilder;
-use tracing::Level;
 use tracing::{error, info};
-use tracing_subscriber::FmtSubscriber;
 
 use controller::new_router;
-use middleware::{access_log, entry, processing_limit};
+use middleware::{
+    access_log, deprecation, elevation_policy, entry, processing_limit, request_archive,
+    security_headers, session_policy, track_cancellation, verify_signature,
+};
 use state::get_app_state;
-use util::is_development;
 
+mod anonymize;
 mod asset;
 mod cache;
 mod config;
 mod controller;
 mod db;
+mod draft;
 mod entities;
+mod entitlement;
 mod error;
+mod feature;
 mod httptrace;
 mod keygrip;
+mod logger;
 mod middleware;
 mod request;
+mod selftest;
+mod sensitive;
+mod startup;
 mod state;
+mod task;
 mod task_local;
 mod util;
 
-fn init_logger() {
-    let mut level = Level::INFO;
-    if let Ok(log_level) = env::var("LOG_LEVEL") {
-        if let Ok(value) = Level::from_str(log_level.as_str()) {
-            level = value;
-        }
-    }
-
-    let timer = tracing_subscriber::fmt::time::OffsetTime::local_rfc_3339().unwrap_or_else(|_| {
-        tracing_subscriber::fmt::time::OffsetTime::new(
-            time::UtcOffset::from_hms(0, 0, 0).unwrap(),
-            time::format_description::well_known::Rfc3339,
-        )
-    });
-
-    // TODO HTTPTraceLayer 需要通过trace的记录，有无可能仅针对此layer处理
-    // .with(httptrace::HTTPTraceLayer)
-    let subscriber = FmtSubscriber::builder()
-        .with_max_level(level)
-        .with_timer(timer)
-        .with_ansi(is_development())
-        .finish();
-    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
-}
-
 async fn test() {
     println!("{}:{}", util::uuid(), util::uuid());
     #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -111,26 +92,57 @@ async fn test() {
     // data.save(db)
 }
 
-// 检查依赖服务失败直接panic
-async fn check_dependencies() -> Result<(), String> {
-    db::get_database()
-        .await
-        .ping()
-        .await
-        .map_err(|err| err.to_string())?;
-    request::must_get_httpbin_instance();
-    cache::redis_ping().await.map_err(|err| err.to_string())?;
-    Ok(())
+// 依赖服务的初始化，相互独立可并发执行
+fn init_tasks() -> Vec<startup::InitTask> {
+    vec![
+        startup::InitTask::new("database", async {
+            db::get_database()
+                .await
+                .ping()
+                .await
+                .map_err(|err| err.to_string())
+        }),
+        startup::InitTask::new("redis", async {
+            cache::redis_ping()
+                .await
+                .map(|_| ())
+                .map_err(|err| err.to_string())
+        }),
+        startup::InitTask::new("httpbin", async {
+            request::must_get_httpbin_instance();
+            Ok(())
+        }),
+    ]
 }
 
 #[tokio::main]
 async fn run() {
     // test().await;
-    if let Err(err) = check_dependencies().await {
-        error!(err, "check dependencies fail");
-        std::process::exit(1);
-    }
     let basic_config = config::must_new_basic_config();
+    // 初始化失败直接退出
+    let init_durations = match startup::run_init_tasks(
+        init_tasks(),
+        basic_config.init_parallelism,
+        basic_config.init_timeout,
+    )
+    .await
+    {
+        Ok(durations) => durations,
+        Err(err) => {
+            error!(err, "check dependencies fail");
+            std::process::exit(1);
+        }
+    };
+    // 启动时校验session与安全配置，配置有误则直接失败
+    config::must_new_session_config();
+    config::must_new_security_config();
+    // 未声明输出场景的字段仅输出告警
+    db::check_entity_profiles();
+    db::register_setting_hooks();
+    feature::register_hooks();
+    sensitive::register_hooks();
+    // 订阅缓存失效通知，其它实例更新数据后及时删除本实例的lru缓存
+    cache::spawn_invalidation_subscriber();
     let app_state = get_app_state();
 
     // build our application with a route
@@ -146,23 +158,70 @@ async fn run() {
                 .layer(from_fn_with_state(app_state, entry))
                 // 记录访问日志
                 .layer(from_fn_with_state(app_state, access_log))
+                // 记录客户端断开而取消的请求
+                .layer(from_fn(track_cancellation))
+                // 归档配置路由的请求
+                .layer(from_fn(request_archive))
                 // 正在处理请求的限制
-                .layer(from_fn_with_state(app_state, processing_limit)),
+                .layer(from_fn_with_state(app_state, processing_limit))
+                // 内部服务调用的签名校验
+                .layer(from_fn(verify_signature))
+                // 敏感操作需要二次验证
+                .layer(from_fn(elevation_policy))
+                // 根据路由规则校验是否需要登录
+                .layer(from_fn(session_policy))
+                // 安全相关的响应头
+                .layer(from_fn(security_headers))
+                // 废弃接口的响应头与调用记录
+                .layer(from_fn(deprecation)),
         );
     // TODO fall back 记录404统计
 
     info!("listening on http://{}/", basic_config.listen);
-    let listener = tokio::net::TcpListener::bind(basic_config.listen)
+    let listener = tokio::net::TcpListener::bind(&basic_config.listen)
         .await
         .unwrap();
-    app_state.run();
-    axum::serve(
+    // 自检失败则不设置为运行状态，可修复后通过接口重新自检
+    let running = !basic_config.selftest || selftest::run_on_startup().await;
+    if running {
+        app_state.run();
+    }
+    task::start_task_workers();
+    entitlement::start_expiry_warning();
+    task::start_client_error_cleanup(basic_config.client_error_max_rows as u64);
+    if middleware::is_request_archive_enabled() {
+        db::start_request_archive_cleanup(middleware::get_request_archive_retention());
+    }
+    // 启动完成后输出汇总信息，便于确认启动时的配置
+    info!(
+        category = "startup",
+        version = env!("CARGO_PKG_VERSION"),
+        commit = asset::get_commit(),
+        env = config::get_env(),
+        listen = basic_config.listen,
+        processing_limit = basic_config.processing_limit,
+        features = feature::get_feature_names().await.join(","),
+        tasks = task::get_task_categories().join(","),
+        license = entitlement::entitlements().mode,
+        init = startup::format_init_durations(&init_durations),
+        running,
+        "application is ready"
+    );
+    if running {
+        state::write_readiness_file(
+            &basic_config.readiness_file,
+            app_state.get_started_at(),
+            &basic_config.listen,
+        );
+    }
+    let result = axum::serve(
         listener,
         app.into_make_service_with_connect_info::<SocketAddr>(),
     )
-    // .with_graceful_shutdown(shutdown_signal())
-    .await
-    .unwrap();
+    .with_graceful_shutdown(shutdown_signal())
+    .await;
+    state::remove_readiness_file(&basic_config.readiness_file);
+    result.unwrap();
 }
 
 async fn shutdown_signal() {
@@ -174,7 +233,6 @@ async fn shutdown_signal() {
 
     #[cfg(unix)]
     let terminate = async {
-        get_app_state().stop();
         signal::unix::signal(signal::unix::SignalKind::terminate())
             .expect("failed to install signal handler")
             .recv()
@@ -189,6 +247,7 @@ async fn shutdown_signal() {
         _ = terminate => {},
     }
 
+    get_app_state().stop();
     info!("signal received, starting graceful shutdown");
 }
 
@@ -198,6 +257,11 @@ fn main() {
         error!(category = "panic", message = e.to_string(),);
         std::process::exit(1);
     }));
-    init_logger();
+    logger::init_logger();
+    // 子命令：对数据库副本脱敏
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("anonymize") {
+        std::process::exit(anonymize::run_command(&args[1..]));
+    }
     run();
 }
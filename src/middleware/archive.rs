@@ -0,0 +1,139 @@
+use crate::config::{must_new_archive_config, ArchiveConfig};
+use crate::db::{add_request_archive, RequestArchiveData};
+use crate::error::HttpResult;
+use crate::task_local::TRACE_ID;
+use crate::util::{get_account_from_context, glob_match, read_http_body};
+use axum::http::HeaderMap;
+use axum::{body::Body, http::Request, middleware::Next, response::Response};
+use once_cell::sync::Lazy;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Semaphore;
+use tracing::error;
+
+/// 重放请求时设置的请求头，此类请求不再归档
+pub const REPLAY_ID_HEADER: &str = "x-replay-id";
+
+static ARCHIVE_CONFIG: Lazy<ArchiveConfig> = Lazy::new(must_new_archive_config);
+static ARCHIVE_PERMITS: Lazy<Arc<Semaphore>> =
+    Lazy::new(|| Arc::new(Semaphore::new(ARCHIVE_CONFIG.concurrency)));
+
+// 包含凭证的请求头，归档时仅保留名称
+const REDACTED_HEADERS: [&str; 4] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "x-api-key",
+];
+pub const REDACTED_VALUE: &str = "[redacted]";
+
+fn is_redacted_header(name: &str) -> bool {
+    REDACTED_HEADERS.contains(&name) || name.starts_with("x-signature") || name.contains("token")
+}
+
+// 请求头转换为json，敏感的请求头隐藏其值
+fn redact_headers(headers: &HeaderMap) -> Value {
+    let mut map = Map::new();
+    for (name, value) in headers.iter() {
+        let name = name.as_str();
+        let value = if is_redacted_header(name) {
+            REDACTED_VALUE.to_string()
+        } else {
+            value.to_str().unwrap_or_default().to_string()
+        };
+        map.insert(name.to_string(), Value::String(value));
+    }
+    Value::Object(map)
+}
+
+// 截取请求数据，超出长度的标记为已截断
+fn truncate_body(data: &[u8], limit: usize) -> (Option<String>, bool) {
+    if data.is_empty() {
+        return (None, false);
+    }
+    let truncated = data.len() > limit;
+    let data = &data[..data.len().min(limit)];
+    (Some(String::from_utf8_lossy(data).to_string()), truncated)
+}
+
+/// 归档配置路由的请求(如第三方回调)，便于排查问题及重放，
+/// 写入数据库为异步执行，并发写入超出限制时直接忽略
+pub async fn request_archive(req: Request<Body>, next: Next) -> HttpResult<Response> {
+    let path = req.uri().path().to_string();
+    if req.headers().contains_key(REPLAY_ID_HEADER)
+        || !ARCHIVE_CONFIG
+            .patterns
+            .iter()
+            .any(|pattern| glob_match(pattern, &path))
+    {
+        return Ok(next.run(req).await);
+    }
+    let start = Instant::now();
+    let method = req.method().to_string();
+    let uri = req.uri().to_string();
+    let headers = redact_headers(req.headers());
+    let (parts, body) = req.into_parts();
+    let bytes = read_http_body(body).await?;
+    let (body, body_truncated) = truncate_body(&bytes, ARCHIVE_CONFIG.body_limit);
+    let req = Request::from_parts(parts, Body::from(bytes));
+
+    let resp = next.run(req).await;
+
+    let Ok(permit) = ARCHIVE_PERMITS.clone().try_acquire_owned() else {
+        return Ok(resp);
+    };
+    let data = RequestArchiveData {
+        method,
+        uri,
+        path,
+        headers,
+        body,
+        body_truncated,
+        status: resp.status().as_u16(),
+        latency: start.elapsed().as_millis() as u64,
+        trace_id: TRACE_ID.try_with(|id| id.clone()).unwrap_or_default(),
+        account: get_account_from_context(resp.extensions()),
+    };
+    tokio::spawn(async move {
+        if let Err(err) = add_request_archive(data).await {
+            error!(category = "request_archive", error = err.message);
+        }
+        drop(permit);
+    });
+    Ok(resp)
+}
+
+/// 是否有需要归档的路由
+pub fn is_request_archive_enabled() -> bool {
+    !ARCHIVE_CONFIG.patterns.is_empty()
+}
+
+/// 归档的保存时长
+pub fn get_request_archive_retention() -> std::time::Duration {
+    ARCHIVE_CONFIG.retention
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{redact_headers, truncate_body};
+    use axum::http::{HeaderMap, HeaderValue};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn archive() {
+        let mut headers = HeaderMap::new();
+        headers.insert("content-type", HeaderValue::from_static("application/json"));
+        headers.insert("authorization", HeaderValue::from_static("Bearer abc"));
+        headers.insert("x-signature-256", HeaderValue::from_static("sha256=abc"));
+        headers.insert("x-refresh-token", HeaderValue::from_static("abc"));
+        assert_eq!(
+            r#"{"authorization":"[redacted]","content-type":"application/json","x-refresh-token":"[redacted]","x-signature-256":"[redacted]"}"#,
+            redact_headers(&headers).to_string()
+        );
+
+        assert_eq!((None, false), truncate_body(b"", 10));
+        assert_eq!((Some("abc".to_string()), false), truncate_body(b"abc", 10));
+        assert_eq!((Some("ab".to_string()), true), truncate_body(b"abc", 2));
+    }
+}
@@ -0,0 +1,40 @@
+use super::RouteLabel;
+use axum::{body::Body, http::Request, middleware::Next, response::Response};
+use tracing::warn;
+
+// 请求处理未完成时被drop(客户端断开连接)，则输出日志
+struct CancellationGuard {
+    method: String,
+    route: String,
+    entity: String,
+    done: bool,
+}
+
+impl Drop for CancellationGuard {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        warn!(
+            category = "request_cancelled",
+            method = self.method,
+            route = self.route,
+            entity = self.entity,
+        );
+    }
+}
+
+/// 统计客户端断开导致取消的请求，
+/// 处理函数的future在客户端断开时会被drop，因此数据库查询等也随之中止
+pub async fn track_cancellation(req: Request<Body>, next: Next) -> Response {
+    let label = RouteLabel::from_extensions(req.extensions(), req.uri().path());
+    let mut guard = CancellationGuard {
+        method: req.method().to_string(),
+        route: label.route,
+        entity: label.entity,
+        done: false,
+    };
+    let resp = next.run(req).await;
+    guard.done = true;
+    resp
+}
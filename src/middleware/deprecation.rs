@@ -0,0 +1,242 @@
+use crate::cache::get_default_redis_cache;
+use crate::error::HttpResult;
+use crate::util::{get_account_from_context, get_header_value, SIGNATURE_KEY_HEADER};
+use axum::http::{HeaderName, HeaderValue};
+use axum::response::{IntoResponseParts, ResponseParts};
+use axum::{body::Body, http::Request, middleware::Next, response::Response};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::Duration;
+use tracing::error;
+
+// 使用记录保存的天数
+const DEPRECATION_USAGE_DAYS: i64 = 30;
+
+/// 已废弃的功能，路由可设置至response中，
+/// 由deprecation中间件设置响应头并记录调用方
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeprecatedFeature {
+    pub name: &'static str,
+    // 废弃的时间(秒)
+    pub deprecated_at: i64,
+    // 计划下线的时间(秒)
+    pub sunset_at: i64,
+}
+
+/// 当前请求使用的所有废弃功能
+#[derive(Debug, Clone, Default)]
+pub struct DeprecatedFeatures(pub Vec<DeprecatedFeature>);
+
+impl IntoResponseParts for DeprecatedFeature {
+    type Error = Infallible;
+    fn into_response_parts(self, mut res: ResponseParts) -> Result<ResponseParts, Self::Error> {
+        let mut features = res
+            .extensions_mut()
+            .remove::<DeprecatedFeatures>()
+            .unwrap_or_default();
+        features.0.push(self);
+        res.extensions_mut().insert(features);
+        Ok(res)
+    }
+}
+
+// 如`Sun, 06 Nov 1994 08:49:37 GMT`
+fn format_http_date(seconds: i64) -> String {
+    DateTime::<Utc>::from_timestamp(seconds, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+// 多个废弃功能时使用最早的废弃与下线时间
+fn deprecation_headers(features: &[DeprecatedFeature]) -> Vec<(HeaderName, String)> {
+    let mut headers = vec![];
+    if let Some(deprecated_at) = features.iter().map(|item| item.deprecated_at).min() {
+        headers.push((
+            HeaderName::from_static("deprecation"),
+            format!("@{deprecated_at}"),
+        ));
+    }
+    if let Some(sunset_at) = features.iter().map(|item| item.sunset_at).min() {
+        headers.push((
+            HeaderName::from_static("sunset"),
+            format_http_date(sunset_at),
+        ));
+    }
+    headers
+}
+
+fn get_usage_key(date: &DateTime<Utc>) -> String {
+    format!("deprecation:{}", date.format("%Y%m%d"))
+}
+
+async fn record_deprecated_usage(feature: &str, consumer: &str) -> HttpResult<()> {
+    get_default_redis_cache()
+        .hincr(
+            &get_usage_key(&Utc::now()),
+            &format!("{feature}|{consumer}"),
+            1,
+            Some(Duration::from_secs(
+                (DEPRECATION_USAGE_DAYS as u64 + 1) * 24 * 3600,
+            )),
+        )
+        .await?;
+    Ok(())
+}
+
+/// 调用方对废弃功能的使用次数
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DeprecatedUsage {
+    pub feature: String,
+    pub consumer: String,
+    pub count: i64,
+    // 最近一次使用的日期
+    pub last_seen: String,
+}
+
+// 汇总每日的记录，days需按日期从新到旧排列
+fn merge_usages(days: Vec<(String, HashMap<String, i64>)>) -> Vec<DeprecatedUsage> {
+    let mut usages: Vec<DeprecatedUsage> = vec![];
+    for (date, counts) in days {
+        for (field, count) in counts {
+            let Some((feature, consumer)) = field.split_once('|') else {
+                continue;
+            };
+            if let Some(item) = usages
+                .iter_mut()
+                .find(|item| item.feature == feature && item.consumer == consumer)
+            {
+                item.count += count;
+                continue;
+            }
+            usages.push(DeprecatedUsage {
+                feature: feature.to_string(),
+                consumer: consumer.to_string(),
+                count,
+                last_seen: date.clone(),
+            });
+        }
+    }
+    usages.sort_by(|a, b| a.feature.cmp(&b.feature).then(b.count.cmp(&a.count)));
+    usages
+}
+
+/// 最近30天各调用方对废弃功能的使用，用于确认下线前仍依赖的调用方
+pub async fn list_deprecated_usages() -> HttpResult<Vec<DeprecatedUsage>> {
+    let now = Utc::now();
+    let mut days = vec![];
+    for offset in 0..DEPRECATION_USAGE_DAYS {
+        let date = now - ChronoDuration::days(offset);
+        let counts = get_default_redis_cache()
+            .hget_all(&get_usage_key(&date))
+            .await?;
+        days.push((date.format("%Y-%m-%d").to_string(), counts));
+    }
+    Ok(merge_usages(days))
+}
+
+/// 响应中包含废弃功能时设置Deprecation与Sunset响应头，
+/// 并记录调用方(内部服务的key或账号)
+pub async fn deprecation(req: Request<Body>, next: Next) -> HttpResult<Response> {
+    let key_id = get_header_value(req.headers(), SIGNATURE_KEY_HEADER);
+    let mut resp = next.run(req).await;
+    let Some(features) = resp.extensions().get::<DeprecatedFeatures>().cloned() else {
+        return Ok(resp);
+    };
+    for (name, value) in deprecation_headers(&features.0) {
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            resp.headers_mut().insert(name, value);
+        }
+    }
+    let consumer = if !key_id.is_empty() {
+        format!("key:{key_id}")
+    } else {
+        let account = get_account_from_context(resp.extensions());
+        if account.is_empty() {
+            "anonymous".to_string()
+        } else {
+            format!("account:{account}")
+        }
+    };
+    tokio::spawn(async move {
+        for feature in features.0.iter() {
+            if let Err(err) = record_deprecated_usage(feature.name, &consumer).await {
+                error!(category = "deprecation", error = err.message);
+            }
+        }
+    });
+    Ok(resp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deprecation_headers, merge_usages, DeprecatedFeature, DeprecatedUsage};
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+
+    #[test]
+    fn deprecation() {
+        let headers = deprecation_headers(&[
+            DeprecatedFeature {
+                name: "users_refresh_get",
+                deprecated_at: 1792108800,
+                sunset_at: 1806537600,
+            },
+            DeprecatedFeature {
+                name: "legacy",
+                deprecated_at: 1800000000,
+                sunset_at: 1810000000,
+            },
+        ]);
+        let headers: Vec<(String, String)> = headers
+            .into_iter()
+            .map(|(name, value)| (name.to_string(), value))
+            .collect();
+        assert_eq!(
+            vec![
+                ("deprecation".to_string(), "@1792108800".to_string()),
+                (
+                    "sunset".to_string(),
+                    "Thu, 01 Apr 2027 00:00:00 GMT".to_string()
+                ),
+            ],
+            headers
+        );
+
+        let usages = merge_usages(vec![
+            (
+                "2026-10-16".to_string(),
+                HashMap::from([
+                    ("users_refresh_get|account:tree".to_string(), 2),
+                    ("invalid".to_string(), 1),
+                ]),
+            ),
+            (
+                "2026-10-15".to_string(),
+                HashMap::from([
+                    ("users_refresh_get|account:tree".to_string(), 3),
+                    ("users_refresh_get|key:billing".to_string(), 10),
+                ]),
+            ),
+        ]);
+        assert_eq!(
+            vec![
+                DeprecatedUsage {
+                    feature: "users_refresh_get".to_string(),
+                    consumer: "key:billing".to_string(),
+                    count: 10,
+                    last_seen: "2026-10-15".to_string(),
+                },
+                DeprecatedUsage {
+                    feature: "users_refresh_get".to_string(),
+                    consumer: "account:tree".to_string(),
+                    count: 5,
+                    last_seen: "2026-10-16".to_string(),
+                },
+            ],
+            usages
+        );
+    }
+}
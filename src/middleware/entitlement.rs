@@ -0,0 +1,60 @@
+use crate::entitlement::entitlements;
+use crate::error::{HttpError, HttpResult};
+use axum::http::StatusCode;
+use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
+
+/// 校验当前部署是否有该功能的授权
+pub async fn require_entitlement(
+    State(feature): State<&'static str>,
+    req: Request<Body>,
+    next: Next,
+) -> HttpResult<Response> {
+    if !entitlements().has(feature) {
+        return Err(HttpError {
+            message: format!("Feature {feature} is not licensed"),
+            code: "feature_not_licensed".to_string(),
+            status: StatusCode::FORBIDDEN.as_u16(),
+            ..Default::default()
+        });
+    }
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::require_entitlement;
+    use crate::entitlement::FEATURE_ENTITY_EXPORT;
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+    use axum::middleware::from_fn_with_state;
+    use axum::routing::get;
+    use axum::Router;
+    use pretty_assertions::assert_eq;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn unlicensed() {
+        // 测试环境未配置授权文件，为社区版
+        let router = Router::new().route(
+            "/export",
+            get(|| async { "ok" }).layer(from_fn_with_state(
+                FEATURE_ENTITY_EXPORT,
+                require_entitlement,
+            )),
+        );
+        let resp = router
+            .oneshot(Request::get("/export").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, resp.status());
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(
+            true,
+            std::str::from_utf8(&body)
+                .unwrap()
+                .contains("feature_not_licensed")
+        );
+    }
+}
@@ -1,14 +1,46 @@
+use crate::config::must_new_basic_config;
 use crate::task_local::*;
 use crate::util::{
-    get_device_id_from_cookie, random_string, set_header_if_not_exist, set_no_cache_if_not_exist,
+    get_device_id_from_cookie, get_header_value, random_string, set_header_if_not_exist,
+    set_no_cache_if_not_exist, TraceContext, TRACEPARENT_HEADER,
 };
 use axum::{body::Body, http::Request, middleware::Next, response::Response};
 use axum_extra::extract::cookie::CookieJar;
 use chrono::Utc;
+use once_cell::sync::Lazy;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// 新建trace的采样比例(0-100)，可在运行时调整
+static TRACE_SAMPLING: Lazy<AtomicU8> =
+    Lazy::new(|| AtomicU8::new(must_new_basic_config().trace_sampling));
+
+/// 新建trace的采样比例
+pub fn get_trace_sampling() -> u8 {
+    TRACE_SAMPLING.load(Ordering::Relaxed)
+}
+
+/// 调整新建trace的采样比例，如排查问题时临时调高
+pub fn set_trace_sampling(value: u8) {
+    TRACE_SAMPLING.store(value.min(100), Ordering::Relaxed);
+}
+
+fn should_sample() -> bool {
+    let value = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .subsec_nanos()
+        % 100;
+    (value as u8) < get_trace_sampling()
+}
 
 pub async fn entry(jar: CookieJar, req: Request<Body>, next: Next) -> Response {
     let trace_id = random_string(6);
     let device_id = get_device_id_from_cookie(&jar);
+    // 上游已有trace则沿用其采样标记，否则按比例采样
+    let trace_context =
+        TraceContext::from_traceparent(&get_header_value(req.headers(), TRACEPARENT_HEADER))
+            .unwrap_or_else(|| TraceContext::new(should_sample()));
 
     // 设置请求处理开始时间
     STARTED_AT
@@ -22,14 +54,21 @@ pub async fn entry(jar: CookieJar, req: Request<Body>, next: Next) -> Response {
                             // 设置请求的trace id
                             TRACE_ID
                                 .scope(trace_id.clone(), async {
-                                    let mut resp = next.run(req).await;
-                                    let headers = resp.headers_mut();
-                                    set_no_cache_if_not_exist(headers);
-                                    // 忽略出错
-                                    let _ =
-                                        set_header_if_not_exist(headers, "X-Trace-Id", &trace_id);
+                                    TRACE_CONTEXT
+                                        .scope(trace_context, async {
+                                            let mut resp = next.run(req).await;
+                                            let headers = resp.headers_mut();
+                                            set_no_cache_if_not_exist(headers);
+                                            // 忽略出错
+                                            let _ = set_header_if_not_exist(
+                                                headers,
+                                                "X-Trace-Id",
+                                                &trace_id,
+                                            );
 
-                                    resp
+                                            resp
+                                        })
+                                        .await
                                 })
                                 .await
                         })
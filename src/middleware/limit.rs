@@ -1,21 +1,38 @@
 use crate::cache::get_default_redis_cache;
 use crate::error::{HttpError, HttpResult};
 use crate::state::AppState;
+use crate::util::insert_header;
 use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
 use axum_client_ip::InsecureClientIp;
 use std::net::IpAddr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+
+// 根据正在处理的请求数与平均处理时长估算可重试的时长，
+// 限制在1秒至1分钟之间
+fn estimate_retry_after(processing: i32, limit: i32, latency_avg: Duration) -> Duration {
+    let limit = limit.max(1) as u32;
+    let processing = processing.max(0) as u32;
+    let value = latency_avg * processing / limit;
+    value.clamp(Duration::from_secs(1), Duration::from_secs(60))
+}
 
 pub async fn processing_limit(
     State(state): State<&AppState>,
     req: Request<Body>,
     next: Next,
 ) -> HttpResult<Response> {
-    if state.increase_processing() > state.processing_limit && state.processing_limit != 0 {
+    let processing = state.increase_processing();
+    if processing > state.processing_limit && state.processing_limit != 0 {
         state.decrease_processing();
-        return Err(HttpError::new_with_status("Too Many Requests", 429));
+        let retry_after =
+            estimate_retry_after(processing, state.processing_limit, state.get_latency_avg());
+        return Err(
+            HttpError::new_with_status("Too Many Requests", 429).with_retry_after(retry_after)
+        );
     }
+    let start = Instant::now();
     let resp = next.run(req).await;
+    state.record_latency(start.elapsed());
     state.decrease_processing();
     Ok(resp)
 }
@@ -91,11 +108,50 @@ pub async fn limiter(
 ) -> HttpResult<Response<Body>> {
     let (key, ttl) = get_limit_params(ip, &params);
 
-    let count = get_default_redis_cache().incr(&key, 1, Some(ttl)).await?;
-    if count > params.max {
+    // 检查与计数在同一脚本中执行，避免并发时超出限制
+    let (allowed, counts) = get_default_redis_cache()
+        .limit(&[(&key, params.max, ttl)])
+        .await?;
+    let (count, reset) = counts.first().copied().unwrap_or_default();
+    if !allowed {
         let msg = format!("请求过于频繁，请稍候再试！({count}/{})", params.max);
-        return Err(HttpError::new_with_category(&msg, "limiter"));
+        // 计数重置后即可重试
+        return Err(
+            HttpError::new_with_category_status(&msg, "limiter", 429).with_retry_after(reset)
+        );
     }
-    let resp = next.run(req).await;
+    let mut resp = next.run(req).await;
+    let remaining = (params.max - count).max(0);
+    let values = [
+        ("X-RateLimit-Limit".to_string(), params.max.to_string()),
+        ("X-RateLimit-Remaining".to_string(), remaining.to_string()),
+    ]
+    .into();
+    // 设置失败忽略
+    let _ = insert_header(resp.headers_mut(), values);
     Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::estimate_retry_after;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn retry_after() {
+        // 无处理时长记录时为最小值
+        assert_eq!(
+            Duration::from_secs(1),
+            estimate_retry_after(101, 100, Duration::ZERO)
+        );
+        assert_eq!(
+            Duration::from_secs(4),
+            estimate_retry_after(200, 100, Duration::from_secs(2))
+        );
+        assert_eq!(
+            Duration::from_secs(60),
+            estimate_retry_after(1000, 10, Duration::from_secs(30))
+        );
+    }
+}
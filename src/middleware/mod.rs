@@ -1,11 +1,27 @@
+mod archive;
+mod cancel;
 mod common;
+mod deprecation;
+mod entitlement;
 mod entry;
 mod limit;
+mod refresh_token;
+mod route;
+mod security;
 mod session;
+mod signature;
 mod stats;
 
+pub use archive::*;
+pub use cancel::track_cancellation;
 pub use common::*;
-pub use entry::entry;
+pub use deprecation::*;
+pub use entitlement::*;
+pub use entry::{entry, get_trace_sampling, set_trace_sampling};
 pub use limit::*;
+pub use refresh_token::*;
+pub use route::*;
+pub use security::*;
 pub use session::*;
+pub use signature::*;
 pub use stats::access_log;
@@ -0,0 +1,285 @@
+use super::get_session_generation;
+use crate::cache::get_default_redis_cache;
+use crate::error::{HttpError, HttpResult};
+use crate::util;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+// 刷新token的有效期
+const REFRESH_TOKEN_TTL: Duration = Duration::from_secs(30 * 24 * 3600);
+// 每个账号最多的刷新token数量
+const REFRESH_TOKEN_LIMIT: usize = 20;
+
+/// 长期有效的刷新token，用于免密码重新登录，
+/// 与设备绑定且使用后轮换
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RefreshToken {
+    pub id: String,
+    pub account: String,
+    pub tenant_id: i64,
+    pub device_id: String,
+    // 首次创建的时间，轮换后保持不变
+    pub created_at: i64,
+    pub last_used_at: i64,
+    // 创建时账号的session代数，修改密码后失效
+    generation: i64,
+    secret_hash: String,
+}
+
+/// 刷新token的展示信息，用于用户确认已登录的设备
+#[derive(Debug, Clone, Serialize)]
+pub struct RefreshTokenInfo {
+    pub id: String,
+    pub device_id: String,
+    pub created_at: String,
+    pub last_used_at: String,
+}
+
+impl From<RefreshToken> for RefreshTokenInfo {
+    fn from(value: RefreshToken) -> Self {
+        RefreshTokenInfo {
+            id: value.id,
+            device_id: value.device_id,
+            created_at: util::from_timestamp(value.created_at, 0),
+            last_used_at: util::from_timestamp(value.last_used_at, 0),
+        }
+    }
+}
+
+fn get_token_key(id: &str) -> String {
+    format!("rt:{id}")
+}
+
+// 账号的刷新token id列表(redis list)，最新的在前
+fn get_account_key(account: &str) -> String {
+    format!("rt:list:{account}")
+}
+
+async fn find_token(id: &str) -> HttpResult<Option<RefreshToken>> {
+    let token = get_default_redis_cache()
+        .get_struct(&get_token_key(id))
+        .await?;
+    Ok(token)
+}
+
+// 保存刷新token，返回给客户端的值为`id.secret`，
+// 服务端仅保存secret的hash
+async fn save_refresh_token(
+    account: &str,
+    tenant_id: i64,
+    device_id: &str,
+    created_at: i64,
+) -> HttpResult<String> {
+    if device_id.is_empty() {
+        return Err(HttpError::new_with_category(
+            "Device id is required",
+            "refresh_token",
+        ));
+    }
+    let id = util::random_string(16);
+    // uuid v7包含时间戳，因此使用随机字符串(192位)
+    let secret = util::random_string(32);
+    let token = RefreshToken {
+        id: id.clone(),
+        account: account.to_string(),
+        tenant_id,
+        device_id: device_id.to_string(),
+        created_at,
+        last_used_at: util::timestamp(),
+        generation: get_session_generation(account).await?,
+        secret_hash: util::sha256(secret.as_bytes()),
+    };
+    let cache = get_default_redis_cache();
+    cache
+        .set_struct(&get_token_key(&id), &token, Some(REFRESH_TOKEN_TTL))
+        .await?;
+    let removed = cache
+        .lpush_trim(
+            &get_account_key(account),
+            &id,
+            REFRESH_TOKEN_LIMIT,
+            Some(REFRESH_TOKEN_TTL),
+        )
+        .await?;
+    // 超出数量的最早的token失效
+    for item in removed.iter() {
+        cache.del(&get_token_key(item)).await?;
+    }
+    Ok(format!("{id}.{secret}"))
+}
+
+/// 创建刷新token
+pub async fn create_refresh_token(
+    account: &str,
+    tenant_id: i64,
+    device_id: &str,
+) -> HttpResult<String> {
+    save_refresh_token(account, tenant_id, device_id, util::timestamp()).await
+}
+
+/// 校验刷新token并使其失效，成功后需调用rotate生成新的token
+pub async fn consume_refresh_token(value: &str, device_id: &str) -> HttpResult<RefreshToken> {
+    let invalid_err = HttpError::new_with_category("Refresh token is invalid", "refresh_token");
+    let Some((id, secret)) = value.split_once('.') else {
+        return Err(invalid_err);
+    };
+    let Some(token) = find_token(id).await? else {
+        return Err(invalid_err);
+    };
+    if token.secret_hash != util::sha256(secret.as_bytes()) || token.device_id != device_id {
+        return Err(invalid_err);
+    }
+    // 以删除成功作为认领，并发时同一token仅可被使用一次
+    let cache = get_default_redis_cache();
+    if !cache.remove(&get_token_key(id)).await? {
+        return Err(invalid_err);
+    }
+    cache.lrem(&get_account_key(&token.account), id).await?;
+    if token.generation < get_session_generation(&token.account).await? {
+        return Err(invalid_err);
+    }
+    Ok(token)
+}
+
+/// 轮换刷新token，保留首次创建的时间
+pub async fn rotate_refresh_token(token: &RefreshToken) -> HttpResult<String> {
+    save_refresh_token(
+        &token.account,
+        token.tenant_id,
+        &token.device_id,
+        token.created_at,
+    )
+    .await
+}
+
+/// 账号的所有刷新token
+pub async fn list_refresh_tokens(account: &str) -> HttpResult<Vec<RefreshTokenInfo>> {
+    let cache = get_default_redis_cache();
+    let key = get_account_key(account);
+    let mut tokens = vec![];
+    for id in cache.lrange(&key).await?.iter() {
+        match find_token(id).await? {
+            Some(token) => tokens.push(token.into()),
+            // 清除已过期的
+            None => {
+                cache.lrem(&key, id).await?;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// 删除刷新token，仅可删除该账号的
+pub async fn revoke_refresh_token(account: &str, id: &str) -> HttpResult<()> {
+    let cache = get_default_redis_cache();
+    if !cache.lrem(&get_account_key(account), id).await? {
+        return Err(HttpError::new_with_category(
+            "Refresh token is not found",
+            "refresh_token",
+        ));
+    }
+    cache.del(&get_token_key(id)).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        consume_refresh_token, create_refresh_token, list_refresh_tokens, rotate_refresh_token,
+    };
+    use crate::cache::get_default_redis_cache;
+    use crate::middleware::session::get_generation_key;
+    use crate::util;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn rotation() {
+        let account = format!("rt-{}", util::uuid());
+        let first = create_refresh_token(&account, 1, "mac").await.unwrap();
+        let (_, secret) = first.split_once('.').unwrap();
+        assert_eq!(32, secret.len());
+
+        let token = consume_refresh_token(&first, "mac").await.unwrap();
+        assert_eq!(account, token.account);
+        // 已使用的token失效
+        assert_eq!(
+            "Refresh token is invalid",
+            consume_refresh_token(&first, "mac")
+                .await
+                .unwrap_err()
+                .message
+        );
+
+        let second = rotate_refresh_token(&token).await.unwrap();
+        let rotated = consume_refresh_token(&second, "mac").await.unwrap();
+        assert_eq!(token.created_at, rotated.created_at);
+        assert_eq!(0, list_refresh_tokens(&account).await.unwrap().len());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn concurrent_consume() {
+        let account = format!("rt-{}", util::uuid());
+        let value = create_refresh_token(&account, 1, "mac").await.unwrap();
+        let (first, second) = tokio::join!(
+            consume_refresh_token(&value, "mac"),
+            consume_refresh_token(&value, "mac")
+        );
+        assert_eq!(
+            1,
+            [first.is_ok(), second.is_ok()]
+                .iter()
+                .filter(|ok| **ok)
+                .count()
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn device_mismatch() {
+        let account = format!("rt-{}", util::uuid());
+        let value = create_refresh_token(&account, 1, "mac").await.unwrap();
+        assert_eq!(
+            "Refresh token is invalid",
+            consume_refresh_token(&value, "windows")
+                .await
+                .unwrap_err()
+                .message
+        );
+        // 设备不匹配的请求不影响原设备使用
+        assert_eq!(true, consume_refresh_token(&value, "mac").await.is_ok());
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn generation_invalidation() {
+        let account = format!("rt-{}", util::uuid());
+        let value = create_refresh_token(&account, 1, "mac").await.unwrap();
+        // 修改密码等操作后session代数增加
+        get_default_redis_cache()
+            .incr(&get_generation_key(&account), 1, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            "Refresh token is invalid",
+            consume_refresh_token(&value, "mac")
+                .await
+                .unwrap_err()
+                .message
+        );
+    }
+
+    #[tokio::test]
+    #[ignore = "requires redis"]
+    async fn concurrent_create() {
+        let account = format!("rt-{}", util::uuid());
+        let (first, second) = tokio::join!(
+            create_refresh_token(&account, 1, "mac"),
+            create_refresh_token(&account, 1, "windows")
+        );
+        first.unwrap();
+        second.unwrap();
+        assert_eq!(2, list_refresh_tokens(&account).await.unwrap().len());
+    }
+}
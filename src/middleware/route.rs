@@ -0,0 +1,64 @@
+use axum::extract::MatchedPath;
+use axum::http::Extensions;
+
+/// 未匹配路由(404)的统一标签
+pub static UNMATCHED_ROUTE: &str = "unmatched";
+// 路由参数中的实体名称，其取值为有限集合
+static ENTITY_PARAM: &str = ":entity";
+
+/// 用于日志与统计的路由标签，使用路由定义而非请求路径，
+/// 避免/users/123与/users/456产生不同的值
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RouteLabel {
+    // 路由定义，如/api/inners/entities/:entity/:id
+    pub route: String,
+    // 实体名称，非实体相关路由为空
+    pub entity: String,
+}
+
+impl RouteLabel {
+    pub fn new(matched_path: Option<&str>, path: &str) -> Self {
+        let Some(route) = matched_path else {
+            return RouteLabel {
+                route: UNMATCHED_ROUTE.to_string(),
+                ..Default::default()
+            };
+        };
+        let mut entity = "".to_string();
+        // 根据路由定义中参数的位置获取对应的值
+        if let Some(index) = route.split('/').position(|item| item == ENTITY_PARAM) {
+            if let Some(value) = path.split('/').nth(index) {
+                entity = value.to_string();
+            }
+        }
+        RouteLabel {
+            route: route.to_string(),
+            entity,
+        }
+    }
+    pub fn from_extensions(exts: &Extensions, path: &str) -> Self {
+        let matched_path = exts.get::<MatchedPath>().map(|item| item.as_str());
+        Self::new(matched_path, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RouteLabel, UNMATCHED_ROUTE};
+    use pretty_assertions::assert_eq;
+    #[test]
+    fn route_label() {
+        let route = Some("/api/inners/entities/:entity/:id");
+        let label1 = RouteLabel::new(route, "/api/inners/entities/users/1");
+        let label2 = RouteLabel::new(route, "/api/inners/entities/users/123456");
+        assert_eq!(label1, label2);
+        assert_eq!("/api/inners/entities/:entity/:id", label1.route);
+        assert_eq!("users", label1.entity);
+
+        let label = RouteLabel::new(Some("/api/users/me"), "/api/users/me");
+        assert_eq!("", label.entity);
+
+        let label = RouteLabel::new(None, "/not-found");
+        assert_eq!(UNMATCHED_ROUTE, label.route);
+    }
+}
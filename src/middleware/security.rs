@@ -0,0 +1,197 @@
+use crate::config::{must_new_security_config, SecurityConfig, CSP_SOURCE_KEYWORDS};
+use crate::util;
+use axum::extract::ConnectInfo;
+use axum::http::{header, HeaderValue};
+use axum::{body::Body, http::Request, middleware::Next, response::Response};
+use ipnet::IpNet;
+use once_cell::sync::Lazy;
+use std::net::SocketAddr;
+
+/// csp违规的上报地址
+pub static CSP_REPORT_URI: &str = "/api/commons/csp-report";
+
+static SECURITY_CONFIG: Lazy<SecurityConfig> = Lazy::new(must_new_security_config);
+static DEFAULT_CSP_POLICY: Lazy<CspPolicy> = Lazy::new(|| CspPolicy {
+    default_src: SECURITY_CONFIG.csp_default_src.clone(),
+    script_src: SECURITY_CONFIG.csp_script_src.clone(),
+    style_src: SECURITY_CONFIG.csp_style_src.clone(),
+    img_src: SECURITY_CONFIG.csp_img_src.clone(),
+    connect_src: SECURITY_CONFIG.csp_connect_src.clone(),
+    frame_ancestors: SECURITY_CONFIG.frame_ancestors.clone(),
+});
+
+/// 每个请求生成的csp nonce，
+/// 返回的html中内联脚本需要设置此nonce
+#[derive(Debug, Clone, Default)]
+pub struct CspNonce(pub String);
+
+/// csp策略，路由可设置至response extensions中替换默认策略
+#[derive(Debug, Clone, Default)]
+pub struct CspPolicy {
+    pub default_src: Vec<String>,
+    pub script_src: Vec<String>,
+    pub style_src: Vec<String>,
+    pub img_src: Vec<String>,
+    pub connect_src: Vec<String>,
+    pub frame_ancestors: Vec<String>,
+}
+
+fn format_sources(sources: &[String]) -> String {
+    sources
+        .iter()
+        .map(|item| {
+            if CSP_SOURCE_KEYWORDS.contains(&item.as_str()) {
+                format!("'{item}'")
+            } else {
+                item.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+impl CspPolicy {
+    /// 生成csp响应头，script-src添加nonce
+    pub fn build(&self, nonce: &str) -> String {
+        let mut script_src = format_sources(&self.script_src);
+        script_src.push_str(&format!(" 'nonce-{nonce}'"));
+        [
+            ("default-src", format_sources(&self.default_src)),
+            ("script-src", script_src.trim().to_string()),
+            ("style-src", format_sources(&self.style_src)),
+            ("img-src", format_sources(&self.img_src)),
+            ("connect-src", format_sources(&self.connect_src)),
+            ("frame-ancestors", format_sources(&self.frame_ancestors)),
+            ("report-uri", CSP_REPORT_URI.to_string()),
+        ]
+        .iter()
+        .filter(|(_, value)| !value.is_empty())
+        .map(|(name, value)| format!("{name} {value}"))
+        .collect::<Vec<_>>()
+        .join("; ")
+    }
+}
+
+fn get_frame_options(frame_ancestors: &[String]) -> &'static str {
+    if frame_ancestors.iter().any(|item| item == "self") {
+        "SAMEORIGIN"
+    } else {
+        "DENY"
+    }
+}
+
+// 是否https请求，经过代理时根据x-forwarded-proto判断，
+// 该请求头可被客户端伪造，因此仅信任来自可信代理的请求
+fn is_https(req: &Request<Body>, trusted_proxies: &[IpNet]) -> bool {
+    if req.uri().scheme_str() == Some("https") {
+        return true;
+    }
+    let trusted = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .is_some_and(|ConnectInfo(addr)| {
+            trusted_proxies.iter().any(|net| net.contains(&addr.ip()))
+        });
+    trusted && util::get_header_value(req.headers(), "X-Forwarded-Proto") == "https"
+}
+
+/// 设置安全相关的响应头，响应中已设置的不覆盖
+pub async fn security_headers(mut req: Request<Body>, next: Next) -> Response {
+    let nonce = util::random_string(16);
+    let https = is_https(&req, &SECURITY_CONFIG.trusted_proxies);
+    req.extensions_mut().insert(CspNonce(nonce.clone()));
+    let mut resp = next.run(req).await;
+
+    let policy = resp
+        .extensions()
+        .get::<CspPolicy>()
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_CSP_POLICY.clone());
+    let csp_header = if SECURITY_CONFIG.csp_report_only {
+        header::CONTENT_SECURITY_POLICY_REPORT_ONLY
+    } else {
+        header::CONTENT_SECURITY_POLICY
+    };
+    let mut values = vec![
+        (csp_header, policy.build(&nonce)),
+        (
+            header::X_FRAME_OPTIONS,
+            get_frame_options(&policy.frame_ancestors).to_string(),
+        ),
+        (header::X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        (
+            header::REFERRER_POLICY,
+            SECURITY_CONFIG.referrer_policy.clone(),
+        ),
+    ];
+    if !SECURITY_CONFIG.permissions_disabled.is_empty() {
+        let value = SECURITY_CONFIG
+            .permissions_disabled
+            .iter()
+            .map(|item| format!("{item}=()"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        values.push((header::HeaderName::from_static("permissions-policy"), value));
+    }
+    // 仅https时设置hsts，避免http访问时浏览器无法降级
+    if https && !SECURITY_CONFIG.hsts_max_age.is_zero() {
+        values.push((
+            header::STRICT_TRANSPORT_SECURITY,
+            format!("max-age={}", SECURITY_CONFIG.hsts_max_age.as_secs()),
+        ));
+    }
+    let headers = resp.headers_mut();
+    for (name, value) in values {
+        if headers.contains_key(&name) {
+            continue;
+        }
+        if let Ok(value) = HeaderValue::from_str(&value) {
+            headers.insert(name, value);
+        }
+    }
+    resp
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_https;
+    use axum::body::Body;
+    use axum::extract::ConnectInfo;
+    use axum::http::Request;
+    use ipnet::IpNet;
+    use pretty_assertions::assert_eq;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn https() {
+        let trusted_proxies: Vec<IpNet> = vec!["10.0.0.0/8".parse().unwrap()];
+        let new_req = |peer: Option<&str>| {
+            let mut req = Request::get("/")
+                .header("X-Forwarded-Proto", "https")
+                .body(Body::empty())
+                .unwrap();
+            if let Some(peer) = peer {
+                req.extensions_mut()
+                    .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+            }
+            req
+        };
+
+        assert_eq!(
+            true,
+            is_https(&new_req(Some("10.1.2.3:8080")), &trusted_proxies)
+        );
+        // 非可信代理的请求头忽略
+        assert_eq!(
+            false,
+            is_https(&new_req(Some("1.2.3.4:8080")), &trusted_proxies)
+        );
+        assert_eq!(false, is_https(&new_req(None), &trusted_proxies));
+        assert_eq!(false, is_https(&new_req(Some("10.1.2.3:8080")), &[]));
+
+        let req = Request::get("https://tibba.com/")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(true, is_https(&req, &[]));
+    }
+}
@@ -1,14 +1,15 @@
+use super::ServiceIdentity;
 use crate::config::{must_new_session_config, SessionConfig};
-use crate::db::find_user_by_account;
+use crate::db::{get_cached_user, DEFAULT_TENANT_ID, ROLE_READONLY, ROLE_SERVICE, ROLE_SU};
 use crate::error::{HttpError, HttpResult};
 use crate::util;
 use crate::{cache, task_local::*};
 use axum::body::Body;
 use axum::extract::{FromRequestParts, State};
-use axum::http::header::{HeaderMap, HeaderValue};
+use axum::http::header::{self, HeaderMap, HeaderValue};
 use axum::http::request::Parts;
 use axum::http::Request;
-use axum::http::StatusCode;
+use axum::http::{Method, StatusCode};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use axum::{async_trait, Json};
@@ -16,40 +17,147 @@ use axum_extra::extract::cookie::{Key, SignedCookieJar};
 use cookie::CookieBuilder;
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::time::Duration;
+use utoipa::ToSchema;
 
 static SESSION_CONFIG: Lazy<SessionConfig> = Lazy::new(must_new_session_config);
 static SESSION_KEY: Lazy<Key> = Lazy::new(|| Key::from(SESSION_CONFIG.secret.as_bytes()));
 
+// session数据的版本，调整session的字段时递增并添加对应的升级函数
+const SESSION_VERSION: usize = 3;
+// 各版本升级至下一版本的处理，下标为原版本
+static SESSION_MIGRATIONS: [fn(&mut Map<String, Value>); SESSION_VERSION] =
+    [migrate_session_v0, migrate_session_v1, migrate_session_v2];
+// 各版本升级的次数，均为0时则可删除对应的升级函数
+static SESSION_MIGRATED: [AtomicU64; SESSION_VERSION] =
+    [AtomicU64::new(0), AtomicU64::new(0), AtomicU64::new(0)];
+
+// 未记录版本的session，可能无租户
+fn migrate_session_v0(data: &mut Map<String, Value>) {
+    data.entry("tenant_id").or_insert(Value::from(0));
+}
+
+// 添加session的代数
+fn migrate_session_v1(data: &mut Map<String, Value>) {
+    data.entry("generation").or_insert(Value::from(0));
+}
+
+// 添加二次验证的时间，原有session均未验证
+fn migrate_session_v2(data: &mut Map<String, Value>) {
+    data.entry("elevated_at").or_insert(Value::from(0));
+}
+
+// 账号的session代数保存时长，需大于session的最长有效期
+const SESSION_GENERATION_TTL: Duration = Duration::from_secs(31 * 24 * 3600);
+
+pub(super) fn get_generation_key(account: &str) -> String {
+    format!("ss:gen:{account}")
+}
+
+/// 账号当前的session代数，小于此值的session均已失效
+pub async fn get_session_generation(account: &str) -> HttpResult<i64> {
+    let value: Option<i64> = cache::get_default_redis_cache()
+        .get(&get_generation_key(account))
+        .await?;
+    Ok(value.unwrap_or_default())
+}
+
+// 将session升级至当前版本，升级后的数据在下次保存时写入，
+// 数据无法转换时返回None
+fn migrate_session(value: Value) -> Option<Claim> {
+    let Value::Object(mut data) = value else {
+        return None;
+    };
+    let mut version = data.get("version").and_then(Value::as_u64).unwrap_or(0) as usize;
+    if version < SESSION_VERSION {
+        while version < SESSION_VERSION {
+            SESSION_MIGRATIONS[version](&mut data);
+            SESSION_MIGRATED[version].fetch_add(1, Ordering::Relaxed);
+            version += 1;
+        }
+        data.insert("version".to_string(), Value::from(version));
+    }
+    serde_json::from_value(Value::Object(data)).ok()
+}
+
+/// 各版本session的升级次数
+pub fn get_session_migrations() -> HashMap<String, u64> {
+    SESSION_MIGRATED
+        .iter()
+        .enumerate()
+        .map(|(version, count)| (format!("v{version}"), count.load(Ordering::Relaxed)))
+        .collect()
+}
+
 #[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Claim {
+    // 数据版本
+    #[serde(default)]
+    version: usize,
     // 有效期
     exp: i64,
     // 创建时间
     iat: i64,
     id: String,
     account: String,
+    // 当前所属租户，超级管理员可切换
+    #[serde(default)]
+    tenant_id: i64,
+    // 创建时账号的session代数，修改密码等操作后旧的session失效
+    #[serde(default)]
+    generation: i64,
+    // 二次验证的时间，敏感操作需在有效期内
+    #[serde(default)]
+    elevated_at: i64,
+    // 登录时账号的角色与群组，用于功能开关等允许延迟生效的判断，
+    // 权限校验仍以数据库中的为准
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    groups: Vec<String>,
+    // 未知的字段(如新版本添加的)原样保留
+    #[serde(flatten)]
+    extra: Map<String, Value>,
+    // 登录时生成的刷新token，仅用于响应不保存
+    #[serde(skip)]
+    refresh_token: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-struct ClaimResp {
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ClaimResp {
     account: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refresh_token: Option<String>,
 }
 
 impl Claim {
-    pub fn new(account: &str) -> Self {
+    pub fn new(account: &str, tenant_id: i64) -> Self {
         let iat = util::timestamp();
         Claim {
+            version: SESSION_VERSION,
             exp: iat + SESSION_CONFIG.ttl,
             iat,
             id: "".to_string(),
             account: account.to_string(),
+            tenant_id,
+            ..Default::default()
         }
     }
+    /// 从redis中加载session，数据无法识别时视为未登录
     pub async fn new_from_redis(id: &str) -> HttpResult<Self> {
         let key = Self::get_key(id);
-        let result = cache::get_default_redis_cache().get_struct(&key).await?;
-        Ok(result.unwrap_or_default())
+        let buf: Vec<u8> = cache::get_default_redis_cache().get(&key).await?;
+        if buf.is_empty() {
+            return Ok(Claim::default());
+        }
+        let claim = serde_json::from_slice(&buf).ok().and_then(migrate_session);
+        Ok(claim.unwrap_or_else(|| {
+            warn!(category = "session", id, "session is invalid");
+            Claim::default()
+        }))
     }
     fn get_key(id: &str) -> String {
         format!("ss:{id}")
@@ -57,6 +165,31 @@ impl Claim {
     pub fn get_account(&self) -> String {
         self.account.clone()
     }
+    /// 设置刷新token，仅用于登录的响应
+    pub fn set_refresh_token(&mut self, token: String) {
+        self.refresh_token = Some(token);
+    }
+    /// 获取租户，未记录租户的session使用默认租户
+    pub fn get_tenant_id(&self) -> i64 {
+        if self.tenant_id <= 0 {
+            return DEFAULT_TENANT_ID;
+        }
+        self.tenant_id
+    }
+    pub fn set_tenant_id(&mut self, tenant_id: i64) {
+        self.tenant_id = tenant_id;
+    }
+    /// 设置登录时账号的角色与群组
+    pub fn set_roles_groups(&mut self, roles: Vec<String>, groups: Vec<String>) {
+        self.roles = roles;
+        self.groups = groups;
+    }
+    pub fn get_roles(&self) -> &[String] {
+        &self.roles
+    }
+    pub fn get_groups(&self) -> &[String] {
+        &self.groups
+    }
     pub fn get_expired_at(&self) -> String {
         util::from_timestamp(self.exp, 0)
     }
@@ -64,7 +197,11 @@ impl Claim {
         util::from_timestamp(self.iat, 0)
     }
     pub fn is_expired(&self) -> bool {
-        let value = util::timestamp();
+        self.is_expired_with(&util::Clock::System)
+    }
+    /// 根据指定时钟判断是否过期
+    pub fn is_expired_with(&self, clock: &util::Clock) -> bool {
+        let value = clock.timestamp();
         // 如果创建时间已超过30天，则认为过期
         if value - self.iat > 30 * 24 * 3600 {
             return true;
@@ -93,9 +230,37 @@ impl Claim {
             .await?;
         Ok(())
     }
+    /// 使用账号当前的session代数，登录时设置
+    pub async fn bind_generation(&mut self) -> HttpResult<()> {
+        self.generation = get_session_generation(&self.account).await?;
+        Ok(())
+    }
+    /// 使该账号其它的session均失效，当前session保持有效
+    pub async fn invalidate_others(&mut self) -> HttpResult<()> {
+        let cache = cache::get_default_redis_cache();
+        let key = get_generation_key(&self.account);
+        self.generation = cache.incr(&key, 1, Some(SESSION_GENERATION_TTL)).await?;
+        // incr仅在创建时设置有效期，因此需要重新设置
+        cache
+            .set(&key, self.generation, Some(SESSION_GENERATION_TTL))
+            .await?;
+        self.save().await
+    }
     pub fn destroy(&mut self) {
         self.id = "".to_string();
     }
+    /// 记录二次验证的时间，需调用save保存
+    pub fn elevate(&mut self) {
+        self.elevated_at = util::timestamp();
+    }
+    /// 二次验证的到期时间
+    pub fn get_elevated_until(&self) -> String {
+        util::from_timestamp(self.elevated_at + SESSION_CONFIG.elevation_ttl, 0)
+    }
+    /// 二次验证是否仍在有效期内，刷新session不延长有效期
+    pub fn is_elevated_with(&self, clock: &util::Clock, ttl: i64) -> bool {
+        self.elevated_at > 0 && clock.timestamp() - self.elevated_at <= ttl
+    }
 }
 
 impl IntoResponse for Claim {
@@ -110,18 +275,53 @@ impl IntoResponse for Claim {
             jar.add(c),
             Json(ClaimResp {
                 account: self.account,
+                refresh_token: self.refresh_token,
             }),
         )
             .into_response()
     }
 }
 
+/// 校验session cookie签名后可正确读取，用于启动时的自检
+pub fn check_session_signing() -> HttpResult<()> {
+    let value = util::uuid();
+    let jar = SignedCookieJar::new(SESSION_KEY.clone())
+        .add(CookieBuilder::new(&SESSION_CONFIG.cookie, value.clone()));
+    let resp = jar.into_response();
+    let signed = resp
+        .headers()
+        .get(header::SET_COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| cookie::Cookie::parse(value.to_string()).ok())
+        .map(|item| format!("{}={}", item.name(), item.value()))
+        .unwrap_or_default();
+    let mut headers = HeaderMap::new();
+    if let Ok(cookie) = HeaderValue::from_str(&signed) {
+        headers.insert(header::COOKIE, cookie);
+    }
+    let jar = SignedCookieJar::from_headers(&headers, SESSION_KEY.clone());
+    if jar
+        .get(&SESSION_CONFIG.cookie)
+        .map(|item| item.value().to_string())
+        != Some(value)
+    {
+        return Err(HttpError::new_with_category(
+            "Session signature verify fail",
+            "session",
+        ));
+    }
+    Ok(())
+}
+
 async fn get_claim_from_headers(headers: &HeaderMap<HeaderValue>) -> HttpResult<Claim> {
     let jar = SignedCookieJar::from_headers(headers, SESSION_KEY.clone());
     let result = if let Some(session_id) = jar.get(&SESSION_CONFIG.cookie) {
         let claim = Claim::new_from_redis(session_id.value()).await?;
-        // 如果已过期
-        if claim.is_expired() {
+        // 如果已过期或已失效
+        if claim.is_expired()
+            || (!claim.account.is_empty()
+                && claim.generation < get_session_generation(&claim.account).await?)
+        {
             Claim::default()
         } else {
             claim
@@ -168,7 +368,12 @@ async fn load_claim(
     ACCOUNT
         .scope(account.clone(), async {
             util::set_account_to_context(req.extensions_mut(), util::Account::new(account.clone()));
-            let mut resp = next.run(req).await;
+            // 仅已登录时设置租户，未登录则无法查询租户隔离的表
+            let mut resp = if account.is_empty() {
+                next.run(req).await
+            } else {
+                TENANT_ID.scope(claim.get_tenant_id(), next.run(req)).await
+            };
             // 由于在session之前的中间件无法获取account的值
             // 因此又将account设置至resp extension中
             util::set_account_to_context(resp.extensions_mut(), util::Account::new(account));
@@ -185,11 +390,72 @@ pub async fn should_logged_in(req: Request<Body>, next: Next) -> HttpResult<Resp
     load_claim(true, req, next).await
 }
 
-pub async fn validate_roles(
-    State(valid_roles): State<Vec<String>>,
-    req: Request<Body>,
-    next: Next,
-) -> HttpResult<Response> {
+/// 根据配置的路由规则确定的session策略
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum SessionPolicy {
+    // 未配置规则，由各路由自行处理
+    #[default]
+    Default,
+    // 允许匿名访问
+    Anonymous,
+    // 需要登录
+    Login,
+}
+
+impl SessionPolicy {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SessionPolicy::Default => "default",
+            SessionPolicy::Anonymous => "anonymous",
+            SessionPolicy::Login => "login",
+        }
+    }
+}
+
+fn get_session_policy(path: &str) -> SessionPolicy {
+    let matched = |patterns: &Vec<String>| patterns.iter().any(|item| util::glob_match(item, path));
+    if matched(&SESSION_CONFIG.login_patterns) {
+        SessionPolicy::Login
+    } else if matched(&SESSION_CONFIG.anonymous_patterns) {
+        SessionPolicy::Anonymous
+    } else {
+        SessionPolicy::Default
+    }
+}
+
+/// 统一校验需要登录的路由，避免新增的接口遗漏登录校验，
+/// 匹配的策略设置至response extensions中，用于访问日志
+pub async fn session_policy(req: Request<Body>, next: Next) -> HttpResult<Response> {
+    let policy = get_session_policy(req.uri().path());
+    if policy == SessionPolicy::Login {
+        let claim = get_claim_from_headers(req.headers()).await?;
+        if claim.account.is_empty() {
+            return Err(HttpError {
+                message: "Should be login first".to_string(),
+                status: StatusCode::UNAUTHORIZED.as_u16(),
+                ..Default::default()
+            });
+        }
+    }
+    let mut resp = next.run(req).await;
+    resp.extensions_mut().insert(policy);
+    Ok(resp)
+}
+
+fn is_elevation_required(path: &str, method: &Method) -> bool {
+    ![Method::GET, Method::HEAD].contains(method)
+        && SESSION_CONFIG
+            .elevation_patterns
+            .iter()
+            .any(|item| util::glob_match(item, path))
+}
+
+/// 配置的敏感操作需要session在有效期内完成二次验证，
+/// 否则返回403(elevation_required)，由客户端提示验证后重试
+pub async fn elevation_policy(req: Request<Body>, next: Next) -> HttpResult<Response> {
+    if !is_elevation_required(req.uri().path(), req.method()) {
+        return Ok(next.run(req).await);
+    }
     let claim = get_claim_from_headers(req.headers()).await?;
     if claim.account.is_empty() {
         return Err(HttpError {
@@ -198,26 +464,233 @@ pub async fn validate_roles(
             ..Default::default()
         });
     }
-    // 因为已登录成功，因此账号不存在不会发生
-    let result = find_user_by_account(&claim.account)
-        .await?
-        .ok_or(HttpError::new("账号不存在"))?;
-    let mut valid = false;
-    if let Some(roles) = result.roles {
-        let roles = util::json_value_to_strings(&roles)?.unwrap_or_default();
-        roles.iter().for_each(|item| {
-            if !valid && valid_roles.contains(item) {
-                valid = true;
-            }
+    if !claim.is_elevated_with(&util::Clock::System, SESSION_CONFIG.elevation_ttl) {
+        return Err(HttpError {
+            message: "Please verify your identity again".to_string(),
+            category: "elevation".to_string(),
+            code: "elevation_required".to_string(),
+            status: StatusCode::FORBIDDEN.as_u16(),
+            ..Default::default()
+        });
+    }
+    Ok(next.run(req).await)
+}
+
+/// 是否仅有只读权限，与路由的角色校验一致：
+/// 非su且匹配的角色均为只读角色，同时有其它可写角色则非只读
+pub fn is_readonly_roles(roles: &[String], valid_roles: &[String]) -> bool {
+    if roles.iter().any(|item| item == ROLE_SU) {
+        return false;
+    }
+    let mut matched_roles = roles
+        .iter()
+        .filter(|item| valid_roles.contains(item))
+        .peekable();
+    matched_roles.peek().is_some() && matched_roles.all(|item| item == ROLE_READONLY)
+}
+
+// 校验账号角色是否满足，未登录返回401，角色不匹配返回403，
+// su角色总是允许
+fn check_roles(
+    account: &str,
+    roles: &[String],
+    valid_roles: &[String],
+    method: &Method,
+) -> HttpResult<()> {
+    if account.is_empty() {
+        return Err(HttpError {
+            message: "Should be login first".to_string(),
+            status: StatusCode::UNAUTHORIZED.as_u16(),
+            ..Default::default()
         });
     }
-    if !valid {
+    if roles.iter().any(|item| item == ROLE_SU) {
+        return Ok(());
+    }
+    if !roles.iter().any(|item| valid_roles.contains(item)) {
+        return Err(HttpError::new_with_category_status(
+            "当前登录账号权限不满足",
+            "forbidden",
+            StatusCode::FORBIDDEN.as_u16(),
+        ));
+    }
+    // 仅匹配只读角色时，只允许查询类的请求
+    // 如果同时有其它角色则取并集，只读角色不会减少权限
+    if is_readonly_roles(roles, valid_roles) && ![Method::GET, Method::HEAD].contains(method) {
         return Err(HttpError {
-            message: "当前登录账号权限不满足".to_string(),
+            message: "当前登录账号仅有只读权限".to_string(),
+            category: "forbidden".to_string(),
+            code: "read_only_role".to_string(),
             status: StatusCode::FORBIDDEN.as_u16(),
             ..Default::default()
         });
     }
+    Ok(())
+}
+
+/// 校验登录账号的角色，需在路由上通过from_fn_with_state指定允许的角色
+pub async fn validate_roles(
+    State(valid_roles): State<Vec<String>>,
+    req: Request<Body>,
+    next: Next,
+) -> HttpResult<Response> {
+    // 已通过签名校验的内部服务，若允许服务角色则直接通过
+    if req.extensions().get::<ServiceIdentity>().is_some()
+        && valid_roles.iter().any(|item| item == ROLE_SERVICE)
+    {
+        return Ok(next.run(req).await);
+    }
+    let claim = get_claim_from_headers(req.headers()).await?;
+    let mut roles = vec![];
+    if !claim.account.is_empty() {
+        // 因为已登录成功，因此账号不存在不会发生
+        let result = get_cached_user(&claim.account)
+            .await?
+            .ok_or(HttpError::new("账号不存在"))?;
+        roles = result.get_roles()?;
+    }
+    check_roles(&claim.account, &roles, &valid_roles, req.method())?;
     let resp = next.run(req).await;
     Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{check_roles, is_readonly_roles, migrate_session, Claim, SESSION_VERSION};
+    use crate::util::Clock;
+    use axum::http::Method;
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[test]
+    fn session_migration() {
+        // 未记录版本的session
+        let claim = migrate_session(json!({
+            "exp": 2,
+            "iat": 1,
+            "id": "abc",
+            "account": "tree",
+        }))
+        .unwrap();
+        assert_eq!(SESSION_VERSION, claim.version);
+        assert_eq!("tree", claim.account);
+        assert_eq!(0, claim.tenant_id);
+        assert_eq!(0, claim.generation);
+
+        // 未知字段保留
+        let claim = migrate_session(json!({
+            "version": SESSION_VERSION,
+            "exp": 2,
+            "iat": 1,
+            "id": "abc",
+            "account": "tree",
+            "tenant_id": 3,
+            "generation": 2,
+            "device": "mac",
+        }))
+        .unwrap();
+        assert_eq!(3, claim.tenant_id);
+        assert_eq!(
+            r#"{"version":3,"exp":2,"iat":1,"id":"abc","account":"tree","tenant_id":3,"generation":2,"elevated_at":0,"device":"mac"}"#,
+            serde_json::to_string(&claim).unwrap()
+        );
+
+        assert_eq!(true, migrate_session(json!("abc")).is_none());
+        assert_eq!(true, migrate_session(json!({"account": 1})).is_none());
+    }
+
+    #[test]
+    fn claim_expired() {
+        let clock = Clock::new_test(1_700_000_000);
+        let claim = Claim {
+            iat: clock.timestamp(),
+            exp: clock.timestamp() + 3600,
+            ..Default::default()
+        };
+        assert_eq!(false, claim.is_expired_with(&clock));
+        clock.advance(Duration::from_secs(3601));
+        assert_eq!(true, claim.is_expired_with(&clock));
+
+        // 创建超过30天的session即使已刷新也过期
+        let claim = Claim {
+            iat: clock.timestamp(),
+            exp: clock.timestamp() + 31 * 24 * 3600,
+            ..Default::default()
+        };
+        clock.advance(Duration::from_secs(30 * 24 * 3600));
+        assert_eq!(false, claim.is_expired_with(&clock));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(true, claim.is_expired_with(&clock));
+    }
+
+    #[test]
+    fn claim_elevated() {
+        let clock = Clock::new_test(1_700_000_000);
+        let mut claim = Claim::default();
+        assert_eq!(false, claim.is_elevated_with(&clock, 300));
+        claim.elevated_at = clock.timestamp();
+        clock.advance(Duration::from_secs(300));
+        assert_eq!(true, claim.is_elevated_with(&clock, 300));
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(false, claim.is_elevated_with(&clock, 300));
+    }
+
+    #[test]
+    fn roles() {
+        let valid_roles = vec!["admin".to_string(), "readonly".to_string()];
+        let to_roles =
+            |roles: &[&str]| -> Vec<String> { roles.iter().map(|item| item.to_string()).collect() };
+
+        // 未登录
+        let err = check_roles("", &[], &valid_roles, &Method::GET).unwrap_err();
+        assert_eq!(401, err.status);
+
+        // 角色不匹配
+        let err =
+            check_roles("tree", &to_roles(&["tester"]), &valid_roles, &Method::GET).unwrap_err();
+        assert_eq!(403, err.status);
+        assert_eq!("forbidden", err.category);
+
+        // 角色匹配
+        assert_eq!(
+            true,
+            check_roles("tree", &to_roles(&["admin"]), &valid_roles, &Method::POST).is_ok()
+        );
+
+        // 只读角色仅允许查询
+        assert_eq!(
+            true,
+            check_roles("tree", &to_roles(&["readonly"]), &valid_roles, &Method::GET).is_ok()
+        );
+        let err = check_roles(
+            "tree",
+            &to_roles(&["readonly"]),
+            &valid_roles,
+            &Method::POST,
+        )
+        .unwrap_err();
+        assert_eq!("read_only_role", err.code);
+
+        // su总是允许
+        assert_eq!(
+            true,
+            check_roles("tree", &to_roles(&["su"]), &valid_roles, &Method::DELETE).is_ok()
+        );
+
+        // 只读角色不减少权限
+        assert_eq!(
+            false,
+            is_readonly_roles(&to_roles(&["admin"]), &valid_roles)
+        );
+        assert_eq!(
+            false,
+            is_readonly_roles(&to_roles(&["readonly", "admin"]), &valid_roles)
+        );
+        assert_eq!(false, is_readonly_roles(&to_roles(&["su"]), &valid_roles));
+        assert_eq!(
+            true,
+            is_readonly_roles(&to_roles(&["readonly"]), &valid_roles)
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use crate::config::{must_new_signature_config, SignatureConfig};
+use crate::error::{HttpError, HttpResult};
+use crate::util;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::middleware::Next;
+use axum::response::Response;
+use bytes::Bytes;
+use http_body_util::LengthLimitError;
+use once_cell::sync::Lazy;
+
+static SIGNATURE_CONFIG: Lazy<SignatureConfig> = Lazy::new(must_new_signature_config);
+
+/// 通过签名校验的内部服务
+#[derive(Debug, Clone, Default)]
+pub struct ServiceIdentity {
+    pub key_id: String,
+}
+
+fn new_signature_error(message: &str) -> HttpError {
+    HttpError {
+        message: message.to_string(),
+        category: "signature".to_string(),
+        status: StatusCode::UNAUTHORIZED.as_u16(),
+        ..Default::default()
+    }
+}
+
+// 读取body，超出长度限制返回413
+async fn read_limited_body(body: Body, limit: usize) -> HttpResult<Bytes> {
+    axum::body::to_bytes(body, limit).await.map_err(|err| {
+        let too_large =
+            std::error::Error::source(&err).is_some_and(|source| source.is::<LengthLimitError>());
+        if too_large {
+            HttpError::new_with_category_status(
+                &format!("Body exceeds the limit {limit}"),
+                "signature",
+                StatusCode::PAYLOAD_TOO_LARGE.as_u16(),
+            )
+        } else {
+            HttpError::new_with_category(&err.to_string(), "body_to_bytes")
+        }
+    })
+}
+
+/// 校验内部服务调用的签名，
+/// 配置的路由前缀必须有签名，其它路由若有签名也校验，
+/// 校验成功后设置ServiceIdentity至extensions中
+pub async fn verify_signature(req: Request<Body>, next: Next) -> HttpResult<Response> {
+    let headers = req.headers();
+    let key_id = util::get_header_value(headers, util::SIGNATURE_KEY_HEADER);
+    let path = req.uri().path();
+    let required = SIGNATURE_CONFIG
+        .prefixes
+        .iter()
+        .any(|prefix| path.starts_with(prefix));
+    if key_id.is_empty() && !required {
+        return Ok(next.run(req).await);
+    }
+    let Some((_, secret)) = SIGNATURE_CONFIG.keys.iter().find(|(id, _)| id == &key_id) else {
+        return Err(new_signature_error("Signature key is invalid"));
+    };
+    let timestamp = util::get_header_value(headers, util::SIGNATURE_TIMESTAMP_HEADER)
+        .parse::<i64>()
+        .unwrap_or_default();
+    if (util::timestamp() - timestamp).unsigned_abs() > SIGNATURE_CONFIG.skew.as_secs() {
+        return Err(new_signature_error("Signature is expired"));
+    }
+    let signature = util::get_header_value(headers, util::SIGNATURE_HEADER);
+    let method = req.method().to_string();
+    let path = req
+        .uri()
+        .path_and_query()
+        .map(|item| item.to_string())
+        .unwrap_or_default();
+
+    // 需要读取body计算hash，校验后再重新设置，
+    // 此时签名尚未校验，因此限制读取的长度，超出返回413
+    let (parts, body) = req.into_parts();
+    let body = read_limited_body(body, SIGNATURE_CONFIG.body_limit).await?;
+    if !util::verify_request(&method, &path, timestamp, &body, secret, &signature)? {
+        return Err(new_signature_error("Signature is invalid"));
+    }
+    let mut req = Request::from_parts(parts, Body::from(body));
+    req.extensions_mut().insert(ServiceIdentity { key_id });
+    Ok(next.run(req).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read_limited_body;
+    use axum::body::Body;
+    use pretty_assertions::assert_eq;
+
+    #[tokio::test]
+    async fn limited_body() {
+        let body = read_limited_body(Body::from("abcd"), 4).await.unwrap();
+        assert_eq!(b"abcd".as_slice(), body.as_ref());
+
+        let err = read_limited_body(Body::from("abcde"), 4).await.unwrap_err();
+        assert_eq!(413, err.status);
+        assert_eq!("signature", err.category);
+    }
+}
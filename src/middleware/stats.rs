@@ -1,8 +1,11 @@
-use crate::error::HttpResult;
+use super::{RouteLabel, SessionPolicy};
+use crate::error::{HttpError, HttpResult};
 use crate::state::AppState;
-use crate::util::{get_account_from_context, get_header_value, json_get, read_http_body};
+use crate::util::{
+    get_account_from_context, get_header_value, json_get, read_http_body, NDJSON_CONTENT_TYPE,
+};
 use crate::{task_local::*, tl_error, tl_info};
-use axum::http::Method;
+use axum::http::{header, Method};
 use axum::{body::Body, extract::State, http::Request, middleware::Next, response::Response};
 use axum_client_ip::InsecureClientIp;
 use chrono::Utc;
@@ -15,6 +18,7 @@ pub async fn access_log(
     next: Next,
 ) -> HttpResult<Response<Body>> {
     let start_at = STARTED_AT.with(clone_value_from_task_local);
+    let trace_context = TRACE_CONTEXT.with(clone_value_from_task_local);
     let processing = state.get_processing();
 
     let mut uri = req.uri().to_string();
@@ -23,6 +27,9 @@ pub async fn access_log(
         uri = result.to_string()
     }
     let method = req.method().to_string();
+    let label = RouteLabel::from_extensions(req.extensions(), req.uri().path());
+    let route = label.route;
+    let entity = label.entity;
     let x_forwarded_for = get_header_value(req.headers(), "X-Forwarded-For");
     let referrer = get_header_value(req.headers(), "Referer");
     let mut request_body_size = 0;
@@ -39,14 +46,48 @@ pub async fn access_log(
     // account 在获取session后才能获取
     // 而task local的值已回收，因此只能从extensions中获取
     let account = get_account_from_context(resp.extensions());
+    let session_policy = resp
+        .extensions()
+        .get::<SessionPolicy>()
+        .copied()
+        .unwrap_or_default()
+        .as_str();
 
     let status = resp.status().as_u16();
 
+    // 流式响应不读取数据，避免占用内存
+    if get_header_value(resp.headers(), header::CONTENT_TYPE.as_str()) == NDJSON_CONTENT_TYPE {
+        let cost = Utc::now().timestamp_millis() - start_at;
+        tl_info!(
+            category = "access",
+            account = account,
+            ip = ip.to_string(),
+            x_forwarded_for,
+            referrer,
+            method,
+            route,
+            entity,
+            session_policy,
+            uri,
+            status,
+            cost,
+            processing,
+            request_body_size,
+            trace_parent = trace_context.trace_id,
+            sampled = trace_context.sampled,
+        );
+        return Ok(resp);
+    }
+
     let (parts, body) = resp.into_parts();
     let data = read_http_body(body).await?;
     let mut message = "".to_string();
     if status >= 400 {
-        message = json_get(&data, "message")
+        // 隐藏了出错信息的响应，从extensions中获取原始出错
+        message = match parts.extensions.get::<HttpError>() {
+            Some(err) => err.message.clone(),
+            None => json_get(&data, "message"),
+        };
     }
     if message.is_empty() {
         message = std::string::String::from_utf8_lossy(&data).to_string();
@@ -55,10 +96,6 @@ pub async fn access_log(
     let response_body_size = data.len();
     let res = Response::from_parts(parts, Body::from(data));
 
-    // TODO route 获取（不包括路由参数:id这样)
-    // /users/:id 请求/users/123
-    // route为 /users/:id
-
     let cost = Utc::now().timestamp_millis() - start_at;
 
     tl_info!(
@@ -68,12 +105,17 @@ pub async fn access_log(
         x_forwarded_for,
         referrer,
         method,
+        route,
+        entity,
+        session_policy,
         uri,
         status,
         cost,
         processing,
         request_body_size,
         response_body_size,
+        trace_parent = trace_context.trace_id,
+        sampled = trace_context.sampled,
     );
 
     // 出错日志
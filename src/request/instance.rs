@@ -1,8 +1,12 @@
 use crate::error::HttpError;
-use crate::util::json_get;
+use crate::task_local::{clone_value_from_task_local, TRACE_CONTEXT};
+use crate::util::{
+    json_get, new_span_id, sign_request, timestamp, SIGNATURE_HEADER, SIGNATURE_KEY_HEADER,
+    SIGNATURE_TIMESTAMP_HEADER, TRACEPARENT_HEADER,
+};
 use async_trait::async_trait;
 use axum::http::uri::Uri;
-use axum::http::Method;
+use axum::http::{HeaderValue, Method};
 use bytes::Bytes;
 use chrono::Local;
 // use hyper::client::connect::HttpInfo;
@@ -161,6 +165,72 @@ impl HttpInterceptor for CommonInterceptor {
     }
 }
 
+/// 内部服务调用的拦截器，对请求添加签名
+pub struct SignatureInterceptor {
+    service: String,
+    key_id: String,
+    secret: String,
+}
+
+impl SignatureInterceptor {
+    pub fn new(service: &str, key_id: &str, secret: &str) -> SignatureInterceptor {
+        SignatureInterceptor {
+            service: service.to_string(),
+            key_id: key_id.to_string(),
+            secret: secret.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl HttpInterceptor for SignatureInterceptor {
+    async fn error(&self, status: u16, data: &Bytes) -> Result<()> {
+        handle_error(&self.service, status, data).await
+    }
+    async fn request(&self, req: RequestBuilder) -> Result<RequestBuilder> {
+        let (client, result) = req.build_split();
+        let mut req = result.context(BuildSnafu {
+            service: &self.service,
+        })?;
+        let url = req.url();
+        let path = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        let body = req
+            .body()
+            .and_then(|item| item.as_bytes())
+            .unwrap_or_default();
+        let timestamp = timestamp();
+        let signature = sign_request(req.method().as_str(), &path, timestamp, body, &self.secret)
+            .map_err(|err| Error::Common {
+            service: self.service.clone(),
+            message: err.message,
+        })?;
+        let headers = req.headers_mut();
+        for (key, value) in [
+            (SIGNATURE_KEY_HEADER, self.key_id.clone()),
+            (SIGNATURE_TIMESTAMP_HEADER, timestamp.to_string()),
+            (SIGNATURE_HEADER, signature),
+        ] {
+            let value = HeaderValue::from_str(&value).map_err(|err| Error::Common {
+                service: self.service.clone(),
+                message: err.to_string(),
+            })?;
+            headers.insert(key, value);
+        }
+        Ok(RequestBuilder::from_parts(client, req))
+    }
+    async fn response(&self, data: Bytes) -> Result<Bytes> {
+        Ok(data)
+    }
+    async fn on_done(&self, stats: HttpStats, err: Option<&Error>) -> Result<()> {
+        CommonInterceptor::new(&self.service)
+            .on_done(stats, err)
+            .await
+    }
+}
+
 static EMPTY_QUERY: Option<&[(&str, &str)]> = None;
 static EMPTY_BODY: Option<&[(&str, &str)]> = None;
 
@@ -257,6 +327,10 @@ impl<H: HttpInterceptor> Instance<H> {
         if let Some(value) = params.body {
             req = req.json(value);
         }
+        // 传递trace context，便于关联调用链
+        if let Ok(ctx) = TRACE_CONTEXT.try_with(clone_value_from_task_local) {
+            req = req.header(TRACEPARENT_HEADER, ctx.to_traceparent(&new_span_id()));
+        }
         req = self.interceptor.request(req).await?;
         // TODO dns tcp tls process
         let process_done = new_get_duration();
@@ -1,7 +1,9 @@
-use once_cell::sync::OnceCell;
+use crate::config::must_new_signature_config;
+use once_cell::sync::{Lazy, OnceCell};
 use std::time::Duration;
 
 mod instance;
+mod replay;
 use instance::CommonInterceptor;
 
 pub fn must_get_httpbin_instance() -> &'static Instance<CommonInterceptor> {
@@ -19,4 +21,27 @@ pub fn must_get_httpbin_instance() -> &'static Instance<CommonInterceptor> {
         .unwrap()
 }
 
-pub use instance::Instance;
+// 内部服务的实例，使用第一个key签名，未配置地址或key则为None
+static SERVICE_INSTANCE: Lazy<Option<Instance<SignatureInterceptor>>> = Lazy::new(|| {
+    let config = must_new_signature_config();
+    let (key_id, secret) = config.keys.first()?;
+    if config.service_url.is_empty() {
+        return None;
+    }
+    let service = "service";
+    Instance::new(
+        service,
+        &config.service_url,
+        Duration::from_secs(10),
+        SignatureInterceptor::new(service, key_id, secret),
+    )
+    .ok()
+});
+
+/// 获取调用内部服务的实例，请求均添加签名
+pub fn get_service_instance() -> Option<&'static Instance<SignatureInterceptor>> {
+    SERVICE_INSTANCE.as_ref()
+}
+
+pub use instance::{Instance, SignatureInterceptor};
+pub use replay::*;
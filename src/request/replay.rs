@@ -0,0 +1,48 @@
+use crate::error::{HttpError, HttpResult};
+use axum::body::Bytes;
+use once_cell::sync::Lazy;
+use reqwest::{Client, Method};
+use std::time::Duration;
+
+static REPLAY_CLIENT: Lazy<Client> = Lazy::new(|| {
+    Client::builder()
+        .timeout(Duration::from_secs(30))
+        .build()
+        .unwrap_or_default()
+});
+
+/// 重放请求的响应
+#[derive(Debug, Clone, Default)]
+pub struct ReplayResponse {
+    pub status: u16,
+    pub body: Bytes,
+}
+
+/// 按原始的请求方法、请求头及请求数据发送请求，
+/// 不做json转换
+pub async fn replay(
+    method: &str,
+    url: &str,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+) -> HttpResult<ReplayResponse> {
+    let method = Method::from_bytes(method.as_bytes())
+        .map_err(|err| HttpError::new_with_category(&err.to_string(), "replay"))?;
+    let mut req = REPLAY_CLIENT.request(method, url);
+    for (name, value) in headers {
+        req = req.header(name, value);
+    }
+    if let Some(body) = body {
+        req = req.body(body);
+    }
+    let res = req
+        .send()
+        .await
+        .map_err(|err| HttpError::new_with_category(&err.to_string(), "replay"))?;
+    let status = res.status().as_u16();
+    let body = res
+        .bytes()
+        .await
+        .map_err(|err| HttpError::new_with_category(&err.to_string(), "replay"))?;
+    Ok(ReplayResponse { status, body })
+}
@@ -0,0 +1,186 @@
+use crate::cache::get_default_redis_cache;
+use crate::config::must_new_basic_config;
+use crate::db::get_database;
+use crate::error::{HttpError, HttpResult};
+use crate::middleware::check_session_signing;
+use crate::request::{get_service_instance, Instance, SignatureInterceptor};
+use crate::state::{get_app_state, write_readiness_file};
+use crate::util;
+use chrono::{DateTime, Utc};
+use once_cell::sync::Lazy;
+use sea_orm::{ConnectionTrait, DbBackend, Statement, TransactionTrait};
+use serde::Serialize;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::RwLock;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+// 每一项检查的超时
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 单项检查的结果
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct SelfTestItem {
+    pub name: String,
+    // 关键项失败则不接收请求
+    pub critical: bool,
+    pub ok: bool,
+    pub error: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+/// 自检的结果
+#[derive(Debug, Clone, Serialize, Default)]
+pub struct SelfTestReport {
+    pub ok: bool,
+    pub run_at: Option<DateTime<Utc>>,
+    pub items: Vec<SelfTestItem>,
+}
+
+static LAST_REPORT: Lazy<RwLock<SelfTestReport>> =
+    Lazy::new(|| RwLock::new(SelfTestReport::default()));
+// 是否因自检失败而未设置为运行状态
+static BLOCKED: AtomicBool = AtomicBool::new(false);
+
+async fn check_redis() -> HttpResult<()> {
+    let cache = get_default_redis_cache();
+    let key = format!("selftest:{}", util::uuid());
+    let value = util::uuid();
+    cache
+        .set(&key, &value, Some(Duration::from_secs(60)))
+        .await?;
+    let result: Option<String> = cache.get(&key).await?;
+    cache.del(&key).await?;
+    if result != Some(value) {
+        return Err(HttpError::new_with_category(
+            "Redis read back mismatch",
+            "selftest",
+        ));
+    }
+    Ok(())
+}
+
+async fn check_database() -> HttpResult<()> {
+    let txn = get_database().await.begin().await?;
+    txn.execute(Statement::from_string(DbBackend::MySql, "SELECT 1"))
+        .await?;
+    txn.rollback().await?;
+    Ok(())
+}
+
+async fn check_captcha() -> HttpResult<()> {
+    let (text, data) = util::new_captcha();
+    if text.is_empty() || data.is_empty() {
+        return Err(HttpError::new_with_category(
+            "Captcha render fail",
+            "selftest",
+        ));
+    }
+    Ok(())
+}
+
+async fn check_session() -> HttpResult<()> {
+    check_session_signing()
+}
+
+// 以签名调用内部服务的接口，确认对方接受当前的签名key
+async fn check_service(instance: &Instance<SignatureInterceptor>) -> HttpResult<()> {
+    instance
+        .get::<serde_json::Value>("/api/services/logging")
+        .await?;
+    Ok(())
+}
+
+async fn run_item<F>(name: &str, critical: bool, fut: F) -> SelfTestItem
+where
+    F: Future<Output = HttpResult<()>>,
+{
+    let start = Instant::now();
+    let result = match tokio::time::timeout(CHECK_TIMEOUT, fut).await {
+        Ok(result) => result,
+        Err(_) => Err(HttpError::new_with_category("Check timeout", "selftest")),
+    };
+    let item = SelfTestItem {
+        name: name.to_string(),
+        critical,
+        ok: result.is_ok(),
+        error: result.err().map(|err| err.message),
+        elapsed_ms: start.elapsed().as_millis() as u64,
+    };
+    if item.ok {
+        info!(
+            category = "selftest",
+            name = item.name,
+            elapsed = item.elapsed_ms
+        );
+    } else {
+        error!(
+            category = "selftest",
+            name = item.name,
+            critical = item.critical,
+            elapsed = item.elapsed_ms,
+            error = item.error,
+        );
+    }
+    item
+}
+
+/// 执行自检，各项依次执行且均有超时，关键项均成功才为成功
+pub async fn run() -> SelfTestReport {
+    let mut items = vec![
+        run_item("redis", true, check_redis()).await,
+        run_item("database", true, check_database()).await,
+        run_item("session", true, check_session()).await,
+        run_item("captcha", false, check_captcha()).await,
+    ];
+    // 配置了内部服务才检查
+    if let Some(instance) = get_service_instance() {
+        items.push(run_item("service", false, check_service(instance)).await);
+    }
+    let report = SelfTestReport {
+        ok: items.iter().all(|item| item.ok || !item.critical),
+        run_at: Some(Utc::now()),
+        items,
+    };
+    if let Ok(mut value) = LAST_REPORT.write() {
+        *value = report.clone();
+    }
+    report
+}
+
+/// 启动时执行自检，失败则保持非运行状态，
+/// 负载均衡的健康检查失败不会转发请求
+pub async fn run_on_startup() -> bool {
+    let report = run().await;
+    BLOCKED.store(!report.ok, Ordering::Relaxed);
+    report.ok
+}
+
+/// 重新执行自检，若启动时自检失败而此次成功，则设置为运行状态
+pub async fn rerun() -> SelfTestReport {
+    let report = run().await;
+    if report.ok && BLOCKED.swap(false, Ordering::Relaxed) {
+        let app_state = get_app_state();
+        app_state.run();
+        let basic_config = must_new_basic_config();
+        write_readiness_file(
+            &basic_config.readiness_file,
+            app_state.get_started_at(),
+            &basic_config.listen,
+        );
+        info!(
+            category = "selftest",
+            "application is running after selftest"
+        );
+    }
+    report
+}
+
+/// 最近一次自检的结果
+pub fn get_last_report() -> SelfTestReport {
+    LAST_REPORT
+        .read()
+        .map(|value| value.clone())
+        .unwrap_or_default()
+}
@@ -0,0 +1,230 @@
+use crate::cache::get_default_redis_cache;
+use crate::db::{
+    find_valid_settings_by_category, register_entity_hooks, EntityHooks, HookContext, ROLE_SU,
+    TABLE_NAME_USERS,
+};
+use crate::error::HttpResult;
+use crate::util;
+use axum::async_trait;
+use serde::Deserialize;
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::error;
+
+/// 敏感操作规则对应的配置分类，配置名称为rule
+pub static SENSITIVE_CATEGORY: &str = "sensitive";
+static SENSITIVE_RULE_NAME: &str = "rule";
+
+/// 敏感操作规则的参数，未配置的使用默认值
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SensitiveParams {
+    // 视为高权限的角色，授予或登录时需要告警
+    pub roles: Vec<String>,
+    // 每个账号记录最近登录的ip数量
+    pub login_ip_limit: usize,
+    // 登录ip的保存时长(秒)
+    pub login_ip_ttl: u64,
+}
+
+impl Default for SensitiveParams {
+    fn default() -> Self {
+        SensitiveParams {
+            roles: vec![ROLE_SU.to_string()],
+            login_ip_limit: 20,
+            login_ip_ttl: 90 * 24 * 3600,
+        }
+    }
+}
+
+fn parse_sensitive_params(data: &str) -> Result<SensitiveParams, serde_json::Error> {
+    if data.trim().is_empty() {
+        return Ok(SensitiveParams::default());
+    }
+    serde_json::from_str(data)
+}
+
+/// 获取敏感操作规则的参数，配置读取经由配置缓存，
+/// 加载或解析失败时记录日志并使用默认值，避免告警失效
+pub async fn get_sensitive_params() -> SensitiveParams {
+    let settings = match find_valid_settings_by_category(SENSITIVE_CATEGORY).await {
+        Ok(settings) => settings,
+        Err(err) => {
+            error!(category = "sensitive_params", error = err.message);
+            return SensitiveParams::default();
+        }
+    };
+    let Some(item) = settings
+        .into_iter()
+        .find(|item| item.name == SENSITIVE_RULE_NAME)
+    else {
+        return SensitiveParams::default();
+    };
+    parse_sensitive_params(&item.data).unwrap_or_else(|err| {
+        error!(category = "sensitive_params", error = err.to_string());
+        SensitiveParams::default()
+    })
+}
+
+/// 需要立即告警的敏感操作
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SensitiveRule {
+    // 用户被授予su角色
+    RoleEscalation,
+    // su账号从未使用过的ip登录
+    SuLoginNewIp,
+    // 用于确认告警是否可正常触达
+    Test,
+}
+
+impl SensitiveRule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SensitiveRule::RoleEscalation => "role_escalation",
+            SensitiveRule::SuLoginNewIp => "su_login_new_ip",
+            SensitiveRule::Test => "test",
+        }
+    }
+}
+
+/// 触发敏感操作的告警，直接输出error日志，
+/// 不做合并，由日志告警通知值班人员
+pub fn notify(rule: SensitiveRule, operator: &str, message: &str) {
+    error!(
+        category = "sensitive_action",
+        rule = rule.as_str(),
+        operator,
+        flagged = true,
+        message,
+    );
+}
+
+fn get_roles(value: &Value) -> Vec<String> {
+    util::json_get_strings(value, "roles")
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+}
+
+// 新授予的高权限角色
+fn granted_roles(sensitive_roles: &[String], old: &[String], new: &[String]) -> Vec<String> {
+    sensitive_roles
+        .iter()
+        .filter(|role| new.contains(role) && !old.contains(role))
+        .cloned()
+        .collect()
+}
+
+/// 检查账号的角色变更，新授予高权限角色时告警。
+/// 数据变更处理以及直接通过事务更新角色的流程(如合并用户)均需调用
+pub async fn check_role_escalation(
+    operator: &str,
+    account: &str,
+    id: i64,
+    old: &[String],
+    new: &[String],
+) {
+    let params = get_sensitive_params().await;
+    let roles = granted_roles(&params.roles, old, new);
+    if !roles.is_empty() {
+        notify(
+            SensitiveRule::RoleEscalation,
+            operator,
+            &format!("user {account}({id}) is granted {}", roles.join(",")),
+        );
+    }
+}
+
+struct RoleEscalationHooks {}
+
+#[async_trait]
+impl EntityHooks for RoleEscalationHooks {
+    async fn after_update(
+        &self,
+        ctx: &HookContext,
+        id: i64,
+        old: &Value,
+        new: &Value,
+    ) -> HttpResult<()> {
+        let account = util::json_get_string(new, "account")?.unwrap_or_default();
+        check_role_escalation(&ctx.user, &account, id, &get_roles(old), &get_roles(new)).await;
+        Ok(())
+    }
+}
+
+/// 注册敏感操作相关的数据变更处理
+pub fn register_hooks() {
+    register_entity_hooks(TABLE_NAME_USERS, Arc::new(RoleEscalationHooks {}));
+}
+
+/// 高权限账号登录时检查是否为曾使用过的ip，
+/// 首次登录的仅记录不告警
+pub async fn check_login(account: &str, roles: &[String], ip: &str) -> HttpResult<()> {
+    let params = get_sensitive_params().await;
+    if !roles.iter().any(|item| params.roles.contains(item)) {
+        return Ok(());
+    }
+    let cache = get_default_redis_cache();
+    let key = format!("sensitive:login_ips:{account}");
+    let mut ips: Vec<String> = cache.get_struct(&key).await?.unwrap_or_default();
+    if ips.iter().any(|item| item == ip) {
+        return Ok(());
+    }
+    if !ips.is_empty() {
+        notify(
+            SensitiveRule::SuLoginNewIp,
+            account,
+            &format!("{account} login from new ip {ip}"),
+        );
+    }
+    ips.insert(0, ip.to_string());
+    ips.truncate(params.login_ip_limit);
+    cache
+        .set_struct(&key, &ips, Some(Duration::from_secs(params.login_ip_ttl)))
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{granted_roles, parse_sensitive_params, SensitiveParams};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn sensitive_params() {
+        assert_eq!(
+            SensitiveParams::default(),
+            parse_sensitive_params("").unwrap()
+        );
+
+        let params =
+            parse_sensitive_params(r#"{"roles":["su","admin"],"login_ip_limit":5}"#).unwrap();
+        assert_eq!(vec!["su".to_string(), "admin".to_string()], params.roles);
+        assert_eq!(5, params.login_ip_limit);
+        assert_eq!(SensitiveParams::default().login_ip_ttl, params.login_ip_ttl);
+
+        assert_eq!(true, parse_sensitive_params("{").is_err());
+    }
+
+    #[test]
+    fn granted() {
+        let roles = vec!["su".to_string(), "admin".to_string()];
+        assert_eq!(
+            vec!["admin".to_string()],
+            granted_roles(
+                &roles,
+                &["su".to_string()],
+                &["su".to_string(), "admin".to_string()]
+            )
+        );
+        assert_eq!(
+            true,
+            granted_roles(&roles, &["su".to_string()], &["su".to_string()]).is_empty()
+        );
+        assert_eq!(
+            true,
+            granted_roles(&roles, &[], &["normal".to_string()]).is_empty()
+        );
+    }
+}
@@ -0,0 +1,257 @@
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+use utoipa::ToSchema;
+
+type InitFuture = Pin<Box<dyn Future<Output = Result<(), String>> + Send>>;
+
+/// 启动时的初始化任务，如连接池、缓存等
+pub struct InitTask {
+    name: &'static str,
+    future: InitFuture,
+}
+
+impl InitTask {
+    pub fn new<F>(name: &'static str, future: F) -> Self
+    where
+        F: Future<Output = Result<(), String>> + Send + 'static,
+    {
+        InitTask {
+            name,
+            future: Box::pin(future),
+        }
+    }
+}
+
+/// 初始化任务的耗时
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitDuration {
+    pub name: &'static str,
+    pub elapsed: Duration,
+}
+
+/// 格式化各任务的耗时，用于启动汇总信息，如`database=120ms,redis=30ms`
+pub fn format_init_durations(durations: &[InitDuration]) -> String {
+    durations
+        .iter()
+        .map(|item| format!("{}={}ms", item.name, item.elapsed.as_millis()))
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// 并发执行初始化任务，最多同时执行parallelism个。
+/// 任一任务失败则取消其它任务，并返回失败的任务及被取消的任务，
+/// 超过deadline未完成则返回执行时间最长的未完成任务
+pub async fn run_init_tasks(
+    tasks: Vec<InitTask>,
+    parallelism: usize,
+    deadline: Duration,
+) -> Result<Vec<InitDuration>, String> {
+    let deadline_at = tokio::time::Instant::now() + deadline;
+    let mut queue: VecDeque<InitTask> = tasks.into();
+    let mut running: Vec<(&'static str, Instant)> = vec![];
+    let mut set = JoinSet::new();
+    let mut durations = vec![];
+    loop {
+        while running.len() < parallelism.max(1) {
+            let Some(task) = queue.pop_front() else {
+                break;
+            };
+            let start = Instant::now();
+            running.push((task.name, start));
+            set.spawn(async move {
+                let result = task.future.await;
+                (task.name, start.elapsed(), result)
+            });
+        }
+        if running.is_empty() {
+            break;
+        }
+        let pending = |running: &[(&'static str, Instant)], queue: &VecDeque<InitTask>| {
+            running
+                .iter()
+                .map(|(name, _)| *name)
+                .chain(queue.iter().map(|item| item.name))
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        let result = match tokio::time::timeout_at(deadline_at, set.join_next()).await {
+            Err(_) => {
+                set.abort_all();
+                let slowest = running
+                    .iter()
+                    .min_by_key(|(_, start)| *start)
+                    .map(|(name, _)| *name)
+                    .unwrap_or_default();
+                return Err(format!(
+                    "initialization is not completed in {deadline:?}, slowest task: {slowest}, pending: {}",
+                    pending(&running, &queue)
+                ));
+            }
+            Ok(None) => break,
+            Ok(Some(Err(err))) => {
+                set.abort_all();
+                return Err(format!(
+                    "initialization task panicked: {err}, pending: {}",
+                    pending(&running, &queue)
+                ));
+            }
+            Ok(Some(Ok(result))) => result,
+        };
+        let (name, elapsed, result) = result;
+        running.retain(|(item, _)| *item != name);
+        if let Err(err) = result {
+            set.abort_all();
+            return Err(format!(
+                "initialize {name} fail: {err}, cancelled: {}",
+                pending(&running, &queue)
+            ));
+        }
+        durations.push(InitDuration { name, elapsed });
+    }
+    Ok(durations)
+}
+
+/// 依赖组件的健康状态
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct ComponentHealth {
+    pub ok: bool,
+    pub latency_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// 并发检测各组件，超过deadline未完成的视为失败，
+/// 避免单个组件无响应导致检测接口一直等待
+pub async fn check_components(
+    tasks: Vec<InitTask>,
+    deadline: Duration,
+) -> HashMap<&'static str, ComponentHealth> {
+    let mut set = JoinSet::new();
+    for task in tasks {
+        set.spawn(async move {
+            let start = Instant::now();
+            let result = match tokio::time::timeout(deadline, task.future).await {
+                Ok(result) => result,
+                Err(_) => Err(format!("timeout after {deadline:?}")),
+            };
+            let health = ComponentHealth {
+                ok: result.is_ok(),
+                latency_ms: start.elapsed().as_millis() as u64,
+                error: result.err(),
+            };
+            (task.name, health)
+        });
+    }
+    let mut result = HashMap::new();
+    while let Some(item) = set.join_next().await {
+        if let Ok((name, health)) = item {
+            result.insert(name, health);
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_components, format_init_durations, run_init_tasks, InitDuration, InitTask};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    fn sleep_task(name: &'static str, ms: u64, fail: bool) -> InitTask {
+        InitTask::new(name, async move {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            if fail {
+                return Err("connect refused".to_string());
+            }
+            Ok(())
+        })
+    }
+
+    #[tokio::test]
+    async fn init_tasks() {
+        let durations = run_init_tasks(
+            vec![
+                sleep_task("database", 20, false),
+                sleep_task("redis", 10, false),
+            ],
+            2,
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap();
+        let names: Vec<&str> = durations.iter().map(|item| item.name).collect();
+        assert_eq!(vec!["redis", "database"], names);
+
+        let err = run_init_tasks(
+            vec![
+                sleep_task("database", 200, false),
+                sleep_task("redis", 10, true),
+                sleep_task("httpbin", 10, false),
+            ],
+            2,
+            Duration::from_secs(1),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            "initialize redis fail: connect refused, cancelled: database,httpbin",
+            err
+        );
+
+        let err = run_init_tasks(
+            vec![
+                sleep_task("database", 2000, false),
+                sleep_task("redis", 10, false),
+            ],
+            1,
+            Duration::from_millis(50),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(
+            "initialization is not completed in 50ms, slowest task: database, pending: database,redis",
+            err
+        );
+
+        assert_eq!(
+            "database=120ms,redis=30ms",
+            format_init_durations(&[
+                InitDuration {
+                    name: "database",
+                    elapsed: Duration::from_millis(120),
+                },
+                InitDuration {
+                    name: "redis",
+                    elapsed: Duration::from_millis(30),
+                },
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn components() {
+        let result = check_components(
+            vec![
+                sleep_task("database", 10, false),
+                sleep_task("redis", 10, true),
+                sleep_task("storage", 2000, false),
+            ],
+            Duration::from_millis(50),
+        )
+        .await;
+        assert_eq!(true, result["database"].ok);
+        assert_eq!(None, result["database"].error);
+        assert_eq!(false, result["redis"].ok);
+        assert_eq!(Some("connect refused".to_string()), result["redis"].error);
+        assert_eq!(false, result["storage"].ok);
+        assert_eq!(
+            Some("timeout after 50ms".to_string()),
+            result["storage"].error
+        );
+        assert_eq!(true, result["storage"].latency_ms < 1000);
+    }
+}
@@ -1,27 +1,16 @@
-use crate::config::{must_new_basic_config, must_new_session_config};
-use axum::extract::FromRef;
-use axum_extra::extract::cookie::Key;
+use crate::config::must_new_basic_config;
 use chrono::{DateTime, Utc};
 use once_cell::sync::OnceCell;
-use std::sync::atomic::{AtomicI32, AtomicI8, Ordering};
+use std::sync::atomic::{AtomicI32, AtomicI8, AtomicU64, Ordering};
+use std::time::Duration;
 
 pub struct AppState {
     pub processing_limit: i32,
     status: AtomicI8,
     processing: AtomicI32,
+    // 请求处理时长的滑动平均值(ms)
+    latency_avg: AtomicU64,
     started_at: DateTime<Utc>,
-    key: Key,
-}
-
-#[derive(Clone)]
-pub struct ReadonlyState {
-    key: Key,
-}
-
-impl FromRef<ReadonlyState> for Key {
-    fn from_ref(state: &ReadonlyState) -> Self {
-        state.key.clone()
-    }
 }
 
 const APP_STATUS_STOP: i8 = 0;
@@ -37,6 +26,22 @@ impl AppState {
     pub fn get_processing(&self) -> i32 {
         self.processing.load(Ordering::Relaxed)
     }
+    /// 记录请求的处理时长，使用指数加权平均(新值权重1/8)
+    pub fn record_latency(&self, value: Duration) {
+        let ms = value.as_millis() as u64;
+        let _ = self
+            .latency_avg
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |avg| {
+                if avg == 0 {
+                    return Some(ms.max(1));
+                }
+                Some((avg * 7 + ms) / 8)
+            });
+    }
+    /// 最近请求的平均处理时长
+    pub fn get_latency_avg(&self) -> Duration {
+        Duration::from_millis(self.latency_avg.load(Ordering::Relaxed))
+    }
     pub fn is_running(&self) -> bool {
         let value = self.status.load(Ordering::Relaxed);
         value == APP_STATUS_RUNNING
@@ -50,11 +55,6 @@ impl AppState {
     pub fn get_started_at(&self) -> DateTime<Utc> {
         self.started_at
     }
-    pub fn readonly(&self) -> ReadonlyState {
-        ReadonlyState {
-            key: self.key.clone(),
-        }
-    }
 }
 
 pub fn get_app_state() -> &'static AppState {
@@ -62,13 +62,12 @@ pub fn get_app_state() -> &'static AppState {
     APP_STATE.get_or_init(|| {
         // 在main时已调用，因此不会unwrap
         let basic_config = must_new_basic_config();
-        let session_config = must_new_session_config();
         AppState {
             processing_limit: basic_config.processing_limit,
             started_at: Utc::now(),
             status: AtomicI8::new(0),
             processing: AtomicI32::new(0),
-            key: Key::from(session_config.secret.as_bytes()),
+            latency_avg: AtomicU64::new(0),
         }
     })
 }
@@ -1,3 +1,5 @@
 mod app_state;
+mod readiness;
 
-pub use app_state::{get_app_state, AppState, ReadonlyState};
+pub use app_state::{get_app_state, AppState};
+pub use readiness::{remove_readiness_file, write_readiness_file};
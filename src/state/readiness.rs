@@ -0,0 +1,58 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::Path;
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, Serialize)]
+struct ReadinessInfo<'a> {
+    pid: u32,
+    started_at: DateTime<Utc>,
+    listen: &'a str,
+}
+
+fn write_file(file: &str, data: &[u8]) -> std::io::Result<()> {
+    // 先写入临时文件再重命名，避免读取到不完整的内容
+    let tmp = format!("{file}.tmp");
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, file)
+}
+
+/// 写入就绪文件，用于进程管理工具判断服务是否已就绪，
+/// 写入失败仅输出告警日志
+pub fn write_readiness_file(file: &str, started_at: DateTime<Utc>, listen: &str) {
+    if file.is_empty() {
+        return;
+    }
+    let info = ReadinessInfo {
+        pid: std::process::id(),
+        started_at,
+        listen,
+    };
+    let result = serde_json::to_vec(&info)
+        .map_err(|err| err.to_string())
+        .and_then(|data| write_file(file, &data).map_err(|err| err.to_string()));
+    match result {
+        Ok(()) => info!(category = "readiness", file, "write readiness file success"),
+        Err(err) => warn!(
+            category = "readiness",
+            file,
+            error = err,
+            "write readiness file fail"
+        ),
+    }
+}
+
+/// 删除就绪文件
+pub fn remove_readiness_file(file: &str) {
+    if file.is_empty() || !Path::new(file).exists() {
+        return;
+    }
+    if let Err(err) = std::fs::remove_file(file) {
+        warn!(
+            category = "readiness",
+            file,
+            error = err.to_string(),
+            "remove readiness file fail"
+        );
+    }
+}
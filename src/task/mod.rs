@@ -0,0 +1,239 @@
+use crate::cache::get_default_redis_cache;
+use crate::db::{
+    add_task, fail_task, finish_task, lock_task, prune_client_errors, scan_data_issues,
+    DATA_VALIDATION_ENTITIES,
+};
+use crate::entitlement::entitlements;
+use crate::error::{HttpError, HttpResult};
+use crate::util;
+use chrono::Duration as ChronoDuration;
+use once_cell::sync::Lazy;
+use serde_json::{json, Value};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::time::{sleep, timeout};
+use tracing::{error, info, warn};
+
+pub type TaskFuture = Pin<Box<dyn Future<Output = HttpResult<()>> + Send>>;
+pub type TaskHandler = fn(Value) -> TaskFuture;
+
+/// 任务类型定义
+pub struct TaskDefinition {
+    pub category: &'static str,
+    pub handler: TaskHandler,
+    // 单次执行的超时
+    pub timeout: Duration,
+    // 最多执行次数
+    pub max_attempts: i32,
+    // 需要授权的功能，未授权则不启动
+    pub entitlement: Option<&'static str>,
+}
+
+// 当前实例的标识，用于记录任务由哪个实例执行
+static INSTANCE_ID: Lazy<String> = Lazy::new(util::uuid);
+
+/// 数据校验任务，payload中可指定entity，未指定则校验所有支持的表
+pub static TASK_DATA_VALIDATION: &str = "data_validation";
+
+/// 清除超出最大条数的客户端出错记录，payload中需指定max_rows
+pub static TASK_CLIENT_ERROR_CLEANUP: &str = "client_error_cleanup";
+
+// 已注册的任务类型，新增的任务类型在此添加
+static TASK_DEFINITIONS: Lazy<Vec<TaskDefinition>> = Lazy::new(|| {
+    vec![
+        TaskDefinition {
+            category: TASK_DATA_VALIDATION,
+            handler: run_data_validation,
+            timeout: Duration::from_secs(30 * 60),
+            max_attempts: 1,
+            entitlement: None,
+        },
+        TaskDefinition {
+            category: TASK_CLIENT_ERROR_CLEANUP,
+            handler: run_client_error_cleanup,
+            timeout: Duration::from_secs(10 * 60),
+            max_attempts: 3,
+            entitlement: None,
+        },
+    ]
+});
+
+// 添加清除出错记录任务的间隔
+const CLIENT_ERROR_CLEANUP_INTERVAL: Duration = Duration::from_secs(3600);
+
+fn run_data_validation(payload: Value) -> TaskFuture {
+    Box::pin(async move {
+        let entities = match payload.get("entity").and_then(|value| value.as_str()) {
+            Some(entity) => vec![entity.to_string()],
+            None => DATA_VALIDATION_ENTITIES
+                .iter()
+                .map(|item| item.to_string())
+                .collect(),
+        };
+        for entity in entities.iter() {
+            let count = scan_data_issues(entity).await?;
+            info!(category = "data_validation", entity, count);
+        }
+        Ok(())
+    })
+}
+
+fn run_client_error_cleanup(payload: Value) -> TaskFuture {
+    Box::pin(async move {
+        let Some(max_rows) = payload.get("max_rows").and_then(Value::as_u64) else {
+            return Err(HttpError::new_with_category(
+                "Max rows of client errors is required",
+                "task",
+            ));
+        };
+        let count = prune_client_errors(max_rows).await?;
+        info!(category = "client_error", count, "clean up client errors");
+        Ok(())
+    })
+}
+
+/// 定时添加清除出错记录的任务，多实例时每个间隔仅添加一次
+pub fn start_client_error_cleanup(max_rows: u64) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(CLIENT_ERROR_CLEANUP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let locked = get_default_redis_cache()
+                .lock(
+                    &format!("task:{TASK_CLIENT_ERROR_CLEANUP}"),
+                    Some(CLIENT_ERROR_CLEANUP_INTERVAL - Duration::from_secs(60)),
+                )
+                .await;
+            let result = match locked {
+                Ok(true) => enqueue(
+                    TASK_CLIENT_ERROR_CLEANUP,
+                    json!({ "max_rows": max_rows }),
+                    &INSTANCE_ID,
+                )
+                .await
+                .map(|_| ()),
+                Ok(false) => Ok(()),
+                Err(err) => Err(err.into()),
+            };
+            if let Err(err) = result {
+                error!(
+                    category = "task",
+                    task = TASK_CLIENT_ERROR_CLEANUP,
+                    error = err.message
+                );
+            }
+        }
+    });
+}
+
+fn get_task_definition(category: &str) -> Option<&'static TaskDefinition> {
+    TASK_DEFINITIONS
+        .iter()
+        .find(|item| item.category == category)
+}
+
+/// 添加任务至队列，任务类型需要已注册
+pub async fn enqueue(category: &str, payload: Value, creator: &str) -> HttpResult<i64> {
+    if get_task_definition(category).is_none() {
+        return Err(HttpError::new_with_category(
+            &format!("Task {category} is not registered"),
+            "task",
+        ));
+    }
+    let task = add_task(category, payload, creator).await?;
+    Ok(task.id)
+}
+
+// 执行超时后任务已被其它实例重新获取，不再更新其状态
+fn warn_task_relocked(definition: &TaskDefinition, id: i64) {
+    warn!(
+        category = "task",
+        task = definition.category,
+        id,
+        "task is locked by other instance"
+    );
+}
+
+// 执行一个任务，返回是否有执行任务
+async fn run_once(definition: &TaskDefinition) -> HttpResult<bool> {
+    // 锁定超时的任务可被重新获取
+    let lock_timeout = ChronoDuration::seconds(definition.timeout.as_secs() as i64 * 2 + 60);
+    let Some(task) = lock_task(definition.category, &INSTANCE_ID, lock_timeout).await? else {
+        return Ok(false);
+    };
+    let payload = task.payload.clone().unwrap_or_default();
+    let result = match timeout(definition.timeout, (definition.handler)(payload)).await {
+        Ok(result) => result,
+        Err(_) => Err(HttpError::new_with_category("Task timeout", "task")),
+    };
+    match result {
+        Ok(()) => {
+            if !finish_task(&task).await? {
+                warn_task_relocked(definition, task.id);
+                return Ok(true);
+            }
+            info!(
+                category = "task",
+                task = definition.category,
+                id = task.id,
+                attempts = task.attempts,
+            );
+        }
+        Err(err) => {
+            if !fail_task(&task, &err.message, definition.max_attempts).await? {
+                warn_task_relocked(definition, task.id);
+                return Ok(true);
+            }
+            error!(
+                category = "task",
+                task = definition.category,
+                id = task.id,
+                attempts = task.attempts,
+                error = err.message,
+            );
+        }
+    }
+    Ok(true)
+}
+
+/// 获取已注册的任务类型
+pub fn get_task_categories() -> Vec<String> {
+    TASK_DEFINITIONS
+        .iter()
+        .map(|item| item.category.to_string())
+        .collect()
+}
+
+/// 启动所有任务类型的worker，每种类型一个
+pub fn start_task_workers() {
+    for definition in TASK_DEFINITIONS.iter() {
+        if let Some(feature) = definition.entitlement {
+            if !entitlements().has(feature) {
+                info!(
+                    category = "task_worker",
+                    task = definition.category,
+                    "feature is not licensed, skip"
+                );
+                continue;
+            }
+        }
+        tokio::spawn(async move {
+            loop {
+                match run_once(definition).await {
+                    // 有任务则继续获取下一个
+                    Ok(true) => continue,
+                    Ok(false) => {}
+                    Err(err) => {
+                        error!(
+                            category = "task_worker",
+                            task = definition.category,
+                            error = err.message,
+                        );
+                    }
+                }
+                sleep(Duration::from_secs(1)).await;
+            }
+        });
+    }
+}
@@ -15,4 +15,8 @@ tokio::task_local! {
     pub static DEVICE_ID: String;
     pub static ACCOUNT: String;
     pub static STARTED_AT: i64;
+    // 当前请求所属的租户，未设置时不允许查询租户隔离的表
+    pub static TENANT_ID: i64;
+    // W3C trace context，调用其它服务时传递
+    pub static TRACE_CONTEXT: crate::util::TraceContext;
 }
@@ -0,0 +1,15 @@
+use captcha::filters::{Noise, Wave};
+use captcha::Captcha;
+
+/// 生成图形验证码，返回验证码与base64的图片
+pub fn new_captcha() -> (String, String) {
+    let mut c = Captcha::new();
+    // 设置允许0会导致0的时候不展示，后续确认
+    c.set_chars(&"123456789".chars().collect::<Vec<_>>())
+        .add_chars(4)
+        .apply_filter(Noise::new(0.4))
+        .apply_filter(Wave::new(2.0, 8.0).horizontal())
+        .apply_filter(Wave::new(2.0, 8.0).vertical())
+        .view(120, 38);
+    (c.chars_as_string(), c.as_base64().unwrap_or_default())
+}
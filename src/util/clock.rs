@@ -0,0 +1,67 @@
+use chrono::{DateTime, Utc};
+#[cfg(test)]
+use std::sync::atomic::{AtomicI64, Ordering};
+#[cfg(test)]
+use std::sync::Arc;
+#[cfg(test)]
+use std::time::Duration;
+
+/// 时钟，默认使用系统时间，
+/// 测试时使用可手动调整的时钟，避免依赖sleep
+#[derive(Debug, Clone, Default)]
+pub enum Clock {
+    #[default]
+    System,
+    // 当前时间(毫秒)
+    #[cfg(test)]
+    Test(Arc<AtomicI64>),
+}
+
+impl Clock {
+    /// 创建指定时间(秒)的测试时钟
+    #[cfg(test)]
+    pub fn new_test(timestamp: i64) -> Self {
+        Clock::Test(Arc::new(AtomicI64::new(timestamp * 1000)))
+    }
+    pub fn now(&self) -> DateTime<Utc> {
+        match self {
+            Clock::System => Utc::now(),
+            #[cfg(test)]
+            Clock::Test(value) => {
+                DateTime::from_timestamp_millis(value.load(Ordering::Relaxed)).unwrap_or_default()
+            }
+        }
+    }
+    /// 当前时间戳(秒)
+    pub fn timestamp(&self) -> i64 {
+        self.now().timestamp()
+    }
+    /// 测试时钟前进指定时长，系统时钟无影响
+    #[cfg(test)]
+    pub fn advance(&self, value: Duration) {
+        if let Clock::Test(current) = self {
+            current.fetch_add(value.as_millis() as i64, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Clock;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    #[test]
+    fn advance() {
+        let clock = Clock::new_test(1_700_000_000);
+        assert_eq!(1_700_000_000, clock.timestamp());
+        let other = clock.clone();
+        clock.advance(Duration::from_secs(90));
+        assert_eq!(1_700_000_090, other.timestamp());
+
+        // 系统时钟不受影响
+        let clock = Clock::System;
+        let now = clock.timestamp();
+        clock.advance(Duration::from_secs(3600));
+        assert_eq!(true, clock.timestamp() - now < 10);
+    }
+}
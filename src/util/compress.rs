@@ -1,5 +1,4 @@
 use crate::error::HttpError;
-use lz4_flex::block::{compress_prepend_size, decompress_size_prepended, DecompressError};
 use snafu::{ResultExt, Snafu};
 
 #[derive(Snafu, Debug)]
@@ -9,8 +8,6 @@ pub enum Error {
         category: String,
         source: std::io::Error,
     },
-    #[snafu(display("{source}"))]
-    Lz4Decompress { source: DecompressError },
 }
 impl From<Error> for HttpError {
     fn from(err: Error) -> Self {
@@ -18,9 +15,6 @@ impl From<Error> for HttpError {
             Error::Io { category, source } => {
                 HttpError::new_with_category(&source.to_string(), &category)
             }
-            Error::Lz4Decompress { source } => {
-                HttpError::new_with_category(&source.to_string(), "lz4")
-            }
         }
     }
 }
@@ -46,13 +40,3 @@ pub fn zstd_encode(data: &[u8]) -> Result<Vec<u8>> {
     )?;
     Ok(buf)
 }
-
-// lz4解压
-pub fn lz4_decode(data: &[u8]) -> Result<Vec<u8>> {
-    decompress_size_prepended(data).context(Lz4DecompressSnafu {})
-}
-
-// lz4压缩
-pub fn lz4_encode(data: &[u8]) -> Vec<u8> {
-    compress_prepend_size(data)
-}
@@ -0,0 +1,161 @@
+// 可在浏览器中执行脚本的类型，响应时需作为附件下载
+static ACTIVE_CONTENT_TYPES: &[&str] = &[
+    "text/html",
+    "image/svg+xml",
+    "application/xml",
+    "text/xml",
+    "application/xhtml+xml",
+];
+
+static MAGIC_BYTES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+];
+
+/// 根据文件头识别文件类型，无法识别时返回None
+pub fn sniff_content_type(data: &[u8]) -> Option<&'static str> {
+    for (magic, content_type) in MAGIC_BYTES.iter() {
+        if data.starts_with(magic) {
+            return Some(content_type);
+        }
+    }
+    if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        return Some("image/webp");
+    }
+    // 文本类型仅检查前512字节
+    let head = &data[0..data.len().min(512)];
+    let head = String::from_utf8_lossy(head).to_lowercase();
+    let head = head.trim_start_matches('\u{feff}').trim_start();
+    if head.starts_with("<!doctype html") || head.starts_with("<html") || head.contains("<script") {
+        return Some("text/html");
+    }
+    if head.starts_with("<svg") || (head.starts_with("<?xml") && head.contains("<svg")) {
+        return Some("image/svg+xml");
+    }
+    if head.starts_with("<?xml") {
+        return Some("application/xml");
+    }
+    None
+}
+
+/// 识别的类型与声明的类型是否一致，
+/// 基于zip或xml的格式(如docx)识别为zip或xml也认为一致
+pub fn is_content_type_compatible(expected: &str, sniffed: &str) -> bool {
+    let expected = expected.split(';').next().unwrap_or_default().trim();
+    if expected.eq_ignore_ascii_case(sniffed) {
+        return true;
+    }
+    match sniffed {
+        "application/zip" => expected.starts_with("application/vnd.") || expected.ends_with("+zip"),
+        "application/xml" => expected == "text/xml" || expected.ends_with("+xml"),
+        _ => false,
+    }
+}
+
+/// 获取图片的宽高，仅支持png、gif与jpeg，无法识别时返回None
+pub fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let read_u16_be = |index: usize| -> Option<u32> {
+        let bytes = data.get(index..index + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]) as u32)
+    };
+    match sniff_content_type(data)? {
+        "image/png" => {
+            let bytes = data.get(16..24)?;
+            let width = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+            let height = u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]);
+            Some((width, height))
+        }
+        "image/gif" => {
+            let bytes = data.get(6..10)?;
+            let width = u16::from_le_bytes([bytes[0], bytes[1]]) as u32;
+            let height = u16::from_le_bytes([bytes[2], bytes[3]]) as u32;
+            Some((width, height))
+        }
+        "image/jpeg" => {
+            // 遍历各段，SOF段中记录了宽高
+            let mut index = 2;
+            while index + 4 <= data.len() {
+                if data[index] != 0xff {
+                    return None;
+                }
+                let marker = data[index + 1];
+                if (0xc0..=0xcf).contains(&marker) && ![0xc4, 0xc8, 0xcc].contains(&marker) {
+                    let height = read_u16_be(index + 5)?;
+                    let width = read_u16_be(index + 7)?;
+                    return Some((width, height));
+                }
+                index += 2 + read_u16_be(index + 2)? as usize;
+            }
+            None
+        }
+        _ => None,
+    }
+}
+
+/// 是否需要作为附件下载的类型
+pub fn is_active_content_type(content_type: &str) -> bool {
+    let value = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+    ACTIVE_CONTENT_TYPES.contains(&value.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        image_dimensions, is_active_content_type, is_content_type_compatible, sniff_content_type,
+    };
+    use pretty_assertions::assert_eq;
+    #[test]
+    fn sniff() {
+        assert_eq!(
+            Some("image/png"),
+            sniff_content_type(b"\x89PNG\r\n\x1a\n0000")
+        );
+        assert_eq!(Some("application/pdf"), sniff_content_type(b"%PDF-1.7"));
+        assert_eq!(
+            Some("text/html"),
+            sniff_content_type(b"\n <!DOCTYPE html><html></html>")
+        );
+        assert_eq!(
+            Some("image/svg+xml"),
+            sniff_content_type(b"<?xml version=\"1.0\"?><svg></svg>")
+        );
+        assert_eq!(None, sniff_content_type(b"hello world"));
+
+        assert_eq!(true, is_content_type_compatible("image/png", "image/png"));
+        assert_eq!(
+            false,
+            is_content_type_compatible("application/pdf", "text/html")
+        );
+        assert_eq!(
+            true,
+            is_content_type_compatible(
+                "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+                "application/zip"
+            )
+        );
+        assert_eq!(true, is_active_content_type("text/html; charset=utf-8"));
+        assert_eq!(false, is_active_content_type("image/png"));
+    }
+
+    #[test]
+    fn dimensions() {
+        let mut png = b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR".to_vec();
+        png.extend([0, 0, 0x02, 0x80, 0, 0, 0x01, 0xe0]);
+        assert_eq!(Some((640, 480)), image_dimensions(&png));
+        assert_eq!(Some((10, 20)), image_dimensions(b"GIF89a\x0a\x00\x14\x00"));
+        // APP0段后为SOF0段
+        let jpeg = b"\xff\xd8\xff\xe0\x00\x04\x00\x00\xff\xc0\x00\x11\x08\x00\x78\x00\xa0";
+        assert_eq!(Some((160, 120)), image_dimensions(jpeg));
+        assert_eq!(None, image_dimensions(b"%PDF-1.7"));
+    }
+}
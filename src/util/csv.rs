@@ -0,0 +1,58 @@
+use serde_json::Value;
+
+pub static CSV_CONTENT_TYPE: &str = "text/csv; charset=utf-8";
+
+// 包含逗号、引号或换行的字段需使用双引号包裹，引号转义为两个引号
+fn escape_csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", value.replace('"', "\"\""));
+    }
+    value.to_string()
+}
+
+/// 生成csv的一行(以\r\n结尾)
+pub fn to_csv_line<T: AsRef<str>>(values: &[T]) -> String {
+    let line: Vec<String> = values
+        .iter()
+        .map(|item| escape_csv_field(item.as_ref()))
+        .collect();
+    line.join(",") + "\r\n"
+}
+
+/// 按字段顺序将json转换为csv的一行，
+/// 字符串直接输出，null为空，数组与对象输出为json
+pub fn json_to_csv_line(fields: &[String], value: &Value) -> String {
+    let values: Vec<String> = fields
+        .iter()
+        .map(|field| match value.get(field) {
+            None | Some(Value::Null) => "".to_string(),
+            Some(Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+        })
+        .collect();
+    to_csv_line(&values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{json_to_csv_line, to_csv_line};
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn csv() {
+        assert_eq!("id,name\r\n", to_csv_line(&["id", "name"]));
+        assert_eq!(
+            "a,\"b,c\",\"say \"\"hi\"\"\",\"x\ny\"\r\n",
+            to_csv_line(&["a", "b,c", "say \"hi\"", "x\ny"])
+        );
+        let fields = ["id", "name", "roles", "remark"].map(|item| item.to_string());
+        assert_eq!(
+            "1,tree,\"[\"\"su\"\"]\",\r\n",
+            json_to_csv_line(
+                &fields,
+                &json!({"id": 1, "name": "tree", "roles": ["su"], "remark": null})
+            )
+        );
+    }
+}
@@ -1,9 +1,18 @@
 use crate::error::{HttpError, HttpResult};
 use axum::body::{Body, Bytes};
 use axum::http::{header, header::HeaderName, HeaderMap, HeaderValue};
+use chrono::{DateTime, Utc};
 use http_body_util::BodyExt;
+use hyper::body::Frame;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::pin::Pin;
 use std::str::FromStr;
+use std::task::{Context, Poll};
+use tokio::sync::mpsc::Receiver;
+/// 流式导出数据的content type
+pub static NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
 /// 插入HTTP头
 pub fn insert_header(
     headers: &mut HeaderMap<HeaderValue>,
@@ -55,6 +64,33 @@ pub fn get_header_value(headers: &HeaderMap<HeaderValue>, key: &str) -> String {
     }
 }
 
+/// 格式化为http日期，如`Wed, 21 Oct 2015 07:28:00 GMT`
+pub fn to_http_date(value: &DateTime<Utc>) -> String {
+    value.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// 根据If-None-Match与If-Modified-Since判断客户端缓存是否仍有效，
+/// 有If-None-Match时忽略If-Modified-Since
+pub fn is_not_modified(
+    headers: &HeaderMap<HeaderValue>,
+    entity_tag: &str,
+    last_modified: &DateTime<Utc>,
+) -> bool {
+    let if_none_match = get_header_value(headers, header::IF_NONE_MATCH.as_str());
+    if !if_none_match.is_empty() {
+        return if_none_match.split(',').any(|item| {
+            let item = item.trim();
+            item == "*" || item.trim_start_matches("W/") == entity_tag
+        });
+    }
+    let if_modified_since = get_header_value(headers, header::IF_MODIFIED_SINCE.as_str());
+    let Ok(since) = DateTime::parse_from_rfc2822(&if_modified_since) else {
+        return false;
+    };
+    // http日期仅精确到秒
+    last_modified.timestamp() <= since.timestamp()
+}
+
 /// 读取http body
 pub async fn read_http_body(body: Body) -> HttpResult<Bytes> {
     let bytes = body
@@ -64,3 +100,60 @@ pub async fn read_http_body(body: Body) -> HttpResult<Bytes> {
         .to_bytes();
     Ok(bytes)
 }
+
+/// 基于channel的http body，用于流式响应
+pub struct ChannelBody(Receiver<Bytes>);
+
+impl ChannelBody {
+    pub fn new(rx: Receiver<Bytes>) -> Self {
+        ChannelBody(rx)
+    }
+}
+
+impl hyper::body::Body for ChannelBody {
+    type Data = Bytes;
+    type Error = Infallible;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Self::Data>, Self::Error>>> {
+        // channel关闭则表示数据已写完
+        self.0
+            .poll_recv(cx)
+            .map(|item| item.map(|data| Ok(Frame::data(data))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_not_modified, to_http_date};
+    use axum::http::{header, HeaderMap, HeaderValue};
+    use chrono::{TimeZone, Utc};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn conditional() {
+        let modified = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 0).unwrap();
+        assert_eq!("Wed, 21 Oct 2015 07:28:00 GMT", to_http_date(&modified));
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(false, is_not_modified(&headers, r#""abc""#, &modified));
+
+        headers.insert(
+            header::IF_MODIFIED_SINCE,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(true, is_not_modified(&headers, r#""abc""#, &modified));
+        let later = Utc.with_ymd_and_hms(2015, 10, 21, 7, 28, 1).unwrap();
+        assert_eq!(false, is_not_modified(&headers, r#""abc""#, &later));
+
+        headers.insert(
+            header::IF_NONE_MATCH,
+            HeaderValue::from_static(r#""xyz", W/"abc""#),
+        );
+        assert_eq!(true, is_not_modified(&headers, r#""abc""#, &later));
+        // 有If-None-Match时以其为准
+        assert_eq!(false, is_not_modified(&headers, r#""def""#, &modified));
+    }
+}
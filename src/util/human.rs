@@ -0,0 +1,118 @@
+use super::float_to_fixed;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::time::Duration;
+
+const BINARY_UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+const DECIMAL_UNITS: [&str; 6] = ["B", "kB", "MB", "GB", "TB", "PB"];
+
+/// 格式化字节数，binary为true时以1024为单位(KiB)，
+/// 否则以1000为单位(kB)
+pub fn format_bytes(bytes: u64, binary: bool) -> String {
+    let (base, units) = if binary {
+        (1024.0, BINARY_UNITS)
+    } else {
+        (1000.0, DECIMAL_UNITS)
+    };
+    let mut value = bytes as f64;
+    let mut index = 0;
+    while value >= base && index < units.len() - 1 {
+        value /= base;
+        index += 1;
+    }
+    if index == 0 {
+        return format!("{bytes} B");
+    }
+    format!("{} {}", float_to_fixed(value, 1), units[index])
+}
+
+/// 格式化时长，小于1秒的以毫秒展示，
+/// 其它的按秒、分、时、天展示，precision为小数位数
+pub fn format_duration(value: Duration, precision: usize) -> String {
+    let ms = value.as_millis();
+    if ms < 1000 {
+        return format!("{ms}ms");
+    }
+    let secs = value.as_secs_f64();
+    let (value, unit) = if secs < 60.0 {
+        (secs, "s")
+    } else if secs < 3600.0 {
+        (secs / 60.0, "m")
+    } else if secs < 86400.0 {
+        (secs / 3600.0, "h")
+    } else {
+        (secs / 86400.0, "d")
+    };
+    format!("{}{unit}", float_to_fixed(value, precision))
+}
+
+/// 字节数，序列化时同时输出原始值与格式化后的字符串，
+/// 如`{"bytes": 1048576, "human": "1.0 MiB"}`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HumanBytes(pub u64);
+
+impl Serialize for HumanBytes {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("HumanBytes", 2)?;
+        s.serialize_field("bytes", &self.0)?;
+        s.serialize_field("human", &format_bytes(self.0, true))?;
+        s.end()
+    }
+}
+
+/// 时长，序列化时同时输出毫秒数与格式化后的字符串，
+/// 如`{"ms": 1500, "human": "1.5s"}`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct HumanDuration(pub Duration);
+
+impl Serialize for HumanDuration {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let mut s = serializer.serialize_struct("HumanDuration", 2)?;
+        s.serialize_field("ms", &(self.0.as_millis() as u64))?;
+        s.serialize_field("human", &format_duration(self.0, 1))?;
+        s.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_bytes, format_duration, HumanBytes, HumanDuration};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+
+    #[test]
+    fn bytes() {
+        assert_eq!("0 B", format_bytes(0, true));
+        assert_eq!("1023 B", format_bytes(1023, true));
+        assert_eq!("1.0 KiB", format_bytes(1024, true));
+        assert_eq!("1.0 MiB", format_bytes(1024 * 1024, true));
+        assert_eq!("1.5 kB", format_bytes(1500, false));
+        assert_eq!("2.3 GB", format_bytes(2_300_000_000, false));
+    }
+
+    #[test]
+    fn duration() {
+        assert_eq!("120ms", format_duration(Duration::from_millis(120), 1));
+        assert_eq!("1.5s", format_duration(Duration::from_millis(1500), 1));
+        assert_eq!("2.50m", format_duration(Duration::from_secs(150), 2));
+        assert_eq!("3h", format_duration(Duration::from_secs(3 * 3600), 0));
+        assert_eq!("2.0d", format_duration(Duration::from_secs(2 * 86400), 1));
+    }
+
+    #[test]
+    fn serialize() {
+        assert_eq!(
+            r#"{"bytes":1048576,"human":"1.0 MiB"}"#,
+            serde_json::to_string(&HumanBytes(1048576)).unwrap()
+        );
+        assert_eq!(
+            r#"{"ms":1500,"human":"1.5s"}"#,
+            serde_json::to_string(&HumanDuration(Duration::from_millis(1500))).unwrap()
+        );
+    }
+}
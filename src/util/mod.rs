@@ -1,28 +1,46 @@
+mod captcha;
+mod clock;
 mod compress;
+mod content_type;
 mod context;
+mod csv;
 mod datetime;
 mod duration;
 mod http;
+mod human;
 mod number;
+mod signature;
 mod string;
+mod totp;
+mod trace_context;
 mod value;
 
 use crate::config::get_env;
 
+pub use self::captcha::new_captcha;
 pub use self::http::{
-    get_header_value, insert_header, read_http_body, set_header_if_not_exist,
-    set_no_cache_if_not_exist,
+    get_header_value, insert_header, is_not_modified, read_http_body, set_header_if_not_exist,
+    set_no_cache_if_not_exist, to_http_date, ChannelBody, NDJSON_CONTENT_TYPE,
 };
+pub use clock::Clock;
 pub use compress::Error as CompressError;
-pub use compress::{lz4_decode, lz4_encode, zstd_decode, zstd_encode};
+pub use compress::{zstd_decode, zstd_encode};
+pub use content_type::{
+    image_dimensions, is_active_content_type, is_content_type_compatible, sniff_content_type,
+};
 pub use context::{
     generate_device_id_cookie, get_account_from_context, get_device_id_from_cookie,
     set_account_to_context, Account,
 };
+pub use csv::{json_to_csv_line, to_csv_line, CSV_CONTENT_TYPE};
 pub use datetime::{from_timestamp, now, timestamp};
 pub use duration::{get_duration, get_duration_string};
+pub use human::{format_bytes, HumanBytes, HumanDuration};
 pub use number::float_to_fixed;
+pub use signature::*;
 pub use string::*;
+pub use totp::{generate_totp_secret, get_totp_url, verify_totp, TOTP_PERIOD};
+pub use trace_context::{new_span_id, TraceContext, TRACEPARENT_HEADER};
 pub use value::*;
 
 /// 是否开发环境
@@ -0,0 +1,60 @@
+use super::sha256;
+use crate::error::{HttpError, HttpResult};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 签名使用的key id
+pub static SIGNATURE_KEY_HEADER: &str = "x-signature-key";
+/// 签名的时间戳(秒)
+pub static SIGNATURE_TIMESTAMP_HEADER: &str = "x-signature-timestamp";
+/// 签名
+pub static SIGNATURE_HEADER: &str = "x-signature";
+
+fn new_mac(
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    body: &[u8],
+    secret: &str,
+) -> HttpResult<HmacSha256> {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes())
+        .map_err(|err| HttpError::new_with_category(&err.to_string(), "signature"))?;
+    // 签名内容：method、path、时间戳以及body的hash
+    let data = format!(
+        "{}\n{path}\n{timestamp}\n{}",
+        method.to_uppercase(),
+        sha256(body)
+    );
+    mac.update(data.as_bytes());
+    Ok(mac)
+}
+
+/// 生成请求签名
+pub fn sign_request(
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    body: &[u8],
+    secret: &str,
+) -> HttpResult<String> {
+    let mac = new_mac(method, path, timestamp, body, secret)?;
+    Ok(hex::encode(mac.finalize().into_bytes()))
+}
+
+/// 校验请求签名
+pub fn verify_request(
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    body: &[u8],
+    secret: &str,
+    signature: &str,
+) -> HttpResult<bool> {
+    let Ok(signature) = hex::decode(signature) else {
+        return Ok(false);
+    };
+    let mac = new_mac(method, path, timestamp, body, secret)?;
+    Ok(mac.verify_slice(&signature).is_ok())
+}
@@ -1,4 +1,4 @@
-use super::timestamp;
+use super::{timestamp, Clock};
 use crate::error::HttpResult;
 use crate::{config, error::HttpError};
 use hex::encode;
@@ -45,14 +45,111 @@ pub fn timestamp_hash(value: &str) -> (i64, String) {
     (ts, hash)
 }
 
-pub fn validate_timestamp_hash(ts: i64, value: &str, hash: &str) -> HttpResult<()> {
-    // 超过5分钟
-    let category = "timestamp_hash";
-    if (timestamp() - ts).abs() > 5 * 60 {
+/// 校验时间戳与当前时间的差异是否在允许范围内(秒)
+pub fn validate_timestamp(clock: &Clock, ts: i64, max_age: i64) -> HttpResult<()> {
+    if (clock.timestamp() - ts).abs() > max_age {
         return Err(HttpError::new_with_category(
             "数据已过期，请刷新后重试",
-            category,
+            "timestamp_hash",
         ));
     }
+    Ok(())
+}
+
+pub fn validate_timestamp_hash(ts: i64, value: &str, hash: &str) -> HttpResult<()> {
+    // 超过5分钟
+    validate_timestamp(&Clock::System, ts, 5 * 60)?;
     validate_sign_hash(&format!("{ts}:{value}"), hash)
 }
+
+/// 账号规范化，去除首尾空格并转换为小写
+pub fn normalize_account(account: &str) -> String {
+    account.trim().to_lowercase()
+}
+
+// 常见的与拉丁字母外观相同的字符
+static CONFUSABLE_CHARS: &[(char, char)] = &[
+    ('а', 'a'),
+    ('в', 'b'),
+    ('е', 'e'),
+    ('і', 'i'),
+    ('ј', 'j'),
+    ('к', 'k'),
+    ('м', 'm'),
+    ('н', 'h'),
+    ('о', 'o'),
+    ('р', 'p'),
+    ('с', 'c'),
+    ('ѕ', 's'),
+    ('т', 't'),
+    ('у', 'y'),
+    ('х', 'x'),
+    ('α', 'a'),
+    ('ι', 'i'),
+    ('κ', 'k'),
+    ('ν', 'v'),
+    ('ο', 'o'),
+    ('ρ', 'p'),
+    ('τ', 't'),
+    ('υ', 'u'),
+    ('ı', 'i'),
+];
+
+/// 将账号中易混淆的字符转换为对应的拉丁字母，
+/// 用于判断是否与已有账号仅有易混淆字符的差异
+pub fn account_skeleton(account: &str) -> String {
+    normalize_account(account)
+        .chars()
+        .map(|c| {
+            CONFUSABLE_CHARS
+                .iter()
+                .find(|(from, _)| *from == c)
+                .map(|(_, to)| *to)
+                .unwrap_or(c)
+        })
+        .collect()
+}
+
+/// 路径匹配，*匹配除/外的任意字符，**匹配任意字符
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    fn matches(pattern: &[u8], path: &[u8]) -> bool {
+        match pattern.first() {
+            None => path.is_empty(),
+            Some(b'*') if pattern.get(1) == Some(&b'*') => {
+                (0..=path.len()).any(|index| matches(&pattern[2..], &path[index..]))
+            }
+            Some(b'*') => {
+                let max = path.iter().position(|c| *c == b'/').unwrap_or(path.len());
+                (0..=max).any(|index| matches(&pattern[1..], &path[index..]))
+            }
+            Some(c) => path.first() == Some(c) && matches(&pattern[1..], &path[1..]),
+        }
+    }
+    matches(pattern.as_bytes(), path.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{glob_match, validate_timestamp, Clock};
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    #[test]
+    fn glob() {
+        assert_eq!(true, glob_match("/api/users/me", "/api/users/me"));
+        assert_eq!(true, glob_match("/api/users/*", "/api/users/me"));
+        assert_eq!(false, glob_match("/api/users/*", "/api/users/me/features"));
+        assert_eq!(true, glob_match("/api/users/**", "/api/users/me/features"));
+        assert_eq!(true, glob_match("/api/*/me", "/api/users/me"));
+        assert_eq!(false, glob_match("/api/users", "/api/users/me"));
+    }
+    #[test]
+    fn timestamp_expired() {
+        let clock = Clock::new_test(1_700_000_000);
+        let ts = clock.timestamp();
+        assert_eq!(true, validate_timestamp(&clock, ts, 300).is_ok());
+        clock.advance(Duration::from_secs(300));
+        assert_eq!(true, validate_timestamp(&clock, ts, 300).is_ok());
+        clock.advance(Duration::from_secs(1));
+        assert_eq!(true, validate_timestamp(&clock, ts, 300).is_err());
+    }
+}
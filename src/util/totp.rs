@@ -0,0 +1,106 @@
+use crate::error::{HttpError, HttpResult};
+use nanoid::nanoid;
+use ring::hmac;
+
+const BASE32_ALPHABET: [char; 32] = [
+    'A', 'B', 'C', 'D', 'E', 'F', 'G', 'H', 'I', 'J', 'K', 'L', 'M', 'N', 'O', 'P', 'Q', 'R', 'S',
+    'T', 'U', 'V', 'W', 'X', 'Y', 'Z', '2', '3', '4', '5', '6', '7',
+];
+/// 验证码的有效时长(秒)
+pub const TOTP_PERIOD: i64 = 30;
+const TOTP_DIGITS: u32 = 6;
+const TOTP_ISSUER: &str = "tibba";
+
+/// 生成base32的totp密钥(160位)
+pub fn generate_totp_secret() -> String {
+    nanoid!(32, &BASE32_ALPHABET)
+}
+
+fn base32_decode(value: &str) -> HttpResult<Vec<u8>> {
+    let mut result = vec![];
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in value.trim_end_matches('=').chars() {
+        let Some(index) = BASE32_ALPHABET
+            .iter()
+            .position(|item| *item == c.to_ascii_uppercase())
+        else {
+            return Err(HttpError::new_with_category(
+                "Totp secret is invalid",
+                "totp",
+            ));
+        };
+        buffer = (buffer << 5) | index as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    Ok(result)
+}
+
+// 根据时间窗口生成验证码(RFC 6238，HMAC-SHA1)
+fn totp_code(key: &[u8], step: i64) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA1_FOR_LEGACY_USE_ONLY, key);
+    let tag = hmac::sign(&key, &step.to_be_bytes());
+    let hash = tag.as_ref();
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let value = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    format!(
+        "{:0width$}",
+        value % 10u32.pow(TOTP_DIGITS),
+        width = TOTP_DIGITS as usize
+    )
+}
+
+/// 校验验证码，允许前后各一个时间窗口的偏差，
+/// 成功时返回匹配的时间窗口，用于避免重复使用
+pub fn verify_totp(secret: &str, code: &str, timestamp: i64) -> HttpResult<Option<i64>> {
+    let key = base32_decode(secret)?;
+    let current = timestamp / TOTP_PERIOD;
+    for step in [current, current - 1, current + 1] {
+        if totp_code(&key, step) == code.trim() {
+            return Ok(Some(step));
+        }
+    }
+    Ok(None)
+}
+
+/// 生成认证器使用的otpauth地址
+pub fn get_totp_url(account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{TOTP_ISSUER}:{}?secret={secret}&issuer={TOTP_ISSUER}&algorithm=SHA1&digits={TOTP_DIGITS}&period={TOTP_PERIOD}",
+        urlencoding::encode(account)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base32_decode, generate_totp_secret, totp_code, verify_totp, TOTP_PERIOD};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn totp() {
+        // RFC 6238的测试数据(取后6位)
+        let key = b"12345678901234567890";
+        assert_eq!("287082", totp_code(key, 59 / TOTP_PERIOD));
+        assert_eq!("081804", totp_code(key, 1111111109 / TOTP_PERIOD));
+        assert_eq!("005924", totp_code(key, 1234567890 / TOTP_PERIOD));
+
+        // "12345678901234567890"的base32
+        let secret = "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ";
+        assert_eq!(key.to_vec(), base32_decode(secret).unwrap());
+        assert_eq!(Some(1), verify_totp(secret, "287082", 59).unwrap());
+        assert_eq!(Some(1), verify_totp(secret, "287082", 89).unwrap());
+        assert_eq!(None, verify_totp(secret, "287082", 120).unwrap());
+
+        assert_eq!(20, base32_decode(&generate_totp_secret()).unwrap().len());
+    }
+}
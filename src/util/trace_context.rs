@@ -0,0 +1,107 @@
+use nanoid::nanoid;
+
+const HEX_ALPHABET: [char; 16] = [
+    '0', '1', '2', '3', '4', '5', '6', '7', '8', '9', 'a', 'b', 'c', 'd', 'e', 'f',
+];
+const TRACE_FLAG_SAMPLED: u8 = 0x01;
+/// W3C trace context的请求头
+pub static TRACEPARENT_HEADER: &str = "traceparent";
+
+/// W3C trace context，用于跨实例关联同一请求
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TraceContext {
+    // 32位16进制
+    pub trace_id: String,
+    // 当前处理的span id，16位16进制
+    pub span_id: String,
+    pub sampled: bool,
+}
+
+fn is_hex(value: &str, size: usize) -> bool {
+    value.len() == size
+        && value.chars().all(|c| HEX_ALPHABET.contains(&c))
+        && value.chars().any(|c| c != '0')
+}
+
+/// 生成新的span id
+pub fn new_span_id() -> String {
+    nanoid!(16, &HEX_ALPHABET)
+}
+
+impl TraceContext {
+    /// 生成新的trace
+    pub fn new(sampled: bool) -> Self {
+        TraceContext {
+            trace_id: nanoid!(32, &HEX_ALPHABET),
+            span_id: new_span_id(),
+            sampled,
+        }
+    }
+    /// 解析traceparent(version-trace_id-parent_id-flags)，
+    /// 成功则沿用trace id与采样标记，并生成当前的span id
+    pub fn from_traceparent(value: &str) -> Option<Self> {
+        let arr: Vec<&str> = value.trim().split('-').collect();
+        let [version, trace_id, parent_id, flags] = arr[..] else {
+            return None;
+        };
+        if version != "00" || !is_hex(trace_id, 32) || !is_hex(parent_id, 16) || flags.len() != 2 {
+            return None;
+        }
+        let flags = u8::from_str_radix(flags, 16).ok()?;
+        Some(TraceContext {
+            trace_id: trace_id.to_string(),
+            span_id: new_span_id(),
+            sampled: flags & TRACE_FLAG_SAMPLED != 0,
+        })
+    }
+    /// 生成调用其它服务时的traceparent，span_id为子调用的span
+    pub fn to_traceparent(&self, span_id: &str) -> String {
+        let flags = if self.sampled { TRACE_FLAG_SAMPLED } else { 0 };
+        format!("00-{}-{span_id}-{flags:02x}", self.trace_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TraceContext;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn trace_context() {
+        let ctx = TraceContext::from_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        )
+        .unwrap();
+        assert_eq!("4bf92f3577b34da6a3ce929d0e0e4736", ctx.trace_id);
+        assert_eq!(true, ctx.sampled);
+        assert_eq!(16, ctx.span_id.len());
+        assert_eq!(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            ctx.to_traceparent("00f067aa0ba902b7")
+        );
+
+        let ctx = TraceContext::from_traceparent(
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00",
+        )
+        .unwrap();
+        assert_eq!(false, ctx.sampled);
+
+        for value in [
+            "",
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+        ] {
+            assert_eq!(None, TraceContext::from_traceparent(value));
+        }
+
+        let ctx = TraceContext::new(true);
+        assert_eq!(
+            Some(ctx.trace_id.clone()),
+            TraceContext::from_traceparent(&ctx.to_traceparent(&ctx.span_id))
+                .map(|item| item.trace_id)
+        );
+    }
+}
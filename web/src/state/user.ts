@@ -28,12 +28,7 @@ const refresh = (expiredAt: string) => {
   if (dayjs(expiredAt).unix() - dayjs().unix() > offset) {
     return;
   }
-  request
-    .get<{
-      access_token: string;
-      token_type: string;
-    }>(USER_REFRESH)
-    .catch(console.error);
+  request.post(USER_REFRESH).catch(console.error);
 };
 
 const useUserStore = create<UserState>()((set, get) => ({